@@ -1,5 +1,13 @@
 use std::path::PathBuf;
 
+// The RomFS writer is shared with the library, and only needs the standard library.
+#[path = "src/romfs/builder.rs"]
+#[allow(dead_code)]
+mod builder;
+#[path = "src/romfs/format.rs"]
+#[allow(dead_code)]
+mod format;
+
 fn main() {
     // Open Cargo.toml
     let manifest_dir = std::env::var("CARGO_MANIFEST_DIR").unwrap();
@@ -26,6 +34,11 @@ fn main() {
     // Check if the romfs path exists so we can compile the module
     if romfs_path.exists() {
         println!("cargo:rustc-cfg=romfs_exists");
+
+        // Also build the image, so that it can be embedded or written out without external tools
+        let out_dir = PathBuf::from(std::env::var("OUT_DIR").unwrap());
+        builder::build_dir(&romfs_path, out_dir.join("romfs.bin"))
+            .unwrap_or_else(|e| panic!("Could not build the RomFS image: {e}"));
     }
 
     println!("cargo:rerun-if-changed={manifest_dir}");
@@ -0,0 +1,70 @@
+use std::fmt::Write;
+
+use ctru::console::window::WindowedConsole;
+use ctru::gfx::text::PsfFont;
+use ctru::prelude::*;
+
+fn main() {
+    ctru::use_panic_handler();
+
+    let apt = Apt::init().unwrap();
+    let hid = Hid::init().unwrap();
+    let gfx = Gfx::init().unwrap();
+
+    let mut console = WindowedConsole::new(gfx.top_screen.borrow_mut(), PsfFont::system());
+    let (columns, rows) = (console.columns(), console.rows());
+
+    // A status bar at the top, a log on the left and a help panel on the right.
+    let status = console.add_window(0, 0, columns, 1, 0).unwrap();
+    let log = console
+        .add_window(0, 1, columns - 20, rows - 1, 200)
+        .unwrap();
+    let help = console
+        .add_window(columns - 20, 1, 20, rows - 1, 0)
+        .unwrap();
+
+    write!(
+        console.window(help),
+        "\x1b[1mControls\x1b[0m\n\nA: log a line\nUp/Down: scroll\nStart: exit"
+    )
+    .unwrap();
+    console.window(log).set_cursor_visible(false);
+
+    let mut count = 0;
+
+    while apt.main_loop() {
+        hid.scan_input();
+        let keys = hid.keys_down();
+
+        if keys.contains(KeyPad::KEY_START) {
+            break;
+        }
+        if keys.contains(KeyPad::KEY_A) {
+            count += 1;
+            let color = 31 + count % 6;
+            writeln!(console.window(log), "\x1b[{color}mLine {count}\x1b[0m").unwrap();
+        }
+        if keys.contains(KeyPad::KEY_DUP) {
+            console.window(log).scroll_up(1);
+        }
+        if keys.contains(KeyPad::KEY_DDOWN) {
+            console.window(log).scroll_down(1);
+        }
+
+        let scroll = console.window(log).scroll_position();
+        let status_bar = console.window(status);
+        status_bar.clear();
+        write!(
+            status_bar,
+            "\x1b[30;47m\x1b[2K {count} lines, scrolled back by {scroll}"
+        )
+        .unwrap();
+
+        // Every window has to be drawn again on each frame, because of double buffering.
+        console.render();
+
+        gfx.flush_buffers();
+        gfx.swap_buffers();
+        gfx.wait_for_vblank();
+    }
+}
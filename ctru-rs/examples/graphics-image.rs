@@ -0,0 +1,56 @@
+use ctru::gfx::image::{Dither, Image};
+use ctru::gfx::Screen as _;
+use ctru::prelude::*;
+
+/// Ferris image taken from <https://rustacean.net> and scaled down to 320x240px.
+///
+/// Unlike the `graphics-bitmap` example, the image is stored as a regular PNG file
+/// and converted to the layout of the frame buffer at runtime.
+static IMAGE: &[u8] = include_bytes!("assets/ferris.png");
+
+fn main() {
+    ctru::use_panic_handler();
+
+    let gfx = Gfx::init().expect("Couldn't obtain GFX controller");
+    let hid = Hid::init().expect("Couldn't obtain HID controller");
+    let apt = Apt::init().expect("Couldn't obtain APT controller");
+    let _console = Console::init(gfx.top_screen.borrow_mut());
+
+    println!("\x1b[21;16HPress Start to exit.");
+
+    let image = Image::decode(IMAGE).expect("Couldn't decode image");
+
+    let mut bottom_screen = gfx.bottom_screen.borrow_mut();
+
+    // We don't need double buffering in this example.
+    // In this way we can draw our image only once on screen.
+    bottom_screen.set_double_buffering(false);
+
+    // Convert the image to the rotated layout and pixel format of the frame buffer.
+    let format = bottom_screen.get_framebuffer_format();
+    let data = image.to_framebuffer(format, Dither::Ordered);
+
+    let frame_buffer = bottom_screen.get_raw_framebuffer();
+
+    // Copy the image into the frame buffer
+    unsafe {
+        frame_buffer.ptr.copy_from(data.as_ptr(), data.len());
+    }
+
+    // Main loop
+    while apt.main_loop() {
+        //Scan all the inputs. This should be done once for each frame
+        hid.scan_input();
+
+        if hid.keys_down().contains(KeyPad::KEY_START) {
+            break;
+        }
+
+        // Flush and swap framebuffers
+        gfx.flush_buffers();
+        gfx.swap_buffers();
+
+        //Wait for VBlank
+        gfx.wait_for_vblank();
+    }
+}
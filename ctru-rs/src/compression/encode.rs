@@ -0,0 +1,401 @@
+//! Compressors for the formats of the system's decoders
+//!
+//! The output follows what the official tools produce: a header, then the compressed data padded
+//! to a multiple of 4 bytes.
+
+use std::cmp::Reverse;
+use std::collections::BinaryHeap;
+
+use super::{Format, Header};
+
+/// Compresses `data`, header included.
+///
+/// # Examples
+///
+/// ```
+/// use ctru::compression::{self, Format, Header};
+///
+/// let packed = compression::compress(Format::Rle, &[0; 4096]);
+/// let header = Header::read(&packed[..]).unwrap();
+///
+/// assert_eq!(header.len(), 4096);
+/// assert!(packed.len() < 100);
+/// ```
+pub fn compress(format: Format, data: &[u8]) -> Vec<u8> {
+    let mut out = vec![format as u8];
+    // An empty stream also needs the long header, since a size of 0 announces it.
+    if data.is_empty() || data.len() > Header::SHORT_MAX_LEN {
+        out.extend([0; 3]);
+        out.extend((data.len() as u32).to_le_bytes());
+    } else {
+        out.extend(&(data.len() as u32).to_le_bytes()[..3]);
+    }
+
+    if !data.is_empty() {
+        match format {
+            Format::Lz10 => lz(data, &mut out, 18, lz10_match),
+            Format::Lz11 => lz(data, &mut out, 0x10110, lz11_match),
+            Format::Huffman4 => huffman(&nibbles(data), 4, &mut out),
+            Format::Huffman8 => huffman(data, 8, &mut out),
+            Format::Rle => rle(data, &mut out),
+        }
+    }
+
+    out.resize((out.len() + 3) / 4 * 4, 0);
+    out
+}
+
+/// Farthest distance a match can refer to.
+const WINDOW: usize = 0x1000;
+
+/// Shortest match worth encoding.
+const MIN_MATCH: usize = 3;
+
+/// Amount of earlier positions tried when looking for a match.
+const MAX_CHAIN: usize = 256;
+
+const HASH_BITS: u32 = 14;
+
+const NO_POSITION: usize = usize::MAX;
+
+/// Finds earlier occurrences of the data at a position, through chains of positions sharing the hash
+/// of their first bytes.
+struct MatchFinder<'a> {
+    data: &'a [u8],
+    head: Vec<usize>,
+    prev: Vec<usize>,
+}
+
+impl<'a> MatchFinder<'a> {
+    fn new(data: &'a [u8]) -> Self {
+        Self {
+            data,
+            head: vec![NO_POSITION; 1 << HASH_BITS],
+            prev: vec![NO_POSITION; data.len()],
+        }
+    }
+
+    fn hash(&self, pos: usize) -> Option<usize> {
+        let bytes = self.data.get(pos..pos + MIN_MATCH)?;
+        let key = u32::from_le_bytes([bytes[0], bytes[1], bytes[2], 0]);
+        Some((key.wrapping_mul(0x9e37_79b1) >> (32 - HASH_BITS)) as usize)
+    }
+
+    fn insert(&mut self, pos: usize) {
+        if let Some(hash) = self.hash(pos) {
+            self.prev[pos] = self.head[hash];
+            self.head[hash] = pos;
+        }
+    }
+
+    /// Returns the length and distance of the longest match at `pos`, if any.
+    fn longest(&self, pos: usize, max_len: usize) -> Option<(usize, usize)> {
+        let max_len = max_len.min(self.data.len() - pos);
+        let mut best: Option<(usize, usize)> = None;
+
+        let mut candidate = self.head[self.hash(pos)?];
+        for _ in 0..MAX_CHAIN {
+            if candidate == NO_POSITION || pos - candidate > WINDOW {
+                break;
+            }
+
+            // Matches may overlap with the data they produce.
+            let len = (0..max_len)
+                .take_while(|&i| self.data[candidate + i] == self.data[pos + i])
+                .count();
+            if len >= MIN_MATCH && best.map_or(true, |(best_len, _)| len > best_len) {
+                best = Some((len, pos - candidate));
+                if len == max_len {
+                    break;
+                }
+            }
+            candidate = self.prev[candidate];
+        }
+
+        best
+    }
+}
+
+/// Compresses with LZSS, greedily taking the longest match at each position.
+fn lz(data: &[u8], out: &mut Vec<u8>, max_len: usize, encode: fn(usize, usize, &mut Vec<u8>)) {
+    let mut finder = MatchFinder::new(data);
+    let mut pos = 0;
+    let mut flags_at = 0;
+    let mut tokens = 8;
+
+    while pos < data.len() {
+        if tokens == 8 {
+            flags_at = out.len();
+            out.push(0);
+            tokens = 0;
+        }
+
+        match finder.longest(pos, max_len) {
+            Some((len, distance)) => {
+                out[flags_at] |= 0x80 >> tokens;
+                encode(len, distance - 1, out);
+                for pos in pos..pos + len {
+                    finder.insert(pos);
+                }
+                pos += len;
+            }
+            None => {
+                out.push(data[pos]);
+                finder.insert(pos);
+                pos += 1;
+            }
+        }
+        tokens += 1;
+    }
+}
+
+fn lz10_match(len: usize, distance: usize, out: &mut Vec<u8>) {
+    out.extend([((len - 3) << 4 | distance >> 8) as u8, distance as u8]);
+}
+
+fn lz11_match(len: usize, distance: usize, out: &mut Vec<u8>) {
+    match len {
+        0..=0x10 => out.extend([((len - 1) << 4 | distance >> 8) as u8, distance as u8]),
+        0x11..=0x110 => {
+            let len = len - 0x11;
+            out.extend([
+                (len >> 4) as u8,
+                ((len & 0xf) << 4 | distance >> 8) as u8,
+                distance as u8,
+            ]);
+        }
+        _ => {
+            let len = len - 0x111;
+            out.extend([
+                (0x10 | len >> 12) as u8,
+                (len >> 4) as u8,
+                ((len & 0xf) << 4 | distance >> 8) as u8,
+                distance as u8,
+            ]);
+        }
+    }
+}
+
+fn rle(data: &[u8], out: &mut Vec<u8>) {
+    let mut literals_from = 0;
+    let mut pos = 0;
+
+    let flush = |out: &mut Vec<u8>, literals: &[u8]| {
+        for chunk in literals.chunks(0x80) {
+            out.push(chunk.len() as u8 - 1);
+            out.extend(chunk);
+        }
+    };
+
+    while pos < data.len() {
+        let run = data[pos..]
+            .iter()
+            .take(0x82)
+            .take_while(|&&byte| byte == data[pos])
+            .count();
+
+        if run >= 3 {
+            flush(out, &data[literals_from..pos]);
+            out.extend([0x80 | (run - 3) as u8, data[pos]]);
+            pos += run;
+            literals_from = pos;
+        } else {
+            pos += 1;
+        }
+    }
+    flush(out, &data[literals_from..]);
+}
+
+/// Splits bytes into 4-bit symbols, low nibble first.
+fn nibbles(data: &[u8]) -> Vec<u8> {
+    data.iter()
+        .flat_map(|byte| [byte & 0xf, byte >> 4])
+        .collect()
+}
+
+enum Node {
+    Leaf(u8),
+    Internal(usize, usize),
+}
+
+/// Highest distance, in pairs of nodes, between a node and its children in the tree table.
+const MAX_CHILD_DISTANCE: usize = 0x40;
+
+fn huffman(symbols: &[u8], bits: u32, out: &mut Vec<u8>) {
+    let mut counts = vec![0u64; 1 << bits];
+    for &symbol in symbols {
+        counts[symbol as usize] += 1;
+    }
+
+    // Trees which don't fit in the table are flattened until they do, complete trees always fitting.
+    let (nodes, root, table) = loop {
+        let (nodes, root) = huffman_tree(&counts);
+        if let Some(table) = huffman_table(&nodes, root) {
+            break (nodes, root, table);
+        }
+        for count in counts.iter_mut().filter(|count| **count > 0) {
+            *count = *count / 2 + 1;
+        }
+    };
+    out.extend(table);
+
+    let mut codes = vec![Vec::new(); 1 << bits];
+    let mut stack = vec![(root, Vec::new())];
+    while let Some((node, code)) = stack.pop() {
+        match nodes[node] {
+            Node::Leaf(symbol) => codes[symbol as usize] = code,
+            Node::Internal(zero, one) => {
+                let mut one_code = code.clone();
+                one_code.push(true);
+                let mut zero_code = code;
+                zero_code.push(false);
+                stack.extend([(zero, zero_code), (one, one_code)]);
+            }
+        }
+    }
+
+    // Codes are packed into little endian words, from their most significant bit.
+    let mut word = 0u32;
+    let mut used = 0;
+    for &symbol in symbols {
+        for &bit in &codes[symbol as usize] {
+            word = word << 1 | bit as u32;
+            used += 1;
+            if used == 32 {
+                out.extend(word.to_le_bytes());
+                used = 0;
+            }
+        }
+    }
+    if used > 0 {
+        out.extend((word << (32 - used)).to_le_bytes());
+    }
+}
+
+/// Builds a Huffman tree from the amount of occurrences of each symbol.
+fn huffman_tree(counts: &[u64]) -> (Vec<Node>, usize) {
+    let mut nodes = Vec::new();
+    let mut heap = BinaryHeap::new();
+    for (symbol, &count) in counts.iter().enumerate() {
+        if count > 0 {
+            heap.push(Reverse((count, nodes.len())));
+            nodes.push(Node::Leaf(symbol as u8));
+        }
+    }
+    // The tree needs at least two leaves.
+    for (symbol, &count) in counts.iter().enumerate().take(2) {
+        if heap.len() < 2 && count == 0 {
+            heap.push(Reverse((0, nodes.len())));
+            nodes.push(Node::Leaf(symbol as u8));
+        }
+    }
+
+    while heap.len() > 1 {
+        let Reverse((zero_count, zero)) = heap.pop().unwrap();
+        let Reverse((one_count, one)) = heap.pop().unwrap();
+        heap.push(Reverse((zero_count + one_count, nodes.len())));
+        nodes.push(Node::Internal(zero, one));
+    }
+
+    let Reverse((_, root)) = heap.pop().unwrap();
+    (nodes, root)
+}
+
+/// Lays out a tree in the format of the decoders, or returns `None` if it doesn't fit.
+///
+/// The table starts with its size and the root node, followed by pairs of sibling nodes. Internal
+/// nodes hold the distance to the pair of their children, which can't be more than 64 pairs away,
+/// and whether each child is a leaf.
+fn huffman_table(nodes: &[Node], root: usize) -> Option<Vec<u8>> {
+    // Amount of internal nodes in the subtree of each node, children being created before parents.
+    let mut sizes = vec![0; nodes.len()];
+    for (i, node) in nodes.iter().enumerate() {
+        if let Node::Internal(zero, one) = *node {
+            sizes[i] = 1 + sizes[zero] + sizes[one];
+        }
+    }
+
+    let mut table = vec![0, 0];
+    // Internal nodes whose children are yet to be placed, with their index in the table, in the
+    // order they were placed in.
+    let mut pending = vec![(1, root)];
+    let deadline = |index: usize| index / 2 + MAX_CHILD_DISTANCE;
+
+    while !pending.is_empty() {
+        let pair = table.len() / 2;
+
+        // Descending into the latest node keeps few nodes pending, as long as the earlier ones can
+        // still be placed in time afterwards.
+        let last = pending.len() - 1;
+        let latest_fits = last < MAX_CHILD_DISTANCE - 2
+            && pending[..last]
+                .iter()
+                .enumerate()
+                .all(|(i, &(index, _))| deadline(index) > pair + i);
+        let (index, node) = pending.remove(if latest_fits { last } else { 0 });
+        if deadline(index) < pair {
+            return None;
+        }
+
+        let (zero, one) = match nodes[node] {
+            Node::Internal(zero, one) => (zero, one),
+            Node::Leaf(_) => unreachable!("leaves are never pending"),
+        };
+        let mut value = (pair - index / 2 - 1) as u8;
+        let mut children = Vec::new();
+        for (child, flag) in [(zero, 0x80), (one, 0x40)] {
+            match nodes[child] {
+                Node::Leaf(symbol) => {
+                    value |= flag;
+                    table.push(symbol);
+                }
+                Node::Internal(..) => {
+                    children.push((table.len(), child));
+                    table.push(0);
+                }
+            }
+        }
+        table[index] = value;
+
+        // The smaller subtree comes last, so that it's completed first.
+        children.sort_by_key(|&(_, child)| Reverse(sizes[child]));
+        pending.extend(children);
+    }
+
+    table.resize((table.len() + 3) / 4 * 4, 0);
+    table[0] = u8::try_from(table.len() / 2 - 1).ok()?;
+    Some(table)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn huffman_layout() {
+        // A complete tree over every byte is as wide as trees get, and fits.
+        let (nodes, root) = huffman_tree(&[1; 256]);
+        let table = huffman_table(&nodes, root).unwrap();
+        assert_eq!(table.len(), 512);
+        assert_eq!(table[0], 0xff);
+
+        // As do very deep ones.
+        let counts: Vec<u64> = (0..256).map(|i| 1 << (i / 5)).collect();
+        let (nodes, root) = huffman_tree(&counts);
+        assert!(huffman_table(&nodes, root).is_some());
+
+        let (nodes, root) = huffman_tree(&[0, 0, 5]);
+        assert_eq!(huffman_table(&nodes, root).unwrap(), [1, 0xc0, 0, 2]);
+    }
+
+    #[test]
+    fn lz_tokens() {
+        let mut out = Vec::new();
+        lz(b"abcabcabcabc", &mut out, 18, lz10_match);
+        assert_eq!(out, [0b0001_0000, b'a', b'b', b'c', 0x60, 0x02]);
+
+        let mut out = Vec::new();
+        lz11_match(0x10110, 0xfff, &mut out);
+        assert_eq!(out, [0x1f, 0xff, 0xff, 0xff]);
+    }
+}
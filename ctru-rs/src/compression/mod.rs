@@ -0,0 +1,303 @@
+//! Compressed data in the formats of the system's BIOS
+//!
+//! Assets of 3DS (and DS) software are commonly compressed with LZ10, LZ11, Huffman or run-length
+//! encoding, each stream starting with a header giving its format and decompressed size.
+//! Decompression goes through the fast decoders of `libctru`, while compression is implemented in
+//! pure Rust and doesn't depend on the 3DS, so assets can also be packed on the host.
+//!
+//! # Examples
+//!
+//! ```no_run
+//! use ctru::compression::{self, Format};
+//!
+//! let packed = compression::compress(Format::Lz11, b"tiles tiles tiles tiles");
+//! let unpacked = compression::decompress(&packed[..]).unwrap();
+//! assert_eq!(unpacked, b"tiles tiles tiles tiles");
+//! ```
+
+#[cfg(feature = "host-mock")]
+use crate::mock::sys as ctru_sys;
+
+use std::ffi::c_void;
+use std::io::Error as IoError;
+use std::io::ErrorKind as IoErrorKind;
+use std::io::Read;
+use std::io::Result as IoResult;
+use std::slice;
+
+mod encode;
+
+pub use encode::compress;
+
+/// Compression formats understood by the decoders of the system.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+#[repr(u8)]
+pub enum Format {
+    /// LZSS with matches of up to 18 bytes.
+    Lz10 = 0x10,
+    /// LZSS with matches of up to 65808 bytes.
+    Lz11 = 0x11,
+    /// Huffman coding of 4-bit symbols, low nibble first.
+    Huffman4 = 0x24,
+    /// Huffman coding of bytes.
+    Huffman8 = 0x28,
+    /// Run-length encoding.
+    Rle = 0x30,
+}
+
+impl TryFrom<u8> for Format {
+    type Error = IoError;
+
+    fn try_from(value: u8) -> IoResult<Self> {
+        match value {
+            0x10 => Ok(Self::Lz10),
+            0x11 => Ok(Self::Lz11),
+            0x24 => Ok(Self::Huffman4),
+            0x28 => Ok(Self::Huffman8),
+            0x30 => Ok(Self::Rle),
+            _ => Err(IoError::new(
+                IoErrorKind::InvalidData,
+                format!("unknown compression format {value:#04x}"),
+            )),
+        }
+    }
+}
+
+/// Header at the start of every compressed stream.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Header {
+    format: Format,
+    len: usize,
+}
+
+impl Header {
+    /// Largest decompressed size which fits in the short, 4 bytes long header.
+    pub(crate) const SHORT_MAX_LEN: usize = 0xff_ffff;
+
+    /// Reads a header, which is 4 bytes long, or 8 bytes for data larger than 16 MiB.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if reading fails, or the format is unknown.
+    pub fn read<R: Read>(mut reader: R) -> IoResult<Self> {
+        let mut header = [0; 4];
+        reader.read_exact(&mut header)?;
+
+        let format = Format::try_from(header[0])?;
+        let mut len = u32::from_le_bytes([header[1], header[2], header[3], 0]);
+        if len == 0 {
+            reader.read_exact(&mut header)?;
+            len = u32::from_le_bytes(header);
+        }
+
+        Ok(Self {
+            format,
+            len: len as usize,
+        })
+    }
+
+    /// Returns the format of the stream.
+    pub fn format(&self) -> Format {
+        self.format
+    }
+
+    /// Returns the size of the data once decompressed.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Returns `true` if the stream decompresses to nothing.
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+}
+
+/// Decompresses a whole stream, header included.
+///
+/// The decoders read their input in chunks, so `reader` may be consumed past the end of the stream.
+/// Wrap it with [`Read::take`] if the data that follows must be read afterwards.
+///
+/// # Errors
+///
+/// This function will return an error if reading fails, the stream is corrupted, or the size given by
+/// its header can't be allocated.
+pub fn decompress<R: Read>(mut reader: R) -> IoResult<Vec<u8>> {
+    let header = Header::read(&mut reader)?;
+
+    // The size comes from the stream, so a corrupted header mustn't abort the application.
+    let mut buf = Vec::new();
+    buf.try_reserve_exact(header.len())
+        .map_err(|e| IoError::new(IoErrorKind::OutOfMemory, e))?;
+    buf.resize(header.len(), 0);
+    decompress_raw(header.format(), reader, &mut buf)?;
+    Ok(buf)
+}
+
+/// Decompresses a whole stream, header included, to the start of `buf`.
+///
+/// Returns the size of the decompressed data. See [`decompress`] for how `reader` is consumed.
+///
+/// # Errors
+///
+/// This function will return an error if `buf` is too short to hold the decompressed data, reading
+/// fails, or the stream is corrupted.
+pub fn decompress_into<R: Read>(mut reader: R, buf: &mut [u8]) -> IoResult<usize> {
+    let header = Header::read(&mut reader)?;
+    if buf.len() < header.len() {
+        return Err(IoError::new(
+            IoErrorKind::InvalidInput,
+            crate::Error::BufferTooShort {
+                provided: buf.len(),
+                wanted: header.len(),
+            },
+        ));
+    }
+
+    decompress_raw(header.format(), reader, &mut buf[..header.len()])?;
+    Ok(header.len())
+}
+
+/// Decompresses the data following a header of the given format, until `buf` is full.
+///
+/// # Errors
+///
+/// This function will return an error if reading fails, or the stream is corrupted.
+pub fn decompress_raw<R: Read>(format: Format, mut reader: R, buf: &mut [u8]) -> IoResult<()> {
+    if buf.is_empty() {
+        return Ok(());
+    }
+
+    let iov = ctru_sys::decompressIOVec {
+        data: buf.as_mut_ptr().cast(),
+        size: buf.len(),
+    };
+    let mut input = Input {
+        reader: &mut reader,
+        error: None,
+    };
+    let userdata = (&mut input as *mut Input).cast();
+
+    let success = unsafe {
+        match format {
+            Format::Lz10 => ctru_sys::decompressV_LZSS(&iov, 1, Some(read_callback), userdata, 0),
+            Format::Lz11 => ctru_sys::decompressV_LZ11(&iov, 1, Some(read_callback), userdata, 0),
+            Format::Huffman4 => {
+                ctru_sys::decompressV_Huff(4, &iov, 1, Some(read_callback), userdata, 0)
+            }
+            Format::Huffman8 => {
+                ctru_sys::decompressV_Huff(8, &iov, 1, Some(read_callback), userdata, 0)
+            }
+            Format::Rle => ctru_sys::decompressV_RLE(&iov, 1, Some(read_callback), userdata, 0),
+        }
+    };
+
+    match input.error {
+        Some(error) => Err(error),
+        None if !success => Err(IoError::new(
+            IoErrorKind::InvalidData,
+            "corrupted or truncated compressed data",
+        )),
+        None => Ok(()),
+    }
+}
+
+/// Source of the compressed data, passed to the decoders as user data.
+struct Input<'a> {
+    reader: &'a mut dyn Read,
+    /// First error returned by the reader, which aborts decompression.
+    error: Option<IoError>,
+}
+
+unsafe extern "C" fn read_callback(
+    userdata: *mut c_void,
+    buffer: *mut c_void,
+    size: usize,
+) -> isize {
+    let input = &mut *userdata.cast::<Input>();
+    let buffer = slice::from_raw_parts_mut(buffer.cast::<u8>(), size);
+
+    // Fill the buffer as much as possible, so that short reads aren't taken for the end of the data.
+    let mut filled = 0;
+    while filled < buffer.len() {
+        match input.reader.read(&mut buffer[filled..]) {
+            Ok(0) => break,
+            Ok(n) => filled += n,
+            Err(e) if e.kind() == IoErrorKind::Interrupted => {}
+            Err(e) => {
+                input.error = Some(e);
+                return -1;
+            }
+        }
+    }
+
+    filled as isize
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Data mixing runs, repetitions at various distances and noise.
+    fn sample() -> Vec<u8> {
+        let mut data = b"Lorem ipsum dolor sit amet, lorem ipsum dolor sit amet. ".repeat(40);
+        data.extend(std::iter::repeat(0xaa).take(300));
+        let mut state = 0x1234_5678u32;
+        data.extend((0..5000).map(|_| {
+            state ^= state << 13;
+            state ^= state >> 17;
+            state ^= state << 5;
+            state as u8
+        }));
+        data.extend((0..=255).cycle().take(2000));
+        data
+    }
+
+    const FORMATS: [Format; 5] = [
+        Format::Lz10,
+        Format::Lz11,
+        Format::Huffman4,
+        Format::Huffman8,
+        Format::Rle,
+    ];
+
+    #[test]
+    fn round_trip() {
+        let data = sample();
+
+        for format in FORMATS {
+            for input in [&data[..], b"", b"a", b"abcabc", &data[..1000]] {
+                let packed = compress(format, input);
+                let header = Header::read(&packed[..]).unwrap();
+                assert_eq!(header.format(), format);
+                assert_eq!(header.len(), input.len());
+
+                assert_eq!(decompress(&packed[..]).unwrap(), input, "{format:?}");
+            }
+        }
+
+        assert!(compress(Format::Lz10, &data).len() < data.len());
+        assert!(compress(Format::Lz11, &data).len() < compress(Format::Lz10, &data).len());
+        assert!(compress(Format::Rle, &[0; 4096]).len() < 100);
+    }
+
+    #[test]
+    fn streaming() {
+        let data = sample();
+        let packed = compress(Format::Lz11, &data);
+
+        // One byte at a time, through a reader which doesn't fill whole buffers.
+        let reader = std::io::BufReader::with_capacity(1, &packed[..]);
+        let mut buf = vec![0; data.len() + 10];
+        assert_eq!(decompress_into(reader, &mut buf).unwrap(), data.len());
+        assert_eq!(buf[..data.len()], data);
+
+        let error = decompress_into(&packed[..], &mut buf[..10]).unwrap_err();
+        assert_eq!(error.kind(), IoErrorKind::InvalidInput);
+
+        let error = decompress(&packed[..packed.len() / 2]).unwrap_err();
+        assert_eq!(error.kind(), IoErrorKind::InvalidData);
+
+        let error = decompress(&[0x42, 1, 0, 0, 0][..]).unwrap_err();
+        assert_eq!(error.kind(), IoErrorKind::InvalidData);
+    }
+}
@@ -1,9 +1,19 @@
+//! Text output on the screens
+//!
+//! [`Console`] redirects the standard output to one of the screens, using the console of `libctru`.
+//! For more control, [`window::WindowedConsole`] splits a screen into several windows with their own
+//! scrollback and colors, driven by the [`terminal::Terminal`] emulator.
+
+pub mod terminal;
+pub mod window;
+
 use std::cell::RefMut;
 use std::default::Default;
 
 use ctru_sys::{consoleClear, consoleInit, consoleSelect, consoleSetWindow, PrintConsole};
 
 use crate::gfx::Screen;
+use crate::Error;
 
 static mut EMPTY_CONSOLE: PrintConsole = unsafe { const_zero::const_zero!(PrintConsole) };
 
@@ -63,6 +73,37 @@ impl<'screen> Console<'screen> {
     pub unsafe fn set_window(&mut self, x: i32, y: i32, width: i32, height: i32) {
         consoleSetWindow(self.context.as_mut(), x, y, width, height);
     }
+
+    /// Resizes the console to fit in a smaller portion of the screen, checking that it does.
+    ///
+    /// The first two arguments are the coordinates of the top-left corner of the console,
+    /// and the second pair is its new width and height, all in characters.
+    ///
+    /// # Errors
+    ///
+    /// This function will return [`Error::InvalidWindow`] if the window is empty or doesn't fit on
+    /// the screen.
+    pub fn try_set_window(&mut self, x: u8, y: u8, width: u8, height: u8) -> crate::Result<()> {
+        let (screen_width, screen_height) = (self.context.consoleWidth, self.context.consoleHeight);
+
+        if width == 0
+            || height == 0
+            || x as i32 + width as i32 > screen_width
+            || y as i32 + height as i32 > screen_height
+        {
+            return Err(Error::InvalidWindow {
+                x: x.into(),
+                y: y.into(),
+                width: width.into(),
+                height: height.into(),
+            });
+        }
+
+        // Safety: the window was checked to fit on the screen.
+        unsafe { self.set_window(x.into(), y.into(), width.into(), height.into()) };
+
+        Ok(())
+    }
 }
 
 impl Drop for Console<'_> {
@@ -0,0 +1,717 @@
+//! Terminal emulation
+//!
+//! A [`Terminal`] is a grid of character [`Cell`]s fed with text through [`fmt::Write`]. It handles
+//! line wrapping, scrolling into a scrollback buffer, and the common ANSI escape sequences:
+//!
+//! * colors and attributes (`ESC[…m`), with the 16 standard colors, the 256-color palette
+//!   and 24-bit colors,
+//! * cursor movement (`ESC[A` to `ESC[H`), saving and restoring (`ESC[s`, `ESC[u`, `ESC 7`, `ESC 8`)
+//!   and visibility (`ESC[?25h`, `ESC[?25l`),
+//! * erasing (`ESC[J`, `ESC[K`) and scrolling (`ESC[S`, `ESC[T`).
+//!
+//! Unsupported sequences are ignored. The terminal is plain Rust: drawing it on a screen is the job of a
+//! [`WindowedConsole`](super::window::WindowedConsole), and its contents can be read back at any time.
+//!
+//! # Examples
+//!
+//! ```
+//! use std::fmt::Write;
+//! use ctru::console::terminal::Terminal;
+//!
+//! let mut terminal = Terminal::new(40, 4, 100);
+//! write!(terminal, "\x1b[31mError:\x1b[0m file not found").unwrap();
+//!
+//! assert_eq!(terminal.text(), "Error: file not found");
+//! ```
+
+use std::collections::VecDeque;
+use std::fmt;
+
+use bitflags::bitflags;
+
+use crate::gfx::canvas::Color;
+
+/// Distance between tab stops, in columns.
+const TAB_SIZE: u16 = 4;
+
+/// The 16 standard colors, in the order of their ANSI codes (black, red, green, yellow,
+/// blue, magenta, cyan, white, then their bright variants).
+const PALETTE: [Color; 16] = [
+    Color::rgb(0x00, 0x00, 0x00),
+    Color::rgb(0xaa, 0x00, 0x00),
+    Color::rgb(0x00, 0xaa, 0x00),
+    Color::rgb(0xaa, 0x55, 0x00),
+    Color::rgb(0x00, 0x00, 0xaa),
+    Color::rgb(0xaa, 0x00, 0xaa),
+    Color::rgb(0x00, 0xaa, 0xaa),
+    Color::rgb(0xaa, 0xaa, 0xaa),
+    Color::rgb(0x55, 0x55, 0x55),
+    Color::rgb(0xff, 0x55, 0x55),
+    Color::rgb(0x55, 0xff, 0x55),
+    Color::rgb(0xff, 0xff, 0x55),
+    Color::rgb(0x55, 0x55, 0xff),
+    Color::rgb(0xff, 0x55, 0xff),
+    Color::rgb(0x55, 0xff, 0xff),
+    Color::rgb(0xff, 0xff, 0xff),
+];
+
+bitflags! {
+    /// Text attributes set with the `ESC[…m` sequences.
+    #[derive(Default)]
+    pub struct Attributes: u8 {
+        const BOLD          = 1;
+        const FAINT         = 2;
+        const UNDERLINE     = 4;
+        const REVERSE       = 8;
+        const CONCEAL       = 16;
+        const STRIKETHROUGH = 32;
+    }
+}
+
+/// The colors and attributes of a cell.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Style {
+    pub foreground: Color,
+    pub background: Color,
+    pub attributes: Attributes,
+}
+
+/// A single character of a terminal, with its style.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Cell {
+    pub character: char,
+    pub style: Style,
+}
+
+/// A grid of characters emulating a text terminal, with a scrollback buffer.
+#[derive(Clone, Debug)]
+pub struct Terminal {
+    columns: u16,
+    rows: u16,
+    /// Rows currently on screen.
+    lines: Vec<Vec<Cell>>,
+    /// Rows that scrolled off the top of the screen, oldest first.
+    scrollback: VecDeque<Vec<Cell>>,
+    scrollback_limit: usize,
+    /// Amount of scrollback rows shown above the screen rows.
+    view_offset: usize,
+    cursor: (u16, u16),
+    saved_cursor: (u16, u16),
+    cursor_visible: bool,
+    /// Whether the cursor went past the last column, to wrap before the next character.
+    pending_wrap: bool,
+    style: Style,
+    parser: Parser,
+}
+
+/// State of the escape sequence parser.
+#[derive(Clone, Debug)]
+enum Parser {
+    Ground,
+    /// After an `ESC` character.
+    Escape,
+    /// Inside a control sequence (`ESC[`).
+    Csi {
+        params: Vec<u16>,
+        /// Whether the sequence starts with `?`.
+        private: bool,
+    },
+}
+
+impl Style {
+    /// Returns the colors the cell is displayed with, as `(foreground, background)`,
+    /// once the attributes are taken into account.
+    pub fn display_colors(&self) -> (Color, Color) {
+        let (mut foreground, mut background) = (self.foreground, self.background);
+
+        if self.attributes.contains(Attributes::FAINT) {
+            foreground = Color::rgb(foreground.r / 2, foreground.g / 2, foreground.b / 2);
+        }
+        if self.attributes.contains(Attributes::REVERSE) {
+            std::mem::swap(&mut foreground, &mut background);
+        }
+        if self.attributes.contains(Attributes::CONCEAL) {
+            foreground = background;
+        }
+
+        (foreground, background)
+    }
+}
+
+impl Default for Style {
+    /// Light gray on black, without any attribute.
+    fn default() -> Self {
+        Self {
+            foreground: PALETTE[7],
+            background: PALETTE[0],
+            attributes: Attributes::empty(),
+        }
+    }
+}
+
+impl Cell {
+    /// Returns an empty cell, keeping the colors of `style`.
+    fn blank(style: Style) -> Self {
+        Self {
+            character: ' ',
+            style: Style {
+                attributes: Attributes::empty(),
+                ..style
+            },
+        }
+    }
+}
+
+impl Default for Cell {
+    fn default() -> Self {
+        Self::blank(Style::default())
+    }
+}
+
+impl Terminal {
+    /// Creates an empty terminal of `columns` by `rows` characters, keeping up to
+    /// `scrollback` rows that scrolled off the top.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `columns` or `rows` is zero.
+    pub fn new(columns: u16, rows: u16, scrollback: usize) -> Self {
+        assert!(columns > 0 && rows > 0, "a terminal can't be empty");
+
+        Self {
+            columns,
+            rows,
+            lines: vec![vec![Cell::default(); columns as usize]; rows as usize],
+            scrollback: VecDeque::new(),
+            scrollback_limit: scrollback,
+            view_offset: 0,
+            cursor: (0, 0),
+            saved_cursor: (0, 0),
+            cursor_visible: true,
+            pending_wrap: false,
+            style: Style::default(),
+            parser: Parser::Ground,
+        }
+    }
+
+    /// Returns the width of the terminal in characters.
+    pub fn columns(&self) -> u16 {
+        self.columns
+    }
+
+    /// Returns the height of the terminal in characters.
+    pub fn rows(&self) -> u16 {
+        self.rows
+    }
+
+    /// Returns the position of the cursor, as `(column, row)`.
+    pub fn cursor(&self) -> (u16, u16) {
+        self.cursor
+    }
+
+    /// Moves the cursor, keeping it inside the terminal.
+    pub fn set_cursor(&mut self, column: u16, row: u16) {
+        self.cursor = (column.min(self.columns - 1), row.min(self.rows - 1));
+        self.pending_wrap = false;
+    }
+
+    /// Returns whether the cursor is displayed.
+    pub fn cursor_visible(&self) -> bool {
+        self.cursor_visible
+    }
+
+    /// Shows or hides the cursor.
+    pub fn set_cursor_visible(&mut self, visible: bool) {
+        self.cursor_visible = visible;
+    }
+
+    /// Returns the style applied to the next characters.
+    pub fn style(&self) -> Style {
+        self.style
+    }
+
+    /// Sets the style applied to the next characters.
+    pub fn set_style(&mut self, style: Style) {
+        self.style = style;
+    }
+
+    /// Clears the screen and moves the cursor to the top-left corner. The scrollback is kept.
+    pub fn clear(&mut self) {
+        let blank = Cell::blank(self.style);
+        self.lines.iter_mut().for_each(|line| line.fill(blank));
+        self.set_cursor(0, 0);
+    }
+
+    /// Clears the scrollback buffer.
+    pub fn clear_scrollback(&mut self) {
+        self.scrollback.clear();
+        self.view_offset = 0;
+    }
+
+    /// Returns the amount of rows in the scrollback buffer.
+    pub fn scrollback_len(&self) -> usize {
+        self.scrollback.len()
+    }
+
+    /// Returns how many rows the view is scrolled back, `0` showing the screen.
+    pub fn scroll_position(&self) -> usize {
+        self.view_offset
+    }
+
+    /// Scrolls the view back by `rows` rows, into the scrollback buffer.
+    ///
+    /// While scrolled back, the view stays on the same rows when new text is written.
+    pub fn scroll_up(&mut self, rows: usize) {
+        self.view_offset = (self.view_offset + rows).min(self.scrollback.len());
+    }
+
+    /// Scrolls the view forward by `rows` rows, towards the screen.
+    pub fn scroll_down(&mut self, rows: usize) {
+        self.view_offset = self.view_offset.saturating_sub(rows);
+    }
+
+    /// Scrolls the view back to the screen.
+    pub fn scroll_to_bottom(&mut self) {
+        self.view_offset = 0;
+    }
+
+    /// Returns a row of the view, taking the scroll position into account,
+    /// or `None` if `row` is outside of the terminal.
+    pub fn visible_line(&self, row: u16) -> Option<&[Cell]> {
+        if row >= self.rows {
+            return None;
+        }
+
+        let row = row as usize;
+        if row < self.view_offset {
+            Some(&self.scrollback[self.scrollback.len() - self.view_offset + row])
+        } else {
+            Some(&self.lines[row - self.view_offset])
+        }
+    }
+
+    /// Returns every row, from the oldest one in the scrollback to the last one on screen.
+    pub fn lines(&self) -> impl Iterator<Item = &[Cell]> {
+        self.scrollback
+            .iter()
+            .chain(self.lines.iter())
+            .map(Vec::as_slice)
+    }
+
+    /// Returns the text of every row, without styles or trailing blank space.
+    pub fn text(&self) -> String {
+        let mut text = String::new();
+
+        for line in self.lines() {
+            let content: String = line.iter().map(|cell| cell.character).collect();
+            text.push_str(content.trim_end());
+            text.push('\n');
+        }
+
+        text.trim_end_matches('\n').to_owned()
+    }
+
+    fn put_char(&mut self, c: char) {
+        if self.pending_wrap {
+            self.line_feed();
+            self.pending_wrap = false;
+        }
+
+        let (column, row) = self.cursor;
+        self.lines[row as usize][column as usize] = Cell {
+            character: c,
+            style: self.style,
+        };
+
+        if column + 1 < self.columns {
+            self.cursor.0 += 1;
+        } else {
+            self.pending_wrap = true;
+        }
+    }
+
+    /// Moves the cursor to the start of the next line, scrolling if needed.
+    fn line_feed(&mut self) {
+        self.cursor.0 = 0;
+        self.pending_wrap = false;
+
+        if self.cursor.1 + 1 < self.rows {
+            self.cursor.1 += 1;
+        } else {
+            self.scroll_lines_up(1);
+        }
+    }
+
+    /// Moves the screen rows up, pushing the top ones to the scrollback.
+    fn scroll_lines_up(&mut self, count: u16) {
+        for _ in 0..count.min(self.rows) {
+            let line = self.lines.remove(0);
+            self.lines
+                .push(vec![Cell::blank(self.style); self.columns as usize]);
+
+            if self.scrollback_limit == 0 {
+                continue;
+            }
+            if self.scrollback.len() == self.scrollback_limit {
+                self.scrollback.pop_front();
+            }
+            self.scrollback.push_back(line);
+
+            if self.view_offset > 0 {
+                // Keep showing the same rows.
+                self.view_offset = (self.view_offset + 1).min(self.scrollback.len());
+            }
+        }
+    }
+
+    /// Moves the screen rows down, inserting blank rows at the top.
+    fn scroll_lines_down(&mut self, count: u16) {
+        for _ in 0..count.min(self.rows) {
+            self.lines.pop();
+            self.lines
+                .insert(0, vec![Cell::blank(self.style); self.columns as usize]);
+        }
+    }
+
+    /// Blanks the cells of `row` in the range of columns.
+    fn erase(&mut self, row: u16, columns: std::ops::Range<u16>) {
+        let blank = Cell::blank(self.style);
+        let end = columns.end.min(self.columns) as usize;
+        self.lines[row as usize][columns.start as usize..end].fill(blank);
+    }
+
+    fn control(&mut self, c: char) {
+        match c {
+            '\n' => self.line_feed(),
+            '\r' => self.set_cursor(0, self.cursor.1),
+            '\t' => {
+                let next = (self.cursor.0 / TAB_SIZE + 1) * TAB_SIZE;
+                self.set_cursor(next, self.cursor.1);
+            }
+            '\x08' => self.set_cursor(self.cursor.0.saturating_sub(1), self.cursor.1),
+            '\x1b' => self.parser = Parser::Escape,
+            _ => {}
+        }
+    }
+
+    fn escape(&mut self, c: char) {
+        self.parser = Parser::Ground;
+
+        match c {
+            '[' => {
+                self.parser = Parser::Csi {
+                    params: Vec::new(),
+                    private: false,
+                }
+            }
+            '7' => self.saved_cursor = self.cursor,
+            '8' => self.set_cursor(self.saved_cursor.0, self.saved_cursor.1),
+            'c' => {
+                self.style = Style::default();
+                self.cursor_visible = true;
+                self.clear();
+                self.clear_scrollback();
+            }
+            _ => {}
+        }
+    }
+
+    fn csi(&mut self, final_byte: char, params: &[u16], private: bool) {
+        // Missing or zero counts default to one.
+        let count = params.first().copied().unwrap_or(0).max(1);
+        let (column, row) = self.cursor;
+
+        if private {
+            if params == [25] {
+                match final_byte {
+                    'h' => self.cursor_visible = true,
+                    'l' => self.cursor_visible = false,
+                    _ => {}
+                }
+            }
+            return;
+        }
+
+        match final_byte {
+            'A' => self.set_cursor(column, row.saturating_sub(count)),
+            'B' => self.set_cursor(column, row.saturating_add(count)),
+            'C' => self.set_cursor(column.saturating_add(count), row),
+            'D' => self.set_cursor(column.saturating_sub(count), row),
+            'E' => self.set_cursor(0, row.saturating_add(count)),
+            'F' => self.set_cursor(0, row.saturating_sub(count)),
+            'G' => self.set_cursor(count - 1, row),
+            'H' | 'f' => {
+                let column = params.get(1).copied().unwrap_or(0).max(1);
+                self.set_cursor(column - 1, count - 1);
+            }
+            'J' => match params.first().copied().unwrap_or(0) {
+                0 => {
+                    self.erase(row, column..self.columns);
+                    (row + 1..self.rows).for_each(|row| self.erase(row, 0..self.columns));
+                }
+                1 => {
+                    (0..row).for_each(|row| self.erase(row, 0..self.columns));
+                    self.erase(row, 0..column + 1);
+                }
+                2 => (0..self.rows).for_each(|row| self.erase(row, 0..self.columns)),
+                3 => {
+                    (0..self.rows).for_each(|row| self.erase(row, 0..self.columns));
+                    self.clear_scrollback();
+                }
+                _ => {}
+            },
+            'K' => match params.first().copied().unwrap_or(0) {
+                0 => self.erase(row, column..self.columns),
+                1 => self.erase(row, 0..column + 1),
+                2 => self.erase(row, 0..self.columns),
+                _ => {}
+            },
+            'S' => self.scroll_lines_up(count),
+            'T' => self.scroll_lines_down(count),
+            's' => self.saved_cursor = self.cursor,
+            'u' => self.set_cursor(self.saved_cursor.0, self.saved_cursor.1),
+            'm' => self.select_graphic_rendition(params),
+            _ => {}
+        }
+    }
+
+    fn select_graphic_rendition(&mut self, params: &[u16]) {
+        if params.is_empty() {
+            self.style = Style::default();
+            return;
+        }
+
+        let mut params = params.iter().copied();
+        while let Some(param) = params.next() {
+            let style = &mut self.style;
+
+            match param {
+                0 => *style = Style::default(),
+                1 => style.attributes.insert(Attributes::BOLD),
+                2 => style.attributes.insert(Attributes::FAINT),
+                4 => style.attributes.insert(Attributes::UNDERLINE),
+                7 => style.attributes.insert(Attributes::REVERSE),
+                8 => style.attributes.insert(Attributes::CONCEAL),
+                9 => style.attributes.insert(Attributes::STRIKETHROUGH),
+                22 => style
+                    .attributes
+                    .remove(Attributes::BOLD | Attributes::FAINT),
+                24 => style.attributes.remove(Attributes::UNDERLINE),
+                27 => style.attributes.remove(Attributes::REVERSE),
+                28 => style.attributes.remove(Attributes::CONCEAL),
+                29 => style.attributes.remove(Attributes::STRIKETHROUGH),
+                30..=37 => style.foreground = PALETTE[param as usize - 30],
+                39 => style.foreground = Style::default().foreground,
+                40..=47 => style.background = PALETTE[param as usize - 40],
+                49 => style.background = Style::default().background,
+                90..=97 => style.foreground = PALETTE[param as usize - 90 + 8],
+                100..=107 => style.background = PALETTE[param as usize - 100 + 8],
+                38 | 48 => {
+                    let color = match params.next() {
+                        Some(5) => params.next().map(palette_color),
+                        Some(2) => match (params.next(), params.next(), params.next()) {
+                            (Some(r), Some(g), Some(b)) => {
+                                Some(Color::rgb(r as u8, g as u8, b as u8))
+                            }
+                            _ => None,
+                        },
+                        _ => None,
+                    };
+
+                    match (param, color) {
+                        (38, Some(color)) => style.foreground = color,
+                        (48, Some(color)) => style.background = color,
+                        _ => {}
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+}
+
+/// Returns a color of the 256-color palette.
+fn palette_color(index: u16) -> Color {
+    match index {
+        0..=15 => PALETTE[index as usize],
+        // 6x6x6 color cube.
+        16..=231 => {
+            let level = |value: u16| if value == 0 { 0 } else { value as u8 * 40 + 55 };
+            let index = index - 16;
+            Color::rgb(level(index / 36), level(index / 6 % 6), level(index % 6))
+        }
+        // Grayscale ramp.
+        _ => {
+            let value = (index.min(255) - 232) as u8 * 10 + 8;
+            Color::rgb(value, value, value)
+        }
+    }
+}
+
+impl fmt::Write for Terminal {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        for c in s.chars() {
+            match std::mem::replace(&mut self.parser, Parser::Ground) {
+                Parser::Ground if c.is_control() => self.control(c),
+                Parser::Ground => self.put_char(c),
+                Parser::Escape => self.escape(c),
+                Parser::Csi {
+                    mut params,
+                    mut private,
+                } => match c {
+                    '0'..='9' => {
+                        let digit = c as u16 - '0' as u16;
+                        match params.last_mut() {
+                            Some(param) => *param = param.saturating_mul(10).saturating_add(digit),
+                            None => params.push(digit),
+                        }
+                        self.parser = Parser::Csi { params, private };
+                    }
+                    ';' => {
+                        if params.is_empty() {
+                            params.push(0);
+                        }
+                        params.push(0);
+                        self.parser = Parser::Csi { params, private };
+                    }
+                    '?' if params.is_empty() => {
+                        private = true;
+                        self.parser = Parser::Csi { params, private };
+                    }
+                    '\x40'..='\x7e' => self.csi(c, &params, private),
+                    // Malformed sequence, drop it.
+                    _ => {}
+                },
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fmt::Write;
+
+    use super::*;
+
+    fn feed(columns: u16, rows: u16, scrollback: usize, text: &str) -> Terminal {
+        let mut terminal = Terminal::new(columns, rows, scrollback);
+        terminal.write_str(text).unwrap();
+        terminal
+    }
+
+    fn screen(terminal: &Terminal) -> Vec<String> {
+        (0..terminal.rows())
+            .map(|row| {
+                terminal
+                    .visible_line(row)
+                    .unwrap()
+                    .iter()
+                    .map(|cell| cell.character)
+                    .collect()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn wrapping() {
+        let terminal = feed(5, 3, 0, "hello world\nab\tc");
+
+        assert_eq!(screen(&terminal), [" worl", "d    ", "ab  c"]);
+        assert_eq!(terminal.text(), " worl\nd\nab  c");
+
+        // Writing the last column doesn't wrap until another character comes.
+        let mut terminal = feed(5, 3, 0, "abcde");
+        assert_eq!(terminal.cursor(), (4, 0));
+        terminal.write_str("\n").unwrap();
+        assert_eq!(terminal.cursor(), (0, 1));
+
+        let terminal = feed(8, 1, 0, "ab\tc\rX");
+        assert_eq!(screen(&terminal), ["Xb  c   "]);
+    }
+
+    #[test]
+    fn scrollback() {
+        let mut terminal = feed(4, 2, 3, "1\n2\n3\n4\n5\n6");
+
+        assert_eq!(screen(&terminal), ["5   ", "6   "]);
+        assert_eq!(terminal.scrollback_len(), 3);
+        assert_eq!(terminal.text(), "2\n3\n4\n5\n6");
+
+        terminal.scroll_up(2);
+        assert_eq!(screen(&terminal), ["3   ", "4   "]);
+        terminal.scroll_up(10);
+        assert_eq!(terminal.scroll_position(), 3);
+        assert_eq!(screen(&terminal), ["2   ", "3   "]);
+
+        // The view stays on the same rows while new ones come in.
+        terminal.scroll_down(1);
+        terminal.write_str("\n7").unwrap();
+        assert_eq!(screen(&terminal), ["3   ", "4   "]);
+
+        terminal.scroll_to_bottom();
+        assert_eq!(screen(&terminal), ["6   ", "7   "]);
+    }
+
+    #[test]
+    fn colors() {
+        let terminal = feed(
+            8,
+            1,
+            0,
+            "a\x1b[1;31mb\x1b[44;22mc\x1b[0md\x1b[38;5;196;48;2;1;2;3me\x1b[7mf\x1b[mg",
+        );
+        let line = terminal.visible_line(0).unwrap();
+
+        assert_eq!(line[0].style, Style::default());
+        assert_eq!(line[1].style.foreground, PALETTE[1]);
+        assert_eq!(line[1].style.attributes, Attributes::BOLD);
+        assert_eq!(line[2].style.background, PALETTE[4]);
+        assert_eq!(line[2].style.attributes, Attributes::empty());
+        assert_eq!(line[3].style, Style::default());
+        assert_eq!(line[4].style.foreground, Color::rgb(255, 0, 0));
+        assert_eq!(line[4].style.background, Color::rgb(1, 2, 3));
+        assert_eq!(
+            line[5].style.display_colors(),
+            (Color::rgb(1, 2, 3), Color::rgb(255, 0, 0))
+        );
+        assert_eq!(line[6].style, Style::default());
+        assert_eq!(terminal.text(), "abcdefg");
+    }
+
+    #[test]
+    fn cursor_movement() {
+        let mut terminal = feed(6, 3, 0, "\x1b[2;3Hx\x1b[Ay\x1b[2Cz\x1b[10B!");
+        assert_eq!(screen(&terminal), ["   y z", "  x   ", "     !"]);
+
+        terminal
+            .write_str("\x1b[s\x1b[1;1H\x1b[K\x1b[u?\x1b[?25l")
+            .unwrap();
+        assert_eq!(screen(&terminal), ["      ", "  x   ", "     ?"]);
+        assert!(!terminal.cursor_visible());
+
+        terminal.write_str("\x1b[2;4H\x1b[1J").unwrap();
+        assert_eq!(screen(&terminal), ["      ", "      ", "     ?"]);
+
+        terminal.set_cursor(100, 100);
+        assert_eq!(terminal.cursor(), (5, 2));
+        terminal.write_str("\x1b[2J\x1b[5Gab").unwrap();
+        assert_eq!(screen(&terminal)[2], "    ab");
+    }
+
+    #[test]
+    fn partial_sequences() {
+        let mut terminal = Terminal::new(10, 1, 0);
+
+        // Sequences can be split across writes, and unknown ones are dropped.
+        terminal.write_str("a\x1b[3").unwrap();
+        terminal.write_str("2mb\x1b]c\x1b[99;99zd").unwrap();
+
+        assert_eq!(terminal.text(), "abcd");
+        assert_eq!(
+            terminal.visible_line(0).unwrap()[1].style.foreground,
+            PALETTE[2]
+        );
+    }
+}
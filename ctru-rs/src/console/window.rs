@@ -0,0 +1,323 @@
+//! Console windows drawn in software
+//!
+//! A [`WindowedConsole`] splits a screen into several non-overlapping windows, each backed by its own
+//! [`Terminal`] with a scrollback buffer. Text is written to the windows through [`fmt::Write`](std::fmt::Write),
+//! independently of the standard output, and the windows are drawn with a monospace [`PsfFont`]
+//! every time [`WindowedConsole::render`] is called.
+//!
+//! # Examples
+//!
+//! ```no_run
+//! use std::fmt::Write;
+//!
+//! use ctru::console::window::WindowedConsole;
+//! use ctru::gfx::text::PsfFont;
+//! use ctru::prelude::*;
+//!
+//! let gfx = Gfx::init().unwrap();
+//! let mut console = WindowedConsole::new(gfx.top_screen.borrow_mut(), PsfFont::system());
+//!
+//! // A status bar on the first row, and a log below it.
+//! let status = console.add_window(0, 0, console.columns(), 1, 0).unwrap();
+//! let log = console.add_window(0, 1, console.columns(), console.rows() - 1, 500).unwrap();
+//!
+//! write!(console.window(status), "\x1b[30;47m Ready ").unwrap();
+//! writeln!(console.window(log), "\x1b[32mConnected\x1b[0m to the server").unwrap();
+//!
+//! console.render();
+//! gfx.flush_buffers();
+//! gfx.swap_buffers();
+//! ```
+
+use std::cell::RefMut;
+
+use super::terminal::{Attributes, Terminal};
+use crate::gfx::canvas::Canvas;
+use crate::gfx::text::{Font, PsfFont};
+use crate::gfx::Screen;
+use crate::Error;
+
+/// Several console windows sharing a screen.
+pub struct WindowedConsole<'screen> {
+    screen: RefMut<'screen, dyn Screen>,
+    font: PsfFont,
+    columns: u16,
+    rows: u16,
+    windows: Vec<Window>,
+}
+
+/// Identifies a window of a [`WindowedConsole`].
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct WindowId(usize);
+
+#[derive(Debug)]
+struct Window {
+    column: u16,
+    row: u16,
+    terminal: Terminal,
+}
+
+impl<'screen> WindowedConsole<'screen> {
+    /// Creates a console without any window on the chosen screen, drawing text with `font`.
+    pub fn new(mut screen: RefMut<'screen, dyn Screen>, font: PsfFont) -> Self {
+        // The framebuffer reports its dimensions before rotation.
+        let framebuffer = screen.get_raw_framebuffer();
+        let (width, height) = (framebuffer.height, framebuffer.width);
+
+        Self {
+            columns: width / font.width(),
+            rows: height / font.height(),
+            screen,
+            font,
+            windows: Vec::new(),
+        }
+    }
+
+    /// Returns the width of the screen in characters.
+    pub fn columns(&self) -> u16 {
+        self.columns
+    }
+
+    /// Returns the height of the screen in characters.
+    pub fn rows(&self) -> u16 {
+        self.rows
+    }
+
+    /// Adds a window of `columns` by `rows` characters, with its top-left corner at `(column, row)`,
+    /// keeping up to `scrollback` rows of history.
+    ///
+    /// # Errors
+    ///
+    /// This function will return [`Error::InvalidWindow`] if the window is empty or doesn't fit on the
+    /// screen, and [`Error::OverlappingWindow`] if it overlaps another window.
+    pub fn add_window(
+        &mut self,
+        column: u16,
+        row: u16,
+        columns: u16,
+        rows: u16,
+        scrollback: usize,
+    ) -> crate::Result<WindowId> {
+        let window = Window {
+            column,
+            row,
+            terminal: Terminal::new(columns.max(1), rows.max(1), scrollback),
+        };
+
+        if columns == 0 || rows == 0 || !window.fits(self.columns, self.rows) {
+            return Err(Error::InvalidWindow {
+                x: column,
+                y: row,
+                width: columns,
+                height: rows,
+            });
+        }
+        if self.windows.iter().any(|other| other.overlaps(&window)) {
+            return Err(Error::OverlappingWindow);
+        }
+
+        self.windows.push(window);
+        Ok(WindowId(self.windows.len() - 1))
+    }
+
+    /// Returns the terminal of a window, to write to it or read it back.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `id` was returned by another console.
+    pub fn window(&mut self, id: WindowId) -> &mut Terminal {
+        &mut self.windows[id.0].terminal
+    }
+
+    /// Draws every window onto the current framebuffer of the screen.
+    ///
+    /// With double buffering enabled, this must be called on each frame.
+    pub fn render(&mut self) {
+        let mut canvas = self.screen.get_canvas();
+        let (width, height) = (self.font.width() as i32, self.font.height() as i32);
+
+        for window in &self.windows {
+            window.terminal.draw(
+                &mut canvas,
+                &self.font,
+                window.column as i32 * width,
+                window.row as i32 * height,
+            );
+        }
+    }
+}
+
+impl Window {
+    fn fits(&self, columns: u16, rows: u16) -> bool {
+        self.column as u32 + self.terminal.columns() as u32 <= columns as u32
+            && self.row as u32 + self.terminal.rows() as u32 <= rows as u32
+    }
+
+    fn overlaps(&self, other: &Window) -> bool {
+        let horizontal = self.column < other.column + other.terminal.columns()
+            && other.column < self.column + self.terminal.columns();
+        let vertical = self.row < other.row + other.terminal.rows()
+            && other.row < self.row + self.terminal.rows();
+
+        horizontal && vertical
+    }
+}
+
+impl Terminal {
+    /// Draws the visible rows of the terminal onto a canvas, with its top-left corner at `(x, y)`.
+    ///
+    /// Every cell takes the size of a glyph of `font`. Characters missing from the font are drawn as `?`.
+    pub fn draw(&self, canvas: &mut Canvas, font: &PsfFont, x: i32, y: i32) {
+        let (width, height) = (font.width() as i32, font.height() as i32);
+        let show_cursor = self.cursor_visible() && self.scroll_position() == 0;
+
+        for row in 0..self.rows() {
+            let line = self.visible_line(row).unwrap();
+
+            for (column, cell) in line.iter().enumerate() {
+                let (cell_x, cell_y) = (x + column as i32 * width, y + row as i32 * height);
+                let (mut foreground, mut background) = cell.style.display_colors();
+                if show_cursor && self.cursor() == (column as u16, row) {
+                    std::mem::swap(&mut foreground, &mut background);
+                }
+
+                canvas.fill_rect(cell_x, cell_y, width as u32, height as u32, background);
+
+                let attributes = cell.style.attributes;
+                if let Some(glyph) = font.glyph(cell.character).or_else(|| font.glyph('?')) {
+                    // Bold text is drawn a second time, one pixel to the right.
+                    let thickness = if attributes.contains(Attributes::BOLD) {
+                        2
+                    } else {
+                        1
+                    };
+
+                    for glyph_y in 0..glyph.height {
+                        for glyph_x in 0..glyph.width {
+                            if glyph.coverage(glyph_x, glyph_y) < 128 {
+                                continue;
+                            }
+
+                            for offset in 0..thickness.min(width - glyph_x as i32) {
+                                canvas.set_pixel(
+                                    cell_x + glyph_x as i32 + offset,
+                                    cell_y + glyph_y as i32,
+                                    foreground,
+                                );
+                            }
+                        }
+                    }
+                }
+
+                if attributes.contains(Attributes::UNDERLINE) {
+                    canvas.fill_rect(cell_x, cell_y + height - 1, width as u32, 1, foreground);
+                }
+                if attributes.contains(Attributes::STRIKETHROUGH) {
+                    canvas.fill_rect(cell_x, cell_y + height / 2, width as u32, 1, foreground);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fmt::Write;
+
+    use super::*;
+    use crate::gfx::canvas::Color;
+    use crate::services::gspgpu::FramebufferFormat;
+
+    /// A PSF2 font of 4x4 glyphs where `#` is a hollow square and every other glyph is empty.
+    fn font() -> PsfFont {
+        let mut data = vec![0x72, 0xb5, 0x4a, 0x86];
+        for field in [0u32, 32, 0, 256, 4, 4, 4] {
+            data.extend_from_slice(&field.to_le_bytes());
+        }
+        data.resize(32 + 256 * 4, 0);
+        data[32 + b'#' as usize * 4..][..4].copy_from_slice(&[0xf0, 0x90, 0x90, 0xf0]);
+        PsfFont::from_bytes(&data).unwrap()
+    }
+
+    fn window(column: u16, row: u16, columns: u16, rows: u16) -> Window {
+        Window {
+            column,
+            row,
+            terminal: Terminal::new(columns, rows, 0),
+        }
+    }
+
+    #[test]
+    fn placement() {
+        let status = window(0, 0, 40, 1);
+
+        assert!(status.fits(40, 30));
+        assert!(!window(30, 0, 11, 1).fits(40, 30));
+        assert!(!window(0, 25, 40, 6).fits(40, 30));
+
+        assert!(status.overlaps(&window(39, 0, 1, 5)));
+        assert!(!status.overlaps(&window(0, 1, 40, 29)));
+        assert!(window(5, 5, 10, 10).overlaps(&window(0, 0, 6, 6)));
+        assert!(!window(5, 5, 10, 10).overlaps(&window(0, 0, 5, 30)));
+    }
+
+    #[test]
+    fn rendering() {
+        let font = font();
+        let mut data = vec![0; 16 * 8 * 3];
+        let mut canvas = Canvas::from_buffer(&mut data, 16, 8, FramebufferFormat::Bgr8).unwrap();
+        canvas.clear(Color::BLUE);
+
+        let mut terminal = Terminal::new(3, 1, 0);
+        terminal
+            .write_str("#\x1b[7m \x1b[0;4;31m#\x1b[?25l")
+            .unwrap();
+        terminal.draw(&mut canvas, &font, 2, 2);
+
+        let image = crate::gfx::image::Image::from_canvas(&canvas);
+        let rows: Vec<String> = (0..8)
+            .map(|y| {
+                (0..16)
+                    .map(|x| match image.get_pixel(x, y).unwrap() {
+                        Color::BLUE => '~',
+                        Color::BLACK => '.',
+                        color if color.r == 0xaa && color.g == 0xaa => 'w',
+                        color if color.r == 0xaa => 'r',
+                        _ => '?',
+                    })
+                    .collect()
+            })
+            .collect();
+
+        assert_eq!(
+            rows,
+            [
+                "~~~~~~~~~~~~~~~~",
+                "~~~~~~~~~~~~~~~~",
+                "~~wwwwwwwwrrrr~~",
+                "~~w..wwwwwr..r~~",
+                "~~w..wwwwwr..r~~",
+                "~~wwwwwwwwrrrr~~",
+                "~~~~~~~~~~~~~~~~",
+                "~~~~~~~~~~~~~~~~",
+            ]
+        );
+    }
+
+    #[test]
+    fn cursor() {
+        let font = font();
+        let mut data = vec![0; 8 * 4 * 3];
+        let mut canvas = Canvas::from_buffer(&mut data, 8, 4, FramebufferFormat::Bgr8).unwrap();
+
+        // The cursor is drawn in reverse video, but not while scrolled back.
+        let mut terminal = Terminal::new(2, 1, 10);
+        terminal.write_str("\n#").unwrap();
+        terminal.draw(&mut canvas, &font, 0, 0);
+        assert_eq!(canvas.get_pixel(5, 0), Some(Color::rgb(0xaa, 0xaa, 0xaa)));
+
+        terminal.scroll_up(1);
+        terminal.draw(&mut canvas, &font, 0, 0);
+        assert_eq!(canvas.get_pixel(5, 0), Some(Color::BLACK));
+    }
+}
@@ -61,6 +61,29 @@ pub enum Error {
         /// Size of the requested data (in bytes).
         wanted: usize,
     },
+    /// A console window is empty or doesn't fit on the screen.
+    InvalidWindow {
+        /// Column of the top-left corner of the window.
+        x: u16,
+        /// Row of the top-left corner of the window.
+        y: u16,
+        /// Width of the window, in characters.
+        width: u16,
+        /// Height of the window, in characters.
+        height: u16,
+    },
+    /// A console window overlaps another window of the same console.
+    OverlappingWindow,
+    /// A sample rate isn't a positive number of Hz.
+    InvalidSampleRate(f32),
+    /// Another RomFS image is already mounted under this device name.
+    MountNameInUse(String),
+    /// A path or name passed to the system contains a null byte.
+    InteriorNul,
+    /// An I/O operation of the standard library failed, such as spawning a thread.
+    Io(std::io::Error),
+    /// A buffer of this size can't be shared with a service.
+    BufferTooLarge(usize),
 }
 
 impl Error {
@@ -112,6 +135,24 @@ impl fmt::Debug for Error {
                 .field("provided", provided)
                 .field("wanted", wanted)
                 .finish(),
+            Self::InvalidWindow {
+                x,
+                y,
+                width,
+                height,
+            } => f
+                .debug_struct("InvalidWindow")
+                .field("x", x)
+                .field("y", y)
+                .field("width", width)
+                .field("height", height)
+                .finish(),
+            Self::OverlappingWindow => f.debug_tuple("OverlappingWindow").finish(),
+            Self::InvalidSampleRate(rate) => f.debug_tuple("InvalidSampleRate").field(rate).finish(),
+            Self::MountNameInUse(name) => f.debug_tuple("MountNameInUse").field(name).finish(),
+            Self::InteriorNul => f.debug_tuple("InteriorNul").finish(),
+            Self::Io(err) => f.debug_tuple("Io").field(err).finish(),
+            Self::BufferTooLarge(size) => f.debug_tuple("BufferTooLarge").field(size).finish(),
         }
     }
 }
@@ -132,7 +173,16 @@ impl fmt::Display for Error {
             Self::OutputAlreadyRedirected => {
                 write!(f, "output streams are already redirected to 3dslink")
             }
-            Self::BufferTooShort{provided, wanted} => write!(f, "the provided buffer's length is too short (length = {provided}) to hold the wanted data (size = {wanted})")
+            Self::BufferTooShort{provided, wanted} => write!(f, "the provided buffer's length is too short (length = {provided}) to hold the wanted data (size = {wanted})"),
+            Self::InvalidWindow { x, y, width, height } => write!(f, "a console window of {width}x{height} at ({x}, {y}) is empty or doesn't fit on the screen"),
+            Self::OverlappingWindow => write!(f, "console window overlaps another window"),
+            Self::InvalidSampleRate(rate) => write!(f, "invalid sample rate of {rate} Hz"),
+            Self::MountNameInUse(name) => {
+                write!(f, "another RomFS image is already mounted at {name}:/")
+            }
+            Self::InteriorNul => write!(f, "path or name contains a null byte"),
+            Self::Io(err) => write!(f, "{err}"),
+            Self::BufferTooLarge(size) => write!(f, "a buffer of {size} bytes is too large"),
         }
     }
 }
@@ -0,0 +1,612 @@
+//! Software 2D drawing
+//!
+//! The framebuffers of the 3DS are rotated by 90°: they are stored column by column, each column
+//! going from the bottom of the screen to its top. A [`Canvas`] hides this layout (and the pixel
+//! format of the framebuffer) behind plain screen coordinates, with `(0, 0)` at the top-left corner
+//! of the screen, `x` growing to the right and `y` growing downwards.
+//!
+//! A canvas can draw to a screen (see [`Screen::get_canvas`](super::Screen::get_canvas)) as well as to
+//! an ordinary buffer in memory laid out the same way, which can be compared against reference images
+//! in tests running on any machine.
+//!
+//! # Examples
+//!
+//! ```no_run
+//! use ctru::gfx::canvas::Color;
+//! use ctru::gfx::Screen as _;
+//! use ctru::prelude::*;
+//!
+//! let gfx = Gfx::init().unwrap();
+//! let mut bottom_screen = gfx.bottom_screen.borrow_mut();
+//! let mut canvas = bottom_screen.get_canvas();
+//!
+//! canvas.clear(Color::BLACK);
+//! canvas.fill_rect(10, 10, 100, 50, Color::rgb(0xce, 0x42, 0x2b));
+//! canvas.draw_line(0, 0, 319, 239, Color::WHITE);
+//! // Half transparent, blended with what was drawn before.
+//! canvas.fill_circle(160, 120, 40, Color::rgba(0, 0, 255, 128));
+//!
+//! gfx.flush_buffers();
+//! gfx.swap_buffers();
+//! ```
+
+use super::RawFrameBuffer;
+use crate::services::gspgpu::FramebufferFormat;
+use crate::Error;
+
+/// A color with 8 bits per channel, including alpha.
+///
+/// Colors are converted to the pixel format of the canvas when drawn,
+/// dropping the precision (and alpha channel) the format can't store.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub struct Color {
+    pub r: u8,
+    pub g: u8,
+    pub b: u8,
+    /// Opacity, from `0` (fully transparent) to `255` (opaque).
+    pub a: u8,
+}
+
+/// A drawing surface using the rotated layout of the 3DS framebuffers.
+pub struct Canvas<'a> {
+    data: &'a mut [u8],
+    width: u16,
+    height: u16,
+    format: FramebufferFormat,
+}
+
+impl Color {
+    pub const BLACK: Color = Color::rgb(0, 0, 0);
+    pub const WHITE: Color = Color::rgb(255, 255, 255);
+    pub const RED: Color = Color::rgb(255, 0, 0);
+    pub const GREEN: Color = Color::rgb(0, 255, 0);
+    pub const BLUE: Color = Color::rgb(0, 0, 255);
+    pub const TRANSPARENT: Color = Color::rgba(0, 0, 0, 0);
+
+    /// Returns an opaque color.
+    pub const fn rgb(r: u8, g: u8, b: u8) -> Self {
+        Self { r, g, b, a: 255 }
+    }
+
+    /// Returns a color with the specified opacity.
+    pub const fn rgba(r: u8, g: u8, b: u8, a: u8) -> Self {
+        Self { r, g, b, a }
+    }
+
+    /// Returns the result of drawing this color over `dst`, using its alpha channel as opacity.
+    pub fn blend_over(self, dst: Color) -> Color {
+        match self.a {
+            255 => return self,
+            0 => return dst,
+            _ => {}
+        }
+
+        let src_a = self.a as u32;
+        let dst_a = dst.a as u32 * (255 - src_a) / 255;
+        let out_a = src_a + dst_a;
+
+        let channel = |src: u8, dst: u8| ((src as u32 * src_a + dst as u32 * dst_a) / out_a) as u8;
+
+        Color {
+            r: channel(self.r, dst.r),
+            g: channel(self.g, dst.g),
+            b: channel(self.b, dst.b),
+            a: out_a as u8,
+        }
+    }
+
+    /// Writes the color to `pixel`, which must be as long as the pixel depth of `format`.
+    pub(crate) fn encode(self, format: FramebufferFormat, pixel: &mut [u8]) {
+        let Color { r, g, b, a } = self;
+        let (r, g, b, a) = (r as u16, g as u16, b as u16, a as u16);
+
+        let packed = match format {
+            FramebufferFormat::Rgba8 => {
+                pixel.copy_from_slice(&[self.a, self.b, self.g, self.r]);
+                return;
+            }
+            FramebufferFormat::Bgr8 => {
+                pixel.copy_from_slice(&[self.b, self.g, self.r]);
+                return;
+            }
+            FramebufferFormat::Rgb565 => (r >> 3) << 11 | (g >> 2) << 5 | b >> 3,
+            FramebufferFormat::Rgb5A1 => (r >> 3) << 11 | (g >> 3) << 6 | (b >> 3) << 1 | a >> 7,
+            FramebufferFormat::Rgba4 => (r >> 4) << 12 | (g >> 4) << 8 | (b >> 4) << 4 | a >> 4,
+        };
+
+        pixel.copy_from_slice(&packed.to_le_bytes());
+    }
+
+    /// Reads a color written in `format`. Formats without an alpha channel read as opaque.
+    pub(crate) fn decode(format: FramebufferFormat, pixel: &[u8]) -> Self {
+        match format {
+            FramebufferFormat::Rgba8 => Color::rgba(pixel[3], pixel[2], pixel[1], pixel[0]),
+            FramebufferFormat::Bgr8 => Color::rgb(pixel[2], pixel[1], pixel[0]),
+            _ => {
+                let packed = u16::from_le_bytes([pixel[0], pixel[1]]);
+
+                match format {
+                    FramebufferFormat::Rgb565 => {
+                        Color::rgb(expand5(packed >> 11), expand6(packed >> 5), expand5(packed))
+                    }
+                    FramebufferFormat::Rgb5A1 => Color::rgba(
+                        expand5(packed >> 11),
+                        expand5(packed >> 6),
+                        expand5(packed >> 1),
+                        if packed & 1 != 0 { 255 } else { 0 },
+                    ),
+                    _ => Color::rgba(
+                        expand4(packed >> 12),
+                        expand4(packed >> 8),
+                        expand4(packed >> 4),
+                        expand4(packed),
+                    ),
+                }
+            }
+        }
+    }
+}
+
+fn expand4(value: u16) -> u8 {
+    let value = (value & 0xf) as u8;
+    value << 4 | value
+}
+
+fn expand5(value: u16) -> u8 {
+    let value = (value & 0x1f) as u8;
+    value << 3 | value >> 2
+}
+
+fn expand6(value: u16) -> u8 {
+    let value = (value & 0x3f) as u8;
+    value << 2 | value >> 4
+}
+
+impl<'a> Canvas<'a> {
+    /// Creates a canvas drawing to a buffer in memory.
+    ///
+    /// `width` and `height` are the dimensions of the canvas in screen coordinates
+    /// (e.g. 400 by 240 for the top screen), and `data` must hold at least
+    /// `width * height` pixels in the specified format.
+    ///
+    /// # Errors
+    ///
+    /// This function will return [`Error::BufferTooShort`] if `data` can't hold all the pixels.
+    pub fn from_buffer(
+        data: &'a mut [u8],
+        width: u16,
+        height: u16,
+        format: FramebufferFormat,
+    ) -> crate::Result<Self> {
+        let wanted = width as usize * height as usize * format.pixel_depth_bytes();
+
+        if data.len() < wanted {
+            return Err(Error::BufferTooShort {
+                provided: data.len(),
+                wanted,
+            });
+        }
+
+        Ok(Self {
+            data: &mut data[..wanted],
+            width,
+            height,
+            format,
+        })
+    }
+
+    /// Creates a canvas drawing to a framebuffer.
+    ///
+    /// # Safety
+    ///
+    /// The framebuffer must be valid for the whole lifetime of the canvas,
+    /// and `format` must be the format it was allocated with.
+    /// [`Screen::get_canvas`](super::Screen::get_canvas) is a safe alternative.
+    pub unsafe fn from_raw_framebuffer(
+        framebuffer: RawFrameBuffer<'a>,
+        format: FramebufferFormat,
+    ) -> Self {
+        // The framebuffer reports its dimensions before rotation.
+        let width = framebuffer.height;
+        let height = framebuffer.width;
+        let len = width as usize * height as usize * format.pixel_depth_bytes();
+
+        Self {
+            data: std::slice::from_raw_parts_mut(framebuffer.ptr, len),
+            width,
+            height,
+            format,
+        }
+    }
+
+    /// Returns the width of the canvas in pixels.
+    pub fn width(&self) -> u16 {
+        self.width
+    }
+
+    /// Returns the height of the canvas in pixels.
+    pub fn height(&self) -> u16 {
+        self.height
+    }
+
+    /// Returns the pixel format of the canvas.
+    pub fn format(&self) -> FramebufferFormat {
+        self.format
+    }
+
+    /// Returns the raw contents of the canvas, in the rotated layout.
+    pub fn as_bytes(&self) -> &[u8] {
+        self.data
+    }
+
+    /// Returns the offset in bytes of a pixel, or `None` if it lies outside of the canvas.
+    fn offset(&self, x: i32, y: i32) -> Option<usize> {
+        if x < 0 || y < 0 || x >= self.width as i32 || y >= self.height as i32 {
+            return None;
+        }
+
+        let (x, y) = (x as usize, y as usize);
+        let height = self.height as usize;
+
+        Some((x * height + (height - 1 - y)) * self.format.pixel_depth_bytes())
+    }
+
+    /// Returns the color of a pixel, or `None` if it lies outside of the canvas.
+    pub fn get_pixel(&self, x: i32, y: i32) -> Option<Color> {
+        let offset = self.offset(x, y)?;
+        let depth = self.format.pixel_depth_bytes();
+
+        Some(Color::decode(
+            self.format,
+            &self.data[offset..offset + depth],
+        ))
+    }
+
+    /// Sets the color of a pixel, without blending. Pixels outside of the canvas are ignored.
+    pub fn set_pixel(&mut self, x: i32, y: i32, color: Color) {
+        if let Some(offset) = self.offset(x, y) {
+            let depth = self.format.pixel_depth_bytes();
+            color.encode(self.format, &mut self.data[offset..offset + depth]);
+        }
+    }
+
+    /// Draws a pixel, blending it with the current color according to its alpha channel.
+    /// Pixels outside of the canvas are ignored.
+    pub fn blend_pixel(&mut self, x: i32, y: i32, color: Color) {
+        match color.a {
+            0 => {}
+            255 => self.set_pixel(x, y, color),
+            _ => {
+                if let Some(dst) = self.get_pixel(x, y) {
+                    self.set_pixel(x, y, color.blend_over(dst));
+                }
+            }
+        }
+    }
+
+    /// Sets every pixel of the canvas to the same color, without blending.
+    pub fn clear(&mut self, color: Color) {
+        let depth = self.format.pixel_depth_bytes();
+        let mut pixel = [0; 4];
+        color.encode(self.format, &mut pixel[..depth]);
+
+        for chunk in self.data.chunks_exact_mut(depth) {
+            chunk.copy_from_slice(&pixel[..depth]);
+        }
+    }
+
+    /// Fills a rectangle with its top-left corner at `(x, y)`.
+    pub fn fill_rect(&mut self, x: i32, y: i32, width: u32, height: u32, color: Color) {
+        let x_end = x
+            .saturating_add(width.min(i32::MAX as u32) as i32)
+            .min(self.width as i32);
+        let y_end = y
+            .saturating_add(height.min(i32::MAX as u32) as i32)
+            .min(self.height as i32);
+
+        for px in x.max(0)..x_end {
+            for py in y.max(0)..y_end {
+                self.blend_pixel(px, py, color);
+            }
+        }
+    }
+
+    /// Draws the 1 pixel wide outline of a rectangle with its top-left corner at `(x, y)`.
+    pub fn draw_rect(&mut self, x: i32, y: i32, width: u32, height: u32, color: Color) {
+        if width == 0 || height == 0 {
+            return;
+        }
+
+        self.fill_rect(x, y, width, 1, color);
+        if height > 1 {
+            self.fill_rect(x, y + height as i32 - 1, width, 1, color);
+        }
+        if height > 2 {
+            self.fill_rect(x, y + 1, 1, height - 2, color);
+            if width > 1 {
+                self.fill_rect(x + width as i32 - 1, y + 1, 1, height - 2, color);
+            }
+        }
+    }
+
+    /// Draws a 1 pixel wide line between two points, both included.
+    pub fn draw_line(&mut self, x0: i32, y0: i32, x1: i32, y1: i32, color: Color) {
+        let dx = (x1 - x0).abs();
+        let dy = -(y1 - y0).abs();
+        let step_x = if x0 < x1 { 1 } else { -1 };
+        let step_y = if y0 < y1 { 1 } else { -1 };
+
+        let (mut x, mut y) = (x0, y0);
+        let mut err = dx + dy;
+
+        loop {
+            self.blend_pixel(x, y, color);
+
+            if x == x1 && y == y1 {
+                break;
+            }
+
+            let e2 = 2 * err;
+            if e2 >= dy {
+                err += dy;
+                x += step_x;
+            }
+            if e2 <= dx {
+                err += dx;
+                y += step_y;
+            }
+        }
+    }
+
+    /// Draws the 1 pixel wide outline of a circle.
+    pub fn draw_circle(&mut self, cx: i32, cy: i32, radius: u32, color: Color) {
+        let mut x = radius as i32;
+        let mut y = 0;
+        let mut err = 1 - x;
+
+        while x >= y {
+            // Skip the duplicate points on the axes and diagonals, so that they are only blended once.
+            let points: &[(i32, i32)] = if x == 0 {
+                &[(0, 0)]
+            } else if y == 0 {
+                &[(x, 0), (-x, 0), (0, x), (0, -x)]
+            } else if x == y {
+                &[(x, x), (-x, x), (x, -x), (-x, -x)]
+            } else {
+                &[
+                    (x, y),
+                    (-x, y),
+                    (x, -y),
+                    (-x, -y),
+                    (y, x),
+                    (-y, x),
+                    (y, -x),
+                    (-y, -x),
+                ]
+            };
+
+            for (px, py) in points {
+                self.blend_pixel(cx + px, cy + py, color);
+            }
+
+            y += 1;
+            if err < 0 {
+                err += 2 * y + 1;
+            } else {
+                x -= 1;
+                err += 2 * (y - x) + 1;
+            }
+        }
+    }
+
+    /// Fills a circle.
+    pub fn fill_circle(&mut self, cx: i32, cy: i32, radius: u32, color: Color) {
+        let (cx, cy, radius) = (cx as i64, cy as i64, radius as i64);
+        let y_end = (cy + radius).min(self.height as i64 - 1);
+
+        for y in (cy - radius).max(0)..=y_end {
+            let dy = (y - cy) as i128;
+            let span = ((radius as i128 * radius as i128 - dy * dy) as f64).sqrt() as i64;
+            let x_end = (cx + span).min(self.width as i64 - 1);
+
+            for x in (cx - span).max(0)..=x_end {
+                self.blend_pixel(x as i32, y as i32, color);
+            }
+        }
+    }
+
+    /// Draws an image with its top-left corner at `(x, y)`, blending it according to its alpha channel.
+    ///
+    /// `pixels` holds the image row by row, each row being `width` pixels long.
+    pub fn blit(&mut self, x: i32, y: i32, width: u32, pixels: &[Color]) {
+        if width == 0 {
+            return;
+        }
+
+        for (row, line) in pixels.chunks(width as usize).enumerate() {
+            for (column, color) in line.iter().enumerate() {
+                self.blend_pixel(x + column as i32, y + row as i32, *color);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FORMATS: [FramebufferFormat; 5] = [
+        FramebufferFormat::Rgba8,
+        FramebufferFormat::Bgr8,
+        FramebufferFormat::Rgb565,
+        FramebufferFormat::Rgb5A1,
+        FramebufferFormat::Rgba4,
+    ];
+
+    /// Renders the canvas as one line of text per row, with `#` for white pixels and `.` for black ones.
+    fn render(canvas: &Canvas) -> String {
+        let mut text = String::new();
+
+        for y in 0..canvas.height() as i32 {
+            for x in 0..canvas.width() as i32 {
+                text.push(match canvas.get_pixel(x, y).unwrap() {
+                    Color::WHITE => '#',
+                    Color::BLACK => '.',
+                    _ => '?',
+                });
+            }
+            text.push('\n');
+        }
+
+        text
+    }
+
+    #[test]
+    fn rotated_layout() {
+        let mut data = vec![0; 4 * 3 * 3];
+        let mut canvas = Canvas::from_buffer(&mut data, 4, 3, FramebufferFormat::Bgr8).unwrap();
+
+        canvas.set_pixel(0, 0, Color::rgb(1, 2, 3));
+        canvas.set_pixel(3, 2, Color::rgb(4, 5, 6));
+
+        // The top-left pixel ends the first column, the bottom-right one starts the last column.
+        assert_eq!(&data[6..9], &[3, 2, 1]);
+        assert_eq!(&data[27..30], &[6, 5, 4]);
+    }
+
+    #[test]
+    fn pixel_formats() {
+        let color = Color::rgba(0xff, 0x80, 0x10, 0xff);
+
+        for format in FORMATS {
+            let mut data = vec![0; 2 * 2 * format.pixel_depth_bytes()];
+            let mut canvas = Canvas::from_buffer(&mut data, 2, 2, format).unwrap();
+
+            canvas.set_pixel(1, 0, color);
+            let read = canvas.get_pixel(1, 0).unwrap();
+
+            let tolerance = if let FramebufferFormat::Rgba4 = format {
+                17
+            } else {
+                8
+            };
+            for (a, b) in [
+                (read.r, color.r),
+                (read.g, color.g),
+                (read.b, color.b),
+                (read.a, color.a),
+            ] {
+                assert!(a.abs_diff(b) <= tolerance, "{format:?}: {read:?}");
+            }
+        }
+
+        let mut pixel = [0; 2];
+        Color::rgb(0xff, 0, 0).encode(FramebufferFormat::Rgb565, &mut pixel);
+        assert_eq!(u16::from_le_bytes(pixel), 0xf800);
+        Color::rgba(0, 0, 0xff, 0xff).encode(FramebufferFormat::Rgb5A1, &mut pixel);
+        assert_eq!(u16::from_le_bytes(pixel), 0x003f);
+
+        let mut pixel = [0; 4];
+        Color::rgba(1, 2, 3, 4).encode(FramebufferFormat::Rgba8, &mut pixel);
+        assert_eq!(pixel, [4, 3, 2, 1]);
+    }
+
+    #[test]
+    fn buffer_too_short() {
+        let mut data = vec![0; 10];
+
+        assert!(matches!(
+            Canvas::from_buffer(&mut data, 4, 4, FramebufferFormat::Rgb565),
+            Err(Error::BufferTooShort {
+                provided: 10,
+                wanted: 32
+            })
+        ));
+    }
+
+    #[test]
+    fn shapes() {
+        let mut data = vec![0; 9 * 7 * 3];
+        let mut canvas = Canvas::from_buffer(&mut data, 9, 7, FramebufferFormat::Bgr8).unwrap();
+
+        canvas.draw_rect(0, 0, 9, 7, Color::WHITE);
+        canvas.draw_line(1, 1, 7, 5, Color::WHITE);
+        canvas.fill_rect(6, 1, 2, 2, Color::WHITE);
+
+        assert_eq!(
+            render(&canvas),
+            "\
+#########
+##....###
+#.##..###
+#...#...#
+#....##.#
+#......##
+#########
+"
+        );
+
+        canvas.clear(Color::BLACK);
+        canvas.draw_circle(4, 3, 3, Color::WHITE);
+        canvas.fill_circle(4, 3, 1, Color::WHITE);
+
+        assert_eq!(
+            render(&canvas),
+            "\
+...###...
+..#...#..
+.#..#..#.
+.#.###.#.
+.#..#..#.
+..#...#..
+...###...
+"
+        );
+    }
+
+    #[test]
+    fn clipping() {
+        let mut data = vec![0; 4 * 4 * 2];
+        let mut canvas = Canvas::from_buffer(&mut data, 4, 4, FramebufferFormat::Rgb565).unwrap();
+
+        canvas.fill_rect(-10, 2, 100, 100, Color::WHITE);
+        canvas.draw_line(-5, -5, 20, 20, Color::WHITE);
+        canvas.draw_circle(0, 0, 50, Color::WHITE);
+
+        assert_eq!(render(&canvas), "#...\n.#..\n####\n####\n");
+        assert_eq!(canvas.get_pixel(4, 0), None);
+
+        canvas.clear(Color::BLACK);
+        canvas.fill_circle(i32::MIN, i32::MAX, u32::MAX, Color::WHITE);
+        canvas.fill_circle(2, 2, u32::MAX, Color::WHITE);
+
+        assert_eq!(render(&canvas), "####\n####\n####\n####\n");
+    }
+
+    #[test]
+    fn blending() {
+        let mut data = vec![0; 3 * 4];
+        let mut canvas = Canvas::from_buffer(&mut data, 3, 1, FramebufferFormat::Rgba8).unwrap();
+
+        canvas.clear(Color::rgb(0, 0, 200));
+        canvas.blit(
+            0,
+            0,
+            3,
+            &[
+                Color::rgba(200, 0, 0, 255),
+                Color::rgba(200, 0, 0, 128),
+                Color::TRANSPARENT,
+            ],
+        );
+
+        assert_eq!(canvas.get_pixel(0, 0), Some(Color::rgb(200, 0, 0)));
+        assert_eq!(canvas.get_pixel(1, 0), Some(Color::rgb(100, 0, 99)));
+        assert_eq!(canvas.get_pixel(2, 0), Some(Color::rgb(0, 0, 200)));
+
+        // Drawing over a transparent background keeps the alpha of the drawn color.
+        canvas.clear(Color::TRANSPARENT);
+        canvas.blend_pixel(0, 0, Color::rgba(10, 20, 30, 100));
+        assert_eq!(canvas.get_pixel(0, 0), Some(Color::rgba(10, 20, 30, 100)));
+    }
+}
@@ -0,0 +1,1087 @@
+//! Image decoding, encoding and conversion to the framebuffer layout
+//!
+//! An [`Image`] holds plain, unrotated RGBA pixels. It can be drawn onto a [`Canvas`], or converted
+//! all at once into the rotated layout and pixel format of a framebuffer with [`Image::to_framebuffer`],
+//! optionally dithering it for the 16 bits per pixel formats. [`Image::from_framebuffer`] goes the
+//! other way, which is how [`Gfx::screenshot`](super::Gfx::screenshot) captures the screens.
+//!
+//! Images can always be saved as BMP or PNG files with [`Image::write_bmp`] and [`Image::write_png`].
+//! With the `image-decoding` feature enabled, they can also be decoded from PNG, BMP and TGA files.
+//!
+//! # Examples
+//!
+//! ```no_run
+//! # #[cfg(feature = "image-decoding")]
+//! # {
+//! use ctru::gfx::image::{Dither, Image};
+//! use ctru::gfx::Screen as _;
+//! use ctru::prelude::*;
+//!
+//! let gfx = Gfx::init().unwrap();
+//! let image = Image::open("romfs:/ferris.png").unwrap();
+//!
+//! let mut bottom_screen = gfx.bottom_screen.borrow_mut();
+//! let format = bottom_screen.get_framebuffer_format();
+//! let data = image.to_framebuffer(format, Dither::Ordered);
+//!
+//! let frame_buffer = bottom_screen.get_raw_framebuffer();
+//! unsafe {
+//!     frame_buffer.ptr.copy_from(data.as_ptr(), data.len());
+//! }
+//! # }
+//! ```
+
+use std::io::{self, Write};
+#[cfg(feature = "image-decoding")]
+use std::path::Path;
+
+use super::canvas::{Canvas, Color};
+use crate::services::gspgpu::FramebufferFormat;
+
+/// An image made of RGBA pixels, stored row by row.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Image {
+    width: u16,
+    height: u16,
+    pixels: Vec<Color>,
+}
+
+/// Dithering applied when converting an image to a format with less than 8 bits per channel.
+///
+/// Dithering only affects the color channels of [`FramebufferFormat::Rgb565`],
+/// [`FramebufferFormat::Rgb5A1`] and [`FramebufferFormat::Rgba4`]. It hides the banding
+/// of smooth gradients, at the cost of some noise.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum Dither {
+    /// Round every pixel down to the nearest color of the format.
+    #[default]
+    None,
+    /// Apply a 4x4 Bayer matrix. Fast, and stable between frames of an animation.
+    Ordered,
+    /// Diffuse the rounding error of each pixel to its neighbours. Better looking on still images.
+    FloydSteinberg,
+}
+
+/// 4x4 Bayer matrix, with thresholds from 0 to 15.
+const BAYER: [[u8; 4]; 4] = [[0, 8, 2, 10], [12, 4, 14, 6], [3, 11, 1, 9], [15, 7, 13, 5]];
+
+impl Image {
+    /// Creates an image from its pixels, stored row by row.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `pixels` doesn't hold exactly `width * height` pixels.
+    pub fn new(width: u16, height: u16, pixels: Vec<Color>) -> Self {
+        assert_eq!(pixels.len(), width as usize * height as usize);
+
+        Self {
+            width,
+            height,
+            pixels,
+        }
+    }
+
+    /// Creates an image from the contents of a [`Canvas`].
+    pub fn from_canvas(canvas: &Canvas) -> Self {
+        let (width, height) = (canvas.width(), canvas.height());
+        let pixels = (0..height as i32)
+            .flat_map(|y| (0..width as i32).map(move |x| (x, y)))
+            .map(|(x, y)| canvas.get_pixel(x, y).unwrap())
+            .collect();
+
+        Self::new(width, height, pixels)
+    }
+
+    /// Creates an image from pixels stored in the rotated layout used by the framebuffers.
+    ///
+    /// `width` and `height` are the dimensions of the image in screen coordinates
+    /// (e.g. 400 by 240 for the top screen), and `data` must hold at least
+    /// `width * height` pixels in the specified format. This is the reverse of [`Image::to_framebuffer`].
+    ///
+    /// # Errors
+    ///
+    /// This function will return [`Error::BufferTooShort`](crate::Error::BufferTooShort)
+    /// if `data` doesn't hold all the pixels.
+    pub fn from_framebuffer(
+        data: &[u8],
+        width: u16,
+        height: u16,
+        format: FramebufferFormat,
+    ) -> crate::Result<Self> {
+        let depth = format.pixel_depth_bytes();
+        let wanted = width as usize * height as usize * depth;
+
+        if data.len() < wanted {
+            return Err(crate::Error::BufferTooShort {
+                provided: data.len(),
+                wanted,
+            });
+        }
+
+        let height = height as usize;
+        let pixels = (0..height)
+            .flat_map(|y| (0..width as usize).map(move |x| (x, y)))
+            .map(|(x, y)| {
+                let offset = (x * height + (height - 1 - y)) * depth;
+                Color::decode(format, &data[offset..offset + depth])
+            })
+            .collect();
+
+        Ok(Self::new(width, height as u16, pixels))
+    }
+
+    /// Returns the width of the image in pixels.
+    pub fn width(&self) -> u16 {
+        self.width
+    }
+
+    /// Returns the height of the image in pixels.
+    pub fn height(&self) -> u16 {
+        self.height
+    }
+
+    /// Returns the pixels of the image, row by row.
+    pub fn pixels(&self) -> &[Color] {
+        &self.pixels
+    }
+
+    /// Returns the color of a pixel, or `None` if it lies outside of the image.
+    pub fn get_pixel(&self, x: u16, y: u16) -> Option<Color> {
+        if x >= self.width || y >= self.height {
+            return None;
+        }
+
+        Some(self.pixels[y as usize * self.width as usize + x as usize])
+    }
+
+    /// Draws the image onto a canvas with its top-left corner at `(x, y)`,
+    /// blending it according to its alpha channel.
+    pub fn draw(&self, canvas: &mut Canvas, x: i32, y: i32) {
+        canvas.blit(x, y, self.width as u32, &self.pixels);
+    }
+
+    /// Converts the image to the rotated layout used by the framebuffers, in the specified format.
+    ///
+    /// The result can be copied as is into a framebuffer of the same size as the image
+    /// (see [`Screen::get_raw_framebuffer`](super::Screen::get_raw_framebuffer)).
+    pub fn to_framebuffer(&self, format: FramebufferFormat, dither: Dither) -> Vec<u8> {
+        let mut data = vec![0; self.pixels.len() * format.pixel_depth_bytes()];
+        let mut canvas = Canvas::from_buffer(&mut data, self.width, self.height, format)
+            .expect("buffer should be large enough for the image");
+
+        let bits = color_bits(format);
+        let pixels = match (dither, bits) {
+            (_, None) | (Dither::None, _) => self.pixels.clone(),
+            (Dither::Ordered, Some(bits)) => self.ordered_dither(bits),
+            (Dither::FloydSteinberg, Some(bits)) => self.floyd_steinberg(bits),
+        };
+
+        for (i, color) in pixels.into_iter().enumerate() {
+            let x = i % self.width as usize;
+            let y = i / self.width as usize;
+            canvas.set_pixel(x as i32, y as i32, color);
+        }
+
+        data
+    }
+
+    /// Encodes the image as an uncompressed 24 bits per pixel BMP file. The alpha channel is dropped.
+    pub fn write_bmp<W: Write>(&self, mut writer: W) -> io::Result<()> {
+        const HEADERS_SIZE: u32 = 14 + 40;
+
+        let stride = (self.width as u32 * 3 + 3) & !3;
+        let image_size = stride * self.height as u32;
+
+        let mut headers = Vec::with_capacity(HEADERS_SIZE as usize);
+        // File header
+        headers.extend_from_slice(b"BM");
+        headers.extend_from_slice(&(HEADERS_SIZE + image_size).to_le_bytes());
+        headers.extend_from_slice(&[0; 4]);
+        headers.extend_from_slice(&HEADERS_SIZE.to_le_bytes());
+        // BITMAPINFOHEADER
+        headers.extend_from_slice(&40u32.to_le_bytes());
+        headers.extend_from_slice(&(self.width as i32).to_le_bytes());
+        headers.extend_from_slice(&(self.height as i32).to_le_bytes());
+        headers.extend_from_slice(&1u16.to_le_bytes());
+        headers.extend_from_slice(&24u16.to_le_bytes());
+        headers.extend_from_slice(&0u32.to_le_bytes());
+        headers.extend_from_slice(&image_size.to_le_bytes());
+        // 72 DPI, no palette.
+        headers.extend_from_slice(&2835u32.to_le_bytes());
+        headers.extend_from_slice(&2835u32.to_le_bytes());
+        headers.extend_from_slice(&[0; 8]);
+        writer.write_all(&headers)?;
+
+        // Rows are stored bottom-up, as BGR, padded to a multiple of 4 bytes.
+        let mut row = Vec::with_capacity(stride as usize);
+        for y in (0..self.height as usize).rev() {
+            row.clear();
+            for color in self.row(y) {
+                row.extend_from_slice(&[color.b, color.g, color.r]);
+            }
+            row.resize(stride as usize, 0);
+            writer.write_all(&row)?;
+        }
+
+        Ok(())
+    }
+
+    /// Encodes the image as a 24 bits per pixel PNG file. The alpha channel is dropped.
+    ///
+    /// The image data is stored without compression, which keeps encoding cheap enough
+    /// to run on the console, but makes the files about as large as BMP files.
+    pub fn write_png<W: Write>(&self, mut writer: W) -> io::Result<()> {
+        const MAX_BLOCK_SIZE: usize = u16::MAX as usize;
+
+        writer.write_all(b"\x89PNG\r\n\x1a\n")?;
+
+        let mut header = Vec::with_capacity(13);
+        header.extend_from_slice(&(self.width as u32).to_be_bytes());
+        header.extend_from_slice(&(self.height as u32).to_be_bytes());
+        // 8 bits per channel, RGB, default compression, filtering and no interlacing.
+        header.extend_from_slice(&[8, 2, 0, 0, 0]);
+        write_png_chunk(&mut writer, b"IHDR", &header)?;
+
+        // Every row starts with its filter type, which is always "none".
+        let mut scanlines =
+            Vec::with_capacity(self.height as usize * (self.width as usize * 3 + 1));
+        for y in 0..self.height as usize {
+            scanlines.push(0);
+            for color in self.row(y) {
+                scanlines.extend_from_slice(&[color.r, color.g, color.b]);
+            }
+        }
+
+        // A zlib stream made of stored deflate blocks.
+        let blocks = scanlines.len() / MAX_BLOCK_SIZE + 1;
+        let mut data = Vec::with_capacity(scanlines.len() + blocks * 5 + 6);
+        data.extend_from_slice(&[0x78, 0x01]);
+        for i in 0..blocks {
+            let block =
+                &scanlines[i * MAX_BLOCK_SIZE..((i + 1) * MAX_BLOCK_SIZE).min(scanlines.len())];
+            let len = block.len() as u16;
+
+            data.push((i == blocks - 1) as u8);
+            data.extend_from_slice(&len.to_le_bytes());
+            data.extend_from_slice(&(!len).to_le_bytes());
+            data.extend_from_slice(block);
+        }
+        data.extend_from_slice(&adler32(&scanlines).to_be_bytes());
+        write_png_chunk(&mut writer, b"IDAT", &data)?;
+
+        write_png_chunk(&mut writer, b"IEND", &[])
+    }
+
+    /// Sets the alpha channel of every pixel to fully opaque.
+    pub(crate) fn make_opaque(&mut self) {
+        self.pixels.iter_mut().for_each(|color| color.a = 255);
+    }
+
+    /// Returns the pixels of the row `y`.
+    fn row(&self, y: usize) -> &[Color] {
+        let width = self.width as usize;
+        &self.pixels[y * width..(y + 1) * width]
+    }
+
+    fn ordered_dither(&self, bits: [u8; 3]) -> Vec<Color> {
+        self.pixels
+            .iter()
+            .enumerate()
+            .map(|(i, color)| {
+                let x = i % self.width as usize;
+                let y = i / self.width as usize;
+                let threshold = BAYER[y % 4][x % 4] as u16;
+
+                // Round up to the next level for a share of the thresholds proportional to the distance to it.
+                let channel = |value: u8, bits: u8| {
+                    let (lower, upper) = surrounding_levels(value, bits);
+                    if threshold * ((upper - lower) as u16) < (value - lower) as u16 * 16 {
+                        upper
+                    } else {
+                        lower
+                    }
+                };
+
+                Color {
+                    r: channel(color.r, bits[0]),
+                    g: channel(color.g, bits[1]),
+                    b: channel(color.b, bits[2]),
+                    a: color.a,
+                }
+            })
+            .collect()
+    }
+
+    fn floyd_steinberg(&self, bits: [u8; 3]) -> Vec<Color> {
+        let width = self.width as usize;
+        let mut output = Vec::with_capacity(self.pixels.len());
+        // Errors accumulated for the current and the next row, with a pixel of padding on each side.
+        let mut current = vec![[0i32; 3]; width + 2];
+        let mut next = vec![[0i32; 3]; width + 2];
+
+        for row in self.pixels.chunks(width.max(1)) {
+            for (x, color) in row.iter().enumerate() {
+                let mut result = *color;
+
+                for (channel, value) in [&mut result.r, &mut result.g, &mut result.b]
+                    .into_iter()
+                    .enumerate()
+                {
+                    let wanted = (*value as i32 + current[x + 1][channel] / 16).clamp(0, 255);
+                    let (lower, upper) = surrounding_levels(wanted as u8, bits[channel]);
+                    let quantized = if wanted - lower as i32 > upper as i32 - wanted {
+                        upper
+                    } else {
+                        lower
+                    };
+                    let error = wanted - quantized as i32;
+
+                    current[x + 2][channel] += error * 7;
+                    next[x][channel] += error * 3;
+                    next[x + 1][channel] += error * 5;
+                    next[x + 2][channel] += error;
+
+                    *value = quantized;
+                }
+
+                output.push(result);
+            }
+
+            std::mem::swap(&mut current, &mut next);
+            next.iter_mut().for_each(|errors| *errors = [0; 3]);
+        }
+
+        output
+    }
+}
+
+/// Returns the amount of bits of the red, green and blue channels of a format,
+/// or `None` if they already have 8 bits.
+fn color_bits(format: FramebufferFormat) -> Option<[u8; 3]> {
+    match format {
+        FramebufferFormat::Rgba8 | FramebufferFormat::Bgr8 => None,
+        FramebufferFormat::Rgb565 => Some([5, 6, 5]),
+        FramebufferFormat::Rgb5A1 => Some([5, 5, 5]),
+        FramebufferFormat::Rgba4 => Some([4, 4, 4]),
+    }
+}
+
+/// Expands a channel of `bits` bits to 8 bits, the way the hardware does.
+fn expand(level: u8, bits: u8) -> u8 {
+    let value = level << (8 - bits);
+    value | value >> bits
+}
+
+/// Returns the two levels of a channel of `bits` bits surrounding an 8-bit value, expanded to 8 bits.
+fn surrounding_levels(value: u8, bits: u8) -> (u8, u8) {
+    let mut level = value >> (8 - bits);
+    if expand(level, bits) > value {
+        level -= 1;
+    }
+
+    let max = (1u8 << bits) - 1;
+    (
+        expand(level, bits),
+        expand(level.saturating_add(1).min(max), bits),
+    )
+}
+
+/// Writes a PNG chunk, along with its length and checksum.
+fn write_png_chunk<W: Write>(writer: &mut W, kind: &[u8; 4], data: &[u8]) -> io::Result<()> {
+    writer.write_all(&(data.len() as u32).to_be_bytes())?;
+    writer.write_all(kind)?;
+    writer.write_all(data)?;
+    writer.write_all(&crc32(&[kind, data]).to_be_bytes())
+}
+
+/// CRC-32 lookup table, for the polynomial used by PNG.
+const CRC32_TABLE: [u32; 256] = {
+    let mut table = [0; 256];
+    let mut i = 0;
+
+    while i < 256 {
+        let mut crc = i as u32;
+        let mut bit = 0;
+        while bit < 8 {
+            crc = if crc & 1 != 0 {
+                0xedb8_8320 ^ (crc >> 1)
+            } else {
+                crc >> 1
+            };
+            bit += 1;
+        }
+        table[i] = crc;
+        i += 1;
+    }
+
+    table
+};
+
+/// Computes the CRC-32 of the concatenation of `parts`.
+fn crc32(parts: &[&[u8]]) -> u32 {
+    !parts
+        .iter()
+        .flat_map(|part| part.iter())
+        .fold(!0, |crc, &byte| {
+            CRC32_TABLE[((crc ^ byte as u32) & 0xff) as usize] ^ (crc >> 8)
+        })
+}
+
+/// Computes the Adler-32 checksum used by zlib streams.
+fn adler32(data: &[u8]) -> u32 {
+    const MODULO: u32 = 65521;
+    // Largest amount of bytes that can be summed before `b` could overflow.
+    const CHUNK_SIZE: usize = 5552;
+
+    let (mut a, mut b) = (1, 0);
+    for chunk in data.chunks(CHUNK_SIZE) {
+        for &byte in chunk {
+            a += byte as u32;
+            b += a;
+        }
+        a %= MODULO;
+        b %= MODULO;
+    }
+
+    b << 16 | a
+}
+
+#[cfg(feature = "image-decoding")]
+fn invalid_data(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+#[cfg(feature = "image-decoding")]
+/// Reads a little-endian integer of up to 4 bytes.
+fn le_value(bytes: &[u8]) -> u32 {
+    bytes
+        .iter()
+        .rev()
+        .fold(0, |value, &byte| value << 8 | byte as u32)
+}
+
+#[cfg(feature = "image-decoding")]
+/// Reads a little-endian integer of `N` bytes at `offset`.
+fn read_le<const N: usize>(data: &[u8], offset: usize) -> io::Result<u32> {
+    data.get(offset..offset + N)
+        .map(le_value)
+        .ok_or_else(|| invalid_data("truncated image"))
+}
+
+#[cfg(feature = "image-decoding")]
+fn dimension(value: i64) -> io::Result<u16> {
+    u16::try_from(value).map_err(|_| invalid_data("unsupported image dimensions"))
+}
+
+#[cfg(feature = "image-decoding")]
+/// Scales a channel extracted with a bit mask to 8 bits.
+fn scale_channel(value: u32, mask: u32) -> u8 {
+    if mask == 0 {
+        return 0;
+    }
+
+    let max = mask >> mask.trailing_zeros();
+    let value = (value & mask) >> mask.trailing_zeros();
+
+    ((value * 255 + max / 2) / max) as u8
+}
+
+#[cfg(feature = "image-decoding")]
+impl Image {
+    /// Reads and decodes an image file, e.g. from the RomFS. See [`Image::decode`].
+    pub fn open<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        Self::decode(&std::fs::read(path)?)
+    }
+
+    /// Decodes a PNG, BMP or TGA image, guessing the format from its contents.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error of kind [`InvalidData`](io::ErrorKind::InvalidData)
+    /// if the image is invalid or uses an unsupported variant of its format.
+    pub fn decode(data: &[u8]) -> io::Result<Self> {
+        if data.starts_with(b"\x89PNG") {
+            Self::decode_png(data)
+        } else if data.starts_with(b"BM") {
+            Self::decode_bmp(data)
+        } else {
+            // TGA files don't have a magic number.
+            Self::decode_tga(data)
+        }
+    }
+
+    /// Decodes a PNG image of any color type and bit depth.
+    pub fn decode_png(data: &[u8]) -> io::Result<Self> {
+        let to_io = |e: png::DecodingError| io::Error::new(io::ErrorKind::InvalidData, e);
+
+        let mut decoder = png::Decoder::new(data);
+        decoder.set_transformations(png::Transformations::EXPAND | png::Transformations::STRIP_16);
+
+        let mut reader = decoder.read_info().map_err(to_io)?;
+        let mut buffer = vec![0; reader.output_buffer_size()];
+        let info = reader.next_frame(&mut buffer).map_err(to_io)?;
+
+        let width = dimension(info.width as i64)?;
+        let height = dimension(info.height as i64)?;
+        let buffer = &buffer[..info.buffer_size()];
+
+        let pixels = match info.color_type {
+            png::ColorType::Grayscale => buffer.iter().map(|&l| Color::rgb(l, l, l)).collect(),
+            png::ColorType::GrayscaleAlpha => buffer
+                .chunks_exact(2)
+                .map(|p| Color::rgba(p[0], p[0], p[0], p[1]))
+                .collect(),
+            png::ColorType::Rgb => buffer
+                .chunks_exact(3)
+                .map(|p| Color::rgb(p[0], p[1], p[2]))
+                .collect(),
+            png::ColorType::Rgba => buffer
+                .chunks_exact(4)
+                .map(|p| Color::rgba(p[0], p[1], p[2], p[3]))
+                .collect(),
+            png::ColorType::Indexed => return Err(invalid_data("unexpanded PNG palette")),
+        };
+
+        Ok(Self::new(width, height, pixels))
+    }
+
+    /// Decodes an uncompressed BMP image with 1, 4, 8, 16, 24 or 32 bits per pixel.
+    pub fn decode_bmp(data: &[u8]) -> io::Result<Self> {
+        const BI_RGB: u32 = 0;
+        const BI_BITFIELDS: u32 = 3;
+        const BI_ALPHABITFIELDS: u32 = 6;
+
+        if !data.starts_with(b"BM") {
+            return Err(invalid_data("not a BMP image"));
+        }
+
+        let pixel_offset = read_le::<4>(data, 10)? as usize;
+        let header_size = read_le::<4>(data, 14)? as usize;
+        if header_size < 40 {
+            return Err(invalid_data("unsupported BMP header"));
+        }
+
+        let width = read_le::<4>(data, 18)? as i32;
+        let height = read_le::<4>(data, 22)? as i32;
+        let bpp = read_le::<2>(data, 28)?;
+        let compression = read_le::<4>(data, 30)?;
+        let colors_used = read_le::<4>(data, 46)? as usize;
+
+        let (r_mask, g_mask, b_mask, a_mask) = match (compression, bpp) {
+            (BI_RGB, 16) => (0x7c00, 0x03e0, 0x001f, 0),
+            (BI_RGB, 24 | 32) => (0xff_0000, 0x00_ff00, 0x00_00ff, 0),
+            (BI_RGB, _) => (0, 0, 0, 0),
+            (BI_BITFIELDS | BI_ALPHABITFIELDS, 16 | 32) => (
+                read_le::<4>(data, 54)?,
+                read_le::<4>(data, 58)?,
+                read_le::<4>(data, 62)?,
+                if compression == BI_ALPHABITFIELDS || header_size >= 56 {
+                    read_le::<4>(data, 66)?
+                } else {
+                    0
+                },
+            ),
+            _ => return Err(invalid_data("unsupported BMP compression")),
+        };
+
+        let palette = if bpp <= 8 {
+            let count = if colors_used == 0 {
+                1 << bpp
+            } else {
+                colors_used
+            };
+            let entries = header_size
+                .checked_add(14)
+                .and_then(|start| data.get(start..))
+                .ok_or_else(|| invalid_data("truncated BMP palette"))?;
+
+            // Stops at the first missing entry, so `i * 4` stays within the data.
+            (0..count)
+                .map(|i| {
+                    let entry = entries
+                        .get(i * 4..i * 4 + 3)
+                        .ok_or_else(|| invalid_data("truncated BMP palette"))?;
+                    Ok(Color::rgb(entry[2], entry[1], entry[0]))
+                })
+                .collect::<io::Result<Vec<_>>>()?
+        } else {
+            Vec::new()
+        };
+
+        if !matches!(bpp, 1 | 4 | 8 | 16 | 24 | 32) {
+            return Err(invalid_data("unsupported BMP bit depth"));
+        }
+
+        let top_down = height < 0;
+        let width = dimension(width as i64)?;
+        let height = dimension((height as i64).abs())?;
+        let stride = (width as usize * bpp as usize + 31) / 32 * 4;
+
+        let pixel_end = stride
+            .checked_mul(height as usize)
+            .and_then(|size| size.checked_add(pixel_offset));
+        if pixel_end.map_or(true, |end| end > data.len()) {
+            return Err(invalid_data("truncated BMP pixels"));
+        }
+
+        let mut pixels = Vec::new();
+        pixels
+            .try_reserve_exact(width as usize * height as usize)
+            .map_err(|e| io::Error::new(io::ErrorKind::OutOfMemory, e))?;
+        for y in 0..height as usize {
+            let row = if top_down { y } else { height as usize - 1 - y };
+            let start = pixel_offset + row * stride;
+            let row = data
+                .get(start..start + stride)
+                .ok_or_else(|| invalid_data("truncated BMP pixels"))?;
+
+            for x in 0..width as usize {
+                let color = match bpp {
+                    1 | 4 | 8 => {
+                        let bit = x * bpp as usize;
+                        let index =
+                            (row[bit / 8] >> (8 - bpp as usize - bit % 8)) & ((1 << bpp) - 1);
+                        *palette
+                            .get(index as usize)
+                            .ok_or_else(|| invalid_data("invalid BMP palette index"))?
+                    }
+                    _ => {
+                        let bytes = bpp as usize / 8;
+                        let value = le_value(&row[x * bytes..(x + 1) * bytes]);
+
+                        Color::rgba(
+                            scale_channel(value, r_mask),
+                            scale_channel(value, g_mask),
+                            scale_channel(value, b_mask),
+                            if a_mask == 0 {
+                                255
+                            } else {
+                                scale_channel(value, a_mask)
+                            },
+                        )
+                    }
+                };
+
+                pixels.push(color);
+            }
+        }
+
+        Ok(Self::new(width, height, pixels))
+    }
+
+    /// Decodes a TGA image, either color-mapped, true-color or grayscale, with or without RLE compression.
+    pub fn decode_tga(data: &[u8]) -> io::Result<Self> {
+        let id_length = read_le::<1>(data, 0)? as usize;
+        let color_map_type = read_le::<1>(data, 1)?;
+        let image_type = read_le::<1>(data, 2)?;
+        let map_first = read_le::<2>(data, 3)? as usize;
+        let map_length = read_le::<2>(data, 5)? as usize;
+        let map_depth = read_le::<1>(data, 7)?;
+        let width = read_le::<2>(data, 12)? as u16;
+        let height = read_le::<2>(data, 14)? as u16;
+        let depth = read_le::<1>(data, 16)?;
+        let descriptor = read_le::<1>(data, 17)?;
+
+        let alpha_bits = descriptor & 0x0f;
+        let right_to_left = descriptor & 0x10 != 0;
+        let top_to_bottom = descriptor & 0x20 != 0;
+
+        let rle = image_type & 0x08 != 0;
+        let kind = image_type & 0x07;
+        if color_map_type > 1 || !matches!(kind, 1..=3) || (kind == 1) != (color_map_type == 1) {
+            return Err(invalid_data("unsupported TGA image type"));
+        }
+
+        // Reads a true-color pixel of `depth` bits.
+        let color = |bytes: &[u8], depth: u32| -> io::Result<Color> {
+            Ok(match depth {
+                15 | 16 => {
+                    let value = u16::from_le_bytes([bytes[0], bytes[1]]) as u32;
+                    Color::rgba(
+                        scale_channel(value, 0x7c00),
+                        scale_channel(value, 0x03e0),
+                        scale_channel(value, 0x001f),
+                        if depth == 16 && alpha_bits > 0 && value & 0x8000 == 0 {
+                            0
+                        } else {
+                            255
+                        },
+                    )
+                }
+                24 => Color::rgb(bytes[2], bytes[1], bytes[0]),
+                32 => Color::rgba(
+                    bytes[2],
+                    bytes[1],
+                    bytes[0],
+                    if alpha_bits > 0 { bytes[3] } else { 255 },
+                ),
+                _ => return Err(invalid_data("unsupported TGA pixel depth")),
+            })
+        };
+
+        let mut offset = 18 + id_length;
+
+        let color_map = if color_map_type == 1 {
+            if !matches!(map_depth, 15 | 16 | 24 | 32) {
+                return Err(invalid_data("unsupported TGA color map depth"));
+            }
+
+            let entry_size = (map_depth as usize + 7) / 8;
+            let map = data
+                .get(offset..offset + map_length * entry_size)
+                .ok_or_else(|| invalid_data("truncated TGA color map"))?;
+            offset += map.len();
+
+            map.chunks_exact(entry_size)
+                .map(|entry| color(entry, map_depth))
+                .collect::<io::Result<Vec<_>>>()?
+        } else {
+            Vec::new()
+        };
+
+        let pixel_size = (depth as usize + 7) / 8;
+        let decode_pixel = |bytes: &[u8]| -> io::Result<Color> {
+            match kind {
+                1 => {
+                    let index = le_value(bytes) as usize & ((1 << depth) - 1);
+                    index
+                        .checked_sub(map_first)
+                        .and_then(|index| color_map.get(index))
+                        .copied()
+                        .ok_or_else(|| invalid_data("invalid TGA color map index"))
+                }
+                2 => color(bytes, depth),
+                _ => match depth {
+                    8 => Ok(Color::rgb(bytes[0], bytes[0], bytes[0])),
+                    16 => Ok(Color::rgba(bytes[0], bytes[0], bytes[0], bytes[1])),
+                    _ => Err(invalid_data("unsupported TGA pixel depth")),
+                },
+            }
+        };
+
+        if pixel_size == 0 || (kind == 1 && depth > 16) {
+            return Err(invalid_data("unsupported TGA pixel depth"));
+        }
+
+        let mut input = data
+            .get(offset..)
+            .ok_or_else(|| invalid_data("truncated TGA pixels"))?;
+
+        // Each RLE packet holds at most 128 pixels, in at least a header and a pixel.
+        let max_count = if rle {
+            input.len() / (1 + pixel_size) * 128
+        } else {
+            input.len() / pixel_size
+        };
+        let count = width as usize * height as usize;
+        if count > max_count {
+            return Err(invalid_data("truncated TGA pixels"));
+        }
+
+        let mut decoded = Vec::new();
+        decoded
+            .try_reserve_exact(count)
+            .map_err(|e| io::Error::new(io::ErrorKind::OutOfMemory, e))?;
+        let mut take = |len: usize| -> io::Result<&[u8]> {
+            if input.len() < len {
+                return Err(invalid_data("truncated TGA pixels"));
+            }
+            let (head, tail) = input.split_at(len);
+            input = tail;
+            Ok(head)
+        };
+
+        while decoded.len() < count {
+            if rle {
+                let header = take(1)?[0];
+                let run = (header & 0x7f) as usize + 1;
+
+                if header & 0x80 != 0 {
+                    let pixel = decode_pixel(take(pixel_size)?)?;
+                    decoded.extend(std::iter::repeat(pixel).take(run));
+                } else {
+                    for _ in 0..run {
+                        decoded.push(decode_pixel(take(pixel_size)?)?);
+                    }
+                }
+            } else {
+                decoded.push(decode_pixel(take(pixel_size)?)?);
+            }
+        }
+        decoded.truncate(count);
+
+        // Reorder the pixels to start from the top-left corner.
+        let (w, h) = (width as usize, height as usize);
+        let pixels = (0..count)
+            .map(|i| {
+                let (x, y) = (i % w, i / w);
+                let x = if right_to_left { w - 1 - x } else { x };
+                let y = if top_to_bottom { y } else { h - 1 - y };
+                decoded[y * w + x]
+            })
+            .collect();
+
+        Ok(Self::new(width, height, pixels))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn framebuffer_layout() {
+        let image = Image::new(
+            2,
+            2,
+            vec![
+                Color::rgb(1, 2, 3),
+                Color::rgb(4, 5, 6),
+                Color::rgb(7, 8, 9),
+                Color::rgb(10, 11, 12),
+            ],
+        );
+
+        // Columns going up: bottom-left, top-left, bottom-right, top-right.
+        assert_eq!(
+            image.to_framebuffer(FramebufferFormat::Bgr8, Dither::None),
+            [9, 8, 7, 3, 2, 1, 12, 11, 10, 6, 5, 4]
+        );
+        assert_eq!(
+            image.to_framebuffer(FramebufferFormat::Rgba8, Dither::FloydSteinberg)[..4],
+            [255, 9, 8, 7]
+        );
+
+        let mut data = image.to_framebuffer(FramebufferFormat::Rgb565, Dither::None);
+        let canvas = Canvas::from_buffer(&mut data, 2, 2, FramebufferFormat::Rgb565).unwrap();
+        assert_eq!(canvas.get_pixel(1, 0), Some(Color::rgb(0, 4, 0)));
+    }
+
+    #[test]
+    fn quantization() {
+        assert_eq!(surrounding_levels(255, 5), (255, 255));
+        assert_eq!(surrounding_levels(0, 4), (0, 0x11));
+        assert_eq!(surrounding_levels(0x84, 4), (0x77, 0x88));
+        assert_eq!(surrounding_levels(0x87, 6), (0x86, 0x8a));
+    }
+
+    #[test]
+    fn dithering_preserves_average() {
+        let image = Image::new(64, 64, vec![Color::rgb(0x7f, 0x7f, 0x7f); 64 * 64]);
+
+        for format in [FramebufferFormat::Rgb565, FramebufferFormat::Rgba4] {
+            let average_red = |dither| {
+                let mut data = image.to_framebuffer(format, dither);
+                let canvas = Canvas::from_buffer(&mut data, 64, 64, format).unwrap();
+                let converted = Image::from_canvas(&canvas);
+
+                converted.pixels().iter().map(|c| c.r as f32).sum::<f32>() / (64.0 * 64.0)
+            };
+
+            // Without dithering, the whole image gets rounded down to the same darker color.
+            assert!(127.0 - average_red(Dither::None) > 3.0, "{format:?}");
+            assert!(
+                (127.0 - average_red(Dither::Ordered)).abs() < 1.0,
+                "{format:?}"
+            );
+            assert!(
+                (127.0 - average_red(Dither::FloydSteinberg)).abs() < 1.0,
+                "{format:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn ordered_dithering_pattern() {
+        let image = Image::new(4, 1, vec![Color::rgb(0x7f, 0x7f, 0x7f); 4]);
+        let mut data = image.to_framebuffer(FramebufferFormat::Rgba4, Dither::Ordered);
+        let canvas = Canvas::from_buffer(&mut data, 4, 1, FramebufferFormat::Rgba4).unwrap();
+
+        // 0x7f is about halfway between 0x77 and 0x88, and the first thresholds are 0, 8, 2 and 10 of 16.
+        let reds: Vec<u8> = Image::from_canvas(&canvas)
+            .pixels()
+            .iter()
+            .map(|c| c.r)
+            .collect();
+        assert_eq!(reds, [0x88, 0x77, 0x88, 0x77]);
+    }
+
+    #[test]
+    fn framebuffer_round_trip() {
+        let pixels = (0..12)
+            .map(|i| Color::rgba(i * 20, 255 - i, i, 255))
+            .collect();
+        let image = Image::new(4, 3, pixels);
+
+        for format in [FramebufferFormat::Rgba8, FramebufferFormat::Bgr8] {
+            let data = image.to_framebuffer(format, Dither::None);
+            assert_eq!(Image::from_framebuffer(&data, 4, 3, format).unwrap(), image);
+        }
+
+        let data = image.to_framebuffer(FramebufferFormat::Rgb565, Dither::None);
+        let converted = Image::from_framebuffer(&data, 4, 3, FramebufferFormat::Rgb565).unwrap();
+        assert_eq!(
+            converted.get_pixel(3, 2),
+            Some(Color::rgb(0xde, 0xf7, 0x08))
+        );
+
+        assert!(matches!(
+            Image::from_framebuffer(&data, 4, 4, FramebufferFormat::Rgb565),
+            Err(crate::Error::BufferTooShort {
+                provided: 24,
+                wanted: 32
+            })
+        ));
+    }
+
+    #[test]
+    fn bmp_encoding() {
+        let image = Image::new(
+            2,
+            2,
+            vec![Color::RED, Color::GREEN, Color::BLUE, Color::WHITE],
+        );
+        let mut bmp = Vec::new();
+        image.write_bmp(&mut bmp).unwrap();
+
+        assert_eq!(bmp.len(), 54 + 2 * 8);
+        assert_eq!(bmp[..2], *b"BM");
+        assert_eq!(bmp[2..6], 70u32.to_le_bytes());
+        assert_eq!(bmp[28..30], 24u16.to_le_bytes());
+        // Bottom row first, as BGR and padded to 8 bytes.
+        assert_eq!(
+            bmp[54..],
+            [255, 0, 0, 255, 255, 255, 0, 0, 0, 0, 255, 0, 255, 0, 0, 0]
+        );
+    }
+
+    #[test]
+    fn png_encoding() {
+        assert_eq!(crc32(&[b"IEND"]), 0xae42_6082);
+        assert_eq!(crc32(&[b"IE", b"ND"]), 0xae42_6082);
+        assert_eq!(adler32(b"Wikipedia"), 0x11e6_0398);
+        assert_eq!(adler32(&vec![255; 100_000]), 0x149a_302c);
+
+        let image = Image::new(300, 300, vec![Color::rgb(1, 2, 3); 300 * 300]);
+        let mut png = Vec::new();
+        image.write_png(&mut png).unwrap();
+
+        assert_eq!(png[..8], *b"\x89PNG\r\n\x1a\n");
+        assert_eq!(png[12..16], *b"IHDR");
+        assert_eq!(png[16..24], [0, 0, 1, 44, 0, 0, 1, 44]);
+        assert_eq!(png[png.len() - 12..], *b"\0\0\0\0IEND\xae\x42\x60\x82");
+
+        // 300 rows of 901 bytes, split into stored blocks of at most 65535 bytes.
+        let idat_len = u32::from_be_bytes(png[33..37].try_into().unwrap());
+        assert_eq!(png[37..41], *b"IDAT");
+        assert_eq!(idat_len as usize, 2 + 300 * 901 + 5 * 5 + 4);
+        assert_eq!(png[43..48], [0, 0xff, 0xff, 0, 0]);
+    }
+
+    #[cfg(feature = "image-decoding")]
+    #[test]
+    fn bmp() {
+        // 2x2, 24 bits per pixel, bottom-up, with rows padded to 8 bytes.
+        let mut bmp = b"BM".to_vec();
+        for value in [70u32, 0, 54, 40, 2, 2] {
+            bmp.extend_from_slice(&value.to_le_bytes());
+        }
+        bmp.extend_from_slice(&1u16.to_le_bytes());
+        bmp.extend_from_slice(&24u16.to_le_bytes());
+        bmp.extend_from_slice(&[0; 24]);
+        bmp.extend_from_slice(&[0, 0, 255, 0, 255, 0, 0, 0]);
+        bmp.extend_from_slice(&[255, 0, 0, 255, 255, 255, 0, 0]);
+
+        let image = Image::decode(&bmp).unwrap();
+        assert_eq!(
+            image.pixels(),
+            [Color::BLUE, Color::WHITE, Color::RED, Color::GREEN]
+        );
+
+        assert!(Image::decode_bmp(&bmp[..60]).is_err());
+
+        // Headers pointing past the end of the file.
+        let mut offset = bmp.clone();
+        offset[10..14].copy_from_slice(&u32::MAX.to_le_bytes());
+        assert!(Image::decode_bmp(&offset).is_err());
+
+        bmp[18..22].copy_from_slice(&0xffffu32.to_le_bytes());
+        bmp[22..26].copy_from_slice(&0xffffu32.to_le_bytes());
+        assert!(Image::decode_bmp(&bmp).is_err());
+    }
+
+    #[cfg(feature = "image-decoding")]
+    #[test]
+    fn tga() {
+        let header = |image_type: u8, depth: u8, descriptor: u8| {
+            let mut header = vec![0, 0, image_type, 0, 0, 0, 0, 0, 0, 0, 0, 0, 2, 0, 2, 0];
+            header.extend_from_slice(&[depth, descriptor]);
+            header
+        };
+
+        // True-color with alpha, top-to-bottom, RLE compressed.
+        let mut rle = header(10, 32, 0x28);
+        rle.extend_from_slice(&[0x81, 0, 0, 255, 255, 0x01, 255, 0, 0, 128, 0, 255, 0, 255]);
+
+        let image = Image::decode(&rle).unwrap();
+        assert_eq!(
+            image.pixels(),
+            [
+                Color::RED,
+                Color::RED,
+                Color::rgba(0, 0, 255, 128),
+                Color::GREEN
+            ]
+        );
+
+        // Grayscale, bottom-to-top.
+        let mut gray = header(3, 8, 0);
+        gray.extend_from_slice(&[1, 2, 3, 4]);
+
+        let image = Image::decode_tga(&gray).unwrap();
+        assert_eq!(image.get_pixel(0, 0), Some(Color::rgb(3, 3, 3)));
+        assert_eq!(image.get_pixel(1, 1), Some(Color::rgb(2, 2, 2)));
+
+        assert!(Image::decode_tga(&gray[..20]).is_err());
+        assert!(Image::decode_tga(&header(4, 8, 0)).is_err());
+
+        // A color map with 0 bits per entry.
+        let mut mapped = header(1, 8, 0);
+        mapped[1] = 1;
+        mapped[5] = 1;
+        mapped.extend_from_slice(&[0; 4]);
+        assert!(Image::decode_tga(&mapped).is_err());
+
+        // Dimensions that don't fit in the pixel data.
+        let mut large = header(10, 32, 0);
+        large[12..16].copy_from_slice(&[0xff; 4]);
+        large.extend_from_slice(&[0xff, 0, 0, 0, 0]);
+        assert!(Image::decode_tga(&large).is_err());
+    }
+
+    #[cfg(feature = "image-decoding")]
+    #[test]
+    fn encoding_round_trip() {
+        let pixels = (0..35).map(|i| Color::rgb(i * 7, 255 - i, i % 3)).collect();
+        let image = Image::new(7, 5, pixels);
+
+        let mut bmp = Vec::new();
+        image.write_bmp(&mut bmp).unwrap();
+        assert_eq!(Image::decode(&bmp).unwrap(), image);
+
+        let mut png = Vec::new();
+        image.write_png(&mut png).unwrap();
+        assert_eq!(Image::decode(&png).unwrap(), image);
+    }
+
+    #[cfg(feature = "image-decoding")]
+    #[test]
+    fn png() {
+        let image = Image::decode(include_bytes!("../../examples/assets/ferris.png")).unwrap();
+
+        assert_eq!((image.width(), image.height()), (320, 240));
+        // The corners of the image are transparent.
+        assert_eq!(image.get_pixel(0, 0).unwrap().a, 0);
+        assert!(image.pixels().iter().any(|c| c.a == 255));
+    }
+}
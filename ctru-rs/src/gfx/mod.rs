@@ -1,5 +1,9 @@
 //! LCD screens manipulation helper
 
+pub mod canvas;
+pub mod image;
+pub mod text;
+
 use std::cell::{Ref, RefCell, RefMut};
 use std::marker::PhantomData;
 use std::sync::Mutex;
@@ -7,6 +11,8 @@ use std::sync::Mutex;
 use crate::error::Result;
 use crate::services::gspgpu::{self, FramebufferFormat};
 use crate::services::ServiceReference;
+use canvas::Canvas;
+use image::Image;
 
 mod private {
     use super::{BottomScreen, TopScreen, TopScreenLeft, TopScreenRight};
@@ -47,6 +53,18 @@ pub trait Screen: private::Sealed {
         }
     }
 
+    /// Returns a [`Canvas`] to draw on the current framebuffer of the screen.
+    ///
+    /// Like with [`Screen::get_raw_framebuffer`], the canvas is only valid for the current frame
+    /// if double buffering is enabled.
+    fn get_canvas(&mut self) -> Canvas {
+        let format = self.get_framebuffer_format();
+
+        // SAFETY: the framebuffer was allocated by `libctru` for the current format of the screen,
+        // and stays alive as long as the screen is borrowed.
+        unsafe { Canvas::from_raw_framebuffer(self.get_raw_framebuffer(), format) }
+    }
+
     /// Sets whether to use double buffering. Enabled by default.
     ///
     /// Note that even when double buffering is disabled, one should still use the `swap_buffers`
@@ -180,6 +198,55 @@ impl Gfx {
         unsafe { ctru_sys::gfxSwapBuffersGpu() };
     }
 
+    /// Captures the contents of a screen as an unrotated, opaque image.
+    ///
+    /// The current framebuffer of `screen` is read in its [`FramebufferFormat`]. With wide mode
+    /// enabled the top screen is captured at 800x240, and the [`Side`] of a [`TopScreen3D`] half
+    /// selects which eye is captured.
+    ///
+    /// With double buffering enabled, the current framebuffer is the one being drawn to, which is
+    /// only displayed after the next call to [`Gfx::swap_buffers`]. Take the screenshot after drawing
+    /// a frame, but before swapping the buffers.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use ctru::prelude::*;
+    /// use ctru::services::fs::{File, Fs};
+    ///
+    /// let gfx = Gfx::init().unwrap();
+    /// let fs = Fs::init().unwrap();
+    /// let sdmc = fs.sdmc().unwrap();
+    ///
+    /// let screenshot = gfx.screenshot(&*gfx.bottom_screen.borrow());
+    /// let file = File::create(&sdmc, "/screenshot.png").unwrap();
+    /// screenshot.write_png(file).unwrap();
+    /// ```
+    pub fn screenshot<S: Screen + ?Sized>(&self, screen: &S) -> Image {
+        let format = screen.get_framebuffer_format();
+        let mut width = 0;
+        let mut height = 0;
+
+        let data = unsafe {
+            let ptr = ctru_sys::gfxGetFramebuffer(
+                screen.as_raw(),
+                screen.side().into(),
+                &mut width,
+                &mut height,
+            );
+            let len = width as usize * height as usize * format.pixel_depth_bytes();
+
+            std::slice::from_raw_parts(ptr, len)
+        };
+
+        // The framebuffer reports its dimensions before rotation.
+        let mut image = Image::from_framebuffer(data, height, width, format)
+            .expect("framebuffer should hold a whole screen");
+        image.make_opaque();
+
+        image
+    }
+
     /// Waits for the vertical blank interrupt
     ///
     /// Use this to synchronize your application with the refresh rate of the LCD screens
@@ -292,7 +359,7 @@ impl From<Side> for ctru_sys::gfx3dSide_t {
     }
 }
 
-#[cfg(test)]
+#[cfg(all(test, not(feature = "host-mock")))]
 mod tests {
     use super::*;
     use crate::Error;
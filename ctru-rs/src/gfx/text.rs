@@ -0,0 +1,916 @@
+//! Bitmap font text rendering
+//!
+//! Text is drawn onto a [`Canvas`], so it can be mixed freely with other drawing code and rendered
+//! into memory as well as to the screens. Two bitmap font formats are supported:
+//!
+//! * [`PsfFont`], the PC Screen Font format (versions 1 and 2) used by the Linux console.
+//!   Fonts with a Unicode table can cover any script, including Japanese.
+//! * [`BmFont`], the text descriptor format of AngelCode's BMFont, with proportional glyphs and kerning.
+//!
+//! Fonts are usually loaded from the RomFS (see [`romfs`](crate::romfs)).
+//!
+//! # Examples
+//!
+//! ```no_run
+//! use ctru::gfx::canvas::Color;
+//! use ctru::gfx::text::{PsfFont, TextRenderer};
+//! use ctru::gfx::Screen as _;
+//! use ctru::prelude::*;
+//!
+//! let gfx = Gfx::init().unwrap();
+//! let _romfs = ctru::romfs::RomFS::init().unwrap();
+//! let font = PsfFont::open("romfs:/fonts/unifont.psf").unwrap();
+//!
+//! let mut bottom_screen = gfx.bottom_screen.borrow_mut();
+//! let mut canvas = bottom_screen.get_canvas();
+//!
+//! TextRenderer::new(&font)
+//!     .color(Color::WHITE)
+//!     .wrap_width(300)
+//!     .draw(&mut canvas, 10, 10, "こんにちは! This text wraps around at 300 pixels.");
+//! ```
+
+use std::collections::HashMap;
+use std::io;
+use std::path::Path;
+
+use super::canvas::{Canvas, Color};
+
+/// A bitmap font.
+pub trait Font {
+    /// Returns the distance between the top of two consecutive lines, in pixels.
+    fn line_height(&self) -> u16;
+
+    /// Returns the glyph of a character, or `None` if the font doesn't cover it.
+    fn glyph(&self, c: char) -> Option<Glyph<'_>>;
+
+    /// Returns the adjustment of the advance between two consecutive characters.
+    fn kerning(&self, _first: char, _second: char) -> i16 {
+        0
+    }
+}
+
+/// The image of a single character.
+#[derive(Copy, Clone, Debug)]
+pub struct Glyph<'a> {
+    /// Width of the image in pixels.
+    pub width: u16,
+    /// Height of the image in pixels.
+    pub height: u16,
+    /// Horizontal offset from the pen position to the left of the image.
+    pub x_offset: i16,
+    /// Vertical offset from the top of the line to the top of the image.
+    pub y_offset: i16,
+    /// Horizontal distance the pen moves after the character.
+    pub advance: i16,
+    bitmap: Bitmap<'a>,
+}
+
+#[derive(Copy, Clone, Debug)]
+enum Bitmap<'a> {
+    /// One bit per pixel, most significant bit first, each row padded to a whole byte.
+    Bits { data: &'a [u8], stride: usize },
+    /// A rectangle of an 8-bit coverage image.
+    Alpha { page: &'a FontPage, x: u16, y: u16 },
+}
+
+/// A font in the PC Screen Font format (PSF1 or PSF2).
+#[derive(Clone, Debug)]
+pub struct PsfFont {
+    width: u16,
+    height: u16,
+    glyph_size: usize,
+    glyph_count: usize,
+    glyphs: Vec<u8>,
+    /// Glyph index of each character, if the font has a Unicode table.
+    unicode: Option<HashMap<char, usize>>,
+}
+
+/// A font in the BMFont text format.
+#[derive(Clone, Debug)]
+pub struct BmFont {
+    line_height: u16,
+    pages: Vec<FontPage>,
+    chars: HashMap<char, BmChar>,
+    kernings: HashMap<(char, char), i16>,
+}
+
+/// A texture page of a [`BmFont`], holding the coverage (alpha) of its pixels row by row.
+#[derive(Clone, Debug)]
+pub struct FontPage {
+    width: u16,
+    height: u16,
+    alpha: Vec<u8>,
+}
+
+#[derive(Copy, Clone, Debug)]
+struct BmChar {
+    x: u16,
+    y: u16,
+    width: u16,
+    height: u16,
+    x_offset: i16,
+    y_offset: i16,
+    advance: i16,
+    page: usize,
+}
+
+/// Draws text with a [`Font`].
+#[derive(Copy, Clone, Debug)]
+pub struct TextRenderer<'f, F: Font + ?Sized> {
+    font: &'f F,
+    color: Color,
+    wrap_width: Option<u32>,
+    line_spacing: i32,
+}
+
+impl Glyph<'_> {
+    /// Returns the coverage of a pixel of the glyph, from `0` (empty) to `255` (fully covered).
+    pub fn coverage(&self, x: u16, y: u16) -> u8 {
+        if x >= self.width || y >= self.height {
+            return 0;
+        }
+
+        match self.bitmap {
+            Bitmap::Bits { data, stride } => {
+                let byte = data[y as usize * stride + x as usize / 8];
+                if byte & (0x80 >> (x % 8)) != 0 {
+                    255
+                } else {
+                    0
+                }
+            }
+            Bitmap::Alpha { page, x: px, y: py } => page.alpha(px + x, py + y),
+        }
+    }
+}
+
+fn invalid_data(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+const PSF1_MAGIC: [u8; 2] = [0x36, 0x04];
+const PSF1_MODE512: u8 = 0x01;
+const PSF1_MODEHASTAB: u8 = 0x02;
+const PSF1_MODESEQ: u8 = 0x04;
+const PSF1_SEPARATOR: u16 = 0xffff;
+const PSF1_STARTSEQ: u16 = 0xfffe;
+
+const PSF2_MAGIC: [u8; 4] = [0x72, 0xb5, 0x4a, 0x86];
+const PSF2_HAS_UNICODE_TABLE: u32 = 0x01;
+const PSF2_SEPARATOR: u8 = 0xff;
+const PSF2_STARTSEQ: u8 = 0xfe;
+
+impl PsfFont {
+    /// Reads a font from a file, e.g. in the RomFS.
+    pub fn open<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        Self::from_bytes(&std::fs::read(path)?)
+    }
+
+    /// Parses a PSF1 or PSF2 font.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error of kind [`InvalidData`](io::ErrorKind::InvalidData)
+    /// if `data` isn't a valid PSF font.
+    pub fn from_bytes(data: &[u8]) -> io::Result<Self> {
+        if data.starts_with(&PSF1_MAGIC) {
+            Self::parse_psf1(data)
+        } else if data.starts_with(&PSF2_MAGIC) {
+            Self::parse_psf2(data)
+        } else {
+            Err(invalid_data("not a PSF font"))
+        }
+    }
+
+    /// Returns a copy of the 8x8 font built into `libctru`, used by the [`Console`](crate::console::Console).
+    ///
+    /// Glyphs are looked up by character code, so only ASCII characters are guaranteed to look right.
+    pub fn system() -> Self {
+        // SAFETY: the default console is a static structure of `libctru`, and its font data
+        // holds `numChars` glyphs of 8 bytes each.
+        let (glyphs, first, count) = unsafe {
+            let font = &(*ctru_sys::consoleGetDefault()).font;
+            let glyphs = std::slice::from_raw_parts(font.gfx, font.numChars as usize * 8);
+
+            (glyphs, font.asciiOffset as usize, font.numChars as usize)
+        };
+
+        // Pad the font with empty glyphs up to its first character.
+        let mut data = vec![0; first * 8];
+        data.extend_from_slice(glyphs);
+
+        Self {
+            width: 8,
+            height: 8,
+            glyph_size: 8,
+            glyph_count: first + count,
+            glyphs: data,
+            unicode: None,
+        }
+    }
+
+    fn parse_psf1(data: &[u8]) -> io::Result<Self> {
+        let mode = *data
+            .get(2)
+            .ok_or_else(|| invalid_data("truncated PSF header"))?;
+        let height = *data
+            .get(3)
+            .ok_or_else(|| invalid_data("truncated PSF header"))? as usize;
+        let glyph_count = if mode & PSF1_MODE512 != 0 { 512 } else { 256 };
+
+        if height == 0 {
+            return Err(invalid_data("invalid PSF glyph size"));
+        }
+
+        let glyphs_end = 4 + glyph_count * height;
+        let glyphs = data
+            .get(4..glyphs_end)
+            .ok_or_else(|| invalid_data("truncated PSF glyphs"))?;
+
+        let unicode = if mode & (PSF1_MODEHASTAB | PSF1_MODESEQ) != 0 {
+            let mut table = HashMap::new();
+            let mut index = 0;
+            let mut in_sequence = false;
+
+            for entry in data[glyphs_end..].chunks_exact(2) {
+                match u16::from_le_bytes([entry[0], entry[1]]) {
+                    PSF1_SEPARATOR => {
+                        index += 1;
+                        in_sequence = false;
+                    }
+                    PSF1_STARTSEQ => in_sequence = true,
+                    code if !in_sequence => {
+                        if let Some(c) = char::from_u32(code as u32) {
+                            table.entry(c).or_insert(index);
+                        }
+                    }
+                    _ => {}
+                }
+            }
+
+            Some(table)
+        } else {
+            None
+        };
+
+        Ok(Self {
+            width: 8,
+            height: height as u16,
+            glyph_size: height,
+            glyph_count,
+            glyphs: glyphs.to_vec(),
+            unicode,
+        })
+    }
+
+    fn parse_psf2(data: &[u8]) -> io::Result<Self> {
+        let field = |index: usize| -> io::Result<u32> {
+            data.get(index * 4..index * 4 + 4)
+                .map(|bytes| u32::from_le_bytes(bytes.try_into().unwrap()))
+                .ok_or_else(|| invalid_data("truncated PSF header"))
+        };
+
+        let header_size = field(2)? as usize;
+        let flags = field(3)?;
+        let glyph_count = field(4)? as usize;
+        let glyph_size = field(5)? as usize;
+        let height = field(6)?;
+        let width = field(7)?;
+
+        if width == 0 || height == 0 || width > u16::MAX as u32 || height > u16::MAX as u32 {
+            return Err(invalid_data("invalid PSF glyph size"));
+        }
+        if glyph_size < ((width as usize + 7) / 8) * height as usize {
+            return Err(invalid_data("invalid PSF glyph size"));
+        }
+
+        let glyphs_end = glyph_count
+            .checked_mul(glyph_size)
+            .and_then(|len| len.checked_add(header_size))
+            .ok_or_else(|| invalid_data("invalid PSF glyph count"))?;
+        let glyphs = data
+            .get(header_size..glyphs_end)
+            .ok_or_else(|| invalid_data("truncated PSF glyphs"))?;
+
+        let unicode = if flags & PSF2_HAS_UNICODE_TABLE != 0 {
+            let mut table = HashMap::new();
+
+            for (index, entry) in data[glyphs_end..]
+                .split(|&b| b == PSF2_SEPARATOR)
+                .take(glyph_count)
+                .enumerate()
+            {
+                // Multi-character sequences come after the single characters and are ignored.
+                let singles = entry
+                    .split(|&b| b == PSF2_STARTSEQ)
+                    .next()
+                    .unwrap_or_default();
+                let singles = std::str::from_utf8(singles)
+                    .map_err(|_| invalid_data("invalid UTF-8 in PSF Unicode table"))?;
+
+                for c in singles.chars() {
+                    table.entry(c).or_insert(index);
+                }
+            }
+
+            Some(table)
+        } else {
+            None
+        };
+
+        Ok(Self {
+            width: width as u16,
+            height: height as u16,
+            glyph_size,
+            glyph_count,
+            glyphs: glyphs.to_vec(),
+            unicode,
+        })
+    }
+
+    /// Returns the width of every glyph of the font.
+    pub fn width(&self) -> u16 {
+        self.width
+    }
+
+    /// Returns the height of every glyph of the font.
+    pub fn height(&self) -> u16 {
+        self.height
+    }
+}
+
+impl Font for PsfFont {
+    fn line_height(&self) -> u16 {
+        self.height
+    }
+
+    fn glyph(&self, c: char) -> Option<Glyph<'_>> {
+        let index = match &self.unicode {
+            Some(table) => *table.get(&c)?,
+            // Without a table, glyphs follow the character codes (usually ASCII or Latin-1).
+            None => c as usize,
+        };
+
+        if index >= self.glyph_count {
+            return None;
+        }
+
+        let start = index * self.glyph_size;
+
+        Some(Glyph {
+            width: self.width,
+            height: self.height,
+            x_offset: 0,
+            y_offset: 0,
+            advance: self.width as i16,
+            bitmap: Bitmap::Bits {
+                data: &self.glyphs[start..start + self.glyph_size],
+                stride: (self.width as usize + 7) / 8,
+            },
+        })
+    }
+}
+
+impl FontPage {
+    /// Creates a page from its coverage values, stored row by row.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `alpha` doesn't hold exactly `width * height` values.
+    pub fn new(width: u16, height: u16, alpha: Vec<u8>) -> Self {
+        assert_eq!(alpha.len(), width as usize * height as usize);
+
+        Self {
+            width,
+            height,
+            alpha,
+        }
+    }
+
+    /// Creates a page from an image, using the alpha channel of its pixels as coverage.
+    pub fn from_colors(width: u16, height: u16, pixels: &[Color]) -> Self {
+        Self::new(width, height, pixels.iter().map(|c| c.a).collect())
+    }
+
+    fn alpha(&self, x: u16, y: u16) -> u8 {
+        if x >= self.width || y >= self.height {
+            return 0;
+        }
+
+        self.alpha[y as usize * self.width as usize + x as usize]
+    }
+}
+
+impl BmFont {
+    /// Parses a BMFont descriptor in the text format.
+    ///
+    /// `load_page` is called with the file name of every page listed by the descriptor,
+    /// and must return its contents.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error of kind [`InvalidData`](io::ErrorKind::InvalidData)
+    /// if the descriptor is invalid, or any error returned by `load_page`.
+    pub fn parse<L>(descriptor: &str, mut load_page: L) -> io::Result<Self>
+    where
+        L: FnMut(&str) -> io::Result<FontPage>,
+    {
+        let mut line_height = None;
+        let mut page_files: Vec<(usize, String)> = Vec::new();
+        let mut chars = HashMap::new();
+        let mut kernings = HashMap::new();
+
+        for line in descriptor.lines() {
+            let mut tokens = Tokens(line.trim());
+            let tag = match tokens.next() {
+                Some((tag, None)) => tag,
+                _ => continue,
+            };
+            let attributes: HashMap<&str, &str> = tokens
+                .filter_map(|(key, value)| Some((key, value?)))
+                .collect();
+
+            let int = |key: &str| -> io::Result<i64> {
+                attributes
+                    .get(key)
+                    .and_then(|value| value.parse().ok())
+                    .ok_or_else(|| {
+                        invalid_data(&format!(
+                            "missing or invalid `{key}` in BMFont `{tag}` line"
+                        ))
+                    })
+            };
+            let char_code = |key: &str| -> io::Result<Option<char>> {
+                Ok(u32::try_from(int(key)?).ok().and_then(char::from_u32))
+            };
+
+            match tag {
+                "common" => line_height = Some(int("lineHeight")? as u16),
+                "page" => {
+                    let file = attributes
+                        .get("file")
+                        .ok_or_else(|| invalid_data("missing `file` in BMFont `page` line"))?;
+                    page_files.push((int("id")? as usize, file.to_string()));
+                }
+                "char" => {
+                    if let Some(c) = char_code("id")? {
+                        chars.insert(
+                            c,
+                            BmChar {
+                                x: int("x")? as u16,
+                                y: int("y")? as u16,
+                                width: int("width")? as u16,
+                                height: int("height")? as u16,
+                                x_offset: int("xoffset")? as i16,
+                                y_offset: int("yoffset")? as i16,
+                                advance: int("xadvance")? as i16,
+                                page: int("page").unwrap_or(0) as usize,
+                            },
+                        );
+                    }
+                }
+                "kerning" => {
+                    if let (Some(first), Some(second)) = (char_code("first")?, char_code("second")?)
+                    {
+                        kernings.insert((first, second), int("amount")? as i16);
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        let line_height =
+            line_height.ok_or_else(|| invalid_data("missing BMFont `common` line"))?;
+
+        page_files.sort_by_key(|(id, _)| *id);
+        if page_files
+            .iter()
+            .enumerate()
+            .any(|(index, (id, _))| index != *id)
+        {
+            return Err(invalid_data("BMFont page ids are not contiguous"));
+        }
+        let pages = page_files
+            .iter()
+            .map(|(_, file)| load_page(file))
+            .collect::<io::Result<Vec<_>>>()?;
+
+        if chars.values().any(|c| c.page >= pages.len()) {
+            return Err(invalid_data("BMFont character on a missing page"));
+        }
+
+        Ok(Self {
+            line_height,
+            pages,
+            chars,
+            kernings,
+        })
+    }
+}
+
+impl Font for BmFont {
+    fn line_height(&self) -> u16 {
+        self.line_height
+    }
+
+    fn glyph(&self, c: char) -> Option<Glyph<'_>> {
+        let bm = self.chars.get(&c)?;
+
+        Some(Glyph {
+            width: bm.width,
+            height: bm.height,
+            x_offset: bm.x_offset,
+            y_offset: bm.y_offset,
+            advance: bm.advance,
+            bitmap: Bitmap::Alpha {
+                page: &self.pages[bm.page],
+                x: bm.x,
+                y: bm.y,
+            },
+        })
+    }
+
+    fn kerning(&self, first: char, second: char) -> i16 {
+        self.kernings.get(&(first, second)).copied().unwrap_or(0)
+    }
+}
+
+/// Splits a BMFont descriptor line into `key=value` pairs, with support for quoted values.
+struct Tokens<'a>(&'a str);
+
+impl<'a> Iterator for Tokens<'a> {
+    type Item = (&'a str, Option<&'a str>);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let rest = self.0.trim_start();
+        if rest.is_empty() {
+            return None;
+        }
+
+        let key_end = rest
+            .find(|c: char| c == '=' || c.is_whitespace())
+            .unwrap_or(rest.len());
+        let key = &rest[..key_end];
+        let rest = &rest[key_end..];
+
+        let Some(value) = rest.strip_prefix('=') else {
+            self.0 = rest;
+            return Some((key, None));
+        };
+
+        let (value, rest) = if let Some(quoted) = value.strip_prefix('"') {
+            let end = quoted.find('"').unwrap_or(quoted.len());
+            (&quoted[..end], quoted.get(end + 1..).unwrap_or_default())
+        } else {
+            let end = value.find(char::is_whitespace).unwrap_or(value.len());
+            (&value[..end], &value[end..])
+        };
+
+        self.0 = rest;
+        Some((key, Some(value)))
+    }
+}
+
+/// Returns whether a line can be broken before or after the character, as in Chinese and Japanese text.
+fn is_wide(c: char) -> bool {
+    matches!(c,
+        '\u{1100}'..='\u{115f}'     // Hangul Jamo
+        | '\u{2e80}'..='\u{a4cf}'   // CJK radicals to Yi, including kana and ideographs
+        | '\u{ac00}'..='\u{d7a3}'   // Hangul syllables
+        | '\u{f900}'..='\u{faff}'   // CJK compatibility ideographs
+        | '\u{ff00}'..='\u{ffef}'   // Halfwidth and fullwidth forms
+        | '\u{20000}'..='\u{3fffd}' // Supplementary ideographs
+    )
+}
+
+impl<'f, F: Font + ?Sized> TextRenderer<'f, F> {
+    /// Creates a renderer drawing opaque white text without wrapping.
+    pub fn new(font: &'f F) -> Self {
+        Self {
+            font,
+            color: Color::WHITE,
+            wrap_width: None,
+            line_spacing: 0,
+        }
+    }
+
+    /// Sets the color of the text. Its alpha channel is combined with the coverage of the glyphs.
+    pub fn color(mut self, color: Color) -> Self {
+        self.color = color;
+        self
+    }
+
+    /// Wraps lines longer than `width` pixels, between words or around Chinese and Japanese characters.
+    pub fn wrap_width(mut self, width: u32) -> Self {
+        self.wrap_width = Some(width);
+        self
+    }
+
+    /// Adds space between lines, on top of the line height of the font.
+    pub fn line_spacing(mut self, spacing: i32) -> Self {
+        self.line_spacing = spacing;
+        self
+    }
+
+    /// Returns the glyph used for a character, falling back to `U+FFFD` and `?` for missing characters.
+    fn glyph(&self, c: char) -> Option<Glyph<'f>> {
+        self.font
+            .glyph(c)
+            .or_else(|| self.font.glyph(char::REPLACEMENT_CHARACTER))
+            .or_else(|| self.font.glyph('?'))
+    }
+
+    /// Returns the width of a single line of text, in pixels.
+    fn line_width(&self, line: &str) -> i32 {
+        let mut width = 0;
+        let mut previous = None;
+
+        for c in line.chars() {
+            if let Some(previous) = previous {
+                width += self.font.kerning(previous, c) as i32;
+            }
+            width += self.glyph(c).map_or(0, |g| g.advance as i32);
+            previous = Some(c);
+        }
+
+        width
+    }
+
+    /// Splits the text into the lines to draw.
+    fn lines<'t>(&self, text: &'t str) -> Vec<&'t str> {
+        let mut lines = Vec::new();
+
+        for paragraph in text.split('\n') {
+            let paragraph = paragraph.strip_suffix('\r').unwrap_or(paragraph);
+
+            let max_width = match self.wrap_width {
+                Some(width) => width as i32,
+                None => {
+                    lines.push(paragraph);
+                    continue;
+                }
+            };
+
+            let mut start = 0;
+            // End of the current line and start of the next one, if it had to be broken now.
+            let mut last_break: Option<(usize, usize)> = None;
+
+            for (i, c) in paragraph.char_indices() {
+                let next = i + c.len_utf8();
+
+                if c.is_whitespace() {
+                    // Spaces can hang past the end of the line.
+                    last_break = Some((i, next));
+                    continue;
+                }
+                if is_wide(c) && i > start {
+                    last_break = Some((i, i));
+                }
+
+                if i > start && self.line_width(&paragraph[start..next]) > max_width {
+                    let (end, next_start) = last_break.unwrap_or((i, i));
+
+                    lines.push(paragraph[start..end].trim_end());
+                    start = next_start;
+                    last_break = None;
+                }
+
+                if is_wide(c) {
+                    last_break = Some((next, next));
+                }
+            }
+
+            lines.push(&paragraph[start..]);
+        }
+
+        lines
+    }
+
+    /// Returns the size in pixels of the area covered by the text, as `(width, height)`.
+    pub fn measure(&self, text: &str) -> (u32, u32) {
+        let lines = self.lines(text);
+        let width = lines
+            .iter()
+            .map(|line| self.line_width(line))
+            .max()
+            .unwrap_or(0);
+
+        (width.max(0) as u32, self.height(lines.len()))
+    }
+
+    fn height(&self, lines: usize) -> u32 {
+        let line_height = self.font.line_height() as i32;
+        let height = lines as i32 * line_height + (lines as i32 - 1).max(0) * self.line_spacing;
+
+        height.max(0) as u32
+    }
+
+    /// Draws text with the top-left corner of its first line at `(x, y)`.
+    ///
+    /// Returns the size of the area covered by the text, like [`TextRenderer::measure`].
+    pub fn draw(&self, canvas: &mut Canvas, x: i32, y: i32, text: &str) -> (u32, u32) {
+        let lines = self.lines(text);
+        let mut width = 0;
+        let mut line_y = y;
+
+        for line in &lines {
+            let mut pen_x = x;
+            let mut previous = None;
+
+            for c in line.chars() {
+                if let Some(previous) = previous {
+                    pen_x += self.font.kerning(previous, c) as i32;
+                }
+                previous = Some(c);
+
+                let glyph = match self.glyph(c) {
+                    Some(glyph) => glyph,
+                    None => continue,
+                };
+                self.draw_glyph(canvas, &glyph, pen_x, line_y);
+                pen_x += glyph.advance as i32;
+            }
+
+            width = width.max(pen_x - x);
+            line_y += self.font.line_height() as i32 + self.line_spacing;
+        }
+
+        (width.max(0) as u32, self.height(lines.len()))
+    }
+
+    fn draw_glyph(&self, canvas: &mut Canvas, glyph: &Glyph, x: i32, y: i32) {
+        let left = x + glyph.x_offset as i32;
+        let top = y + glyph.y_offset as i32;
+
+        for gy in 0..glyph.height {
+            for gx in 0..glyph.width {
+                let coverage = glyph.coverage(gx, gy);
+                if coverage == 0 {
+                    continue;
+                }
+
+                let alpha = (self.color.a as u32 * coverage as u32 / 255) as u8;
+                canvas.blend_pixel(
+                    left + gx as i32,
+                    top + gy as i32,
+                    Color {
+                        a: alpha,
+                        ..self.color
+                    },
+                );
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::services::gspgpu::FramebufferFormat;
+
+    /// Builds a PSF2 font with 4x5 glyphs for `A`, `B`, `?` and `あ`, with a Unicode table.
+    fn psf2_font() -> Vec<u8> {
+        let glyphs: [[u8; 5]; 4] = [
+            [0x60, 0x90, 0xf0, 0x90, 0x90], // A
+            [0xe0, 0x90, 0xe0, 0x90, 0xe0], // B
+            [0x60, 0x10, 0x20, 0x00, 0x20], // ?
+            [0x40, 0xf0, 0x60, 0xb0, 0x60], // あ
+        ];
+
+        let mut data = PSF2_MAGIC.to_vec();
+        for field in [0u32, 32, PSF2_HAS_UNICODE_TABLE, 4, 5, 5, 4] {
+            data.extend_from_slice(&field.to_le_bytes());
+        }
+        data.extend(glyphs.iter().flatten());
+        for entry in ["Aa", "B", "?", "あ"] {
+            data.extend_from_slice(entry.as_bytes());
+            data.push(PSF2_SEPARATOR);
+        }
+
+        data
+    }
+
+    fn render(canvas: &Canvas) -> String {
+        let mut text = String::new();
+
+        for y in 0..canvas.height() as i32 {
+            for x in 0..canvas.width() as i32 {
+                text.push(match canvas.get_pixel(x, y).unwrap() {
+                    Color::BLACK => '.',
+                    Color::WHITE => '#',
+                    _ => '+',
+                });
+            }
+            text.push('\n');
+        }
+
+        text
+    }
+
+    #[test]
+    fn psf1() {
+        let mut data = vec![0x36, 0x04, 0x00, 2];
+        data.extend((0..256).flat_map(|i| [i as u8, !(i as u8)]));
+
+        let font = PsfFont::from_bytes(&data).unwrap();
+        let glyph = font.glyph('A').unwrap();
+
+        assert_eq!((font.width(), font.height()), (8, 2));
+        // 'A' is 0x41: the first row has bits 1 and 7 set, the second row all the others.
+        assert_eq!(glyph.coverage(1, 0), 255);
+        assert_eq!(glyph.coverage(7, 0), 255);
+        assert_eq!(glyph.coverage(0, 0), 0);
+        assert_eq!(glyph.coverage(0, 1), 255);
+        assert!(font.glyph('あ').is_none());
+
+        assert!(PsfFont::from_bytes(&data[..100]).is_err());
+        assert!(PsfFont::from_bytes(b"not a font").is_err());
+
+        let error = PsfFont::from_bytes(&[0x36, 0x04, 0x00, 0]).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn psf2_rendering() {
+        let font = PsfFont::from_bytes(&psf2_font()).unwrap();
+
+        let mut data = vec![0; 16 * 5 * 3];
+        let mut canvas = Canvas::from_buffer(&mut data, 16, 5, FramebufferFormat::Bgr8).unwrap();
+        canvas.clear(Color::BLACK);
+
+        // 'a' shares the glyph of 'A', and 'Z' is missing so it falls back to '?'.
+        let size = TextRenderer::new(&font).draw(&mut canvas, 0, 0, "aBあZ");
+        assert_eq!(size, (16, 5));
+
+        assert_eq!(
+            render(&canvas),
+            "\
+.##.###..#...##.
+#..##..#####...#
+#######..##...#.
+#..##..##.##....
+#..####..##...#.
+"
+        );
+    }
+
+    #[test]
+    fn word_wrapping() {
+        let font = PsfFont::from_bytes(&psf2_font()).unwrap();
+        let renderer = TextRenderer::new(&font).wrap_width(12);
+
+        assert_eq!(renderer.lines("A B AB"), ["A B", "AB"]);
+        assert_eq!(renderer.lines("ABABAB"), ["ABA", "BAB"]);
+        assert_eq!(renderer.lines("A\nB"), ["A", "B"]);
+        // Japanese text can be broken between any two characters.
+        assert_eq!(renderer.lines("Aあああ"), ["Aああ", "あ"]);
+
+        assert_eq!(renderer.line_spacing(2).measure("A B AB"), (12, 12));
+    }
+
+    #[test]
+    fn bmfont() {
+        let descriptor = r#"info face="Test Font" size=4 bold=0 italic=0
+common lineHeight=6 base=5 scaleW=4 scaleH=2 pages=1 packed=0
+page id=0 file="test_0.png"
+chars count=2
+char id=65   x=0 y=0 width=2 height=2 xoffset=0 yoffset=1 xadvance=3 page=0 chnl=15
+char id=86   x=2 y=0 width=2 height=2 xoffset=1 yoffset=0 xadvance=3 page=0 chnl=15
+kernings count=1
+kerning first=65 second=86 amount=-1
+"#;
+
+        let font = BmFont::parse(descriptor, |file| {
+            assert_eq!(file, "test_0.png");
+            Ok(FontPage::new(
+                4,
+                2,
+                vec![255, 128, 0, 255, 255, 255, 255, 0],
+            ))
+        })
+        .unwrap();
+
+        assert_eq!(font.line_height(), 6);
+        assert_eq!(font.kerning('A', 'V'), -1);
+
+        let mut data = vec![0; 6 * 3 * 3];
+        let mut canvas = Canvas::from_buffer(&mut data, 6, 3, FramebufferFormat::Bgr8).unwrap();
+        canvas.clear(Color::BLACK);
+
+        assert_eq!(
+            TextRenderer::new(&font).draw(&mut canvas, 0, 0, "AV"),
+            (5, 6)
+        );
+        assert_eq!(
+            render(&canvas),
+            "\
+....#.
+#+.#..
+##....
+"
+        );
+
+        assert!(BmFont::parse("char id=65", |_| unreachable!()).is_err());
+    }
+}
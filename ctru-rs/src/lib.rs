@@ -5,10 +5,12 @@
 #![feature(try_trait_v2)]
 #![feature(allocator_api)]
 #![feature(nonnull_slice_from_raw_parts)]
-#![test_runner(test_runner::run)]
+#![cfg_attr(not(feature = "host-mock"), test_runner(test_runner::run))]
 
 // Nothing is imported from these crates but their inclusion here assures correct linking of the missing implementations.
+#[cfg(target_os = "horizon")]
 extern crate linker_fix_3ds;
+#[cfg(target_os = "horizon")]
 extern crate pthread_3ds;
 
 #[no_mangle]
@@ -60,34 +62,19 @@ fn panic_hook_setup() {
 }
 
 pub mod applets;
+pub mod compression;
 pub mod console;
 pub mod error;
 pub mod gfx;
 pub mod linear;
 pub mod mii;
+#[cfg(feature = "host-mock")]
+pub mod mock;
 pub mod prelude;
+pub mod romfs;
 pub mod services;
 
-cfg_if::cfg_if! {
-    if #[cfg(all(feature = "romfs", romfs_exists))] {
-        pub mod romfs;
-    } else {
-        pub mod romfs {
-            //! The RomFS folder has not been detected and/or the `romfs` feature has not been enabled.
-            //!
-            //! Configure the path in Cargo.toml (the default path is "romfs"). Paths are relative to the
-            //! `CARGO_MANIFEST_DIR` environment variable, which is the directory containing the manifest of
-            //! your package.
-            //!
-            //! ```toml
-            //! [package.metadata.cargo-3ds]
-            //! romfs_dir = "romfs"
-            //! ```
-        }
-    }
-}
-
-#[cfg(test)]
+#[cfg(all(test, not(feature = "host-mock")))]
 mod test_runner;
 
 pub use crate::error::{Error, Result};
@@ -0,0 +1,522 @@
+//! Simulated console used to run code on the host
+//!
+//! This module only gets compiled if the `host-mock` feature is enabled.
+//!
+//! With the feature enabled, the [`Hid`](crate::services::hid::Hid), [`Fs`](crate::services::fs::Fs)
+//! and [`Cfgu`](crate::services::cfgu::Cfgu) services stop calling into `libctru` and talk to an
+//! in-process simulated console instead. The console offers scripted button, touch and circle pad input,
+//! motion sensors and sliders, an in-memory SD card, configurable system settings and a fake clock, which makes it possible to test
+//! code built on top of those services with a plain `cargo test` on a Linux machine.
+//!
+//! Besides the SD card, the simulated filesystem holds save data archives, which must be formatted before use
+//! and roll back uncommitted changes like the real ones, and extra data archives, which are created when first opened.
+//!
+//! Every thread gets its own simulated console, starting from a freshly booted state.
+//! This way, tests run in parallel by `cargo test` can't interfere with each other.
+//!
+//! # Examples
+//!
+//! ```no_run
+//! use ctru::mock::{self, InputFrame};
+//! use ctru::services::hid::{Hid, KeyPad};
+//!
+//! mock::queue_input(InputFrame::pressed(KeyPad::KEY_A));
+//!
+//! let hid = Hid::init().unwrap();
+//! hid.scan_input();
+//!
+//! assert!(hid.keys_down().contains(KeyPad::KEY_A));
+//! ```
+
+pub(crate) mod sys;
+
+use std::cell::RefCell;
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::path::Path;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::services::cfgu::{Language, Region, SystemModel};
+use crate::services::hid::{AccelVector, AngularRate};
+
+pub use crate::services::hid::InputFrame;
+
+/// Time elapsed on the fake clock for every call to [`Hid::scan_input`](crate::services::hid::Hid::scan_input).
+pub const FRAME_DURATION: Duration = Duration::from_nanos(16_666_667);
+
+/// Resets the simulated console of the current thread to its freshly booted state.
+pub fn reset() {
+    with_console(|console| *console = Console::default());
+}
+
+/// Appends a frame to the scripted input.
+///
+/// Each call to [`Hid::scan_input`](crate::services::hid::Hid::scan_input) consumes one frame.
+/// Once the script runs out, the console behaves as if no input was given.
+pub fn queue_input(frame: InputFrame) {
+    with_console(|console| console.hid.script.push_back(frame));
+}
+
+/// Appends multiple frames to the scripted input. See [`queue_input`].
+pub fn queue_inputs<I: IntoIterator<Item = InputFrame>>(frames: I) {
+    with_console(|console| console.hid.script.extend(frames));
+}
+
+/// Returns the amount of scripted frames that have not been scanned yet.
+pub fn pending_inputs() -> usize {
+    with_console(|console| console.hid.script.len())
+}
+
+/// Sets the reading of the accelerometer, reported while it is enabled.
+pub fn set_accelerometer(vector: AccelVector) {
+    with_console(|console| console.hid.accelerometer = vector);
+}
+
+/// Sets the reading of the gyroscope, reported while it is enabled.
+pub fn set_gyroscope(rate: AngularRate) {
+    with_console(|console| console.hid.gyroscope = rate);
+}
+
+/// Sets the position of the sound volume slider, from `0` to `63`.
+pub fn set_volume(volume: u8) {
+    with_console(|console| console.hid.volume = volume.min(63));
+}
+
+/// Sets the position of the 3D depth slider, from `0.0` to `1.0`.
+pub fn set_slider_3d(position: f32) {
+    with_console(|console| console.hid.slider_3d = position.clamp(0.0, 1.0));
+}
+
+/// Sets the region returned by [`Cfgu::get_region`](crate::services::cfgu::Cfgu::get_region).
+pub fn set_region(region: Region) {
+    with_console(|console| console.config.region = region.into());
+}
+
+/// Sets the language returned by [`Cfgu::get_language`](crate::services::cfgu::Cfgu::get_language).
+pub fn set_language(language: Language) {
+    with_console(|console| console.config.language = language.into());
+}
+
+/// Sets the model returned by [`Cfgu::get_model`](crate::services::cfgu::Cfgu::get_model).
+pub fn set_model(model: SystemModel) {
+    with_console(|console| console.config.model = model.into());
+}
+
+/// Sets whether the console reports NFC support.
+pub fn set_nfc_supported(supported: bool) {
+    with_console(|console| console.config.nfc_supported = supported);
+}
+
+/// Returns the current time of the fake clock.
+pub fn now() -> SystemTime {
+    with_console(|console| console.clock)
+}
+
+/// Sets the current time of the fake clock.
+pub fn set_clock(time: SystemTime) {
+    with_console(|console| console.clock = time);
+}
+
+/// Moves the fake clock forward.
+pub fn advance_clock(duration: Duration) {
+    with_console(|console| console.clock += duration);
+}
+
+/// Creates (or overwrites) a file on the simulated SD card, creating its parent directories if needed.
+pub fn write_sdmc_file<P: AsRef<Path>>(path: P, contents: &[u8]) {
+    let path = normalize(&path.as_ref().to_string_lossy());
+
+    with_console(|console| {
+        let mtime = console.clock;
+        let tree = console.fs.sdmc_mut();
+        tree.create_parents(&path);
+        tree.nodes
+            .insert(path, Node::file(contents.to_vec(), mtime));
+    });
+}
+
+/// Returns the contents of a file on the simulated SD card, if it exists.
+pub fn read_sdmc_file<P: AsRef<Path>>(path: P) -> Option<Vec<u8>> {
+    let path = normalize(&path.as_ref().to_string_lossy());
+
+    with_console(|console| match console.fs.sdmc_mut().nodes.get(&path) {
+        Some(Node::File { data, .. }) => Some(data.clone()),
+        _ => None,
+    })
+}
+
+/// Creates a directory (and all of its parents) on the simulated SD card.
+pub fn create_sdmc_dir<P: AsRef<Path>>(path: P) {
+    let path = normalize(&path.as_ref().to_string_lossy());
+
+    with_console(|console| {
+        let tree = console.fs.sdmc_mut();
+        tree.create_parents(&path);
+        tree.nodes.entry(path).or_insert(Node::Dir);
+    });
+}
+
+/// Returns whether a file or directory exists on the simulated SD card.
+pub fn sdmc_exists<P: AsRef<Path>>(path: P) -> bool {
+    let path = normalize(&path.as_ref().to_string_lossy());
+
+    with_console(|console| console.fs.sdmc_mut().nodes.contains_key(&path))
+}
+
+thread_local! {
+    static CONSOLE: RefCell<Console> = RefCell::new(Console::default());
+}
+
+/// Runs a closure with exclusive access to the simulated console of the current thread.
+pub(crate) fn with_console<R>(f: impl FnOnce(&mut Console) -> R) -> R {
+    CONSOLE.with(|console| f(&mut console.borrow_mut()))
+}
+
+pub(crate) struct Console {
+    pub(crate) hid: HidState,
+    pub(crate) config: ConfigState,
+    pub(crate) clock: SystemTime,
+    pub(crate) fs: FsState,
+}
+
+impl Default for Console {
+    fn default() -> Self {
+        Self {
+            hid: HidState::default(),
+            config: ConfigState::default(),
+            // 2023-01-01 00:00:00 UTC, so that timestamps stay stable between runs.
+            clock: UNIX_EPOCH + Duration::from_secs(1_672_531_200),
+            fs: FsState::default(),
+        }
+    }
+}
+
+pub(crate) struct HidState {
+    pub(crate) script: VecDeque<InputFrame>,
+    pub(crate) current: InputFrame,
+    pub(crate) previous: InputFrame,
+    pub(crate) accelerometer: AccelVector,
+    pub(crate) accelerometer_enabled: bool,
+    pub(crate) gyroscope: AngularRate,
+    pub(crate) gyroscope_enabled: bool,
+    pub(crate) volume: u8,
+    pub(crate) slider_3d: f32,
+}
+
+impl Default for HidState {
+    fn default() -> Self {
+        Self {
+            script: VecDeque::new(),
+            current: InputFrame::default(),
+            previous: InputFrame::default(),
+            // Lying flat on a table.
+            accelerometer: AccelVector {
+                x: 0,
+                y: 0,
+                z: -(AccelVector::ONE_G as i16),
+            },
+            accelerometer_enabled: false,
+            gyroscope: AngularRate::default(),
+            gyroscope_enabled: false,
+            volume: 63,
+            slider_3d: 0.0,
+        }
+    }
+}
+
+pub(crate) struct ConfigState {
+    pub(crate) region: u8,
+    pub(crate) language: u8,
+    pub(crate) model: u8,
+    pub(crate) nfc_supported: bool,
+}
+
+impl Default for ConfigState {
+    fn default() -> Self {
+        Self {
+            region: Region::USA.into(),
+            language: Language::English.into(),
+            model: SystemModel::Model3DS.into(),
+            nfc_supported: false,
+        }
+    }
+}
+
+/// Identifies an archive by its ID and binary path, e.g. the extra data of a given title.
+pub(crate) type ArchiveKey = (ctru_sys::FS_ArchiveID, Vec<u8>);
+
+/// In-memory filesystem shared by all archives of the simulated console.
+#[derive(Default)]
+pub(crate) struct FsState {
+    pub(crate) trees: HashMap<ArchiveKey, Tree>,
+    pub(crate) archives: HashMap<ctru_sys::FS_Archive, ArchiveKey>,
+    pub(crate) saves: HashMap<ArchiveKey, SaveData>,
+    pub(crate) files: HashMap<ctru_sys::Handle, OpenFile>,
+    pub(crate) dirs: HashMap<ctru_sys::Handle, OpenDir>,
+    pub(crate) next_handle: u32,
+}
+
+impl FsState {
+    pub(crate) fn sdmc_mut(&mut self) -> &mut Tree {
+        self.trees
+            .entry((ctru_sys::ARCHIVE_SDMC, Vec::new()))
+            .or_default()
+    }
+
+    pub(crate) fn next_handle(&mut self) -> u32 {
+        self.next_handle += 1;
+        self.next_handle
+    }
+}
+
+/// A formatted save data archive.
+pub(crate) struct SaveData {
+    pub(crate) max_directories: u32,
+    pub(crate) max_files: u32,
+    pub(crate) duplicate_data: bool,
+    /// Contents as of the last commit, restored when the archive gets closed if `duplicate_data` is set.
+    pub(crate) committed: Tree,
+}
+
+pub(crate) struct OpenFile {
+    pub(crate) archive: ArchiveKey,
+    pub(crate) path: String,
+    pub(crate) flags: u32,
+}
+
+pub(crate) struct OpenDir {
+    pub(crate) entries: VecDeque<ctru_sys::FS_DirectoryEntry>,
+}
+
+/// Contents of an archive, indexed by normalized absolute path.
+#[derive(Clone)]
+pub(crate) struct Tree {
+    pub(crate) nodes: BTreeMap<String, Node>,
+}
+
+impl Default for Tree {
+    fn default() -> Self {
+        let mut nodes = BTreeMap::new();
+        nodes.insert(String::from("/"), Node::Dir);
+
+        Self { nodes }
+    }
+}
+
+impl Tree {
+    pub(crate) fn create_parents(&mut self, path: &str) {
+        let parent = match path.rsplit_once('/') {
+            Some((parent, _)) => parent,
+            None => return,
+        };
+        let mut current = String::new();
+
+        for component in parent.split('/').filter(|c| !c.is_empty()) {
+            current.push('/');
+            current.push_str(component);
+            self.nodes.entry(current.clone()).or_insert(Node::Dir);
+        }
+    }
+
+    /// Returns the paths of the direct children of a directory.
+    pub(crate) fn children(&self, path: &str) -> Vec<String> {
+        let prefix = if path == "/" {
+            String::from("/")
+        } else {
+            format!("{path}/")
+        };
+
+        self.nodes
+            .range(prefix.clone()..)
+            .take_while(|(child, _)| child.starts_with(&prefix))
+            .filter(|(child, _)| !child[prefix.len()..].contains('/') && child.len() > prefix.len())
+            .map(|(child, _)| child.clone())
+            .collect()
+    }
+
+    /// Returns the total size of the files in the tree.
+    pub(crate) fn used_bytes(&self) -> u64 {
+        self.nodes
+            .values()
+            .map(|node| match node {
+                Node::File { data, .. } => data.len() as u64,
+                Node::Dir => 0,
+            })
+            .sum()
+    }
+
+    /// Returns the paths of a node and all of its descendants.
+    pub(crate) fn subtree(&self, path: &str) -> Vec<String> {
+        let prefix = format!("{path}/");
+
+        self.nodes
+            .keys()
+            .filter(|node| *node == path || node.starts_with(&prefix))
+            .cloned()
+            .collect()
+    }
+}
+
+#[derive(Clone)]
+pub(crate) enum Node {
+    Dir,
+    File {
+        data: Vec<u8>,
+        attributes: u32,
+        modified: SystemTime,
+    },
+}
+
+impl Node {
+    pub(crate) fn file(data: Vec<u8>, modified: SystemTime) -> Self {
+        Node::File {
+            data,
+            attributes: 0,
+            modified,
+        }
+    }
+}
+
+/// Turns any path into an absolute path with `/` separators and no trailing slash.
+pub(crate) fn normalize(path: &str) -> String {
+    let mut normalized = String::new();
+
+    for component in path
+        .split(['/', '\\'])
+        .filter(|c| !c.is_empty() && *c != ".")
+    {
+        normalized.push('/');
+        normalized.push_str(component);
+    }
+
+    if normalized.is_empty() {
+        normalized.push('/');
+    }
+
+    normalized
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::services::cfgu::Cfgu;
+    use crate::services::fs::{self, File, Fs, OpenOptions};
+    use crate::services::hid::{CirclePosition, Hid, KeyPad, TouchPosition};
+    use std::io::{Read, Write};
+
+    #[test]
+    fn scripted_input() {
+        queue_inputs([
+            InputFrame::pressed(KeyPad::KEY_A),
+            InputFrame::pressed(KeyPad::KEY_A | KeyPad::KEY_B),
+            InputFrame::touch(120, 80),
+        ]);
+
+        let hid = Hid::init().unwrap();
+        let start = now();
+
+        hid.scan_input();
+        assert_eq!(hid.keys_down(), KeyPad::KEY_A);
+
+        hid.scan_input();
+        assert_eq!(hid.keys_down(), KeyPad::KEY_B);
+        assert_eq!(hid.keys_held(), KeyPad::KEY_A | KeyPad::KEY_B);
+
+        hid.scan_input();
+        assert_eq!(hid.keys_up(), KeyPad::KEY_A | KeyPad::KEY_B);
+        assert_eq!(TouchPosition::new().get(), (120, 80));
+        assert_eq!(CirclePosition::new().get(), (0, 0));
+
+        assert_eq!(pending_inputs(), 0);
+        assert_eq!(now().duration_since(start).unwrap(), FRAME_DURATION * 3);
+    }
+
+    #[test]
+    fn motion_sensors() {
+        set_gyroscope(AngularRate {
+            roll: 0,
+            pitch: 0,
+            yaw: 1294,
+        });
+        set_volume(32);
+        set_slider_3d(0.5);
+
+        let mut hid = Hid::init().unwrap();
+        hid.scan_input();
+        assert_eq!(hid.gyroscope_rate(), AngularRate::default());
+
+        hid.enable_accelerometer().unwrap();
+        hid.enable_gyroscope().unwrap();
+        hid.scan_input();
+        assert_eq!(hid.accelerometer_vector().to_g(), [0.0, 0.0, -1.0]);
+        let coefficient = hid.gyroscope_coefficient().unwrap();
+        assert!((hid.gyroscope_rate().to_dps(coefficient)[2] - 90.0).abs() < 0.1);
+
+        assert_eq!(hid.volume().unwrap(), 32);
+        assert_eq!(hid.slider_3d(), 0.5);
+    }
+
+    #[test]
+    fn system_config() {
+        set_region(Region::Japan);
+        set_model(SystemModel::Model2DS);
+
+        let cfgu = Cfgu::init().unwrap();
+
+        assert!(matches!(cfgu.get_region().unwrap(), Region::Japan));
+        assert!(matches!(cfgu.get_model().unwrap(), SystemModel::Model2DS));
+        assert!(matches!(cfgu.get_language().unwrap(), Language::English));
+        assert!(cfgu.is_2ds_family().unwrap());
+    }
+
+    #[test]
+    fn sdmc_files() {
+        write_sdmc_file("/saves/slot1.bin", b"hello");
+
+        let fs = Fs::init().unwrap();
+        let sdmc = fs.sdmc().unwrap();
+
+        let mut contents = String::new();
+        File::open(&sdmc, "/saves/slot1.bin")
+            .unwrap()
+            .read_to_string(&mut contents)
+            .unwrap();
+        assert_eq!(contents, "hello");
+
+        let mut file = OpenOptions::new()
+            .append(true)
+            .archive(&sdmc)
+            .open("/saves/slot1.bin")
+            .unwrap();
+        file.write_all(b", world").unwrap();
+        drop(file);
+
+        assert_eq!(read_sdmc_file("/saves/slot1.bin").unwrap(), b"hello, world");
+        assert!(File::open(&sdmc, "/missing.bin").is_err());
+    }
+
+    #[test]
+    fn sdmc_directories() {
+        let fs = Fs::init().unwrap();
+        let sdmc = fs.sdmc().unwrap();
+
+        fs::create_dir_all(&sdmc, "/a/b").unwrap();
+        File::create(&sdmc, "/a/b/c.txt").unwrap();
+        File::create(&sdmc, "/a/d.txt").unwrap();
+
+        let mut names: Vec<_> = fs::read_dir(&sdmc, "/a")
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name())
+            .collect();
+        names.sort();
+        assert_eq!(names, ["b", "d.txt"]);
+
+        assert!(fs::metadata(&sdmc, "/a/b").unwrap().is_dir());
+        assert!(fs::remove_dir(&sdmc, "/a").is_err());
+
+        fs::rename(&sdmc, "/a", "/e").unwrap();
+        assert!(sdmc_exists("/e/b/c.txt"));
+
+        fs::remove_dir_all(&sdmc, "/e").unwrap();
+        assert!(!sdmc_exists("/e"));
+    }
+}
@@ -0,0 +1,1114 @@
+//! Replacements for the `libctru` functions used by the mocked services.
+//!
+//! Mocked services import this module as `ctru_sys`, so the functions defined here shadow the real
+//! bindings while every type and constant is re-exported unchanged.
+//! Functions that are not replaced still resolve to `libctru` and can't be used on the host.
+
+#![allow(non_snake_case)]
+#![allow(clippy::missing_safety_doc)]
+
+pub use ctru_sys::*;
+
+use std::ffi::CStr;
+use std::mem;
+use std::slice;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use super::{
+    normalize, with_console, ArchiveKey, Node, OpenDir, OpenFile, SaveData, Tree, FRAME_DURATION,
+};
+
+fn fs_error(level: u32, summary: u32, description: u32) -> Result {
+    MAKERESULT(
+        level as Result,
+        summary as Result,
+        RM_FS as Result,
+        description as Result,
+    )
+}
+
+fn not_found() -> Result {
+    fs_error(RL_STATUS, RS_NOTFOUND, RD_NOT_FOUND)
+}
+
+fn already_exists() -> Result {
+    fs_error(RL_STATUS, RS_NOTSUPPORTED, RD_ALREADY_EXISTS)
+}
+
+fn not_empty() -> Result {
+    fs_error(RL_STATUS, RS_INVALIDSTATE, RD_INVALID_SELECTION)
+}
+
+fn invalid_handle() -> Result {
+    fs_error(RL_PERMANENT, RS_WRONGARG, RD_INVALID_HANDLE)
+}
+
+fn invalid_argument() -> Result {
+    fs_error(RL_USAGE, RS_INVALIDARG, RD_INVALID_COMBINATION)
+}
+
+fn not_authorized() -> Result {
+    fs_error(RL_PERMANENT, RS_INVALIDARG, RD_NOT_AUTHORIZED)
+}
+
+fn not_supported() -> Result {
+    fs_error(RL_PERMANENT, RS_NOTSUPPORTED, RD_NOT_IMPLEMENTED)
+}
+
+// HID
+
+pub unsafe fn hidInit() -> Result {
+    0
+}
+
+pub unsafe fn hidExit() {}
+
+pub unsafe fn hidScanInput() {
+    with_console(|console| {
+        let hid = &mut console.hid;
+        hid.previous = hid.current;
+        hid.current = hid.script.pop_front().unwrap_or_default();
+
+        console.clock += FRAME_DURATION;
+    });
+}
+
+pub unsafe fn hidKeysHeld() -> u32_ {
+    with_console(|console| console.hid.current.keys.bits())
+}
+
+pub unsafe fn hidKeysDown() -> u32_ {
+    with_console(|console| (console.hid.current.keys - console.hid.previous.keys).bits())
+}
+
+pub unsafe fn hidKeysUp() -> u32_ {
+    with_console(|console| (console.hid.previous.keys - console.hid.current.keys).bits())
+}
+
+pub unsafe fn hidTouchRead(pos: *mut touchPosition) {
+    let (px, py) = with_console(|console| {
+        let frame = console.hid.current;
+
+        if frame.keys.contains(crate::services::hid::KeyPad::KEY_TOUCH) {
+            frame.touch
+        } else {
+            (0, 0)
+        }
+    });
+
+    *pos = touchPosition { px, py };
+}
+
+pub unsafe fn hidCircleRead(pos: *mut circlePosition) {
+    let (dx, dy) = with_console(|console| console.hid.current.circle_pad);
+
+    *pos = circlePosition { dx, dy };
+}
+
+pub unsafe fn HIDUSER_EnableAccelerometer() -> Result {
+    with_console(|console| console.hid.accelerometer_enabled = true);
+    0
+}
+
+pub unsafe fn HIDUSER_DisableAccelerometer() -> Result {
+    with_console(|console| console.hid.accelerometer_enabled = false);
+    0
+}
+
+pub unsafe fn HIDUSER_EnableGyroscope() -> Result {
+    with_console(|console| console.hid.gyroscope_enabled = true);
+    0
+}
+
+pub unsafe fn HIDUSER_DisableGyroscope() -> Result {
+    with_console(|console| console.hid.gyroscope_enabled = false);
+    0
+}
+
+pub unsafe fn hidAccelRead(vector: *mut accelVector) {
+    let (x, y, z) = with_console(|console| {
+        let hid = &console.hid;
+
+        if hid.accelerometer_enabled {
+            (
+                hid.accelerometer.x,
+                hid.accelerometer.y,
+                hid.accelerometer.z,
+            )
+        } else {
+            (0, 0, 0)
+        }
+    });
+
+    *vector = accelVector { x, y, z };
+}
+
+pub unsafe fn hidGyroRead(rate: *mut angularRate) {
+    let (x, y, z) = with_console(|console| {
+        let hid = &console.hid;
+
+        if hid.gyroscope_enabled {
+            (hid.gyroscope.roll, hid.gyroscope.pitch, hid.gyroscope.yaw)
+        } else {
+            (0, 0, 0)
+        }
+    });
+
+    *rate = angularRate { x, z, y };
+}
+
+pub unsafe fn HIDUSER_GetGyroscopeRawToDpsCoefficient(coeff: *mut f32) -> Result {
+    // Sensitivity of the gyroscope used by retail units, in LSB per degree per second.
+    *coeff = 14.375;
+    0
+}
+
+pub unsafe fn HIDUSER_GetSoundVolume(volume: *mut u8_) -> Result {
+    *volume = with_console(|console| console.hid.volume);
+    0
+}
+
+pub unsafe fn osGet3DSliderState() -> f32 {
+    with_console(|console| console.hid.slider_3d)
+}
+
+// CFGU
+
+pub unsafe fn cfguInit() -> Result {
+    0
+}
+
+pub unsafe fn cfguExit() {}
+
+pub unsafe fn CFGU_SecureInfoGetRegion(region: *mut u8_) -> Result {
+    *region = with_console(|console| console.config.region);
+    0
+}
+
+pub unsafe fn CFGU_GetSystemModel(model: *mut u8_) -> Result {
+    *model = with_console(|console| console.config.model);
+    0
+}
+
+pub unsafe fn CFGU_GetSystemLanguage(language: *mut u8_) -> Result {
+    *language = with_console(|console| console.config.language);
+    0
+}
+
+pub unsafe fn CFGU_IsNFCSupported(is_supported: *mut bool) -> Result {
+    *is_supported = with_console(|console| console.config.nfc_supported);
+    0
+}
+
+pub unsafe fn CFGU_GetModelNintendo2DS(value: *mut u8_) -> Result {
+    let model = with_console(|console| console.config.model);
+
+    *value = u8::from(model as u32 != CFG_MODEL_2DS);
+    0
+}
+
+// FS
+
+pub unsafe fn fsInit() -> Result {
+    0
+}
+
+pub unsafe fn fsExit() {}
+
+pub unsafe fn fsMakePath(type_: FS_PathType, path: *const ::libc::c_void) -> FS_Path {
+    let size = match type_ {
+        PATH_EMPTY => {
+            return FS_Path {
+                type_,
+                size: 1,
+                data: b"\0".as_ptr().cast(),
+            }
+        }
+        PATH_ASCII => libc::strlen(path.cast()) + 1,
+        PATH_UTF16 => {
+            let units = path.cast::<u16>();
+            let mut len = 0;
+            while *units.add(len) != 0 {
+                len += 1;
+            }
+            (len + 1) * 2
+        }
+        _ => 0,
+    };
+
+    FS_Path {
+        type_,
+        size: size as u32,
+        data: path,
+    }
+}
+
+/// Decodes a textual `FS_Path` into a normalized path of the simulated filesystem.
+unsafe fn decode_path(path: &FS_Path) -> String {
+    match path.type_ {
+        PATH_UTF16 => {
+            let units = slice::from_raw_parts(path.data.cast::<u16>(), (path.size / 2) as usize);
+            let len = units.iter().position(|c| *c == 0).unwrap_or(units.len());
+            normalize(&String::from_utf16_lossy(&units[..len]))
+        }
+        PATH_ASCII => normalize(&CStr::from_ptr(path.data.cast()).to_string_lossy()),
+        _ => normalize(""),
+    }
+}
+
+fn parent_of(path: &str) -> &str {
+    match path.rsplit_once('/') {
+        Some(("", _)) | None => "/",
+        Some((parent, _)) => parent,
+    }
+}
+
+fn is_dir(tree: &Tree, path: &str) -> bool {
+    matches!(tree.nodes.get(path), Some(Node::Dir))
+}
+
+/// Runs a closure over the tree of an open archive.
+fn with_tree(archive: FS_Archive, f: impl FnOnce(&mut Tree, SystemTime) -> Result) -> Result {
+    with_console(|console| {
+        let clock = console.clock;
+        let fs = &mut console.fs;
+
+        match fs.archives.get(&archive) {
+            Some(key) => f(fs.trees.entry(key.clone()).or_default(), clock),
+            None => invalid_handle(),
+        }
+    })
+}
+
+/// Runs a closure over the contents of an open file.
+fn with_file(
+    handle: Handle,
+    f: impl FnOnce(&OpenFile, &mut Vec<u8>, &mut SystemTime, SystemTime) -> Result,
+) -> Result {
+    with_console(|console| {
+        let clock = console.clock;
+        let fs = &mut console.fs;
+
+        let file = match fs.files.get(&handle) {
+            Some(file) => file,
+            None => return invalid_handle(),
+        };
+
+        match fs
+            .trees
+            .get_mut(&file.archive)
+            .and_then(|tree| tree.nodes.get_mut(&file.path))
+        {
+            Some(Node::File { data, modified, .. }) => f(file, data, modified, clock),
+            // The file was removed while it was still open.
+            _ => not_found(),
+        }
+    })
+}
+
+/// Runs a closure over the attributes of an open file.
+fn with_file_attributes(handle: Handle, f: impl FnOnce(&mut u32) -> Result) -> Result {
+    with_console(|console| {
+        let fs = &mut console.fs;
+
+        let file = match fs.files.get(&handle) {
+            Some(file) => file,
+            None => return invalid_handle(),
+        };
+
+        match fs
+            .trees
+            .get_mut(&file.archive)
+            .and_then(|tree| tree.nodes.get_mut(&file.path))
+        {
+            Some(Node::File { attributes, .. }) => f(attributes),
+            _ => not_found(),
+        }
+    })
+}
+
+/// Capacity reported for the SD card and the extra data archives.
+const SDMC_CAPACITY: u64 = 2 << 30;
+
+/// Capacity reported for save data archives.
+const SAVE_DATA_CAPACITY: u64 = 512 << 10;
+
+fn is_save_data(id: FS_ArchiveID) -> bool {
+    matches!(
+        id,
+        ARCHIVE_SAVEDATA | ARCHIVE_USER_SAVEDATA | ARCHIVE_SYSTEM_SAVEDATA
+    )
+}
+
+/// Decodes the path of an archive, which is either empty or binary.
+unsafe fn archive_key(id: FS_ArchiveID, path: &FS_Path) -> ArchiveKey {
+    let data = match path.type_ {
+        PATH_BINARY => slice::from_raw_parts(path.data.cast::<u8>(), path.size as usize).to_vec(),
+        _ => Vec::new(),
+    };
+
+    (id, data)
+}
+
+pub unsafe fn FSUSER_OpenArchive(
+    archive: *mut FS_Archive,
+    id: FS_ArchiveID,
+    path: FS_Path,
+) -> Result {
+    let key = archive_key(id, &path);
+
+    with_console(|console| {
+        match id {
+            // Extra data archives spring into existence when first opened.
+            ARCHIVE_SDMC | ARCHIVE_EXTDATA | ARCHIVE_SHARED_EXTDATA | ARCHIVE_BOSS_EXTDATA => (),
+            _ if is_save_data(id) => {
+                if !console.fs.saves.contains_key(&key) {
+                    return not_found();
+                }
+            }
+            _ => return not_supported(),
+        }
+
+        let handle = console.fs.next_handle() as FS_Archive;
+        console.fs.trees.entry(key.clone()).or_default();
+        console.fs.archives.insert(handle, key);
+
+        *archive = handle;
+        0
+    })
+}
+
+pub unsafe fn FSUSER_CloseArchive(archive: FS_Archive) -> Result {
+    with_console(|console| {
+        let fs = &mut console.fs;
+
+        let key = match fs.archives.remove(&archive) {
+            Some(key) => key,
+            None => return invalid_handle(),
+        };
+
+        // Roll back the changes that weren't committed, once the last handle is closed.
+        if let Some(save) = fs.saves.get(&key) {
+            if save.duplicate_data && !fs.archives.values().any(|other| *other == key) {
+                fs.trees.insert(key, save.committed.clone());
+            }
+        }
+
+        0
+    })
+}
+
+pub unsafe fn FSUSER_ControlArchive(
+    archive: FS_Archive,
+    action: FS_ArchiveAction,
+    input: *mut ::libc::c_void,
+    inputSize: u32_,
+    output: *mut ::libc::c_void,
+    outputSize: u32_,
+) -> Result {
+    with_console(|console| {
+        let fs = &mut console.fs;
+
+        let key = match fs.archives.get(&archive) {
+            Some(key) => key,
+            None => return invalid_handle(),
+        };
+
+        match (action, fs.saves.get_mut(key)) {
+            (ARCHIVE_ACTION_COMMIT_SAVE_DATA, Some(save)) => {
+                save.committed = fs.trees[key].clone();
+                0
+            }
+            // Only the SD card keeps track of modification times.
+            (ARCHIVE_ACTION_GET_TIMESTAMP, None) if key.0 == ARCHIVE_SDMC => {
+                if (outputSize as usize) < mem::size_of::<u64>() {
+                    return invalid_argument();
+                }
+
+                let path = decode_path(&FS_Path {
+                    type_: PATH_UTF16,
+                    size: inputSize,
+                    data: input as *const _,
+                });
+                match fs.trees[key].nodes.get(&path) {
+                    Some(Node::File { modified, .. }) => {
+                        // Timestamps are counted in milliseconds since 2000-01-01.
+                        let epoch = UNIX_EPOCH + Duration::from_secs(946_684_800);
+                        let millis = modified
+                            .duration_since(epoch)
+                            .unwrap_or_default()
+                            .as_millis();
+                        output.cast::<u64>().write_unaligned(millis as u64);
+                        0
+                    }
+                    Some(Node::Dir) => not_supported(),
+                    None => not_found(),
+                }
+            }
+            _ => not_supported(),
+        }
+    })
+}
+
+#[allow(clippy::too_many_arguments)]
+pub unsafe fn FSUSER_FormatSaveData(
+    archiveId: FS_ArchiveID,
+    path: FS_Path,
+    _blocks: u32_,
+    directories: u32_,
+    files: u32_,
+    _directoryBuckets: u32_,
+    _fileBuckets: u32_,
+    duplicateData: bool,
+) -> Result {
+    if !is_save_data(archiveId) {
+        return not_supported();
+    }
+
+    let key = archive_key(archiveId, &path);
+
+    with_console(|console| {
+        let fs = &mut console.fs;
+
+        fs.trees.insert(key.clone(), Tree::default());
+        fs.saves.insert(
+            key,
+            SaveData {
+                max_directories: directories,
+                max_files: files,
+                duplicate_data: duplicateData,
+                committed: Tree::default(),
+            },
+        );
+
+        0
+    })
+}
+
+pub unsafe fn FSUSER_GetFormatInfo(
+    totalSize: *mut u32_,
+    directories: *mut u32_,
+    files: *mut u32_,
+    duplicateData: *mut bool,
+    archiveId: FS_ArchiveID,
+    path: FS_Path,
+) -> Result {
+    let key = archive_key(archiveId, &path);
+
+    with_console(|console| match console.fs.saves.get(&key) {
+        Some(save) => {
+            *totalSize = SAVE_DATA_CAPACITY as u32;
+            *directories = save.max_directories;
+            *files = save.max_files;
+            *duplicateData = save.duplicate_data;
+            0
+        }
+        None => not_found(),
+    })
+}
+
+pub unsafe fn FSUSER_GetFreeBytes(freeBytes: *mut u64_, archive: FS_Archive) -> Result {
+    with_console(|console| {
+        let fs = &console.fs;
+
+        let key = match fs.archives.get(&archive) {
+            Some(key) => key,
+            None => return invalid_handle(),
+        };
+        let capacity = if is_save_data(key.0) {
+            SAVE_DATA_CAPACITY
+        } else {
+            SDMC_CAPACITY
+        };
+
+        *freeBytes = capacity.saturating_sub(fs.trees[key].used_bytes());
+        0
+    })
+}
+
+pub unsafe fn FSUSER_OpenFile(
+    out: *mut Handle,
+    archive: FS_Archive,
+    path: FS_Path,
+    openFlags: u32_,
+    _attributes: u32_,
+) -> Result {
+    let path = decode_path(&path);
+
+    if openFlags & (FS_OPEN_READ | FS_OPEN_WRITE) == 0 {
+        return invalid_argument();
+    }
+
+    with_console(|console| {
+        let clock = console.clock;
+        let fs = &mut console.fs;
+
+        let key = match fs.archives.get(&archive) {
+            Some(key) => key.clone(),
+            None => return invalid_handle(),
+        };
+        let tree = fs.trees.entry(key.clone()).or_default();
+
+        match tree.nodes.get(&path) {
+            Some(Node::File { .. }) => (),
+            Some(Node::Dir) => return not_found(),
+            None if openFlags & FS_OPEN_CREATE != 0 && is_dir(tree, parent_of(&path)) => {
+                tree.nodes
+                    .insert(path.clone(), Node::file(Vec::new(), clock));
+            }
+            None => return not_found(),
+        }
+
+        let handle = fs.next_handle();
+        fs.files.insert(
+            handle,
+            OpenFile {
+                archive: key,
+                path,
+                flags: openFlags,
+            },
+        );
+
+        *out = handle;
+        0
+    })
+}
+
+pub unsafe fn FSFILE_Close(handle: Handle) -> Result {
+    with_console(|console| match console.fs.files.remove(&handle) {
+        Some(_) => 0,
+        None => invalid_handle(),
+    })
+}
+
+pub unsafe fn FSFILE_GetSize(handle: Handle, size: *mut u64_) -> Result {
+    with_file(handle, |_, data, _, _| {
+        *size = data.len() as u64;
+        0
+    })
+}
+
+pub unsafe fn FSFILE_Flush(handle: Handle) -> Result {
+    with_file(handle, |_, _, _, _| 0)
+}
+
+pub unsafe fn FSFILE_GetAttributes(handle: Handle, attributes: *mut u32_) -> Result {
+    with_file_attributes(handle, |file_attributes| {
+        *attributes = *file_attributes;
+        0
+    })
+}
+
+pub unsafe fn FSFILE_SetAttributes(handle: Handle, attributes: u32_) -> Result {
+    with_file_attributes(handle, |file_attributes| {
+        *file_attributes = attributes & !FS_ATTRIBUTE_DIRECTORY;
+        0
+    })
+}
+
+pub unsafe fn FSFILE_SetSize(handle: Handle, size: u64_) -> Result {
+    with_file(handle, |file, data, modified, clock| {
+        if file.flags & FS_OPEN_WRITE == 0 {
+            return not_authorized();
+        }
+
+        data.resize(size as usize, 0);
+        *modified = clock;
+        0
+    })
+}
+
+pub unsafe fn FSFILE_Read(
+    handle: Handle,
+    bytesRead: *mut u32_,
+    offset: u64_,
+    buffer: *mut ::libc::c_void,
+    size: u32_,
+) -> Result {
+    with_file(handle, |file, data, _, _| {
+        if file.flags & FS_OPEN_READ == 0 {
+            return not_authorized();
+        }
+
+        let start = data.len().min(offset as usize);
+        let len = (data.len() - start).min(size as usize);
+        let buffer = slice::from_raw_parts_mut(buffer.cast::<u8>(), len);
+        buffer.copy_from_slice(&data[start..start + len]);
+
+        *bytesRead = len as u32;
+        0
+    })
+}
+
+pub unsafe fn FSFILE_Write(
+    handle: Handle,
+    bytesWritten: *mut u32_,
+    offset: u64_,
+    buffer: *const ::libc::c_void,
+    size: u32_,
+    flags: u32_,
+) -> Result {
+    with_file(handle, |file, data, modified, clock| {
+        if file.flags & FS_OPEN_WRITE == 0 {
+            return not_authorized();
+        }
+
+        let start = offset as usize;
+        let end = start + size as usize;
+        if data.len() < end {
+            data.resize(end, 0);
+        }
+        data[start..end].copy_from_slice(slice::from_raw_parts(buffer.cast::<u8>(), size as usize));
+
+        if flags & FS_WRITE_UPDATE_TIME != 0 {
+            *modified = clock;
+        }
+
+        *bytesWritten = size;
+        0
+    })
+}
+
+pub unsafe fn FSUSER_DeleteFile(archive: FS_Archive, path: FS_Path) -> Result {
+    let path = decode_path(&path);
+
+    with_tree(archive, |tree, _| match tree.nodes.get(&path) {
+        Some(Node::File { .. }) => {
+            tree.nodes.remove(&path);
+            0
+        }
+        _ => not_found(),
+    })
+}
+
+pub unsafe fn FSUSER_RenameFile(
+    srcArchive: FS_Archive,
+    srcPath: FS_Path,
+    dstArchive: FS_Archive,
+    dstPath: FS_Path,
+) -> Result {
+    let from = decode_path(&srcPath);
+    let to = decode_path(&dstPath);
+
+    if srcArchive != dstArchive {
+        return not_supported();
+    }
+
+    with_tree(srcArchive, |tree, _| {
+        if !matches!(tree.nodes.get(&from), Some(Node::File { .. })) {
+            return not_found();
+        }
+        if tree.nodes.contains_key(&to) {
+            return already_exists();
+        }
+        if !is_dir(tree, parent_of(&to)) {
+            return not_found();
+        }
+
+        let node = tree.nodes.remove(&from).unwrap();
+        tree.nodes.insert(to, node);
+        0
+    })
+}
+
+pub unsafe fn FSUSER_CreateDirectory(
+    archive: FS_Archive,
+    path: FS_Path,
+    _attributes: u32_,
+) -> Result {
+    let path = decode_path(&path);
+
+    with_tree(archive, |tree, _| {
+        if tree.nodes.contains_key(&path) {
+            return already_exists();
+        }
+        if !is_dir(tree, parent_of(&path)) {
+            return not_found();
+        }
+
+        tree.nodes.insert(path, Node::Dir);
+        0
+    })
+}
+
+pub unsafe fn FSUSER_DeleteDirectory(archive: FS_Archive, path: FS_Path) -> Result {
+    let path = decode_path(&path);
+
+    with_tree(archive, |tree, _| {
+        if path == "/" || !is_dir(tree, &path) {
+            return not_found();
+        }
+        if !tree.children(&path).is_empty() {
+            return not_empty();
+        }
+
+        tree.nodes.remove(&path);
+        0
+    })
+}
+
+pub unsafe fn FSUSER_DeleteDirectoryRecursively(archive: FS_Archive, path: FS_Path) -> Result {
+    let path = decode_path(&path);
+
+    with_tree(archive, |tree, _| {
+        if path == "/" || !is_dir(tree, &path) {
+            return not_found();
+        }
+
+        for node in tree.subtree(&path) {
+            tree.nodes.remove(&node);
+        }
+        0
+    })
+}
+
+pub unsafe fn FSUSER_RenameDirectory(
+    srcArchive: FS_Archive,
+    srcPath: FS_Path,
+    dstArchive: FS_Archive,
+    dstPath: FS_Path,
+) -> Result {
+    let from = decode_path(&srcPath);
+    let to = decode_path(&dstPath);
+
+    if srcArchive != dstArchive {
+        return not_supported();
+    }
+
+    with_tree(srcArchive, |tree, _| {
+        if from == "/" || !is_dir(tree, &from) {
+            return not_found();
+        }
+        if tree.nodes.contains_key(&to) {
+            return already_exists();
+        }
+        if !is_dir(tree, parent_of(&to)) || to.starts_with(&format!("{from}/")) {
+            return not_found();
+        }
+
+        for old in tree.subtree(&from) {
+            let node = tree.nodes.remove(&old).unwrap();
+            tree.nodes
+                .insert(format!("{to}{}", &old[from.len()..]), node);
+        }
+        0
+    })
+}
+
+pub unsafe fn FSUSER_OpenDirectory(out: *mut Handle, archive: FS_Archive, path: FS_Path) -> Result {
+    let path = decode_path(&path);
+
+    with_console(|console| {
+        let fs = &mut console.fs;
+
+        let tree = match fs.archives.get(&archive) {
+            Some(key) => fs.trees.entry(key.clone()).or_default(),
+            None => return invalid_handle(),
+        };
+        if !is_dir(tree, &path) {
+            return not_found();
+        }
+
+        let entries = tree
+            .children(&path)
+            .into_iter()
+            .map(|child| directory_entry(&child, &tree.nodes[&child]))
+            .collect();
+
+        let handle = fs.next_handle();
+        fs.dirs.insert(handle, OpenDir { entries });
+
+        *out = handle;
+        0
+    })
+}
+
+fn directory_entry(path: &str, node: &Node) -> FS_DirectoryEntry {
+    let mut entry = FS_DirectoryEntry {
+        valid: 1,
+        ..Default::default()
+    };
+
+    let name = path.rsplit('/').next().unwrap_or_default();
+    // Leave room for the NUL terminator.
+    let max_len = entry.name.len() - 1;
+    for (dst, src) in entry.name[..max_len].iter_mut().zip(name.encode_utf16()) {
+        *dst = src;
+    }
+
+    match node {
+        Node::Dir => entry.attributes = FS_ATTRIBUTE_DIRECTORY,
+        Node::File {
+            data, attributes, ..
+        } => {
+            entry.attributes = *attributes;
+            entry.fileSize = data.len() as u64;
+        }
+    }
+
+    entry
+}
+
+pub unsafe fn FSDIR_Read(
+    handle: Handle,
+    entriesRead: *mut u32_,
+    entryCount: u32_,
+    entries: *mut FS_DirectoryEntry,
+) -> Result {
+    with_console(|console| {
+        let dir = match console.fs.dirs.get_mut(&handle) {
+            Some(dir) => dir,
+            None => return invalid_handle(),
+        };
+
+        let mut read = 0;
+        while read < entryCount {
+            match dir.entries.pop_front() {
+                Some(entry) => *entries.add(read as usize) = entry,
+                None => break,
+            }
+            read += 1;
+        }
+
+        *entriesRead = read;
+        0
+    })
+}
+
+pub unsafe fn FSDIR_Close(handle: Handle) -> Result {
+    with_console(|console| match console.fs.dirs.remove(&handle) {
+        Some(_) => 0,
+        None => invalid_handle(),
+    })
+}
+
+// Decompression
+
+/// Input of the decoders, fetched in chunks from the callback like `libctru` does, or from memory.
+struct DecompressInput {
+    callback: decompressCallback,
+    userdata: *mut ::libc::c_void,
+    insize: usize,
+    buf: Vec<u8>,
+    pos: usize,
+}
+
+impl DecompressInput {
+    unsafe fn byte(&mut self) -> Option<u8> {
+        if self.pos == self.buf.len() {
+            match self.callback {
+                Some(callback) => {
+                    self.buf.resize(1024, 0);
+                    let read = callback(self.userdata, self.buf.as_mut_ptr().cast(), 1024);
+                    self.buf
+                        .truncate(usize::try_from(read).ok().filter(|&n| n > 0)?);
+                }
+                None if self.insize > 0 => {
+                    self.buf = slice::from_raw_parts(self.userdata.cast(), self.insize).to_vec();
+                    self.insize = 0;
+                }
+                None => return None,
+            }
+            self.pos = 0;
+        }
+
+        self.pos += 1;
+        Some(self.buf[self.pos - 1])
+    }
+}
+
+/// Runs a decoder over the whole output vector.
+unsafe fn decompress_with(
+    iov: *const decompressIOVec,
+    iovcnt: usize,
+    callback: decompressCallback,
+    userdata: *mut ::libc::c_void,
+    insize: usize,
+    decode: impl FnOnce(&mut DecompressInput, &mut [u8]) -> Option<()>,
+) -> bool {
+    let iov = slice::from_raw_parts(iov, iovcnt);
+    let mut out = vec![0; iov.iter().map(|iov| iov.size).sum()];
+    let mut input = DecompressInput {
+        callback,
+        userdata,
+        insize,
+        buf: Vec::new(),
+        pos: 0,
+    };
+    if decode(&mut input, &mut out).is_none() {
+        return false;
+    }
+
+    let mut data = &out[..];
+    for iov in iov {
+        let (head, tail) = data.split_at(iov.size);
+        slice::from_raw_parts_mut(iov.data.cast(), iov.size).copy_from_slice(head);
+        data = tail;
+    }
+    true
+}
+
+/// Copies an earlier run of the output, which may overlap with what it produces.
+fn copy_match(out: &mut [u8], pos: &mut usize, len: usize, distance: usize) -> Option<()> {
+    let from = pos.checked_sub(distance)?;
+    for i in 0..len.min(out.len() - *pos) {
+        out[*pos + i] = out[from + i];
+    }
+    *pos += len.min(out.len() - *pos);
+    Some(())
+}
+
+/// Decodes LZSS blocks, with tokens for matches read by `read_match`.
+unsafe fn decode_lz(
+    input: &mut DecompressInput,
+    out: &mut [u8],
+    read_match: fn(&mut DecompressInput) -> Option<(usize, usize)>,
+) -> Option<()> {
+    let mut pos = 0;
+    while pos < out.len() {
+        let flags = input.byte()?;
+        for bit in (0..8).rev() {
+            if pos == out.len() {
+                break;
+            }
+
+            if flags >> bit & 1 == 0 {
+                out[pos] = input.byte()?;
+                pos += 1;
+            } else {
+                let (len, distance) = read_match(input)?;
+                copy_match(out, &mut pos, len, distance)?;
+            }
+        }
+    }
+    Some(())
+}
+
+pub unsafe fn decompressV_LZSS(
+    iov: *const decompressIOVec,
+    iovcnt: usize,
+    callback: decompressCallback,
+    userdata: *mut ::libc::c_void,
+    insize: usize,
+) -> bool {
+    decompress_with(iov, iovcnt, callback, userdata, insize, |input, out| {
+        decode_lz(input, out, |input| {
+            let (b0, b1) = (input.byte()? as usize, input.byte()? as usize);
+            Some(((b0 >> 4) + 3, ((b0 & 0xf) << 8 | b1) + 1))
+        })
+    })
+}
+
+pub unsafe fn decompressV_LZ11(
+    iov: *const decompressIOVec,
+    iovcnt: usize,
+    callback: decompressCallback,
+    userdata: *mut ::libc::c_void,
+    insize: usize,
+) -> bool {
+    decompress_with(iov, iovcnt, callback, userdata, insize, |input, out| {
+        decode_lz(input, out, |input| {
+            let b0 = input.byte()? as usize;
+            // The high nibble of the distance follows the length, whose size depends on its
+            // first nibble.
+            let (len, high) = match b0 >> 4 {
+                0 => {
+                    let b1 = input.byte()? as usize;
+                    (((b0 & 0xf) << 4 | b1 >> 4) + 0x11, b1 & 0xf)
+                }
+                1 => {
+                    let b1 = input.byte()? as usize;
+                    let b2 = input.byte()? as usize;
+                    (((b0 & 0xf) << 12 | b1 << 4 | b2 >> 4) + 0x111, b2 & 0xf)
+                }
+                len => (len + 1, b0 & 0xf),
+            };
+            let low = input.byte()? as usize;
+            Some((len, (high << 8 | low) + 1))
+        })
+    })
+}
+
+pub unsafe fn decompressV_Huff(
+    bits: usize,
+    iov: *const decompressIOVec,
+    iovcnt: usize,
+    callback: decompressCallback,
+    userdata: *mut ::libc::c_void,
+    insize: usize,
+) -> bool {
+    if bits == 0 || 8 % bits != 0 {
+        return false;
+    }
+
+    decompress_with(iov, iovcnt, callback, userdata, insize, |input, out| {
+        let size = input.byte()?;
+        let mut tree = vec![size];
+        for _ in 1..(size as usize + 1) * 2 {
+            tree.push(input.byte()?);
+        }
+
+        let (mut word, mut used) = (0u32, 32);
+        let (mut unit, mut unit_bits) = (0u8, 0);
+        let mut node = 1;
+        let mut pos = 0;
+        while pos < out.len() {
+            if used == 32 {
+                word = u32::from_le_bytes([
+                    input.byte()?,
+                    input.byte()?,
+                    input.byte()?,
+                    input.byte()?,
+                ]);
+                used = 0;
+            }
+            let one = word >> (31 - used) & 1 == 1;
+            used += 1;
+
+            let value = *tree.get(node)?;
+            let child = (node & !1) + (value & 0x3f) as usize * 2 + 2 + one as usize;
+            let is_leaf = value & if one { 0x40 } else { 0x80 } != 0;
+            if !is_leaf {
+                node = child;
+                continue;
+            }
+
+            unit |= (tree.get(child)? & ((1 << bits) - 1) as u8) << unit_bits;
+            unit_bits += bits;
+            if unit_bits == 8 {
+                out[pos] = unit;
+                pos += 1;
+                (unit, unit_bits) = (0, 0);
+            }
+            node = 1;
+        }
+        Some(())
+    })
+}
+
+pub unsafe fn decompressV_RLE(
+    iov: *const decompressIOVec,
+    iovcnt: usize,
+    callback: decompressCallback,
+    userdata: *mut ::libc::c_void,
+    insize: usize,
+) -> bool {
+    decompress_with(iov, iovcnt, callback, userdata, insize, |input, out| {
+        let mut pos = 0;
+        while pos < out.len() {
+            let flag = input.byte()? as usize;
+            if flag & 0x80 != 0 {
+                let byte = input.byte()?;
+                let len = ((flag & 0x7f) + 3).min(out.len() - pos);
+                out[pos..pos + len].fill(byte);
+                pos += len;
+            } else {
+                for _ in 0..((flag & 0x7f) + 1).min(out.len() - pos) {
+                    out[pos] = input.byte()?;
+                    pos += 1;
+                }
+            }
+        }
+        Some(())
+    })
+}
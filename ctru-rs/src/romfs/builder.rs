@@ -0,0 +1,512 @@
+//! Creation of RomFS images
+//!
+//! [`RomFsBuilder`] lays out the level 3 of a RomFS image, which is what 3DSX files embed and what
+//! `romfsMountFromFile` expects. Names are stored in UTF-16 and looked up through hash tables sized
+//! like the official tools do, entries of a directory are sorted by name, and file data is aligned
+//! to 16 bytes.
+//!
+//! Nothing here depends on the 3DS, so images can be built on the host, for example from a build script
+//! with `ctru-rs` as a build dependency:
+//!
+//! ```no_run
+//! // build.rs
+//! use std::env;
+//! use std::path::PathBuf;
+//!
+//! let output = PathBuf::from(env::var("OUT_DIR").unwrap()).join("assets.romfs");
+//! ctru::romfs::builder::build_dir("assets", &output).unwrap();
+//! println!("cargo:rerun-if-changed=assets");
+//! ```
+//!
+//! The build script of this crate also builds the directory configured in
+//! `package.metadata.cargo-3ds.romfs_dir` into `romfs.bin` in its output directory.
+//!
+//! With the `romfs-builder` feature, `build_from_manifest` builds the directory configured in
+//! `package.metadata.cargo-3ds.romfs_dir` instead, which is the one `cargo-3ds` bundles into the 3DSX:
+//!
+//! ```ignore
+//! // build.rs
+//! use std::env;
+//! use std::path::PathBuf;
+//!
+//! let manifest_dir = env::var("CARGO_MANIFEST_DIR").unwrap();
+//! let output = PathBuf::from(env::var("OUT_DIR").unwrap()).join("romfs.bin");
+//! ctru::romfs::builder::build_from_manifest(&manifest_dir, &output).unwrap();
+//! println!("cargo:rerun-if-changed={manifest_dir}");
+//! ```
+
+use std::collections::{BTreeMap, VecDeque};
+use std::fs;
+use std::io::{self, Read, Write};
+use std::path::{Component, Path, PathBuf};
+
+use super::format::{
+    bucket_count, encode_name, hash, padded_len, DATA_ALIGNMENT, DIR_ENTRY_SIZE, FILE_ENTRY_SIZE,
+    HEADER_SIZE, NONE,
+};
+
+/// Builder of a RomFS image from files in memory and on the host's filesystem.
+///
+/// # Examples
+///
+/// ```
+/// use ctru::romfs::builder::RomFsBuilder;
+/// use ctru::romfs::Pack;
+///
+/// let mut builder = RomFsBuilder::new();
+/// builder.add_file("/levels/1.map", b"#..#".to_vec());
+///
+/// let mut image = Vec::new();
+/// builder.write(&mut image).unwrap();
+///
+/// let pack = Pack::from_bytes(&image).unwrap();
+/// let file = pack.file("/levels/1.map").unwrap();
+/// assert_eq!(pack.bytes(file).unwrap(), b"#..#");
+/// ```
+#[derive(Default)]
+pub struct RomFsBuilder {
+    root: Dir,
+}
+
+#[derive(Default)]
+struct Dir {
+    dirs: BTreeMap<String, Dir>,
+    files: BTreeMap<String, Source>,
+}
+
+enum Source {
+    Data(Vec<u8>),
+    Path(PathBuf),
+}
+
+/// Builds the contents of the `source` directory into a RomFS image at `output`.
+///
+/// # Errors
+///
+/// This function will return an error if `source` can't be read, or `output` can't be written.
+pub fn build_dir<P: AsRef<Path>, Q: AsRef<Path>>(source: P, output: Q) -> io::Result<()> {
+    let mut builder = RomFsBuilder::new();
+    builder.add_dir_all("/", source)?;
+
+    let mut output = io::BufWriter::new(fs::File::create(output)?);
+    builder.write(&mut output)?;
+    output.flush()
+}
+
+#[cfg(feature = "romfs-builder")]
+/// Builds the RomFS directory configured by the package in `manifest_dir` into an image at `output`.
+///
+/// The directory is set by `package.metadata.cargo-3ds.romfs_dir` in its `Cargo.toml`, relative to
+/// `manifest_dir`, and defaults to `romfs`. Returns whether the directory exists: nothing is written
+/// if it doesn't.
+///
+/// # Errors
+///
+/// This function will return an error if the manifest can't be read or parsed, the directory can't be
+/// read, or `output` can't be written.
+pub fn build_from_manifest<P: AsRef<Path>, Q: AsRef<Path>>(
+    manifest_dir: P,
+    output: Q,
+) -> io::Result<bool> {
+    let source = manifest_romfs_dir(manifest_dir.as_ref())?;
+    if !source.exists() {
+        return Ok(false);
+    }
+
+    build_dir(source, output)?;
+    Ok(true)
+}
+
+#[cfg(feature = "romfs-builder")]
+/// Returns the RomFS directory configured by the package in `manifest_dir`.
+fn manifest_romfs_dir(manifest_dir: &Path) -> io::Result<PathBuf> {
+    let manifest = fs::read_to_string(manifest_dir.join("Cargo.toml"))?;
+    let manifest: toml::Value =
+        toml::de::from_str(&manifest).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+
+    let romfs_dir = manifest
+        .get("package")
+        .and_then(|package| package.get("metadata"))
+        .and_then(|metadata| metadata.get("cargo-3ds"))
+        .and_then(|cargo_3ds| cargo_3ds.get("romfs_dir"))
+        .and_then(toml::Value::as_str)
+        .unwrap_or("romfs");
+    Ok(manifest_dir.join(romfs_dir))
+}
+
+impl RomFsBuilder {
+    /// Creates a builder of an empty image.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a file with the given contents, creating its parent directories.
+    ///
+    /// A file that was already added at the same path is replaced.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `path` doesn't end with a file name, or goes through a file added previously.
+    pub fn add_file<P: AsRef<Path>>(&mut self, path: P, data: Vec<u8>) -> &mut Self {
+        self.insert(path.as_ref(), Source::Data(data));
+        self
+    }
+
+    /// Adds an empty directory, creating its parent directories.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `path` goes through a file added previously.
+    pub fn add_dir<P: AsRef<Path>>(&mut self, path: P) -> &mut Self {
+        self.dir_mut(&components(path.as_ref()));
+        self
+    }
+
+    /// Recursively adds the contents of a directory of the host at `path` in the image.
+    ///
+    /// Files are only read when the image is written.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if `source` or one of its subdirectories can't be listed,
+    /// or holds a name which isn't valid UTF-8.
+    pub fn add_dir_all<P: AsRef<Path>, Q: AsRef<Path>>(
+        &mut self,
+        path: P,
+        source: Q,
+    ) -> io::Result<&mut Self> {
+        let path = path.as_ref();
+        self.add_dir(path);
+
+        for entry in fs::read_dir(source)? {
+            let entry = entry?;
+            let name = entry.file_name().into_string().map_err(|name| {
+                io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("{name:?} isn't valid UTF-8"),
+                )
+            })?;
+
+            if entry.file_type()?.is_dir() {
+                self.add_dir_all(path.join(name), entry.path())?;
+            } else {
+                self.insert(&path.join(name), Source::Path(entry.path()));
+            }
+        }
+
+        Ok(self)
+    }
+
+    /// Writes the image.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if a file of the host can't be read or changed size since
+    /// it was added, or if writing fails.
+    pub fn write<W: Write>(&self, mut writer: W) -> io::Result<()> {
+        // Directories are numbered breadth-first, so the children of each one are contiguous.
+        let mut dirs: Vec<(usize, &str, &Dir)> = vec![(0, "", &self.root)];
+        let mut queue = VecDeque::from([0]);
+        while let Some(index) = queue.pop_front() {
+            let dir = dirs[index].2;
+            for (name, child) in &dir.dirs {
+                queue.push_back(dirs.len());
+                dirs.push((index, name, child));
+            }
+        }
+
+        let mut files: Vec<(usize, &str, &Source, u64)> = Vec::new();
+        for (index, (_, _, dir)) in dirs.iter().enumerate() {
+            for (name, source) in &dir.files {
+                let len = match source {
+                    Source::Data(data) => data.len() as u64,
+                    Source::Path(path) => fs::metadata(path)?.len(),
+                };
+                files.push((index, name, source, len));
+            }
+        }
+
+        // Offsets of every entry in its table, with one past the end.
+        let dir_offsets = offsets(dirs.iter().map(|d| d.1), DIR_ENTRY_SIZE);
+        let file_offsets = offsets(files.iter().map(|f| f.1), FILE_ENTRY_SIZE);
+
+        let mut dir_hashes = vec![NONE; bucket_count(dirs.len())];
+        let mut dir_table = Vec::with_capacity(dir_offsets[dirs.len()]);
+        for (index, &(parent, name, _)) in dirs.iter().enumerate() {
+            let name = encode_name(name);
+            let parent_offset = dir_offsets[parent] as u32;
+            let bucket = hash(parent_offset, &name, dir_hashes.len()).unwrap();
+
+            let sibling = (index + 1..dirs.len())
+                .find(|&i| index != 0 && dirs[i].0 == parent)
+                .map_or(NONE, |i| dir_offsets[i] as u32);
+            let child = (1..dirs.len())
+                .find(|&i| dirs[i].0 == index)
+                .map_or(NONE, |i| dir_offsets[i] as u32);
+            let file = (0..files.len())
+                .find(|&i| files[i].0 == index)
+                .map_or(NONE, |i| file_offsets[i] as u32);
+
+            for field in [
+                parent_offset,
+                sibling,
+                child,
+                file,
+                dir_hashes[bucket],
+                name.len() as u32,
+            ] {
+                dir_table.extend_from_slice(&field.to_le_bytes());
+            }
+            dir_table.extend_from_slice(&name);
+            dir_table.resize(dir_offsets[index + 1], 0);
+            dir_hashes[bucket] = dir_offsets[index] as u32;
+        }
+
+        let mut file_hashes = vec![NONE; bucket_count(files.len())];
+        let mut file_table = Vec::with_capacity(file_offsets[files.len()]);
+        let mut data_offsets = Vec::with_capacity(files.len());
+        let mut data_len = 0;
+        for (index, &(parent, name, _, len)) in files.iter().enumerate() {
+            let name = encode_name(name);
+            let parent_offset = dir_offsets[parent] as u32;
+            let bucket = hash(parent_offset, &name, file_hashes.len()).unwrap();
+            let sibling = match files.get(index + 1) {
+                Some(next) if next.0 == parent => file_offsets[index + 1] as u32,
+                _ => NONE,
+            };
+
+            data_len = align(data_len, DATA_ALIGNMENT);
+            data_offsets.push(data_len);
+
+            file_table.extend_from_slice(&parent_offset.to_le_bytes());
+            file_table.extend_from_slice(&sibling.to_le_bytes());
+            file_table.extend_from_slice(&data_len.to_le_bytes());
+            file_table.extend_from_slice(&len.to_le_bytes());
+            file_table.extend_from_slice(&file_hashes[bucket].to_le_bytes());
+            file_table.extend_from_slice(&(name.len() as u32).to_le_bytes());
+            file_table.extend_from_slice(&name);
+            file_table.resize(file_offsets[index + 1], 0);
+            file_hashes[bucket] = file_offsets[index] as u32;
+
+            data_len += len;
+        }
+
+        let dir_hashes: Vec<u8> = dir_hashes.iter().flat_map(|h| h.to_le_bytes()).collect();
+        let file_hashes: Vec<u8> = file_hashes.iter().flat_map(|h| h.to_le_bytes()).collect();
+        let sections = [&dir_hashes, &dir_table, &file_hashes, &file_table];
+
+        let mut header = vec![HEADER_SIZE as u32];
+        let mut offset = HEADER_SIZE as u64;
+        for section in sections {
+            header.extend([offset as u32, section.len() as u32]);
+            offset += section.len() as u64;
+        }
+        let data_start = align(offset, DATA_ALIGNMENT);
+        header.push(data_start as u32);
+
+        for field in header {
+            writer.write_all(&field.to_le_bytes())?;
+        }
+        for section in sections {
+            writer.write_all(section)?;
+        }
+        let mut position = offset;
+
+        for (&(_, _, source, len), data_offset) in files.iter().zip(data_offsets) {
+            let start = data_start + data_offset;
+            write_padding(&mut writer, start - position)?;
+            let written = match source {
+                Source::Data(data) => {
+                    writer.write_all(data)?;
+                    data.len() as u64
+                }
+                Source::Path(path) => io::copy(&mut fs::File::open(path)?, &mut writer)?,
+            };
+            if written != len {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    "a file changed size while the RomFS image was written",
+                ));
+            }
+            position = start + len;
+        }
+
+        Ok(())
+    }
+
+    fn insert(&mut self, path: &Path, source: Source) {
+        let mut components = components(path);
+        let name = components
+            .pop()
+            .expect("the path of a file must have a name");
+
+        let dir = self.dir_mut(&components);
+        assert!(
+            !dir.dirs.contains_key(&name),
+            "{name} was already added as a directory"
+        );
+        dir.files.insert(name, source);
+    }
+
+    fn dir_mut(&mut self, components: &[String]) -> &mut Dir {
+        let mut dir = &mut self.root;
+        for component in components {
+            assert!(
+                !dir.files.contains_key(component),
+                "{component} was already added as a file"
+            );
+            dir = dir.dirs.entry(component.clone()).or_default();
+        }
+        dir
+    }
+}
+
+/// Splits a path of the image into its components, ignoring the root.
+fn components(path: &Path) -> Vec<String> {
+    path.components()
+        .filter_map(|component| match component {
+            Component::Normal(name) => Some(
+                name.to_str()
+                    .expect("RomFS paths must be valid UTF-8")
+                    .to_string(),
+            ),
+            Component::RootDir | Component::CurDir => None,
+            _ => panic!("RomFS paths can't go up a directory"),
+        })
+        .collect()
+}
+
+/// Returns the offsets of consecutive entries with the given names in a metadata table.
+fn offsets<'a>(names: impl Iterator<Item = &'a str>, entry_size: usize) -> Vec<usize> {
+    let mut offsets = vec![0];
+    for name in names {
+        let last = offsets[offsets.len() - 1];
+        offsets.push(last + entry_size + padded_len(&encode_name(name)));
+    }
+    offsets
+}
+
+fn align(value: u64, alignment: u64) -> u64 {
+    (value + alignment - 1) / alignment * alignment
+}
+
+fn write_padding<W: Write>(writer: &mut W, len: u64) -> io::Result<()> {
+    io::copy(&mut io::repeat(0).take(len), writer)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::romfs::{Pack, PackEntry};
+
+    #[test]
+    fn round_trip() {
+        let mut builder = RomFsBuilder::new();
+        builder
+            .add_file("/hello.txt", b"Hello, world!".to_vec())
+            .add_file("levels/2.map", b"#....#".to_vec())
+            .add_file("/levels/1.map", b"#..#".to_vec())
+            .add_file("/levels/bonus/ファイル.txt", "ファイル".as_bytes().to_vec())
+            .add_file("/empty.bin", Vec::new())
+            .add_dir("/saves");
+
+        let mut image = Vec::new();
+        builder.write(&mut image).unwrap();
+        let pack = Pack::from_bytes(&image).unwrap();
+
+        assert_eq!(
+            pack.read_dir("/").unwrap(),
+            [
+                PackEntry::Dir("levels".into()),
+                PackEntry::Dir("saves".into()),
+                PackEntry::File("empty.bin".into(), pack.file("/empty.bin").unwrap()),
+                PackEntry::File("hello.txt".into(), pack.file("/hello.txt").unwrap()),
+            ]
+        );
+        assert_eq!(pack.read_dir("/saves").unwrap(), []);
+
+        for (path, contents) in [
+            ("/hello.txt", &b"Hello, world!"[..]),
+            ("/levels/1.map", b"#..#"),
+            ("/levels/2.map", b"#....#"),
+            ("/levels/bonus/ファイル.txt", "ファイル".as_bytes()),
+        ] {
+            let file = pack.file(path).unwrap();
+            assert_eq!(file.offset() % DATA_ALIGNMENT, 0);
+            assert_eq!(pack.bytes(file).unwrap(), contents);
+        }
+
+        // The header and both hash tables follow the layout of the official tools.
+        assert_eq!(&image[..12], [0x28, 0, 0, 0, 0x28, 0, 0, 0, 0x14, 0, 0, 0]);
+        assert_eq!(bucket_count(4), 5);
+        assert_eq!(bucket_count(20), 23);
+        assert_eq!(bucket_count(120), 127);
+    }
+
+    #[test]
+    fn host_directory() {
+        let source = std::env::temp_dir().join(format!("ctru-romfs-{}", std::process::id()));
+        fs::create_dir_all(source.join("sub")).unwrap();
+        fs::write(source.join("a.txt"), "first").unwrap();
+        fs::write(source.join("sub/b.txt"), "second").unwrap();
+
+        let output = source.with_extension("romfs");
+        build_dir(&source, &output).unwrap();
+        let image = fs::read(&output).unwrap();
+        fs::remove_dir_all(&source).unwrap();
+        fs::remove_file(&output).unwrap();
+
+        let pack = Pack::from_bytes(&image).unwrap();
+        assert_eq!(
+            pack.bytes(pack.file("/sub/b.txt").unwrap()).unwrap(),
+            b"second"
+        );
+        assert_eq!(pack.bytes(pack.file("/a.txt").unwrap()).unwrap(), b"first");
+    }
+
+    /// The build script builds the configured RomFS directory, which is the one of the examples.
+    #[cfg(romfs_exists)]
+    #[test]
+    fn build_script_image() {
+        let image = include_bytes!(concat!(env!("OUT_DIR"), "/romfs.bin"));
+        let pack = Pack::from_bytes(image).unwrap();
+
+        let expected = fs::read(concat!(
+            env!("CARGO_MANIFEST_DIR"),
+            "/examples/romfs/test-file.txt"
+        ))
+        .unwrap();
+        assert_eq!(
+            pack.bytes(pack.file("/test-file.txt").unwrap()).unwrap(),
+            expected
+        );
+    }
+
+    #[cfg(feature = "romfs-builder")]
+    #[test]
+    fn manifest_directory() {
+        let manifest_dir = Path::new(env!("CARGO_MANIFEST_DIR"));
+        let output =
+            std::env::temp_dir().join(format!("ctru-manifest-{}.romfs", std::process::id()));
+        assert!(build_from_manifest(manifest_dir, &output).unwrap());
+        let image = fs::read(&output).unwrap();
+        fs::remove_file(&output).unwrap();
+
+        // The manifest of this crate configures the RomFS directory of the examples.
+        let pack = Pack::from_bytes(&image).unwrap();
+        let expected = fs::read(manifest_dir.join("examples/romfs/test-file.txt")).unwrap();
+        assert_eq!(
+            pack.bytes(pack.file("/test-file.txt").unwrap()).unwrap(),
+            expected
+        );
+
+        let missing = std::env::temp_dir().join(format!("ctru-manifest-{}", std::process::id()));
+        fs::create_dir_all(&missing).unwrap();
+        fs::write(missing.join("Cargo.toml"), "[package]\nname = \"app\"\n").unwrap();
+        let built = build_from_manifest(&missing, &output).unwrap();
+        fs::remove_dir_all(&missing).unwrap();
+        assert!(!built);
+        assert!(!output.exists());
+    }
+}
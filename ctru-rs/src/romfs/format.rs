@@ -0,0 +1,53 @@
+//! Constants and hashing shared by the RomFS reader and writer
+//!
+//! This file is also included by the build script, so it must not refer to the rest of the crate.
+
+/// Value of the offsets which don't point to any entry.
+pub(crate) const NONE: u32 = 0xffff_ffff;
+
+/// Size of the level 3 header.
+pub(crate) const HEADER_SIZE: usize = 0x28;
+
+/// Size of a directory entry, without its name.
+pub(crate) const DIR_ENTRY_SIZE: usize = 0x18;
+
+/// Size of a file entry, without its name.
+pub(crate) const FILE_ENTRY_SIZE: usize = 0x20;
+
+/// Alignment of the file data, and of each file within it.
+pub(crate) const DATA_ALIGNMENT: u64 = 0x10;
+
+/// Hashes the name of an entry into a bucket, like the official tools do.
+pub(crate) fn hash(parent: u32, name: &[u8], buckets: usize) -> Option<usize> {
+    if buckets == 0 {
+        return None;
+    }
+
+    let hash = name
+        .chunks_exact(2)
+        .fold(parent ^ 123_456_789, |hash, unit| {
+            hash.rotate_right(5) ^ u16::from_le_bytes([unit[0], unit[1]]) as u32
+        });
+    Some(hash as usize % buckets)
+}
+
+/// Returns the amount of buckets of a hash table holding `entries`, like the official tools do.
+pub(crate) fn bucket_count(entries: usize) -> usize {
+    match entries {
+        0..=2 => 3,
+        3..=18 => entries | 1,
+        _ => (entries..)
+            .find(|count| [2, 3, 5, 7, 11, 13, 17].iter().all(|p| count % p != 0))
+            .unwrap(),
+    }
+}
+
+/// Encodes a name in UTF-16LE, as stored in the metadata tables.
+pub(crate) fn encode_name(name: &str) -> Vec<u8> {
+    name.encode_utf16().flat_map(u16::to_le_bytes).collect()
+}
+
+/// Returns the size of an encoded name, padded to 4 bytes.
+pub(crate) fn padded_len(name: &[u8]) -> usize {
+    (name.len() + 3) / 4 * 4
+}
@@ -0,0 +1,28 @@
+//! Read-only filesystem images bundled with the application
+//!
+//! The RomFS image of the running application can be mounted at `romfs:/` with [`RomFS`], and its
+//! files accessed through [`std::fs`]. The mount only gets compiled if the configured RomFS directory
+//! is found and the `romfs` feature is enabled.
+//!
+//! Configure the path in Cargo.toml (the default path is "romfs"). Paths are relative to the
+//! `CARGO_MANIFEST_DIR` environment variable, which is the directory containing the manifest of
+//! your package.
+//!
+//! ```toml
+//! [package.metadata.cargo-3ds]
+//! romfs_dir = "romfs"
+//! ```
+//!
+//! Images can also be parsed directly with [`Pack`], which looks files up without going through the
+//! C standard library and gives access to their data without copies when the image is in memory.
+//! New images can be created on the host with [`builder::RomFsBuilder`].
+
+pub mod builder;
+mod format;
+#[cfg(all(feature = "romfs", romfs_exists))]
+mod mount;
+pub mod pack;
+
+#[cfg(all(feature = "romfs", romfs_exists))]
+pub use mount::RomFS;
+pub use pack::{Pack, PackEntry, PackFile};
@@ -0,0 +1,186 @@
+//! Mounting of RomFS images
+//!
+//! This module only gets compiled if the configured RomFS directory is found and the `romfs`
+//! feature is enabled.
+
+use crate::error::ResultCode;
+use crate::Error;
+use std::ffi::{CStr, CString};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+use widestring::WideCString;
+
+use crate::services::fs::FsMediaType;
+use crate::services::ServiceReference;
+
+/// Keeps a RomFS image mounted while it's alive.
+///
+/// Each mount name is reference counted independently: the image is mounted by the first handle
+/// using the name and unmounted when the last one is dropped. A name can't be reused for another
+/// image while it's still mounted.
+pub struct RomFS {
+    _service_handler: ServiceReference,
+    mount_name: String,
+}
+
+/// Image mounted (or last mounted) under a name.
+#[derive(PartialEq, Eq)]
+enum Source {
+    Current,
+    File(PathBuf),
+    Title(u64, u32),
+}
+
+/// Mount names in use, with the image they refer to and their reference counter.
+///
+/// Counters are never freed, which only costs a few bytes per distinct mount name.
+static MOUNTS: Mutex<Vec<(String, Source, &'static Mutex<usize>)>> = Mutex::new(Vec::new());
+
+impl RomFS {
+    /// Mounts the RomFS image of the running application at `romfs:/`.
+    pub fn init() -> crate::Result<Self> {
+        Self::mount("romfs", Source::Current, |name| {
+            ResultCode(unsafe { ctru_sys::romfsMountSelf(name.as_ptr()) })?;
+            Ok(())
+        })
+    }
+
+    /// Mounts the RomFS image stored in a file of the SD card at `{mount_name}:/`.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the file can't be opened or doesn't hold a RomFS image,
+    /// or if `mount_name` is already used by another image.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use ctru::romfs::RomFS;
+    ///
+    /// let _dlc = RomFS::mount_from_file("/3ds/app/dlc.romfs", "dlc").unwrap();
+    /// let level = std::fs::read("dlc:/levels/1.map").unwrap();
+    /// ```
+    pub fn mount_from_file<P: AsRef<Path>>(path: P, mount_name: &str) -> crate::Result<Self> {
+        let path = path.as_ref();
+
+        Self::mount(mount_name, Source::File(path.to_path_buf()), |name| {
+            let wide_path = WideCString::from_str(path).map_err(|_| Error::InteriorNul)?;
+
+            let mut handle = 0;
+            unsafe {
+                ResultCode(ctru_sys::FSUSER_OpenFileDirectly(
+                    &mut handle,
+                    ctru_sys::ARCHIVE_SDMC,
+                    ctru_sys::fsMakePath(ctru_sys::PATH_EMPTY, b"\0".as_ptr() as _),
+                    ctru_sys::fsMakePath(ctru_sys::PATH_UTF16, wide_path.as_ptr() as _),
+                    ctru_sys::FS_OPEN_READ,
+                    0,
+                ))?;
+
+                // The file is owned by the mount from now on, and closed by libctru when unmounting.
+                ResultCode(ctru_sys::romfsMountFromFile(handle, 0, name.as_ptr()))?;
+            }
+            Ok(())
+        })
+    }
+
+    /// Mounts the RomFS image of an installed title at `{title_id:016x}:/`, for example
+    /// `0004000000123400:/`.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the title isn't installed on the given media or has no
+    /// RomFS image.
+    pub fn mount_from_title(title_id: u64, media_type: FsMediaType) -> crate::Result<Self> {
+        let mount_name = format!("{title_id:016x}");
+
+        Self::mount(
+            &mount_name,
+            Source::Title(title_id, media_type as u32),
+            |name| {
+                ResultCode(unsafe {
+                    ctru_sys::romfsMountFromTitle(title_id, media_type as u32, name.as_ptr())
+                })?;
+                Ok(())
+            },
+        )
+    }
+
+    /// Returns the name of the device the image is mounted as, without the trailing colon.
+    pub fn mount_name(&self) -> &str {
+        &self.mount_name
+    }
+
+    fn mount<F>(mount_name: &str, source: Source, mount: F) -> crate::Result<Self>
+    where
+        F: FnOnce(&CStr) -> crate::Result<()>,
+    {
+        let name = CString::new(mount_name).map_err(|_| Error::InteriorNul)?;
+
+        // Held while mounting, so that two images can't race for the same name.
+        let mut mounts = MOUNTS.lock().expect("RomFS mount list is poisoned");
+        let counter = match mounts.iter_mut().find(|(name, ..)| name == mount_name) {
+            Some((_, mounted, counter)) => {
+                if *mounted != source {
+                    if *counter.lock().expect("RomFS mount counter is poisoned") != 0 {
+                        return Err(Error::MountNameInUse(mount_name.to_string()));
+                    }
+                    *mounted = source;
+                }
+                *counter
+            }
+            None => {
+                let counter: &'static Mutex<usize> = Box::leak(Box::new(Mutex::new(0)));
+                mounts.push((mount_name.to_string(), source, counter));
+                counter
+            }
+        };
+
+        let unmount_name = name.clone();
+        let _service_handler = ServiceReference::new(
+            counter,
+            true,
+            || mount(&name),
+            move || {
+                let _ = unsafe { ctru_sys::romfsUnmount(unmount_name.as_ptr()) };
+            },
+        )?;
+
+        Ok(Self {
+            _service_handler,
+            mount_name: mount_name.to_string(),
+        })
+    }
+}
+
+#[cfg(all(test, not(feature = "host-mock")))]
+mod tests {
+    use super::*;
+
+    fn active(mount_name: &str) -> usize {
+        let mounts = MOUNTS.lock().unwrap();
+        let (.., counter) = mounts.iter().find(|(name, ..)| name == mount_name).unwrap();
+        let value = *counter.lock().unwrap();
+        value
+    }
+
+    #[test]
+    fn romfs_counter() {
+        let _romfs = RomFS::init().unwrap();
+        let value = active("romfs");
+
+        assert_eq!(value, 1);
+        assert_eq!(_romfs.mount_name(), "romfs");
+
+        // The name can't be taken by another image while it's mounted.
+        assert!(RomFS::mount_from_file("/3ds/other.romfs", "romfs").is_err());
+        assert_eq!(active("romfs"), 1);
+
+        drop(_romfs);
+
+        let value = active("romfs");
+
+        assert_eq!(value, 0);
+    }
+}
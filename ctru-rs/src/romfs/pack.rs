@@ -0,0 +1,493 @@
+//! Parsing of RomFS images
+//!
+//! A RomFS image stores its directory tree in two metadata tables, one for directories and one for files,
+//! with hash tables to look entries up by name. [`Pack`] loads those tables from any [`Read`] + [`Seek`]
+//! source and resolves paths with them, without going through the C standard library. When the whole image
+//! is in memory, file contents can be borrowed from it instead of being copied.
+//!
+//! Both plain level 3 images, like the ones embedded in 3DSX files, and images starting with an IVFC
+//! header, like the ones of installed titles, are supported.
+//!
+//! # Examples
+//!
+//! ```no_run
+//! use std::fs::File;
+//!
+//! use ctru::romfs::Pack;
+//!
+//! let mut pack = Pack::new(File::open("sdmc:/3ds/app/dlc.romfs").unwrap()).unwrap();
+//!
+//! let file = pack.file("/levels/1.map").expect("missing level");
+//! let level = pack.read(file).unwrap();
+//! ```
+
+use std::io::Error as IoError;
+use std::io::ErrorKind as IoErrorKind;
+use std::io::Result as IoResult;
+use std::io::{Cursor, Read, Seek, SeekFrom, Take};
+use std::path::{Component, Path, PathBuf};
+
+use super::format::{encode_name, hash, DIR_ENTRY_SIZE, FILE_ENTRY_SIZE, HEADER_SIZE, NONE};
+use crate::services::fs::DirTree;
+
+/// Size of the IVFC header, which may come before the level 3 data.
+const IVFC_HEADER_SIZE: usize = 0x60;
+
+/// A parsed RomFS image.
+pub struct Pack<R> {
+    reader: R,
+    /// Offset of the file data in the reader.
+    data_offset: u64,
+    dir_hashes: Vec<u32>,
+    dir_table: Vec<u8>,
+    file_hashes: Vec<u32>,
+    file_table: Vec<u8>,
+}
+
+/// Location of a file's data in a [`Pack`].
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct PackFile {
+    offset: u64,
+    len: u64,
+}
+
+/// An entry of a directory of a [`Pack`], as returned by [`Pack::read_dir`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum PackEntry {
+    Dir(String),
+    File(String, PackFile),
+}
+
+struct DirRecord<'a> {
+    parent: u32,
+    sibling: u32,
+    first_dir: u32,
+    first_file: u32,
+    next_in_bucket: u32,
+    name: &'a [u8],
+}
+
+struct FileRecord<'a> {
+    parent: u32,
+    sibling: u32,
+    data_offset: u64,
+    data_len: u64,
+    next_in_bucket: u32,
+    name: &'a [u8],
+}
+
+impl<R: Read + Seek> Pack<R> {
+    /// Parses the image starting at the current position of `reader`, loading its metadata tables.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error of kind [`InvalidData`](IoErrorKind::InvalidData) if the
+    /// header is malformed, or the error of `reader` if it fails.
+    pub fn new(mut reader: R) -> IoResult<Self> {
+        let mut start = reader.stream_position()?;
+
+        let mut header = [0; IVFC_HEADER_SIZE];
+        reader.read_exact(&mut header[..HEADER_SIZE])?;
+        if &header[..4] == b"IVFC" {
+            reader.read_exact(&mut header[HEADER_SIZE..])?;
+
+            // The level 3 data is aligned to its block size after the master hash.
+            let master_hash_size = u32_at(&header, 0x08).unwrap() as u64;
+            let block_size = 1u64
+                .checked_shl(u32_at(&header, 0x4c).unwrap())
+                .ok_or_else(|| invalid_data("invalid IVFC block size"))?;
+            let level3 = (IVFC_HEADER_SIZE as u64 + master_hash_size + block_size - 1) / block_size
+                * block_size;
+
+            start += level3;
+            reader.seek(SeekFrom::Start(start))?;
+            reader.read_exact(&mut header[..HEADER_SIZE])?;
+        }
+
+        let field = |index: usize| u32_at(&header, 4 * index).unwrap() as u64;
+        if field(0) != HEADER_SIZE as u64 {
+            return Err(invalid_data("invalid RomFS header size"));
+        }
+
+        let mut read_table = |offset: u64, len: u64| -> IoResult<Vec<u8>> {
+            reader.seek(SeekFrom::Start(start + offset))?;
+            let mut table = Vec::new();
+            (&mut reader).take(len).read_to_end(&mut table)?;
+            if table.len() as u64 != len {
+                return Err(IoErrorKind::UnexpectedEof.into());
+            }
+            Ok(table)
+        };
+        let hashes = |table: Vec<u8>| -> Vec<u32> {
+            table
+                .chunks_exact(4)
+                .map(|bytes| u32::from_le_bytes(bytes.try_into().unwrap()))
+                .collect()
+        };
+
+        let dir_hashes = hashes(read_table(field(1), field(2))?);
+        let dir_table = read_table(field(3), field(4))?;
+        let file_hashes = hashes(read_table(field(5), field(6))?);
+        let file_table = read_table(field(7), field(8))?;
+
+        Ok(Self {
+            data_offset: start + field(9),
+            reader,
+            dir_hashes,
+            dir_table,
+            file_hashes,
+            file_table,
+        })
+    }
+
+    /// Reads the whole contents of a file.
+    pub fn read(&mut self, file: PackFile) -> IoResult<Vec<u8>> {
+        let mut data = Vec::new();
+        self.open(file)?.read_to_end(&mut data)?;
+        if data.len() as u64 != file.len {
+            return Err(IoErrorKind::UnexpectedEof.into());
+        }
+        Ok(data)
+    }
+
+    /// Returns a reader over the contents of a file, to stream it instead of reading it at once.
+    pub fn open(&mut self, file: PackFile) -> IoResult<Take<&mut R>> {
+        self.reader.seek(SeekFrom::Start(file.offset))?;
+        Ok((&mut self.reader).take(file.len))
+    }
+
+    /// Returns the underlying reader.
+    pub fn into_inner(self) -> R {
+        self.reader
+    }
+}
+
+impl<'a> Pack<Cursor<&'a [u8]>> {
+    /// Parses an image held in memory, such as one included with [`include_bytes!`].
+    pub fn from_bytes(data: &'a [u8]) -> IoResult<Self> {
+        Self::new(Cursor::new(data))
+    }
+
+    /// Returns the contents of a file, borrowed from the image.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error of kind [`UnexpectedEof`](IoErrorKind::UnexpectedEof) if the
+    /// image is truncated.
+    pub fn bytes(&self, file: PackFile) -> IoResult<&'a [u8]> {
+        let data = *self.reader.get_ref();
+        let range = usize::try_from(file.offset)
+            .ok()
+            .and_then(|start| Some(start..start.checked_add(usize::try_from(file.len).ok()?)?));
+
+        range
+            .and_then(|range| data.get(range))
+            .ok_or_else(|| IoErrorKind::UnexpectedEof.into())
+    }
+}
+
+impl<R> Pack<R> {
+    /// Looks a file up by its path, from the root of the image.
+    pub fn file<P: AsRef<Path>>(&self, path: P) -> Option<PackFile> {
+        let (parent, name) = self.resolve(path.as_ref())?;
+        let name = name?;
+        let offset = self.find_file(parent, &name)?;
+        let file = self.file_record(offset)?;
+
+        Some(PackFile {
+            offset: self.data_offset + file.data_offset,
+            len: file.data_len,
+        })
+    }
+
+    /// Returns whether `path` is a directory of the image.
+    pub fn is_dir<P: AsRef<Path>>(&self, path: P) -> bool {
+        self.dir_offset(path.as_ref()).is_some()
+    }
+
+    /// Lists the subdirectories and files of a directory, or returns `None` if it doesn't exist.
+    pub fn read_dir<P: AsRef<Path>>(&self, path: P) -> Option<Vec<PackEntry>> {
+        let dir = self.dir_record(self.dir_offset(path.as_ref())?)?;
+        let mut entries = Vec::new();
+
+        let mut offset = dir.first_dir;
+        while offset != NONE && entries.len() <= self.dir_table.len() / DIR_ENTRY_SIZE {
+            let child = self.dir_record(offset)?;
+            entries.push(PackEntry::Dir(decode_name(child.name)));
+            offset = child.sibling;
+        }
+
+        let dirs = entries.len();
+        let mut offset = dir.first_file;
+        while offset != NONE && entries.len() - dirs <= self.file_table.len() / FILE_ENTRY_SIZE {
+            let file = self.file_record(offset)?;
+            entries.push(PackEntry::File(
+                decode_name(file.name),
+                PackFile {
+                    offset: self.data_offset + file.data_offset,
+                    len: file.data_len,
+                },
+            ));
+            offset = file.sibling;
+        }
+
+        Some(entries)
+    }
+
+    /// Resolves the parent directory of `path`, returning its offset and the encoded name of the last
+    /// component, if any.
+    fn resolve(&self, path: &Path) -> Option<(u32, Option<Vec<u8>>)> {
+        let mut parent = 0;
+        let mut name: Option<Vec<u8>> = None;
+
+        for component in path.components() {
+            match component {
+                Component::RootDir | Component::CurDir => continue,
+                Component::Normal(component) => {
+                    if let Some(name) = &name {
+                        parent = self.find_dir(parent, name)?;
+                    }
+                    name = Some(encode_name(component.to_str()?));
+                }
+                Component::ParentDir | Component::Prefix(_) => return None,
+            }
+        }
+
+        Some((parent, name))
+    }
+
+    fn dir_offset(&self, path: &Path) -> Option<u32> {
+        match self.resolve(path)? {
+            (parent, Some(name)) => self.find_dir(parent, &name),
+            // The root directory is always the first entry.
+            (_, None) => self.dir_record(0).map(|_| 0),
+        }
+    }
+
+    fn find_dir(&self, parent: u32, name: &[u8]) -> Option<u32> {
+        let bucket = hash(parent, name, self.dir_hashes.len())?;
+        let mut offset = self.dir_hashes[bucket];
+
+        // Bound the length of the chains, in case the image is corrupted.
+        for _ in 0..=self.dir_table.len() / DIR_ENTRY_SIZE {
+            if offset == NONE {
+                break;
+            }
+            let dir = self.dir_record(offset)?;
+            if dir.parent == parent && dir.name == name {
+                return Some(offset);
+            }
+            offset = dir.next_in_bucket;
+        }
+
+        None
+    }
+
+    fn find_file(&self, parent: u32, name: &[u8]) -> Option<u32> {
+        let bucket = hash(parent, name, self.file_hashes.len())?;
+        let mut offset = self.file_hashes[bucket];
+
+        for _ in 0..=self.file_table.len() / FILE_ENTRY_SIZE {
+            if offset == NONE {
+                break;
+            }
+            let file = self.file_record(offset)?;
+            if file.parent == parent && file.name == name {
+                return Some(offset);
+            }
+            offset = file.next_in_bucket;
+        }
+
+        None
+    }
+
+    fn dir_record(&self, offset: u32) -> Option<DirRecord<'_>> {
+        let entry = self.dir_table.get(offset as usize..)?;
+        let name_len = u32_at(entry, 0x14)? as usize;
+
+        Some(DirRecord {
+            parent: u32_at(entry, 0x00)?,
+            sibling: u32_at(entry, 0x04)?,
+            first_dir: u32_at(entry, 0x08)?,
+            first_file: u32_at(entry, 0x0c)?,
+            next_in_bucket: u32_at(entry, 0x10)?,
+            name: entry.get(DIR_ENTRY_SIZE..DIR_ENTRY_SIZE.checked_add(name_len)?)?,
+        })
+    }
+
+    fn file_record(&self, offset: u32) -> Option<FileRecord<'_>> {
+        let entry = self.file_table.get(offset as usize..)?;
+        let name_len = u32_at(entry, 0x1c)? as usize;
+
+        Some(FileRecord {
+            parent: u32_at(entry, 0x00)?,
+            sibling: u32_at(entry, 0x04)?,
+            data_offset: u64_at(entry, 0x08)?,
+            data_len: u64_at(entry, 0x10)?,
+            next_in_bucket: u32_at(entry, 0x18)?,
+            name: entry.get(FILE_ENTRY_SIZE..FILE_ENTRY_SIZE.checked_add(name_len)?)?,
+        })
+    }
+}
+
+impl<R> DirTree for Pack<R> {
+    fn list_dir(&self, path: &Path) -> IoResult<Vec<(PathBuf, bool)>> {
+        let entries = self
+            .read_dir(path)
+            .ok_or_else(|| IoError::from(IoErrorKind::NotFound))?;
+
+        Ok(entries
+            .iter()
+            .map(|entry| {
+                let is_dir = matches!(entry, PackEntry::Dir(_));
+                (path.join(entry.name()), is_dir)
+            })
+            .collect())
+    }
+}
+
+impl PackFile {
+    /// Returns the offset of the file's data in the reader of the [`Pack`].
+    pub fn offset(&self) -> u64 {
+        self.offset
+    }
+
+    /// Returns the size of the file, in bytes.
+    // Like fs::Metadata, an `is_empty` method wouldn't be of much use.
+    #[allow(clippy::len_without_is_empty)]
+    pub fn len(&self) -> u64 {
+        self.len
+    }
+}
+
+impl PackEntry {
+    /// Returns the name of the entry.
+    pub fn name(&self) -> &str {
+        match self {
+            PackEntry::Dir(name) | PackEntry::File(name, _) => name,
+        }
+    }
+}
+
+fn decode_name(name: &[u8]) -> String {
+    let units: Vec<u16> = name
+        .chunks_exact(2)
+        .map(|unit| u16::from_le_bytes([unit[0], unit[1]]))
+        .collect();
+    String::from_utf16_lossy(&units)
+}
+
+fn u32_at(data: &[u8], offset: usize) -> Option<u32> {
+    let bytes = data.get(offset..offset.checked_add(4)?)?;
+    Some(u32::from_le_bytes(bytes.try_into().unwrap()))
+}
+
+fn u64_at(data: &[u8], offset: usize) -> Option<u64> {
+    let bytes = data.get(offset..offset.checked_add(8)?)?;
+    Some(u64::from_le_bytes(bytes.try_into().unwrap()))
+}
+
+fn invalid_data(message: &str) -> IoError {
+    IoError::new(IoErrorKind::InvalidData, message)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::romfs::builder::RomFsBuilder;
+    use crate::services::fs;
+
+    fn image() -> Vec<u8> {
+        let mut image = Vec::new();
+        RomFsBuilder::new()
+            .add_file("/hello.txt", b"Hello, world!".to_vec())
+            .add_file("/levels/1.map", b"#..#".to_vec())
+            .add_file("/levels/2.map", b"#....#".to_vec())
+            .add_file("/levels/bonus/ファイル.txt", "ファイル".as_bytes().to_vec())
+            .add_file("/empty.bin", Vec::new())
+            .write(&mut image)
+            .unwrap();
+        image
+    }
+
+    #[test]
+    fn lookup() {
+        let image = image();
+        let pack = Pack::from_bytes(&image).unwrap();
+
+        let file = pack.file("/levels/2.map").unwrap();
+        assert_eq!(file.len(), 6);
+        assert_eq!(pack.bytes(file).unwrap(), b"#....#");
+        assert_eq!(
+            pack.bytes(pack.file("levels/bonus/ファイル.txt").unwrap())
+                .unwrap(),
+            "ファイル".as_bytes()
+        );
+        assert_eq!(pack.bytes(pack.file("/empty.bin").unwrap()).unwrap(), b"");
+
+        assert!(pack.file("/levels").is_none());
+        assert!(pack.file("/levels/3.map").is_none());
+        assert!(pack.file("/hello.txt/x").is_none());
+        assert!(pack.file("/missing/1.map").is_none());
+        assert!(pack.is_dir("/"));
+        assert!(pack.is_dir("/levels/bonus"));
+        assert!(!pack.is_dir("/hello.txt"));
+
+        assert_eq!(
+            pack.read_dir("/levels").unwrap(),
+            [
+                PackEntry::Dir("bonus".into()),
+                PackEntry::File("1.map".into(), pack.file("/levels/1.map").unwrap()),
+                PackEntry::File("2.map".into(), file),
+            ]
+        );
+        assert!(pack.read_dir("/hello.txt").is_none());
+    }
+
+    #[test]
+    fn reading() {
+        // Images of installed titles start with an IVFC header, and their level 3 is block-aligned.
+        let mut image = vec![0; 0x1000];
+        image[..4].copy_from_slice(b"IVFC");
+        image[0x08..0x0c].copy_from_slice(&0x20u32.to_le_bytes());
+        image[0x4c..0x50].copy_from_slice(&12u32.to_le_bytes());
+        image.extend(self::image());
+
+        // Parsing starts from the current position of the reader.
+        let mut data = vec![0xff; 7];
+        data.extend(image);
+        let mut reader = Cursor::new(data);
+        reader.set_position(7);
+
+        let mut pack = Pack::new(reader).unwrap();
+        let file = pack.file("/hello.txt").unwrap();
+        assert_eq!(file.offset() % 16, 7);
+        assert_eq!(pack.read(file).unwrap(), b"Hello, world!");
+
+        let mut contents = String::new();
+        let file = pack.file("/levels/1.map").unwrap();
+        pack.open(file)
+            .unwrap()
+            .read_to_string(&mut contents)
+            .unwrap();
+        assert_eq!(contents, "#..#");
+
+        assert_eq!(
+            fs::glob(&pack, "/**/*.map").unwrap(),
+            [
+                PathBuf::from("/levels/1.map"),
+                PathBuf::from("/levels/2.map")
+            ]
+        );
+
+        let truncated = &self::image()[..0x40];
+        assert_eq!(
+            Pack::from_bytes(truncated).err().unwrap().kind(),
+            IoErrorKind::UnexpectedEof
+        );
+        assert_eq!(
+            Pack::from_bytes(&[0; 0x28]).err().unwrap().kind(),
+            IoErrorKind::InvalidData
+        );
+    }
+}
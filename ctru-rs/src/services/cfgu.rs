@@ -3,6 +3,8 @@
 //! This module contains basic methods to retrieve and change configuration from the console.
 
 use crate::error::ResultCode;
+#[cfg(feature = "host-mock")]
+use crate::mock::sys as ctru_sys;
 
 #[derive(Copy, Clone, Debug)]
 #[repr(u32)]
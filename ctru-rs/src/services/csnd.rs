@@ -0,0 +1,699 @@
+//! CSND service
+//!
+//! The CSND service drives the sound hardware directly, without the DSP: it is the only way to play
+//! sounds when the DSP firmware needed by [`ndsp`](super::ndsp) isn't available. It offers mono
+//! [channels](Channel) playing PCM or IMA-ADPCM [waves](Wave) from LINEAR memory, and
+//! [capture units](CaptureUnit) recording the mix of the channels back to LINEAR memory.
+
+use std::cell::{Cell, RefCell, RefMut};
+use std::error;
+use std::fmt;
+use std::marker::PhantomData;
+use std::sync::Mutex;
+
+use crate::error::ResultCode;
+use crate::linear::LinearAllocator;
+use crate::services::ndsp::AudioFormat;
+use crate::services::sound::{self, SoundChannel};
+use crate::services::ServiceReference;
+
+const NUMBER_OF_CHANNELS: u8 = ctru_sys::CSND_NUM_CHANNELS as u8;
+
+/// Frequency of the clock of the sound hardware, divided to get the sample rate of channels and
+/// capture units.
+const CLOCK_RATE: f32 = 0x3fe_c3fc as f32;
+
+/// Encoding of the samples of a [`Wave`].
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+#[repr(u32)]
+pub enum Encoding {
+    /// Signed 8-bit PCM.
+    Pcm8 = ctru_sys::CSND_ENCODING_PCM8,
+    /// Signed 16-bit PCM.
+    Pcm16 = ctru_sys::CSND_ENCODING_PCM16,
+    /// IMA-ADPCM, 4 bits per sample.
+    ///
+    /// The data starts with the usual 4-byte header holding the first sample (16-bit) and step index
+    /// (8-bit, followed by a padding byte) of the decoder.
+    ImaAdpcm = ctru_sys::CSND_ENCODING_ADPCM,
+}
+
+/// Format of the samples recorded by a [`CaptureUnit`].
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum CaptureFormat {
+    /// Signed 8-bit PCM.
+    Pcm8,
+    /// Signed 16-bit PCM.
+    Pcm16,
+}
+
+#[derive(Debug)]
+pub enum CsndError {
+    /// Channel ID
+    InvalidChannel(u8),
+    /// Channel ID
+    ChannelAlreadyInUse(u8),
+    /// Every channel is in use or reserved by the system.
+    NoFreeChannel,
+    /// Channel ID
+    WaveBusy(u8),
+    /// The format can't be played by CSND channels.
+    UnsupportedFormat(AudioFormat),
+    /// A sample rate isn't a positive number of Hz.
+    InvalidSampleRate(f32),
+    /// A request to the service failed.
+    Service(crate::Error),
+}
+
+/// Playable wave, holding its samples on LINEAR memory.
+pub struct Wave {
+    buffer: Box<[u8], LinearAllocator>,
+    encoding: Encoding,
+    looping: bool,
+    /// Channel the wave was last played on, and the generation of that playback.
+    played_on_channel: Option<(u8, u32)>,
+}
+
+/// A mono channel of the CSND service.
+pub struct Channel<'csnd> {
+    id: u8,
+    sample_rate: Cell<f32>,
+    volume: Cell<(f32, f32)>,
+    _rf: RefMut<'csnd, ()>,
+}
+
+/// A capture unit of the CSND service, recording what the channels play.
+///
+/// The capture unit is released when dropped.
+pub struct CaptureUnit<'csnd> {
+    id: u32,
+    buffer: Option<Box<[u8], LinearAllocator>>,
+    _csnd: PhantomData<&'csnd Csnd>,
+}
+
+static CSND_ACTIVE: Mutex<usize> = Mutex::new(0);
+
+/// Generations of the channels, counting how many times a wave was started on each of them.
+///
+/// Channels outlive [`Csnd`] and the waves which played on them, so this is global rather than
+/// owned by the service handler.
+static CHANNEL_GENERATIONS: Mutex<Generations> =
+    Mutex::new(Generations([0; NUMBER_OF_CHANNELS as usize]));
+
+/// Counters telling whether a wave is still the last one started on its channel.
+struct Generations([u32; NUMBER_OF_CHANNELS as usize]);
+
+/// Handler of the CSND service.
+///
+/// Only one "instance" of this struct can exist at a time.
+pub struct Csnd {
+    _service_handler: ServiceReference,
+    channel_flags: [RefCell<()>; NUMBER_OF_CHANNELS as usize],
+}
+
+impl Csnd {
+    /// Initialize the CSND service.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if an instance of the `Csnd` struct already exists
+    /// or if there are any issues during initialization.
+    pub fn init() -> crate::Result<Self> {
+        let _service_handler = ServiceReference::new(
+            &CSND_ACTIVE,
+            false,
+            || {
+                ResultCode(unsafe { ctru_sys::csndInit() })?;
+
+                Ok(())
+            },
+            || unsafe {
+                ctru_sys::csndExit();
+            },
+        )?;
+
+        Ok(Self {
+            _service_handler,
+            channel_flags: Default::default(),
+        })
+    }
+
+    /// Return a representation of the specified channel.
+    ///
+    /// # Errors
+    ///
+    /// An error will be returned if the channel ID is not between 0 and 31, if the channel is
+    /// reserved by the system or if it is already being used.
+    pub fn channel(&self, id: u8) -> Result<Channel, CsndError> {
+        let ref_cell = match self.channel_flags.get(id as usize) {
+            Some(ref_cell) if is_available(id) => ref_cell,
+            _ => return Err(CsndError::InvalidChannel(id)),
+        };
+
+        match ref_cell.try_borrow_mut() {
+            Ok(_rf) => Ok(Channel {
+                id,
+                sample_rate: Cell::new(32728.0),
+                volume: Cell::new((1.0, 0.0)),
+                _rf,
+            }),
+            Err(_) => Err(CsndError::ChannelAlreadyInUse(id)),
+        }
+    }
+
+    /// Return the first channel which is neither reserved by the system nor already being used.
+    ///
+    /// # Errors
+    ///
+    /// An error will be returned if there is no such channel.
+    pub fn free_channel(&self) -> Result<Channel, CsndError> {
+        (0..NUMBER_OF_CHANNELS)
+            .find_map(|id| self.channel(id).ok())
+            .ok_or(CsndError::NoFreeChannel)
+    }
+
+    /// Acquire one of the two capture units.
+    ///
+    /// # Errors
+    ///
+    /// An error will be returned if both capture units are already acquired.
+    pub fn capture_unit(&self) -> Result<CaptureUnit, CsndError> {
+        let id = acquire_capture_unit()?;
+
+        Ok(CaptureUnit {
+            id,
+            buffer: None,
+            _csnd: PhantomData,
+        })
+    }
+}
+
+impl Encoding {
+    /// Returns the size of the header before the samples.
+    fn header_size(self) -> usize {
+        match self {
+            Self::Pcm8 | Self::Pcm16 => 0,
+            Self::ImaAdpcm => 4,
+        }
+    }
+
+    /// Returns the amount of samples stored in `len` bytes, including the header.
+    pub fn sample_count(self, len: usize) -> usize {
+        match self {
+            Self::Pcm8 => len,
+            Self::Pcm16 => len / 2,
+            Self::ImaAdpcm => len.saturating_sub(4) * 2,
+        }
+    }
+}
+
+impl Wave {
+    /// Build a new playable wave object from a raw buffer on LINEAR memory.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the buffer is too short to hold the header of [`Encoding::ImaAdpcm`].
+    pub fn new(buffer: Box<[u8], LinearAllocator>, encoding: Encoding, looping: bool) -> Self {
+        assert!(
+            buffer.len() >= encoding.header_size(),
+            "IMA-ADPCM wave without header"
+        );
+
+        Self {
+            buffer,
+            encoding,
+            looping,
+            played_on_channel: None,
+        }
+    }
+
+    /// Return a slice to the audio data (on the LINEAR memory).
+    pub fn get_buffer(&self) -> &[u8] {
+        &self.buffer
+    }
+
+    /// Return a mutable slice to the audio data (on the LINEAR memory).
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the wave is playing, with the id of its channel.
+    pub fn get_buffer_mut(&mut self) -> Result<&mut [u8], CsndError> {
+        match self.playing_channel() {
+            Some(id) => Err(CsndError::WaveBusy(id)),
+            None => Ok(&mut self.buffer),
+        }
+    }
+
+    /// Get the encoding of the audio data.
+    pub fn get_encoding(&self) -> Encoding {
+        self.encoding
+    }
+
+    /// Get the amount of samples in the wave.
+    pub fn get_sample_count(&self) -> usize {
+        self.encoding.sample_count(self.buffer.len())
+    }
+
+    /// Returns whether the wave starts over once it reaches its end.
+    pub fn is_looping(&self) -> bool {
+        self.looping
+    }
+
+    /// Returns the id of the channel playing the wave, if any.
+    ///
+    /// Once another wave is started on the channel, it doesn't play this one anymore even if it is
+    /// still playing.
+    fn playing_channel(&self) -> Option<u8> {
+        let (id, generation) = self.played_on_channel?;
+        // Once the service is closed, it can't be asked whether the channel still plays the wave.
+        if *CSND_ACTIVE.lock().unwrap() == 0 {
+            return None;
+        }
+
+        let current = CHANNEL_GENERATIONS
+            .lock()
+            .unwrap()
+            .is_current(id, generation);
+
+        match current && is_playing(id).unwrap_or(false) {
+            true => Some(id),
+            false => None,
+        }
+    }
+}
+
+impl Channel<'_> {
+    // Returns the channel's id
+    pub fn get_id(&self) -> u8 {
+        self.id
+    }
+
+    /// Starts playing `wave`, stopping whatever the channel was playing before.
+    ///
+    /// # Warning
+    ///
+    /// The hardware reads the samples while playing, so the [Wave] must be kept alive until then.
+    /// To ensure safety, a [Wave] dropped while playing stops its channel.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the wave is already playing on a channel.
+    pub fn play(&self, wave: &mut Wave) -> Result<(), CsndError> {
+        if let Some(id) = wave.playing_channel() {
+            return Err(CsndError::WaveBusy(id));
+        }
+
+        let header_size = wave.encoding.header_size();
+        let samples = &wave.buffer[header_size..];
+        let channel = self.id.into();
+
+        flush_cache(&wave.buffer)?;
+
+        unsafe {
+            if wave.encoding == Encoding::ImaAdpcm {
+                let sample = i16::from_le_bytes([wave.buffer[0], wave.buffer[1]]).into();
+                let index = wave.buffer[2].into();
+                ctru_sys::CSND_SetAdpcmState(channel, 0, sample, index);
+                // Looping goes back to the second block, which starts at the first sample too.
+                if wave.looping {
+                    ctru_sys::CSND_SetAdpcmState(channel, 1, sample, index);
+                }
+                ctru_sys::CSND_SetAdpcmReload(channel, wave.looping);
+            }
+
+            let address = ctru_sys::osConvertVirtToPhys(samples.as_ptr().cast());
+            let flags = channel_flags(self.id, wave.encoding, wave.looping, self.sample_rate.get());
+            let (volume, pan) = self.volume.get();
+            let volumes = volume_register(volume, pan);
+            ctru_sys::CSND_SetChnRegs(
+                flags,
+                address,
+                address,
+                samples.len() as u32,
+                volumes,
+                volumes,
+            );
+        }
+        execute_commands()?;
+
+        // The wave which played before, if any, doesn't own the channel anymore.
+        let generation = CHANNEL_GENERATIONS.lock().unwrap().start(self.id);
+        wave.played_on_channel = Some((self.id, generation));
+
+        Ok(())
+    }
+
+    /// Stops playback.
+    pub fn stop(&self) -> Result<(), CsndError> {
+        unsafe { ctru_sys::CSND_SetPlayState(self.id.into(), 0) };
+        execute_commands().map_err(CsndError::from)
+    }
+
+    /// Returns whether the channel is playing a wave.
+    pub fn is_playing(&self) -> Result<bool, CsndError> {
+        is_playing(self.id).map_err(CsndError::from)
+    }
+
+    /// Set the channel's rate of sampling, taking effect immediately.
+    ///
+    /// # Errors
+    ///
+    /// This function will return [`CsndError::InvalidSampleRate`] if `rate` isn't a positive number.
+    pub fn set_sample_rate(&self, rate: f32) -> Result<(), CsndError> {
+        check_sample_rate(rate)?;
+        self.sample_rate.set(rate);
+        unsafe { ctru_sys::CSND_SetTimer(self.id.into(), timer(rate)) };
+        execute_commands().map_err(CsndError::from)
+    }
+
+    /// Set the channel's volume, between 0 and 1, and pan, from -1 (left) to 1 (right).
+    ///
+    /// The volume is also the one at which capture units record the channel.
+    pub fn set_volume(&self, volume: f32, pan: f32) -> Result<(), CsndError> {
+        self.volume.set((volume, pan));
+        let volumes = volume_register(volume, pan);
+        unsafe { ctru_sys::CSND_SetVol(self.id.into(), volumes, volumes) };
+        execute_commands().map_err(CsndError::from)
+    }
+}
+
+impl SoundChannel for Channel<'_> {
+    type Wave = Wave;
+    type Error = CsndError;
+
+    fn new_wave(
+        buffer: Box<[u8], LinearAllocator>,
+        format: AudioFormat,
+        looping: bool,
+    ) -> Result<Wave, CsndError> {
+        let encoding = match format {
+            AudioFormat::PCM8Mono => Encoding::Pcm8,
+            AudioFormat::PCM16Mono => Encoding::Pcm16,
+            _ => return Err(CsndError::UnsupportedFormat(format)),
+        };
+
+        Ok(Wave::new(buffer, encoding, looping))
+    }
+
+    fn play(&self, wave: &mut Wave) -> Result<(), CsndError> {
+        Channel::play(self, wave)
+    }
+
+    fn stop(&self) -> Result<(), CsndError> {
+        Channel::stop(self)
+    }
+
+    fn is_playing(&self) -> Result<bool, CsndError> {
+        Channel::is_playing(self)
+    }
+
+    fn set_sample_rate(&self, rate: f32) -> Result<(), CsndError> {
+        Channel::set_sample_rate(self, rate)
+    }
+
+    fn set_volume(&self, volume: f32, pan: f32) -> Result<(), CsndError> {
+        Channel::set_volume(self, volume, pan)
+    }
+}
+
+impl CaptureUnit<'_> {
+    // Returns the capture unit's id
+    pub fn get_id(&self) -> u32 {
+        self.id
+    }
+
+    /// Starts recording the channels into `buffer`, at the given rate in Hz.
+    ///
+    /// Once the end of the buffer is reached, recording goes on from its start if `repeat` is set,
+    /// or stops otherwise. A buffer which was being recorded to is dropped, use
+    /// [`CaptureUnit::stop`] first to get it back.
+    ///
+    /// # Errors
+    ///
+    /// This function will return [`CsndError::InvalidSampleRate`] if `rate` isn't a positive number.
+    pub fn start(
+        &mut self,
+        buffer: Box<[u8], LinearAllocator>,
+        format: CaptureFormat,
+        rate: f32,
+        repeat: bool,
+    ) -> Result<(), CsndError> {
+        check_sample_rate(rate)?;
+        self.stop()?;
+        flush_cache(&buffer)?;
+
+        unsafe {
+            let address = ctru_sys::osConvertVirtToPhys(buffer.as_ptr().cast());
+            ctru_sys::CSND_CapSetFormat(self.id, format == CaptureFormat::Pcm8);
+            ctru_sys::CSND_CapSetRepeat(self.id, repeat);
+            ctru_sys::CSND_CapSetTimer(self.id, timer(rate));
+            ctru_sys::CSND_CapSetBuffer(self.id, address, buffer.len() as u32);
+            ctru_sys::CSND_CapEnable(self.id, true);
+        }
+        execute_commands()?;
+
+        self.buffer = Some(buffer);
+
+        Ok(())
+    }
+
+    /// Stops recording, returning the buffer that was recorded to, if any.
+    pub fn stop(&mut self) -> Result<Option<Box<[u8], LinearAllocator>>, CsndError> {
+        if self.buffer.is_some() {
+            unsafe { ctru_sys::CSND_CapEnable(self.id, false) };
+            execute_commands()?;
+        }
+
+        if let Some(buffer) = &self.buffer {
+            invalidate_cache(buffer)?;
+        }
+
+        Ok(self.buffer.take())
+    }
+
+    /// Returns whether the capture unit is recording.
+    pub fn is_capturing(&self) -> Result<bool, CsndError> {
+        update_info()?;
+        let info = unsafe { *ctru_sys::csndGetCapInfo(self.id) };
+
+        Ok(unsafe { info.__bindgen_anon_1.active } != 0)
+    }
+
+    /// Return a slice to the buffer being recorded to, if any.
+    pub fn get_buffer(&self) -> Result<Option<&[u8]>, CsndError> {
+        match &self.buffer {
+            Some(buffer) => {
+                invalidate_cache(buffer)?;
+                Ok(Some(buffer))
+            }
+            None => Ok(None),
+        }
+    }
+}
+
+/// Returns whether the channel may be used by the application.
+fn is_available(id: u8) -> bool {
+    id < NUMBER_OF_CHANNELS && unsafe { ctru_sys::csndChannels } & (1 << id) != 0
+}
+
+fn is_playing(id: u8) -> crate::Result<bool> {
+    let mut status = 0;
+    ResultCode(unsafe { ctru_sys::csndIsPlaying(id.into(), &mut status) })?;
+
+    Ok(status != 0)
+}
+
+fn execute_commands() -> crate::Result<()> {
+    ResultCode(unsafe { ctru_sys::csndExecCmds(true) })?;
+
+    Ok(())
+}
+
+fn update_info() -> crate::Result<()> {
+    ResultCode(unsafe { ctru_sys::CSND_UpdateInfo(true) })?;
+
+    Ok(())
+}
+
+fn acquire_capture_unit() -> crate::Result<u32> {
+    let mut id = 0;
+    ResultCode(unsafe { ctru_sys::CSND_AcquireCapUnit(&mut id) })?;
+
+    Ok(id)
+}
+
+/// Writes the cached contents of a buffer read by the hardware back to memory.
+fn flush_cache(buffer: &[u8]) -> crate::Result<()> {
+    ResultCode(unsafe {
+        ctru_sys::CSND_FlushDataCache(buffer.as_ptr().cast(), buffer.len() as u32)
+    })?;
+
+    Ok(())
+}
+
+/// Discards the cached contents of a buffer written by the hardware.
+fn invalidate_cache(buffer: &[u8]) -> crate::Result<()> {
+    ResultCode(unsafe {
+        ctru_sys::CSND_InvalidateDataCache(buffer.as_ptr().cast(), buffer.len() as u32)
+    })?;
+
+    Ok(())
+}
+
+impl Generations {
+    /// Starts a new generation of the channel, returning it.
+    fn start(&mut self, id: u8) -> u32 {
+        let generation = &mut self.0[id as usize];
+        *generation = generation.wrapping_add(1);
+        *generation
+    }
+
+    /// Returns whether no wave was started on the channel since the given generation.
+    fn is_current(&self, id: u8, generation: u32) -> bool {
+        self.0[id as usize] == generation
+    }
+}
+
+/// Checks that the hardware can be timed at the given rate.
+fn check_sample_rate(rate: f32) -> Result<(), CsndError> {
+    if rate > 0.0 && rate.is_finite() {
+        Ok(())
+    } else {
+        Err(CsndError::InvalidSampleRate(rate))
+    }
+}
+
+/// Returns the timer value making the hardware play or record at the given rate.
+fn timer(rate: f32) -> u32 {
+    (CLOCK_RATE / rate).clamp(0x42 as f32, 0xffff as f32) as u32
+}
+
+/// Packs the volume of both speakers, as expected by the channel registers.
+fn volume_register(volume: f32, pan: f32) -> u32 {
+    let [left, right] = sound::stereo_volumes(volume, pan);
+
+    (left * 0x8000 as f32) as u32 | ((right * 0x8000 as f32) as u32) << 16
+}
+
+/// Returns the flags starting the playback of a wave on the given channel.
+fn channel_flags(id: u8, encoding: Encoding, looping: bool, rate: f32) -> u32 {
+    let loop_mode = if looping {
+        ctru_sys::CSND_LOOPMODE_NORMAL
+    } else {
+        ctru_sys::CSND_LOOPMODE_ONESHOT
+    };
+
+    u32::from(id & 0x1f)
+        | loop_mode << 10
+        | (encoding as u32) << 12
+        | ctru_sys::SOUND_ENABLE
+        | timer(rate) << 16
+}
+
+impl From<crate::Error> for CsndError {
+    fn from(err: crate::Error) -> Self {
+        Self::Service(err)
+    }
+}
+
+impl fmt::Display for CsndError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::InvalidChannel(id) => write!(f, "sound Channel with ID {id} doesn't exist or is reserved by the system"),
+            Self::ChannelAlreadyInUse(id) => write!(f, "sound Channel with ID {id} is already being used. Drop the other instance if you want to use it here"),
+            Self::NoFreeChannel => write!(f, "every sound Channel is already being used"),
+            Self::WaveBusy(id) => write!(f, "the selected Wave is busy playing on channel {id}"),
+            Self::UnsupportedFormat(format) => write!(f, "CSND channels can't play {format:?} audio"),
+            Self::InvalidSampleRate(rate) => write!(f, "invalid sample rate of {rate} Hz"),
+            Self::Service(err) => write!(f, "{err}"),
+        }
+    }
+}
+
+impl error::Error for CsndError {}
+
+impl Drop for Wave {
+    fn drop(&mut self) {
+        if let Some(id) = self.playing_channel() {
+            unsafe { ctru_sys::CSND_SetPlayState(id.into(), 0) };
+            let _ = execute_commands();
+        }
+    }
+}
+
+impl<'csnd> Drop for Channel<'csnd> {
+    fn drop(&mut self) {
+        let _ = self.stop();
+    }
+}
+
+impl<'csnd> Drop for CaptureUnit<'csnd> {
+    fn drop(&mut self) {
+        let _ = self.stop();
+        let _ = unsafe { ctru_sys::CSND_ReleaseCapUnit(self.id) };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn registers() {
+        assert_eq!(timer(32728.0), 2048);
+        assert_eq!(timer(22050.0), 3039);
+        assert_eq!(timer(2_000_000.0), 0x42);
+        assert_eq!(timer(100.0), 0xffff);
+
+        assert!(check_sample_rate(22050.0).is_ok());
+        for rate in [0.0, -1.0, f32::NAN, f32::INFINITY] {
+            assert!(matches!(
+                check_sample_rate(rate),
+                Err(CsndError::InvalidSampleRate(_))
+            ));
+        }
+
+        assert_eq!(volume_register(1.0, 0.0), 0x4000_4000);
+        assert_eq!(volume_register(1.0, -1.0), 0x0000_8000);
+        assert_eq!(volume_register(0.5, 1.0), 0x4000_0000);
+
+        assert_eq!(
+            channel_flags(3, Encoding::Pcm16, true, 32728.0),
+            0x0800_0000
+                | ctru_sys::SOUND_ENABLE
+                | ctru_sys::SOUND_FORMAT_16BIT
+                | ctru_sys::SOUND_REPEAT
+                | 3
+        );
+        assert_eq!(
+            channel_flags(31, Encoding::ImaAdpcm, false, 32728.0),
+            0x0800_0000
+                | ctru_sys::SOUND_ENABLE
+                | ctru_sys::SOUND_FORMAT_ADPCM
+                | ctru_sys::SOUND_ONE_SHOT
+                | 31
+        );
+    }
+
+    #[test]
+    fn generations() {
+        let mut generations = Generations([0; NUMBER_OF_CHANNELS as usize]);
+        let first = generations.start(3);
+        assert!(generations.is_current(3, first));
+
+        // Replaying the channel releases the first wave, while other channels are unaffected.
+        let other = generations.start(4);
+        let second = generations.start(3);
+        assert!(!generations.is_current(3, first));
+        assert!(generations.is_current(3, second));
+        assert!(generations.is_current(4, other));
+    }
+
+    #[test]
+    fn sample_counts() {
+        assert_eq!(Encoding::Pcm8.sample_count(10), 10);
+        assert_eq!(Encoding::Pcm16.sample_count(10), 5);
+        assert_eq!(Encoding::ImaAdpcm.sample_count(10), 12);
+        assert_eq!(Encoding::ImaAdpcm.sample_count(2), 0);
+    }
+}
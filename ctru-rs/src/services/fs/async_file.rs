@@ -0,0 +1,648 @@
+//! Asynchronous file I/O
+//!
+//! The filesystem service only offers blocking calls. [`AsyncFile`] hands them over to a small pool of
+//! worker threads and wakes the task polling it once they complete, so that streaming a large asset
+//! doesn't stall the thread running the executor (and the render loop along with it).
+//!
+//! This module only gets compiled if the `futures-io` or `tokio` feature is enabled, which implement
+//! the asynchronous I/O traits of the respective crates. Both enable `std-threads`, since the worker
+//! pool needs thread support in the standard library.
+
+use std::future;
+use std::io::Error as IoError;
+use std::io::ErrorKind as IoErrorKind;
+use std::io::Result as IoResult;
+use std::io::SeekFrom;
+use std::mem;
+use std::pin::Pin;
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll, Waker};
+use std::thread;
+
+use super::File;
+
+/// Largest amount of data moved by a single operation.
+const MAX_BUF_SIZE: usize = 64 * 1024;
+
+/// Amount of threads of the worker pool.
+const WORKER_COUNT: usize = 2;
+
+/// A [`File`] with asynchronous reads, writes and seeks.
+///
+/// Reads are served from an internal buffer, refilled by a worker thread when it runs out.
+/// Writes are copied to the buffer and return immediately while a worker thread writes them,
+/// so errors may only be reported by the next operation. Flush or close the file to make sure that
+/// every write succeeded.
+///
+/// # Examples
+///
+/// ```no_run
+/// # async fn example() -> std::io::Result<()> {
+/// use futures::io::AsyncReadExt;
+///
+/// use ctru::services::fs::{AsyncFile, File, Fs};
+///
+/// let fs = Fs::init().unwrap();
+/// let sdmc = fs.sdmc().unwrap();
+///
+/// let mut file = AsyncFile::new(File::open(&sdmc, "/3ds/app/level.bin")?);
+/// let mut level = Vec::new();
+/// file.read_to_end(&mut level).await?;
+/// # Ok(())
+/// # }
+/// ```
+pub struct AsyncFile {
+    core: Core<File>,
+}
+
+impl AsyncFile {
+    /// Wraps a file, starting from its current position.
+    pub fn new(file: File) -> Self {
+        let offset = file.offset;
+
+        Self {
+            core: Core::new(file, offset),
+        }
+    }
+
+    /// Waits for the pending operations, and returns the file positioned where this one is.
+    ///
+    /// # Errors
+    ///
+    /// This function will return the error of the last write, if it failed.
+    pub async fn into_file(mut self) -> IoResult<File> {
+        future::poll_fn(|cx| self.core.poll_flush(cx)).await?;
+
+        let offset = self.core.offset;
+        // The workers let go of the file before completing their operation.
+        let mut file = Arc::try_unwrap(self.core.file)
+            .unwrap_or_else(|_| unreachable!("no operation is pending"));
+        file.offset = offset;
+        Ok(file)
+    }
+}
+
+impl From<File> for AsyncFile {
+    fn from(file: File) -> Self {
+        Self::new(file)
+    }
+}
+
+#[cfg(feature = "futures-io")]
+impl futures_io::AsyncRead for AsyncFile {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut [u8],
+    ) -> Poll<IoResult<usize>> {
+        self.get_mut().core.poll_read(cx, buf)
+    }
+}
+
+#[cfg(feature = "futures-io")]
+impl futures_io::AsyncWrite for AsyncFile {
+    fn poll_write(self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &[u8]) -> Poll<IoResult<usize>> {
+        self.get_mut().core.poll_write(cx, buf)
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<IoResult<()>> {
+        self.get_mut().core.poll_flush(cx)
+    }
+
+    fn poll_close(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<IoResult<()>> {
+        self.get_mut().core.poll_flush(cx)
+    }
+}
+
+#[cfg(feature = "futures-io")]
+impl futures_io::AsyncSeek for AsyncFile {
+    fn poll_seek(self: Pin<&mut Self>, cx: &mut Context<'_>, pos: SeekFrom) -> Poll<IoResult<u64>> {
+        self.get_mut().core.poll_seek(cx, pos)
+    }
+}
+
+#[cfg(feature = "tokio")]
+impl tokio::io::AsyncRead for AsyncFile {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut tokio::io::ReadBuf<'_>,
+    ) -> Poll<IoResult<()>> {
+        let n = std::task::ready!(self.get_mut().core.poll_read(cx, buf.initialize_unfilled()))?;
+        buf.advance(n);
+        Poll::Ready(Ok(()))
+    }
+}
+
+#[cfg(feature = "tokio")]
+impl tokio::io::AsyncWrite for AsyncFile {
+    fn poll_write(self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &[u8]) -> Poll<IoResult<usize>> {
+        self.get_mut().core.poll_write(cx, buf)
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<IoResult<()>> {
+        self.get_mut().core.poll_flush(cx)
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<IoResult<()>> {
+        self.get_mut().core.poll_flush(cx)
+    }
+}
+
+#[cfg(feature = "tokio")]
+impl tokio::io::AsyncSeek for AsyncFile {
+    fn start_seek(self: Pin<&mut Self>, pos: SeekFrom) -> IoResult<()> {
+        let core = &mut self.get_mut().core;
+        if core.seek.is_some() {
+            return Err(IoError::new(
+                IoErrorKind::Other,
+                "other file operation is pending, call poll_complete before start_seek",
+            ));
+        }
+
+        core.seek = Some(pos);
+        Ok(())
+    }
+
+    fn poll_complete(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<IoResult<u64>> {
+        let core = &mut self.get_mut().core;
+        match core.seek {
+            Some(pos) => {
+                let result = std::task::ready!(core.poll_seek(cx, pos));
+                core.seek = None;
+                Poll::Ready(result)
+            }
+            None => core.poll_seek(cx, SeekFrom::Current(0)),
+        }
+    }
+}
+
+/// Blocking positional I/O, run by the worker threads.
+trait BlockingFile: Send + Sync + 'static {
+    fn read_at(&self, buf: &mut [u8], offset: u64) -> IoResult<usize>;
+
+    fn write_at(&self, buf: &[u8], offset: u64) -> IoResult<usize>;
+
+    fn len(&self) -> IoResult<u64>;
+}
+
+impl BlockingFile for File {
+    fn read_at(&self, buf: &mut [u8], offset: u64) -> IoResult<usize> {
+        File::read_at(self, buf, offset)
+    }
+
+    fn write_at(&self, buf: &[u8], offset: u64) -> IoResult<usize> {
+        File::write_at(self, buf, offset)
+    }
+
+    fn len(&self) -> IoResult<u64> {
+        self.metadata().map(|metadata| metadata.len())
+    }
+}
+
+/// State machine behind [`AsyncFile`], generic over the file so it can be tested on the host.
+struct Core<F> {
+    file: Arc<F>,
+    /// Position of the file as seen by the user, which excludes the data left in the buffer.
+    offset: u64,
+    state: State,
+    #[cfg_attr(not(feature = "tokio"), allow(dead_code))]
+    seek: Option<SeekFrom>,
+}
+
+enum State {
+    Idle(Buf),
+    Busy(Task<(Operation, Buf)>),
+}
+
+/// Outcome of an operation run by a worker.
+enum Operation {
+    Read(IoResult<usize>),
+    Write(IoResult<()>),
+    Len(IoResult<u64>),
+}
+
+#[derive(Default)]
+struct Buf {
+    data: Vec<u8>,
+    pos: usize,
+}
+
+impl<F: BlockingFile> Core<F> {
+    fn new(file: F, offset: u64) -> Self {
+        Self {
+            file: Arc::new(file),
+            offset,
+            state: State::Idle(Buf::default()),
+            seek: None,
+        }
+    }
+
+    /// Runs an operation on a worker thread, moving the buffer along with it.
+    fn start(&mut self, mut buf: Buf, f: impl FnOnce(&F, &mut Buf) -> Operation + Send + 'static) {
+        let file = self.file.clone();
+        self.state = State::Busy(spawn(move || {
+            let operation = f(&file, &mut buf);
+            (operation, buf)
+        }));
+    }
+
+    /// Waits for the pending operation, if any.
+    fn poll_pending(&mut self, cx: &mut Context<'_>) -> Poll<Option<Operation>> {
+        match &mut self.state {
+            State::Idle(_) => Poll::Ready(None),
+            State::Busy(task) => {
+                let (operation, buf) = std::task::ready!(task.poll(cx));
+                self.state = State::Idle(buf);
+                Poll::Ready(Some(operation))
+            }
+        }
+    }
+
+    fn poll_read(&mut self, cx: &mut Context<'_>, dst: &mut [u8]) -> Poll<IoResult<usize>> {
+        loop {
+            match std::task::ready!(self.poll_pending(cx)) {
+                Some(Operation::Read(Ok(0))) => return Poll::Ready(Ok(0)),
+                Some(Operation::Read(Err(e)) | Operation::Write(Err(e))) => {
+                    return Poll::Ready(Err(e))
+                }
+                _ => {}
+            }
+
+            let buf = match &mut self.state {
+                State::Idle(buf) => buf,
+                State::Busy(_) => unreachable!(),
+            };
+
+            if buf.pos < buf.data.len() || dst.is_empty() {
+                let n = dst.len().min(buf.data.len() - buf.pos);
+                dst[..n].copy_from_slice(&buf.data[buf.pos..][..n]);
+                buf.pos += n;
+                self.offset += n as u64;
+                return Poll::Ready(Ok(n));
+            }
+
+            let (offset, len) = (self.offset, dst.len().min(MAX_BUF_SIZE));
+            let buf = mem::take(buf);
+            self.start(buf, move |file, buf| {
+                buf.data.resize(len, 0);
+                buf.pos = 0;
+                let result = file.read_at(&mut buf.data, offset);
+                buf.data.truncate(*result.as_ref().unwrap_or(&0));
+                Operation::Read(result)
+            });
+        }
+    }
+
+    fn poll_write(&mut self, cx: &mut Context<'_>, src: &[u8]) -> Poll<IoResult<usize>> {
+        if let Some(Operation::Write(Err(e))) = std::task::ready!(self.poll_pending(cx)) {
+            return Poll::Ready(Err(e));
+        }
+
+        let buf = match &mut self.state {
+            State::Idle(buf) => mem::take(buf),
+            State::Busy(_) => unreachable!(),
+        };
+        if src.is_empty() {
+            self.state = State::Idle(buf);
+            return Poll::Ready(Ok(0));
+        }
+
+        // Unread data is dropped, the next read starts from the new position.
+        let n = src.len().min(MAX_BUF_SIZE);
+        let mut buf = Buf {
+            data: buf.data,
+            pos: 0,
+        };
+        buf.data.clear();
+        buf.data.extend_from_slice(&src[..n]);
+
+        let offset = self.offset;
+        self.offset += n as u64;
+        self.start(buf, move |file, buf| {
+            let mut written = 0;
+            while written < buf.data.len() {
+                match file.write_at(&buf.data[written..], offset + written as u64) {
+                    Ok(0) => {
+                        return Operation::Write(Err(IoErrorKind::WriteZero.into()));
+                    }
+                    Ok(n) => written += n,
+                    Err(e) => return Operation::Write(Err(e)),
+                }
+            }
+            buf.data.clear();
+            Operation::Write(Ok(()))
+        });
+
+        Poll::Ready(Ok(n))
+    }
+
+    fn poll_flush(&mut self, cx: &mut Context<'_>) -> Poll<IoResult<()>> {
+        match std::task::ready!(self.poll_pending(cx)) {
+            Some(Operation::Write(Err(e))) => Poll::Ready(Err(e)),
+            _ => Poll::Ready(Ok(())),
+        }
+    }
+
+    fn poll_seek(&mut self, cx: &mut Context<'_>, pos: SeekFrom) -> Poll<IoResult<u64>> {
+        loop {
+            let len = match std::task::ready!(self.poll_pending(cx)) {
+                Some(Operation::Write(Err(e)) | Operation::Len(Err(e))) => {
+                    return Poll::Ready(Err(e))
+                }
+                Some(Operation::Len(Ok(len))) => Some(len),
+                _ => None,
+            };
+
+            let offset = match (pos, len) {
+                (SeekFrom::Start(offset), _) => Some(offset),
+                (SeekFrom::Current(delta), _) => offset_by(self.offset, delta),
+                (SeekFrom::End(delta), Some(len)) => offset_by(len, delta),
+                (SeekFrom::End(_), None) => {
+                    let buf = match &mut self.state {
+                        State::Idle(buf) => mem::take(buf),
+                        State::Busy(_) => unreachable!(),
+                    };
+                    self.start(buf, |file, _| Operation::Len(file.len()));
+                    continue;
+                }
+            };
+
+            return Poll::Ready(match offset {
+                Some(offset) => {
+                    if offset != self.offset {
+                        if let State::Idle(buf) = &mut self.state {
+                            buf.data.clear();
+                            buf.pos = 0;
+                        }
+                        self.offset = offset;
+                    }
+                    Ok(offset)
+                }
+                None => Err(IoError::new(
+                    IoErrorKind::InvalidInput,
+                    "invalid seek to a negative or overflowing position",
+                )),
+            });
+        }
+    }
+}
+
+fn offset_by(base: u64, delta: i64) -> Option<u64> {
+    if delta < 0 {
+        base.checked_sub(delta.unsigned_abs())
+    } else {
+        base.checked_add(delta as u64)
+    }
+}
+
+type Job = Box<dyn FnOnce() + Send>;
+
+/// Sender of the jobs to the worker pool, which is started by the first operation.
+static WORKERS: Mutex<Option<Sender<Job>>> = Mutex::new(None);
+
+/// Handle to the result of a job running on the worker pool.
+struct Task<T> {
+    slot: Arc<Mutex<Slot<T>>>,
+}
+
+struct Slot<T> {
+    result: Option<T>,
+    waker: Option<Waker>,
+}
+
+fn spawn<T: Send + 'static>(job: impl FnOnce() -> T + Send + 'static) -> Task<T> {
+    let slot = Arc::new(Mutex::new(Slot {
+        result: None,
+        waker: None,
+    }));
+
+    let task_slot = slot.clone();
+    let job: Job = Box::new(move || {
+        let result = job();
+        let mut slot = task_slot.lock().unwrap();
+        slot.result = Some(result);
+        if let Some(waker) = slot.waker.take() {
+            waker.wake();
+        }
+    });
+
+    let mut workers = WORKERS.lock().unwrap();
+    let sender = workers.get_or_insert_with(|| {
+        let (sender, receiver) = mpsc::channel();
+        let receiver = Arc::new(Mutex::new(receiver));
+        for _ in 0..WORKER_COUNT {
+            let receiver = receiver.clone();
+            thread::Builder::new()
+                .name("fs-worker".into())
+                .spawn(move || work(&receiver))
+                .expect("failed to start the filesystem worker threads");
+        }
+        sender
+    });
+    sender.send(job).expect("filesystem worker threads stopped");
+
+    Task { slot }
+}
+
+fn work(receiver: &Mutex<Receiver<Job>>) {
+    loop {
+        let job = receiver.lock().unwrap().recv();
+        match job {
+            Ok(job) => job(),
+            Err(_) => return,
+        }
+    }
+}
+
+impl<T> Task<T> {
+    fn poll(&mut self, cx: &mut Context<'_>) -> Poll<T> {
+        let mut slot = self.slot.lock().unwrap();
+        match slot.result.take() {
+            Some(result) => Poll::Ready(result),
+            None => {
+                slot.waker = Some(cx.waker().clone());
+                Poll::Pending
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::task::Wake;
+
+    /// An in-memory file, optionally holding every operation until [`MemoryFile::release`] is called.
+    #[derive(Default)]
+    struct MemoryFile {
+        data: Mutex<Vec<u8>>,
+        held: Mutex<Option<Receiver<()>>>,
+    }
+
+    impl MemoryFile {
+        fn new(data: &[u8]) -> Self {
+            Self {
+                data: Mutex::new(data.to_vec()),
+                held: Mutex::new(None),
+            }
+        }
+
+        /// Holds the operations, returning the sender used to release them one by one.
+        fn hold(&self) -> Sender<()> {
+            let (sender, receiver) = mpsc::channel();
+            *self.held.lock().unwrap() = Some(receiver);
+            sender
+        }
+
+        fn wait(&self) {
+            if let Some(receiver) = &*self.held.lock().unwrap() {
+                receiver.recv().unwrap();
+            }
+        }
+    }
+
+    impl BlockingFile for MemoryFile {
+        fn read_at(&self, buf: &mut [u8], offset: u64) -> IoResult<usize> {
+            self.wait();
+            let data = self.data.lock().unwrap();
+            let data = data.get(offset as usize..).unwrap_or_default();
+            let n = buf.len().min(data.len());
+            buf[..n].copy_from_slice(&data[..n]);
+            Ok(n)
+        }
+
+        fn write_at(&self, buf: &[u8], offset: u64) -> IoResult<usize> {
+            self.wait();
+            let mut data = self.data.lock().unwrap();
+            if offset > 16 {
+                return Err(IoErrorKind::PermissionDenied.into());
+            }
+            let end = offset as usize + buf.len();
+            if data.len() < end {
+                data.resize(end, 0);
+            }
+            data[offset as usize..end].copy_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn len(&self) -> IoResult<u64> {
+            self.wait();
+            Ok(self.data.lock().unwrap().len() as u64)
+        }
+    }
+
+    /// Counts its wake-ups and unparks the thread which created it.
+    struct TestWaker {
+        wakes: AtomicUsize,
+        thread: thread::Thread,
+    }
+
+    impl Wake for TestWaker {
+        fn wake(self: Arc<Self>) {
+            self.wakes.fetch_add(1, Ordering::SeqCst);
+            self.thread.unpark();
+        }
+    }
+
+    fn test_waker() -> (Arc<TestWaker>, Waker) {
+        let waker = Arc::new(TestWaker {
+            wakes: AtomicUsize::new(0),
+            thread: thread::current(),
+        });
+        (waker.clone(), waker.into())
+    }
+
+    fn block_on<T>(mut poll: impl FnMut(&mut Context<'_>) -> Poll<T>) -> T {
+        let (_, waker) = test_waker();
+        let mut cx = Context::from_waker(&waker);
+        loop {
+            match poll(&mut cx) {
+                Poll::Ready(result) => return result,
+                Poll::Pending => thread::park(),
+            }
+        }
+    }
+
+    #[test]
+    fn reading() {
+        let mut core = Core::new(MemoryFile::new(b"0123456789"), 2);
+        let release = core.file.hold();
+        let (counter, waker) = test_waker();
+        let mut cx = Context::from_waker(&waker);
+
+        let mut buf = [0; 4];
+        assert!(core.poll_read(&mut cx, &mut buf).is_pending());
+        assert!(core.poll_read(&mut cx, &mut buf).is_pending());
+
+        release.send(()).unwrap();
+        while counter.wakes.load(Ordering::SeqCst) == 0 {
+            thread::park();
+        }
+        assert_eq!(
+            core.poll_read(&mut cx, &mut buf[..3]).map(Result::unwrap),
+            Poll::Ready(3)
+        );
+        assert_eq!(&buf[..3], b"234");
+
+        // The rest of the buffer is served without another operation.
+        assert_eq!(
+            core.poll_read(&mut cx, &mut buf).map(Result::unwrap),
+            Poll::Ready(1)
+        );
+        assert_eq!(buf[0], b'5');
+        assert_eq!(core.offset, 6);
+
+        drop(core.file.held.lock().unwrap().take());
+        let n = block_on(|cx| core.poll_read(cx, &mut buf)).unwrap();
+        assert_eq!(&buf[..n], b"6789");
+        assert_eq!(block_on(|cx| core.poll_read(cx, &mut buf)).unwrap(), 0);
+    }
+
+    #[test]
+    fn writing() {
+        let mut core = Core::new(MemoryFile::new(b"0123456789"), 0);
+        let release = core.file.hold();
+        let (_, waker) = test_waker();
+        let mut cx = Context::from_waker(&waker);
+
+        // Writes complete immediately, but the next one waits for the previous one.
+        assert_eq!(
+            core.poll_write(&mut cx, b"ab").map(Result::unwrap),
+            Poll::Ready(2)
+        );
+        assert!(core.poll_write(&mut cx, b"cd").is_pending());
+        release.send(()).unwrap();
+        assert_eq!(block_on(|cx| core.poll_write(cx, b"cd")).unwrap(), 2);
+        release.send(()).unwrap();
+        block_on(|cx| core.poll_flush(cx)).unwrap();
+        assert_eq!(&*core.file.data.lock().unwrap(), b"abcd456789");
+
+        // Seeking from the end asks for the length of the file.
+        drop(core.file.held.lock().unwrap().take());
+        assert_eq!(
+            block_on(|cx| core.poll_seek(cx, SeekFrom::End(-2))).unwrap(),
+            8
+        );
+        let mut buf = [0; 8];
+        let n = block_on(|cx| core.poll_read(cx, &mut buf)).unwrap();
+        assert_eq!(&buf[..n], b"89");
+
+        assert_eq!(
+            block_on(|cx| core.poll_seek(cx, SeekFrom::Current(-20)))
+                .unwrap_err()
+                .kind(),
+            IoErrorKind::InvalidInput
+        );
+
+        // Errors are reported by the following operation.
+        block_on(|cx| core.poll_seek(cx, SeekFrom::Start(20))).unwrap();
+        assert_eq!(block_on(|cx| core.poll_write(cx, b"ef")).unwrap(), 2);
+        assert_eq!(
+            block_on(|cx| core.poll_flush(cx)).unwrap_err().kind(),
+            IoErrorKind::PermissionDenied
+        );
+    }
+}
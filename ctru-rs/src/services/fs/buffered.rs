@@ -0,0 +1,215 @@
+//! Buffered reading aligned to the sectors of the storage medium
+
+use std::io::Error as IoError;
+use std::io::ErrorKind as IoErrorKind;
+use std::io::Result as IoResult;
+use std::io::{BufRead, Read, Seek, SeekFrom};
+
+use super::File;
+
+/// Size of the sectors of the SD card, in bytes.
+const SECTOR_SIZE: usize = 512;
+
+/// Default capacity of a [`SectorReader`].
+const DEFAULT_CAPACITY: usize = 32 * SECTOR_SIZE;
+
+/// A buffered reader over a [`File`], tuned for random access.
+///
+/// The buffer is always refilled from a sector boundary, which is the granularity the SD card
+/// is read with anyway. Unlike [`std::io::BufReader`], seeking keeps the buffer when the new position
+/// falls inside of it, so many small reads around the same area of a large file only cost one request
+/// to the filesystem service. Reads larger than the buffer bypass it.
+///
+/// # Examples
+///
+/// ```no_run
+/// use std::io::{Read, Seek, SeekFrom};
+///
+/// use ctru::services::fs::{File, Fs, SectorReader};
+///
+/// let fs = Fs::init().unwrap();
+/// let sdmc = fs.sdmc().unwrap();
+/// let mut pack = SectorReader::new(File::open(&sdmc, "/3ds/app/assets.pak").unwrap());
+///
+/// // Both entries of the index are read with a single request.
+/// let mut entry = [0; 8];
+/// pack.seek(SeekFrom::Start(0x40)).unwrap();
+/// pack.read_exact(&mut entry).unwrap();
+/// pack.read_at(&mut entry, 0x80).unwrap();
+/// ```
+pub struct SectorReader {
+    file: File,
+    buf: Box<[u8]>,
+    /// Offset of the first buffered byte in the file, always a multiple of the sector size.
+    buf_start: u64,
+    /// Amount of valid bytes in the buffer.
+    filled: usize,
+    pos: u64,
+}
+
+impl SectorReader {
+    /// Creates a reader with a 16 KiB buffer, starting from the current position of the file.
+    pub fn new(file: File) -> Self {
+        Self::with_capacity(DEFAULT_CAPACITY, file)
+    }
+
+    /// Creates a reader with a buffer of at least `capacity` bytes, rounded up to a whole number of
+    /// sectors.
+    pub fn with_capacity(capacity: usize, file: File) -> Self {
+        let sectors = ((capacity + SECTOR_SIZE - 1) / SECTOR_SIZE).max(1);
+
+        Self {
+            pos: file.offset,
+            file,
+            buf: vec![0; sectors * SECTOR_SIZE].into_boxed_slice(),
+            buf_start: 0,
+            filled: 0,
+        }
+    }
+
+    /// Returns the capacity of the buffer.
+    pub fn capacity(&self) -> usize {
+        self.buf.len()
+    }
+
+    /// Returns a reference to the underlying file.
+    pub fn get_ref(&self) -> &File {
+        &self.file
+    }
+
+    /// Returns the underlying file, with its cursor moved to the position of the reader.
+    pub fn into_inner(mut self) -> File {
+        self.file.offset = self.pos;
+        self.file
+    }
+
+    /// Reads a number of bytes starting from a given offset, without moving the reader.
+    ///
+    /// Like [`Read::read`], this may read less than the length of `buf` when the data crosses the
+    /// end of the buffer. A return value of 0 means that the end of the file was reached.
+    pub fn read_at(&mut self, buf: &mut [u8], offset: u64) -> IoResult<usize> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        if self.buffered(offset).is_none() {
+            if buf.len() >= self.buf.len() {
+                return self.file.read_at(buf, offset);
+            }
+            self.refill(offset)?;
+        }
+
+        let data = self.buffered(offset).unwrap_or_default();
+        let n = buf.len().min(data.len());
+        buf[..n].copy_from_slice(&data[..n]);
+        Ok(n)
+    }
+
+    /// Returns the buffered data starting from `offset`, if any.
+    fn buffered(&self, offset: u64) -> Option<&[u8]> {
+        let start = offset.checked_sub(self.buf_start)?;
+        if start < self.filled as u64 {
+            Some(&self.buf[start as usize..self.filled])
+        } else {
+            None
+        }
+    }
+
+    /// Fills the buffer from the start of the sector holding `offset`.
+    fn refill(&mut self, offset: u64) -> IoResult<()> {
+        self.buf_start = offset - offset % SECTOR_SIZE as u64;
+        self.filled = 0;
+        self.filled = self.file.read_at(&mut self.buf, self.buf_start)?;
+        Ok(())
+    }
+}
+
+impl Read for SectorReader {
+    fn read(&mut self, buf: &mut [u8]) -> IoResult<usize> {
+        let n = self.read_at(buf, self.pos)?;
+        self.pos += n as u64;
+        Ok(n)
+    }
+}
+
+impl BufRead for SectorReader {
+    fn fill_buf(&mut self) -> IoResult<&[u8]> {
+        if self.buffered(self.pos).is_none() {
+            self.refill(self.pos)?;
+        }
+
+        Ok(self.buffered(self.pos).unwrap_or_default())
+    }
+
+    fn consume(&mut self, amt: usize) {
+        self.pos += amt as u64;
+    }
+}
+
+impl Seek for SectorReader {
+    fn seek(&mut self, pos: SeekFrom) -> IoResult<u64> {
+        let (base, delta) = match pos {
+            SeekFrom::Start(offset) => (offset, 0),
+            SeekFrom::Current(delta) => (self.pos, delta),
+            SeekFrom::End(delta) => (self.file.metadata()?.len(), delta),
+        };
+
+        let pos = if delta < 0 {
+            base.checked_sub(delta.unsigned_abs())
+        } else {
+            base.checked_add(delta as u64)
+        };
+        self.pos = pos.ok_or_else(|| {
+            IoError::new(
+                IoErrorKind::InvalidInput,
+                "invalid seek to a negative or overflowing position",
+            )
+        })?;
+        Ok(self.pos)
+    }
+}
+
+#[cfg(all(test, feature = "host-mock"))]
+mod tests {
+    use super::*;
+    use crate::services::fs::Fs;
+    use std::io::Write;
+
+    #[test]
+    fn sector_reader() {
+        let fs = Fs::init().unwrap();
+        let sdmc = fs.sdmc().unwrap();
+        let data: Vec<u8> = (0..3000u32).map(|i| (i % 251) as u8).collect();
+        File::create(&sdmc, "/assets.pak")
+            .unwrap()
+            .write_all(&data)
+            .unwrap();
+
+        let mut reader =
+            SectorReader::with_capacity(1000, File::open(&sdmc, "/assets.pak").unwrap());
+        assert_eq!(reader.capacity(), 1024);
+
+        let mut buf = [0; 100];
+        reader.seek(SeekFrom::Start(1000)).unwrap();
+        reader.read_exact(&mut buf).unwrap();
+        assert_eq!(&buf[..], &data[1000..1100]);
+
+        // The buffer starts at the sector holding offset 1000, and ends 1024 bytes later.
+        assert_eq!(reader.read_at(&mut buf, 1500).unwrap(), 36);
+        assert_eq!(&buf[..36], &data[1500..1536]);
+        assert_eq!(reader.read_at(&mut buf, 600).unwrap(), 100);
+        assert_eq!(&buf[..], &data[600..700]);
+
+        // Reading past the end, and reads bypassing the buffer.
+        assert_eq!(reader.seek(SeekFrom::End(-10)).unwrap(), 2990);
+        assert_eq!(reader.fill_buf().unwrap(), &data[2990..]);
+        reader.consume(10);
+        assert_eq!(reader.read(&mut buf).unwrap(), 0);
+        let mut large = vec![0; 2000];
+        assert_eq!(reader.read_at(&mut large, 500).unwrap(), 2000);
+        assert_eq!(large, &data[500..2500]);
+        assert!(reader.seek(SeekFrom::Current(-4000)).is_err());
+
+        let mut file = reader.into_inner();
+        assert_eq!(file.stream_position().unwrap(), 3000);
+    }
+}
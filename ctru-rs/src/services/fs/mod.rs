@@ -1,21 +1,45 @@
 //! Filesystem service
 //!
 //! This module contains basic methods to manipulate the contents of the 3DS's filesystem.
-//! Only the SD card is currently supported.
+//! Besides the SD card, archives such as save data and extra data can be opened with [`Fs::open_archive`].
+//! Directory trees can be walked, copied and searched with [`walk_dir`], [`copy_dir_all`] and [`glob`].
+//! Large files can be read at random positions with [`File::read_at`] or through a [`SectorReader`].
 
 use bitflags::bitflags;
 use std::ffi::OsString;
 use std::io::Error as IoError;
 use std::io::ErrorKind as IoErrorKind;
 use std::io::Result as IoResult;
-use std::io::{Read, Seek, SeekFrom, Write};
+use std::io::{self, IoSlice, IoSliceMut, Read, Seek, SeekFrom, Write};
 use std::mem;
 use std::path::{Path, PathBuf};
 use std::ptr;
 use std::slice;
 use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 use widestring::{WideCStr, WideCString};
 
+use crate::error::ResultCode;
+
+#[cfg(feature = "host-mock")]
+use crate::mock::sys as ctru_sys;
+
+#[cfg(any(feature = "futures-io", feature = "tokio"))]
+mod async_file;
+mod buffered;
+mod walk;
+
+#[cfg(any(feature = "futures-io", feature = "tokio"))]
+pub use async_file::AsyncFile;
+pub use buffered::SectorReader;
+pub use walk::{glob, walk_dir, DirTree, WalkDir, WalkEntry};
+
+/// Size of the blocks of save data archives.
+const SAVE_DATA_BLOCK_SIZE: u32 = 0x200;
+
+/// High half of the ID of every shared extra data archive.
+const SHARED_EXTDATA_HIGH_ID: u32 = 0x0004_8000;
+
 bitflags! {
     #[derive(Default)]
     struct FsOpen: u32 {
@@ -86,6 +110,29 @@ pub enum ArchiveID {
     DemoSavedata,
 }
 
+/// Path selecting one archive among the ones sharing an [`ArchiveID`], e.g. the save data of a given title.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ArchivePath {
+    /// No path, for archives that only exist once, such as [`ArchiveID::Sdmc`]
+    /// or [`ArchiveID::Savedata`] (the save data of the running title).
+    Empty,
+    /// A binary path, usually built with one of the constructors of this type.
+    Binary(Vec<u8>),
+}
+
+/// Layout of a save data archive, chosen when formatting it.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct SaveDataFormat {
+    /// Maximum amount of directories in the archive.
+    pub max_directories: u32,
+    /// Maximum amount of files in the archive.
+    pub max_files: u32,
+    /// Whether the archive keeps a second copy of its contents. Changes to such an archive only
+    /// take effect once committed with [`Archive::commit_save_data`], so that an interrupted write
+    /// can't corrupt the save.
+    pub duplicate_data: bool,
+}
+
 /// Represents the filesystem service. No file IO can be performed
 /// until an instance of this struct is created.
 ///
@@ -166,6 +213,8 @@ pub struct Archive {
 pub struct File {
     handle: u32,
     offset: u64,
+    arch_handle: u64,
+    path: WideCString,
 }
 
 /// Metadata information about a file.
@@ -177,6 +226,18 @@ pub struct File {
 pub struct Metadata {
     attributes: u32,
     size: u64,
+    // Archive handle and path of a file, whose modification time is only queried when asked for.
+    file: Option<(u64, WideCString)>,
+}
+
+/// Attributes of a file which can be changed.
+///
+/// This structure is returned from [`Metadata::permissions`], and can be applied with
+/// [`File::set_permissions`] or [`set_permissions`]. Besides the read-only flag,
+/// it holds the hidden and archive flags of the file.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct Permissions {
+    attributes: FsAttribute,
 }
 
 /// Options and flags which can be used to configure how a [`File`] is opened.
@@ -309,17 +370,89 @@ impl Fs {
 
     /// Returns a handle to the SDMC (memory card) Archive.
     pub fn sdmc(&self) -> crate::Result<Archive> {
+        self.open_archive(ArchiveID::Sdmc, ArchivePath::Empty)
+    }
+
+    /// Opens an archive, such as the save data or the extra data of a title.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the archive doesn't exist, if the title lacks
+    /// the permissions to access it, or if it is a save data archive that was never formatted
+    /// (see [`Fs::format_save_data`]).
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use ctru::services::fs::{ArchiveID, ArchivePath, Fs, FsMediaType};
+    ///
+    /// let fs = Fs::init().unwrap();
+    ///
+    /// let save_data = fs.open_archive(ArchiveID::Savedata, ArchivePath::Empty).unwrap();
+    /// let extdata = fs
+    ///     .open_archive(ArchiveID::Extdata, ArchivePath::extdata(FsMediaType::Sd, 0x1234))
+    ///     .unwrap();
+    /// ```
+    pub fn open_archive(&self, id: ArchiveID, path: ArchivePath) -> crate::Result<Archive> {
+        let mut handle = 0;
+
         unsafe {
-            let mut handle = 0;
-            let id = ArchiveID::Sdmc;
-            let path = ctru_sys::fsMakePath(PathType::Empty.into(), ptr::null() as _);
-            let r = ctru_sys::FSUSER_OpenArchive(&mut handle, id.into(), path);
-            if r < 0 {
-                Err(crate::Error::from(r))
-            } else {
-                Ok(Archive { handle, id })
-            }
+            ResultCode(ctru_sys::FSUSER_OpenArchive(
+                &mut handle,
+                id.into(),
+                path.as_raw(),
+            ))?;
+        }
+
+        Ok(Archive { handle, id })
+    }
+
+    /// Formats a save data archive, erasing its contents.
+    ///
+    /// Save data archives must be formatted once before they can be opened.
+    pub fn format_save_data(
+        &self,
+        id: ArchiveID,
+        path: ArchivePath,
+        format: SaveDataFormat,
+    ) -> crate::Result<()> {
+        unsafe {
+            ResultCode(ctru_sys::FSUSER_FormatSaveData(
+                id.into(),
+                path.as_raw(),
+                SAVE_DATA_BLOCK_SIZE,
+                format.max_directories,
+                format.max_files,
+                format.max_directories,
+                format.max_files,
+                format.duplicate_data,
+            ))?;
+        }
+
+        Ok(())
+    }
+
+    /// Returns the layout a save data archive was formatted with.
+    pub fn save_data_format(
+        &self,
+        id: ArchiveID,
+        path: ArchivePath,
+    ) -> crate::Result<SaveDataFormat> {
+        let mut total_size = 0;
+        let mut format = SaveDataFormat::default();
+
+        unsafe {
+            ResultCode(ctru_sys::FSUSER_GetFormatInfo(
+                &mut total_size,
+                &mut format.max_directories,
+                &mut format.max_files,
+                &mut format.duplicate_data,
+                id.into(),
+                path.as_raw(),
+            ))?;
         }
+
+        Ok(format)
     }
 }
 
@@ -330,6 +463,88 @@ impl Archive {
     pub fn get_id(&self) -> ArchiveID {
         self.id
     }
+
+    /// Commits the changes made to a save data archive.
+    ///
+    /// Archives formatted with [`SaveDataFormat::duplicate_data`] discard the changes that
+    /// weren't committed when they are closed.
+    pub fn commit_save_data(&self) -> crate::Result<()> {
+        unsafe {
+            ResultCode(ctru_sys::FSUSER_ControlArchive(
+                self.handle,
+                ctru_sys::ARCHIVE_ACTION_COMMIT_SAVE_DATA,
+                ptr::null_mut(),
+                0,
+                ptr::null_mut(),
+                0,
+            ))?;
+        }
+
+        Ok(())
+    }
+
+    /// Returns the amount of free space in the archive, in bytes.
+    pub fn free_space(&self) -> crate::Result<u64> {
+        let mut free_bytes = 0;
+
+        unsafe {
+            ResultCode(ctru_sys::FSUSER_GetFreeBytes(&mut free_bytes, self.handle))?;
+        }
+
+        Ok(free_bytes)
+    }
+}
+
+impl ArchivePath {
+    /// Returns the path of the save data of a title, for [`ArchiveID::UserSavedata`].
+    pub fn user_save_data(media_type: FsMediaType, title_id: u64) -> Self {
+        Self::from_words(&[media_type as u32, title_id as u32, (title_id >> 32) as u32])
+    }
+
+    /// Returns the path of an extra data archive, for [`ArchiveID::Extdata`] and [`ArchiveID::BossExtdata`].
+    pub fn extdata(media_type: FsMediaType, extdata_id: u64) -> Self {
+        Self::from_words(&[
+            media_type as u32,
+            extdata_id as u32,
+            (extdata_id >> 32) as u32,
+        ])
+    }
+
+    /// Returns the path of a shared extra data archive (e.g. `0xF000000B`), for [`ArchiveID::SharedExtdata`].
+    pub fn shared_extdata(extdata_id: u32) -> Self {
+        Self::from_words(&[FsMediaType::Nand as u32, extdata_id, SHARED_EXTDATA_HIGH_ID])
+    }
+
+    /// Returns the path of a system save data archive, for [`ArchiveID::SystemSavedata`].
+    pub fn system_save_data(save_id: u32) -> Self {
+        Self::from_words(&[FsMediaType::Nand as u32, save_id])
+    }
+
+    fn from_words(words: &[u32]) -> Self {
+        Self::Binary(words.iter().flat_map(|word| word.to_le_bytes()).collect())
+    }
+
+    /// Returns the path in the format used by `libctru`. It points into `self`.
+    fn as_raw(&self) -> ctru_sys::FS_Path {
+        match self {
+            Self::Empty => unsafe { ctru_sys::fsMakePath(PathType::Empty.into(), ptr::null()) },
+            Self::Binary(data) => ctru_sys::FS_Path {
+                type_: PathType::Binary.into(),
+                size: data.len() as u32,
+                data: data.as_ptr().cast(),
+            },
+        }
+    }
+}
+
+impl Default for SaveDataFormat {
+    fn default() -> Self {
+        Self {
+            max_directories: 10,
+            max_files: 10,
+            duplicate_data: true,
+        }
+    }
 }
 
 impl File {
@@ -415,36 +630,72 @@ impl File {
 
     /// Queries metadata about the underlying file.
     pub fn metadata(&self) -> IoResult<Metadata> {
-        // The only metadata we have for files right now is file size.
-        // This is likely to change in the future.
         unsafe {
             let mut size = 0;
             let r = ctru_sys::FSFILE_GetSize(self.handle, &mut size);
+            if r < 0 {
+                return Err(IoError::new(
+                    IoErrorKind::PermissionDenied,
+                    crate::Error::from(r),
+                ));
+            }
+
+            // Not every archive reports the attributes of its files, but the size is still useful.
+            let mut attributes = 0;
+            if ctru_sys::FSFILE_GetAttributes(self.handle, &mut attributes) < 0 {
+                attributes = 0;
+            }
+
+            Ok(Metadata {
+                attributes,
+                size,
+                file: Some((self.arch_handle, self.path.clone())),
+            })
+        }
+    }
+
+    /// Changes the read-only, hidden and archive flags of the underlying file.
+    pub fn set_permissions(&self, perm: Permissions) -> IoResult<()> {
+        unsafe {
+            let r = ctru_sys::FSFILE_SetAttributes(self.handle, perm.attributes.bits());
             if r < 0 {
                 Err(IoError::new(
                     IoErrorKind::PermissionDenied,
                     crate::Error::from(r),
                 ))
             } else {
-                Ok(Metadata {
-                    attributes: 0,
-                    size,
-                })
+                Ok(())
             }
         }
     }
 
-    fn read(&mut self, buf: &mut [u8]) -> IoResult<usize> {
+    /// Reads a number of bytes starting from a given offset, without moving the cursor of the file.
+    ///
+    /// Returns the number of bytes read, which is only smaller than the length of `buf` when the end
+    /// of the file is reached. Unlike [`Read::read`], this only needs a shared reference to the file.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use ctru::services::fs::{File, Fs};
+    ///
+    /// let fs = Fs::init().unwrap();
+    /// let sdmc = fs.sdmc().unwrap();
+    /// let pack = File::open(&sdmc, "/3ds/app/assets.pak").unwrap();
+    ///
+    /// let mut header = [0; 16];
+    /// pack.read_at(&mut header, 0x200).unwrap();
+    /// ```
+    pub fn read_at(&self, buf: &mut [u8], offset: u64) -> IoResult<usize> {
         unsafe {
             let mut n_read = 0;
             let r = ctru_sys::FSFILE_Read(
                 self.handle,
                 &mut n_read,
-                self.offset,
+                offset,
                 buf.as_mut_ptr() as _,
                 buf.len() as u32,
             );
-            self.offset += n_read as u64;
             if r < 0 {
                 Err(IoError::new(IoErrorKind::Other, crate::Error::from(r)))
             } else {
@@ -453,22 +704,20 @@ impl File {
         }
     }
 
-    fn read_to_end(&mut self, buf: &mut Vec<u8>) -> IoResult<usize> {
-        unsafe { read_to_end_uninitialized(self, buf) }
-    }
-
-    fn write(&mut self, buf: &[u8]) -> IoResult<usize> {
+    /// Writes a number of bytes starting from a given offset, without moving the cursor of the file.
+    ///
+    /// Returns the number of bytes written. Writing past the end of the file extends it.
+    pub fn write_at(&self, buf: &[u8], offset: u64) -> IoResult<usize> {
         unsafe {
             let mut n_written = 0;
             let r = ctru_sys::FSFILE_Write(
                 self.handle,
                 &mut n_written,
-                self.offset,
+                offset,
                 buf.as_ptr() as _,
                 buf.len() as u32,
                 FsWrite::FS_WRITE_UPDATE_TIME.bits(),
             );
-            self.offset += n_written as u64;
             if r < 0 {
                 Err(IoError::new(IoErrorKind::Other, crate::Error::from(r)))
             } else {
@@ -476,6 +725,42 @@ impl File {
             }
         }
     }
+
+    /// Flushes the data written to the file to the storage medium.
+    ///
+    /// Writes are otherwise cached by the filesystem service, and may be lost if the console is
+    /// powered off before the file is closed. Unlike this, [`Write::flush`] does nothing, so that
+    /// buffered writers don't flush the cache every time they empty their buffer.
+    ///
+    /// Writes are made without `FS_WRITE_FLUSH`, which would flush the cache after each of them:
+    /// flushing the file once commits everything written through its handle so far, which is what
+    /// the flag would have done for the last write, without paying for it on every call.
+    pub fn sync_all(&self) -> IoResult<()> {
+        unsafe {
+            let r = ctru_sys::FSFILE_Flush(self.handle);
+            if r < 0 {
+                Err(IoError::new(IoErrorKind::Other, crate::Error::from(r)))
+            } else {
+                Ok(())
+            }
+        }
+    }
+
+    fn read(&mut self, buf: &mut [u8]) -> IoResult<usize> {
+        let n_read = self.read_at(buf, self.offset)?;
+        self.offset += n_read as u64;
+        Ok(n_read)
+    }
+
+    fn read_to_end(&mut self, buf: &mut Vec<u8>) -> IoResult<usize> {
+        unsafe { read_to_end_uninitialized(self, buf) }
+    }
+
+    fn write(&mut self, buf: &[u8]) -> IoResult<usize> {
+        let n_written = self.write_at(buf, self.offset)?;
+        self.offset += n_written as u64;
+        Ok(n_written)
+    }
 }
 
 impl Metadata {
@@ -498,6 +783,73 @@ impl Metadata {
     pub fn len(&self) -> u64 {
         self.size
     }
+
+    /// Returns the read-only, hidden and archive flags of the file.
+    pub fn permissions(&self) -> Permissions {
+        let attributes =
+            FsAttribute::from_bits_truncate(self.attributes) - FsAttribute::FS_ATTRIBUTE_DIRECTORY;
+
+        Permissions { attributes }
+    }
+
+    /// Returns the last modification time of the file.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error of kind [`Unsupported`](IoErrorKind::Unsupported)
+    /// if the archive doesn't keep track of modification times, which is the case of
+    /// every archive but the SD card, and of directories.
+    ///
+    /// The time is queried from the archive when this is called, so the archive must still be open.
+    pub fn modified(&self) -> IoResult<SystemTime> {
+        let modified = match &self.file {
+            Some((arch_handle, path)) => modification_time(*arch_handle, path),
+            None => None,
+        };
+
+        modified.ok_or_else(|| {
+            IoError::new(
+                IoErrorKind::Unsupported,
+                "modification time is not available for this entry",
+            )
+        })
+    }
+}
+
+impl Permissions {
+    /// Returns whether the file is read-only.
+    pub fn readonly(&self) -> bool {
+        self.attributes
+            .contains(FsAttribute::FS_ATTRIBUTE_READ_ONLY)
+    }
+
+    /// Sets the read-only flag.
+    pub fn set_readonly(&mut self, readonly: bool) {
+        self.attributes
+            .set(FsAttribute::FS_ATTRIBUTE_READ_ONLY, readonly);
+    }
+
+    /// Returns whether the file is hidden.
+    pub fn hidden(&self) -> bool {
+        self.attributes.contains(FsAttribute::FS_ATTRIBUTE_HIDDEN)
+    }
+
+    /// Sets the hidden flag.
+    pub fn set_hidden(&mut self, hidden: bool) {
+        self.attributes
+            .set(FsAttribute::FS_ATTRIBUTE_HIDDEN, hidden);
+    }
+
+    /// Returns whether the archive flag is set, which marks files changed since the last backup.
+    pub fn archive(&self) -> bool {
+        self.attributes.contains(FsAttribute::FS_ATTRIBUTE_ARCHIVE)
+    }
+
+    /// Sets the archive flag.
+    pub fn set_archive(&mut self, archive: bool) {
+        self.attributes
+            .set(FsAttribute::FS_ATTRIBUTE_ARCHIVE, archive);
+    }
 }
 
 impl OpenOptions {
@@ -609,6 +961,8 @@ impl OpenOptions {
             let mut file = File {
                 handle: file_handle,
                 offset: 0,
+                arch_handle: self.arch_handle,
+                path,
             };
 
             if self.append {
@@ -752,11 +1106,25 @@ pub fn metadata<P: AsRef<Path>>(arch: &Archive, path: P) -> IoResult<Metadata> {
         (_, Ok(_dir)) => Ok(Metadata {
             attributes: FsAttribute::FS_ATTRIBUTE_DIRECTORY.bits(),
             size: 0,
+            file: None,
         }),
         (Err(e), _) => Err(e),
     }
 }
 
+/// Changes the read-only, hidden and archive flags of a file.
+///
+/// # Errors
+///
+/// This function will return an error in the following situations, but is not limited to just
+/// these cases:
+///
+/// * `path` doesn't exist, or points to a directory.
+/// * The user lacks permissions to change the attributes of the file.
+pub fn set_permissions<P: AsRef<Path>>(arch: &Archive, path: P, perm: Permissions) -> IoResult<()> {
+    File::open(arch, path)?.set_permissions(perm)
+}
+
 /// Removes an existing, empty directory.
 ///
 /// # Errors
@@ -797,6 +1165,89 @@ pub fn remove_dir_all<P: AsRef<Path>>(arch: &Archive, path: P) -> IoResult<()> {
     }
 }
 
+/// Copies the contents of a file to another file, which can be in another archive.
+///
+/// The destination is created if it doesn't exist and truncated otherwise. On success, the number
+/// of bytes copied is returned. Unlike [`std::fs::copy`], the attributes of the file aren't copied.
+///
+/// # Errors
+///
+/// This function will return an error in the following situations, but is not limited to just
+/// these cases:
+///
+/// * `from` doesn't exist, or points to a directory.
+/// * The parent directory of `to` doesn't exist.
+/// * The destination archive is read-only or full.
+///
+/// # Examples
+///
+/// ```no_run
+/// use ctru::services::fs::{self, ArchiveID, ArchivePath, Fs};
+///
+/// let fs = Fs::init().unwrap();
+/// let romfs = fs.open_archive(ArchiveID::RomFS, ArchivePath::Empty).unwrap();
+/// let sdmc = fs.sdmc().unwrap();
+///
+/// fs::copy(&romfs, "/default.cfg", &sdmc, "/3ds/app.cfg").unwrap();
+/// ```
+pub fn copy<P: AsRef<Path>, Q: AsRef<Path>>(
+    from_arch: &Archive,
+    from: P,
+    to_arch: &Archive,
+    to: Q,
+) -> IoResult<u64> {
+    let mut reader = File::open(from_arch, from)?;
+    let mut writer = OpenOptions::new()
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .archive(to_arch)
+        .open(to)?;
+
+    io::copy(&mut reader, &mut writer)
+}
+
+/// Recursively copies a directory and all of its contents to another directory, which can be in
+/// another archive.
+///
+/// Missing directories are created, existing ones are merged and existing files are overwritten.
+///
+/// # Errors
+///
+/// This function will return the first error met while walking `from`, creating a directory
+/// or copying a file (see [`copy`]). The files copied until then are left in place.
+pub fn copy_dir_all<P: AsRef<Path>, Q: AsRef<Path>>(
+    from_arch: &Archive,
+    from: P,
+    to_arch: &Archive,
+    to: Q,
+) -> IoResult<()> {
+    let (from, to) = (from.as_ref(), to.as_ref());
+
+    // List everything first, so that copying a directory into itself terminates.
+    let entries = walk_dir(from_arch, from).collect::<IoResult<Vec<_>>>()?;
+
+    for entry in entries {
+        let target = to.join(entry.path().strip_prefix(from).unwrap());
+
+        if entry.is_file() {
+            copy(from_arch, entry.path(), to_arch, &target)?;
+        } else if entry.depth() == 0 {
+            match create_dir_all(to_arch, &target) {
+                Err(_) if metadata(to_arch, &target).map_or(false, |m| m.is_dir()) => {}
+                result => result?,
+            }
+        } else {
+            match create_dir(to_arch, &target) {
+                Err(_) if metadata(to_arch, &target).map_or(false, |m| m.is_dir()) => {}
+                result => result?,
+            }
+        }
+    }
+
+    Ok(())
+}
+
 /// Returns an iterator over the entries within a directory.
 ///
 /// The iterator will yield instances of Result<DirEntry, i32>. New errors
@@ -883,6 +1334,31 @@ where
     }
 }
 
+/// Queries the modification time of a file, for the archives which keep track of it.
+fn modification_time(arch_handle: u64, path: &WideCString) -> Option<SystemTime> {
+    // Timestamps are counted in milliseconds since 2000-01-01.
+    const EPOCH_OFFSET: Duration = Duration::from_secs(946_684_800);
+
+    unsafe {
+        let fs_path = ctru_sys::fsMakePath(PathType::UTF16.into(), path.as_ptr() as _);
+        let mut timestamp: u64 = 0;
+        let r = ctru_sys::FSUSER_ControlArchive(
+            arch_handle,
+            ctru_sys::ARCHIVE_ACTION_GET_TIMESTAMP,
+            fs_path.data as _,
+            fs_path.size,
+            &mut timestamp as *mut u64 as _,
+            mem::size_of::<u64>() as u32,
+        );
+
+        if r < 0 {
+            None
+        } else {
+            Some(UNIX_EPOCH + EPOCH_OFFSET + Duration::from_millis(timestamp))
+        }
+    }
+}
+
 // TODO: Determine if we should check UTF-16 paths for interior NULs
 fn to_utf16(path: &Path) -> WideCString {
     WideCString::from_str(path).unwrap()
@@ -948,6 +1424,27 @@ impl Read for File {
         self.read(buf)
     }
 
+    // Each read is a request to the filesystem service, so scattered reads are done in one go.
+    fn read_vectored(&mut self, bufs: &mut [IoSliceMut<'_>]) -> IoResult<usize> {
+        let mut non_empty = bufs.iter_mut().filter(|buf| !buf.is_empty());
+        match (non_empty.next(), non_empty.next()) {
+            (None, _) => return Ok(0),
+            (Some(buf), None) => return self.read(buf),
+            _ => {}
+        }
+
+        let mut data = vec![0; bufs.iter().map(|buf| buf.len()).sum()];
+        let n_read = self.read(&mut data)?;
+
+        let mut data = &data[..n_read];
+        for buf in bufs {
+            let n = buf.len().min(data.len());
+            buf[..n].copy_from_slice(&data[..n]);
+            data = &data[n..];
+        }
+        Ok(n_read)
+    }
+
     fn read_to_end(&mut self, buf: &mut Vec<u8>) -> IoResult<usize> {
         self.read_to_end(buf)
     }
@@ -958,6 +1455,21 @@ impl Write for File {
         self.write(buf)
     }
 
+    fn write_vectored(&mut self, bufs: &[IoSlice<'_>]) -> IoResult<usize> {
+        let mut non_empty = bufs.iter().filter(|buf| !buf.is_empty());
+        match (non_empty.next(), non_empty.next()) {
+            (None, _) => return Ok(0),
+            (Some(buf), None) => return self.write(buf),
+            _ => {}
+        }
+
+        let mut data = Vec::with_capacity(bufs.iter().map(|buf| buf.len()).sum());
+        for buf in bufs {
+            data.extend_from_slice(buf);
+        }
+        self.write(&data)
+    }
+
     fn flush(&mut self) -> IoResult<()> {
         Ok(())
     }
@@ -1058,3 +1570,209 @@ impl From<ArchiveID> for ctru_sys::FS_ArchiveID {
         }
     }
 }
+
+#[cfg(all(test, feature = "host-mock"))]
+mod tests {
+    use super::*;
+    use crate::mock::{advance_clock, now, sdmc_exists};
+    use crate::services::fs;
+
+    #[test]
+    fn save_data() {
+        let fs = Fs::init().unwrap();
+        let open = || fs.open_archive(ArchiveID::Savedata, ArchivePath::Empty);
+
+        assert!(open().is_err());
+        fs.format_save_data(
+            ArchiveID::Savedata,
+            ArchivePath::Empty,
+            SaveDataFormat::default(),
+        )
+        .unwrap();
+        assert_eq!(
+            fs.save_data_format(ArchiveID::Savedata, ArchivePath::Empty)
+                .unwrap(),
+            SaveDataFormat::default()
+        );
+
+        // Changes that weren't committed are lost when the archive is closed.
+        let save = open().unwrap();
+        File::create(&save, "/lost.bin").unwrap();
+        drop(save);
+        assert!(fs::metadata(&open().unwrap(), "/lost.bin").is_err());
+
+        let save = open().unwrap();
+        let free_space = save.free_space().unwrap();
+        File::create(&save, "/kept.bin")
+            .unwrap()
+            .write_all(&[0; 100])
+            .unwrap();
+        assert_eq!(save.free_space().unwrap(), free_space - 100);
+        save.commit_save_data().unwrap();
+        drop(save);
+        assert_eq!(
+            fs::metadata(&open().unwrap(), "/kept.bin").unwrap().len(),
+            100
+        );
+
+        assert!(fs.sdmc().unwrap().commit_save_data().is_err());
+    }
+
+    #[test]
+    fn extdata() {
+        let fs = Fs::init().unwrap();
+        let extdata = |id| {
+            fs.open_archive(
+                ArchiveID::Extdata,
+                ArchivePath::extdata(FsMediaType::Sd, id),
+            )
+            .unwrap()
+        };
+
+        File::create(&extdata(0x1234), "/scores.bin").unwrap();
+
+        assert!(fs::metadata(&extdata(0x1234), "/scores.bin").is_ok());
+        assert!(fs::metadata(&extdata(0x5678), "/scores.bin").is_err());
+        assert!(!sdmc_exists("/scores.bin"));
+        assert_eq!(
+            ArchivePath::extdata(FsMediaType::Sd, 0x0000_0001_0000_1234),
+            ArchivePath::Binary(vec![1, 0, 0, 0, 0x34, 0x12, 0, 0, 1, 0, 0, 0])
+        );
+    }
+
+    #[test]
+    fn file_metadata() {
+        let fs = Fs::init().unwrap();
+        let sdmc = fs.sdmc().unwrap();
+        let created = now();
+
+        let mut file = File::create(&sdmc, "/notes.txt").unwrap();
+        advance_clock(Duration::from_secs(90));
+        file.write_all(b"remember the milk").unwrap();
+
+        let metadata = file.metadata().unwrap();
+        assert_eq!(metadata.len(), 17);
+        assert_eq!(
+            metadata
+                .modified()
+                .unwrap()
+                .duration_since(created)
+                .unwrap(),
+            Duration::from_secs(90)
+        );
+
+        let mut permissions = metadata.permissions();
+        assert!(!permissions.readonly() && !permissions.hidden());
+        permissions.set_readonly(true);
+        permissions.set_hidden(true);
+        fs::set_permissions(&sdmc, "/notes.txt", permissions).unwrap();
+
+        let metadata = fs::metadata(&sdmc, "/notes.txt").unwrap();
+        assert!(metadata.is_file());
+        assert_eq!(metadata.permissions(), permissions);
+        assert!(!metadata.permissions().archive());
+
+        fs::create_dir(&sdmc, "/docs").unwrap();
+        let metadata = fs::metadata(&sdmc, "/docs").unwrap();
+        assert_eq!(
+            metadata.modified().unwrap_err().kind(),
+            IoErrorKind::Unsupported
+        );
+
+        // Save data doesn't keep track of modification times.
+        let format = SaveDataFormat::default();
+        fs.format_save_data(ArchiveID::Savedata, ArchivePath::Empty, format)
+            .unwrap();
+        let save = fs
+            .open_archive(ArchiveID::Savedata, ArchivePath::Empty)
+            .unwrap();
+        let file = File::create(&save, "/save.bin").unwrap();
+        assert!(file.metadata().unwrap().modified().is_err());
+    }
+
+    #[test]
+    fn copying() {
+        let fs = Fs::init().unwrap();
+        let sdmc = fs.sdmc().unwrap();
+        let extdata = fs
+            .open_archive(
+                ArchiveID::Extdata,
+                ArchivePath::extdata(FsMediaType::Sd, 0x1234),
+            )
+            .unwrap();
+
+        fs::create_dir_all(&sdmc, "/assets/levels").unwrap();
+        File::create(&sdmc, "/assets/levels/1.map")
+            .unwrap()
+            .write_all(b"#..#")
+            .unwrap();
+        File::create(&sdmc, "/assets/title.txt")
+            .unwrap()
+            .write_all(b"Adventure")
+            .unwrap();
+
+        assert_eq!(
+            fs::copy(&sdmc, "/assets/title.txt", &extdata, "/title.txt").unwrap(),
+            9
+        );
+        fs::copy_dir_all(&sdmc, "/assets", &extdata, "/backup/assets").unwrap();
+        // Copying again merges into the existing directories.
+        fs::copy_dir_all(&sdmc, "/assets", &extdata, "/backup/assets").unwrap();
+
+        let mut contents = String::new();
+        File::open(&extdata, "/backup/assets/levels/1.map")
+            .unwrap()
+            .read_to_string(&mut contents)
+            .unwrap();
+        assert_eq!(contents, "#..#");
+        assert_eq!(
+            fs::glob(&extdata, "/**/*.txt").unwrap(),
+            [
+                PathBuf::from("/backup/assets/title.txt"),
+                PathBuf::from("/title.txt")
+            ]
+        );
+
+        // A directory can be copied into itself.
+        fs::copy_dir_all(&sdmc, "/assets", &sdmc, "/assets/old").unwrap();
+        let files = fs::walk_dir(&sdmc, "/assets")
+            .filter(|entry| entry.as_ref().unwrap().is_file())
+            .count();
+        assert_eq!(files, 4);
+    }
+
+    #[test]
+    fn positional_io() {
+        let fs = Fs::init().unwrap();
+        let sdmc = fs.sdmc().unwrap();
+        let mut file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .archive(&sdmc)
+            .open("/pack.bin")
+            .unwrap();
+
+        let bufs = [
+            IoSlice::new(b"head"),
+            IoSlice::new(b""),
+            IoSlice::new(b"er"),
+        ];
+        assert_eq!(file.write_vectored(&bufs).unwrap(), 6);
+        assert_eq!(file.write_at(b"tail", 10).unwrap(), 4);
+        file.sync_all().unwrap();
+
+        // Positional I/O doesn't move the cursor.
+        let mut buf = [0xff; 6];
+        assert_eq!(file.read_at(&mut buf, 2).unwrap(), 6);
+        assert_eq!(&buf, b"ader\0\0");
+        assert_eq!(file.stream_position().unwrap(), 6);
+
+        file.rewind().unwrap();
+        let (mut first, mut second) = ([0; 3], [0; 20]);
+        let mut bufs = [IoSliceMut::new(&mut first), IoSliceMut::new(&mut second)];
+        assert_eq!(file.read_vectored(&mut bufs).unwrap(), 14);
+        assert_eq!(&first, b"hea");
+        assert_eq!(&second[..11], b"der\0\0\0\0tail");
+    }
+}
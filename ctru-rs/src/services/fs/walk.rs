@@ -0,0 +1,549 @@
+//! Recursive traversal and glob matching of directory trees
+//!
+//! [`walk_dir`] and [`glob`] only need to list directories, which is abstracted by the [`DirTree`]
+//! trait. It is implemented for every [`Archive`], whether it's the SD card, save data or RomFS.
+
+use std::cmp::Ordering;
+use std::ffi::OsStr;
+use std::io::Error as IoError;
+use std::io::ErrorKind as IoErrorKind;
+use std::io::Result as IoResult;
+use std::path::{Path, PathBuf};
+use std::vec;
+
+use super::{read_dir, Archive, FsAttribute};
+
+/// A filesystem whose directories can be listed.
+pub trait DirTree {
+    /// Lists the entries of the directory at `path`, as their full paths and whether they are
+    /// directories themselves.
+    fn list_dir(&self, path: &Path) -> IoResult<Vec<(PathBuf, bool)>>;
+}
+
+impl DirTree for Archive {
+    fn list_dir(&self, path: &Path) -> IoResult<Vec<(PathBuf, bool)>> {
+        read_dir(self, path)?
+            .map(|entry| {
+                let entry = entry?;
+                let is_dir = FsAttribute::from_bits_truncate(entry.entry.attributes)
+                    .contains(FsAttribute::FS_ATTRIBUTE_DIRECTORY);
+                Ok((entry.path(), is_dir))
+            })
+            .collect()
+    }
+}
+
+/// An entry found while walking a directory tree.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct WalkEntry {
+    path: PathBuf,
+    depth: usize,
+    is_dir: bool,
+}
+
+/// Recursive iterator over the entries of a directory, created by [`walk_dir`].
+///
+/// Directories are yielded before their contents. Errors met while listing a directory are yielded
+/// in place of its contents, after which the walk goes on with the next entry.
+pub struct WalkDir<'a, T: ?Sized> {
+    tree: &'a T,
+    root: Option<PathBuf>,
+    min_depth: usize,
+    max_depth: usize,
+    sorter: Option<Sorter<'a>>,
+    filter: Option<Filter<'a>>,
+    stack: Vec<vec::IntoIter<IoResult<WalkEntry>>>,
+}
+
+type Sorter<'a> = Box<dyn FnMut(&WalkEntry, &WalkEntry) -> Ordering + 'a>;
+type Filter<'a> = Box<dyn FnMut(&WalkEntry) -> bool + 'a>;
+
+/// Returns a recursive iterator over the directory at `root`, starting with the directory itself.
+///
+/// # Examples
+///
+/// ```no_run
+/// use ctru::services::fs::{self, Fs};
+///
+/// let fs = Fs::init().unwrap();
+/// let sdmc = fs.sdmc().unwrap();
+///
+/// // Every file of the first two levels of the `3ds` directory, in alphabetical order.
+/// for entry in fs::walk_dir(&sdmc, "/3ds").max_depth(2).sort_by_file_name() {
+///     let entry = entry.unwrap();
+///     if entry.is_file() {
+///         println!("{}", entry.path().display());
+///     }
+/// }
+/// ```
+pub fn walk_dir<T: DirTree + ?Sized, P: AsRef<Path>>(tree: &T, root: P) -> WalkDir<'_, T> {
+    WalkDir {
+        tree,
+        root: Some(root.as_ref().to_path_buf()),
+        min_depth: 0,
+        max_depth: usize::MAX,
+        sorter: None,
+        filter: None,
+        stack: Vec::new(),
+    }
+}
+
+/// Returns the paths of every entry of `tree` matching a glob pattern, in alphabetical order.
+///
+/// The pattern is split on `/` and each component is matched against a file name, where:
+///
+/// * `?` matches any character.
+/// * `*` matches any sequence of characters.
+/// * `[abc]` matches one of the listed characters, `[a-z]` one in a range, and `[!a-z]` one outside of it.
+/// * A `**` component matches any number of directories, including none.
+///
+/// Patterns are matched from the root of the tree, and matching is case-sensitive.
+///
+/// # Errors
+///
+/// This function will return an error of kind [`InvalidInput`](IoErrorKind::InvalidInput) if a `[`
+/// is never closed, or the first error met while listing the directories to search.
+///
+/// # Examples
+///
+/// ```no_run
+/// use ctru::services::fs::{self, Fs};
+///
+/// let fs = Fs::init().unwrap();
+/// let sdmc = fs.sdmc().unwrap();
+///
+/// for path in fs::glob(&sdmc, "/saves/**/slot[0-9].sav").unwrap() {
+///     println!("{}", path.display());
+/// }
+/// ```
+pub fn glob<T: DirTree + ?Sized>(tree: &T, pattern: &str) -> IoResult<Vec<PathBuf>> {
+    let components: Vec<&str> = pattern.split('/').filter(|c| !c.is_empty()).collect();
+
+    // Directories before the first wildcard don't need to be searched, but the last component
+    // is always matched so that a pattern without wildcards only finds existing entries.
+    let literal = components
+        .iter()
+        .take(components.len().saturating_sub(1))
+        .take_while(|c| !c.contains(['*', '?', '[']))
+        .count();
+    let root = components[..literal]
+        .iter()
+        .fold(PathBuf::from("/"), |root, c| root.join(c));
+    let segments = components[literal..]
+        .iter()
+        .map(|c| Segment::parse(c))
+        .collect::<IoResult<Vec<_>>>()?;
+
+    let max_depth = if segments.contains(&Segment::AnyDirs) {
+        usize::MAX
+    } else {
+        segments.len()
+    };
+
+    let mut paths = Vec::new();
+    for entry in walk_dir(tree, &root).min_depth(1).max_depth(max_depth) {
+        let entry = entry?;
+        let names: Vec<&str> = entry
+            .path()
+            .strip_prefix(&root)
+            .unwrap()
+            .iter()
+            .map(|name| name.to_str().unwrap_or_default())
+            .collect();
+
+        if match_path(&segments, &names) {
+            paths.push(entry.into_path());
+        }
+    }
+
+    paths.sort();
+    Ok(paths)
+}
+
+impl WalkEntry {
+    /// Returns the full path of the entry.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Returns the full path of the entry, consuming it.
+    pub fn into_path(self) -> PathBuf {
+        self.path
+    }
+
+    /// Returns the name of the entry, if the path doesn't end with `..` or designate the root.
+    pub fn file_name(&self) -> Option<&OsStr> {
+        self.path.file_name()
+    }
+
+    /// Returns the depth of the entry, where the directory the walk started from is at depth 0.
+    pub fn depth(&self) -> usize {
+        self.depth
+    }
+
+    /// Returns whether the entry is a directory.
+    pub fn is_dir(&self) -> bool {
+        self.is_dir
+    }
+
+    /// Returns whether the entry is a file.
+    pub fn is_file(&self) -> bool {
+        !self.is_dir
+    }
+}
+
+impl<'a, T: DirTree + ?Sized> WalkDir<'a, T> {
+    /// Skips the entries shallower than `depth`. Their contents are still walked.
+    pub fn min_depth(mut self, depth: usize) -> Self {
+        self.min_depth = depth;
+        self
+    }
+
+    /// Skips the entries deeper than `depth`, without listing their parent directory. A depth of 0
+    /// only yields the root.
+    pub fn max_depth(mut self, depth: usize) -> Self {
+        self.max_depth = depth;
+        self
+    }
+
+    /// Walks the contents of each directory in the order given by `compare`, instead of the order
+    /// of the filesystem.
+    pub fn sort_by<F>(mut self, compare: F) -> Self
+    where
+        F: FnMut(&WalkEntry, &WalkEntry) -> Ordering + 'a,
+    {
+        self.sorter = Some(Box::new(compare));
+        self
+    }
+
+    /// Walks the contents of each directory in the alphabetical order of their names.
+    pub fn sort_by_file_name(self) -> Self {
+        self.sort_by(|a, b| a.file_name().cmp(&b.file_name()))
+    }
+
+    /// Only yields the entries for which `predicate` returns `true`.
+    ///
+    /// Directories which are rejected are not descended into, which makes this more efficient
+    /// than [`Iterator::filter`] to skip whole subtrees.
+    pub fn filter_entry<P>(mut self, predicate: P) -> Self
+    where
+        P: FnMut(&WalkEntry) -> bool + 'a,
+    {
+        self.filter = Some(Box::new(predicate));
+        self
+    }
+}
+
+impl<'a, T: DirTree + ?Sized> Iterator for WalkDir<'a, T> {
+    type Item = IoResult<WalkEntry>;
+
+    fn next(&mut self) -> Option<IoResult<WalkEntry>> {
+        loop {
+            let entry = match self.root.take() {
+                Some(path) => WalkEntry {
+                    path,
+                    depth: 0,
+                    is_dir: true,
+                },
+                None => match self.stack.last_mut()?.next() {
+                    Some(Ok(entry)) => entry,
+                    Some(Err(e)) => return Some(Err(e)),
+                    None => {
+                        self.stack.pop();
+                        continue;
+                    }
+                },
+            };
+
+            if let Some(filter) = &mut self.filter {
+                if !filter(&entry) {
+                    continue;
+                }
+            }
+
+            if entry.is_dir && entry.depth < self.max_depth {
+                let contents = match self.tree.list_dir(&entry.path) {
+                    Ok(children) => {
+                        let mut children: Vec<WalkEntry> = children
+                            .into_iter()
+                            .map(|(path, is_dir)| WalkEntry {
+                                path,
+                                depth: entry.depth + 1,
+                                is_dir,
+                            })
+                            .collect();
+                        if let Some(sorter) = &mut self.sorter {
+                            children.sort_by(|a, b| sorter(a, b));
+                        }
+                        children.into_iter().map(Ok).collect()
+                    }
+                    Err(e) => vec![Err(e)],
+                };
+                self.stack.push(contents.into_iter());
+            }
+
+            if entry.depth >= self.min_depth {
+                return Some(Ok(entry));
+            }
+        }
+    }
+}
+
+/// A component of a glob pattern.
+#[derive(Debug, PartialEq, Eq)]
+enum Segment {
+    /// `**`, matching any number of directories.
+    AnyDirs,
+    Name(Vec<Token>),
+}
+
+#[derive(Debug, PartialEq, Eq)]
+enum Token {
+    Char(char),
+    /// `?`
+    AnyChar,
+    /// `*`
+    AnyChars,
+    /// `[...]`, as inclusive ranges of characters.
+    Class {
+        negated: bool,
+        ranges: Vec<(char, char)>,
+    },
+}
+
+impl Segment {
+    fn parse(component: &str) -> IoResult<Self> {
+        if component == "**" {
+            return Ok(Segment::AnyDirs);
+        }
+
+        let mut tokens = Vec::new();
+        let mut chars = component.chars().peekable();
+        while let Some(c) = chars.next() {
+            let token = match c {
+                '?' => Token::AnyChar,
+                '*' => Token::AnyChars,
+                '[' => {
+                    let negated = chars.next_if(|c| matches!(c, '!' | '^')).is_some();
+                    let mut ranges = Vec::new();
+                    loop {
+                        let start = match chars.next() {
+                            // A `]` right after the opening bracket is part of the class.
+                            Some(']') if !ranges.is_empty() => break,
+                            Some(start) => start,
+                            None => {
+                                return Err(IoError::new(
+                                    IoErrorKind::InvalidInput,
+                                    "unclosed character class in glob pattern",
+                                ))
+                            }
+                        };
+                        let end = match chars.clone().take(2).collect::<Vec<_>>()[..] {
+                            ['-', end] if end != ']' => {
+                                chars.nth(1);
+                                end
+                            }
+                            _ => start,
+                        };
+                        ranges.push((start, end));
+                    }
+                    Token::Class { negated, ranges }
+                }
+                c => Token::Char(c),
+            };
+            tokens.push(token);
+        }
+
+        Ok(Segment::Name(tokens))
+    }
+}
+
+impl Token {
+    fn matches(&self, c: char) -> bool {
+        match self {
+            Token::Char(expected) => c == *expected,
+            Token::AnyChar | Token::AnyChars => true,
+            Token::Class { negated, ranges } => {
+                ranges
+                    .iter()
+                    .any(|&(start, end)| (start..=end).contains(&c))
+                    != *negated
+            }
+        }
+    }
+}
+
+fn match_path(segments: &[Segment], names: &[&str]) -> bool {
+    match segments.split_first() {
+        None => names.is_empty(),
+        Some((Segment::AnyDirs, rest)) => (0..=names.len()).any(|i| match_path(rest, &names[i..])),
+        Some((Segment::Name(tokens), rest)) => match names.split_first() {
+            Some((name, names)) => {
+                let name: Vec<char> = name.chars().collect();
+                match_name(tokens, &name) && match_path(rest, names)
+            }
+            None => false,
+        },
+    }
+}
+
+fn match_name(tokens: &[Token], name: &[char]) -> bool {
+    match tokens.split_first() {
+        None => name.is_empty(),
+        Some((Token::AnyChars, rest)) => (0..=name.len()).any(|i| match_name(rest, &name[i..])),
+        Some((token, rest)) => match name.split_first() {
+            Some((&c, name)) => token.matches(c) && match_name(rest, name),
+            None => false,
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::BTreeMap;
+
+    /// An in-memory tree, mapping every path to whether it's a directory.
+    struct MemoryTree(BTreeMap<PathBuf, bool>);
+
+    impl MemoryTree {
+        fn new(paths: &[&str]) -> Self {
+            let mut nodes = BTreeMap::from([(PathBuf::from("/"), true)]);
+            for path in paths {
+                let (path, is_dir) = match path.strip_suffix('/') {
+                    Some(dir) => (dir, true),
+                    None => (*path, false),
+                };
+                nodes.insert(PathBuf::from(path), is_dir);
+            }
+            Self(nodes)
+        }
+    }
+
+    impl DirTree for MemoryTree {
+        fn list_dir(&self, path: &Path) -> IoResult<Vec<(PathBuf, bool)>> {
+            if self.0.get(path) != Some(&true) {
+                return Err(IoErrorKind::NotFound.into());
+            }
+
+            // List in reverse order, to make sure the walk doesn't rely on the order of the tree.
+            Ok(self
+                .0
+                .iter()
+                .rev()
+                .filter(|(child, _)| child.parent() == Some(path))
+                .map(|(child, is_dir)| (child.clone(), *is_dir))
+                .collect())
+        }
+    }
+
+    fn tree() -> MemoryTree {
+        MemoryTree::new(&[
+            "/3ds/",
+            "/3ds/app.3dsx",
+            "/3ds/app/",
+            "/3ds/app/config.ini",
+            "/3ds/app/saves/",
+            "/3ds/app/saves/slot1.sav",
+            "/3ds/app/saves/slot2.sav",
+            "/3ds/app/saves/slotA.sav",
+            "/boot.firm",
+            "/notes.txt",
+        ])
+    }
+
+    fn paths(walk: impl Iterator<Item = IoResult<WalkEntry>>) -> Vec<String> {
+        walk.map(|entry| entry.unwrap().path().display().to_string())
+            .collect()
+    }
+
+    #[test]
+    fn walking() {
+        let tree = tree();
+
+        assert_eq!(
+            paths(walk_dir(&tree, "/3ds").sort_by_file_name()),
+            [
+                "/3ds",
+                "/3ds/app",
+                "/3ds/app/config.ini",
+                "/3ds/app/saves",
+                "/3ds/app/saves/slot1.sav",
+                "/3ds/app/saves/slot2.sav",
+                "/3ds/app/saves/slotA.sav",
+                "/3ds/app.3dsx",
+            ]
+        );
+        assert_eq!(
+            paths(
+                walk_dir(&tree, "/")
+                    .min_depth(1)
+                    .max_depth(2)
+                    .sort_by_file_name()
+            ),
+            [
+                "/3ds",
+                "/3ds/app",
+                "/3ds/app.3dsx",
+                "/boot.firm",
+                "/notes.txt"
+            ]
+        );
+        assert_eq!(
+            paths(
+                walk_dir(&tree, "/")
+                    .sort_by_file_name()
+                    .filter_entry(|entry| entry.file_name() != Some("saves".as_ref()))
+                    .filter(|entry| entry.as_ref().unwrap().is_file())
+            ),
+            [
+                "/3ds/app/config.ini",
+                "/3ds/app.3dsx",
+                "/boot.firm",
+                "/notes.txt"
+            ]
+        );
+
+        let depths: Vec<usize> = walk_dir(&tree, "/3ds/app")
+            .map(|entry| entry.unwrap().depth())
+            .collect();
+        assert_eq!(depths.iter().max(), Some(&2));
+
+        let mut walk = walk_dir(&tree, "/missing");
+        assert!(walk.next().unwrap().is_ok());
+        assert_eq!(
+            walk.next().unwrap().unwrap_err().kind(),
+            IoErrorKind::NotFound
+        );
+        assert!(walk.next().is_none());
+    }
+
+    #[test]
+    fn globbing() {
+        let tree = tree();
+        let glob = |pattern| {
+            glob(&tree, pattern)
+                .unwrap()
+                .iter()
+                .map(|path| path.display().to_string())
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(glob("/*.txt"), ["/notes.txt"]);
+        assert_eq!(glob("/*"), ["/3ds", "/boot.firm", "/notes.txt"]);
+        assert_eq!(glob("/3ds/app/saves/slot?.sav").len(), 3);
+        assert_eq!(
+            glob("/3ds/app/saves/slot[0-9].sav"),
+            ["/3ds/app/saves/slot1.sav", "/3ds/app/saves/slot2.sav"]
+        );
+        assert_eq!(glob("/3ds/**/slot[!12].sav"), ["/3ds/app/saves/slotA.sav"]);
+        assert_eq!(glob("**/*.[f-i]*"), ["/3ds/app/config.ini", "/boot.firm"]);
+        assert_eq!(glob("/3ds/app/config.ini"), ["/3ds/app/config.ini"]);
+        assert!(glob("/3ds/app/missing.ini").is_empty());
+
+        assert_eq!(
+            super::glob(&tree, "/slot[0-9.sav").unwrap_err().kind(),
+            IoErrorKind::InvalidInput
+        );
+    }
+}
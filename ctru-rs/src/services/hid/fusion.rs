@@ -0,0 +1,344 @@
+//! Sensor fusion
+//!
+//! The gyroscope is precise over short periods of time but drifts, while the accelerometer
+//! knows where "down" is but is noisy and sensitive to movement. A [`ComplementaryFilter`]
+//! combines both to track the orientation of the console as a [`Quaternion`].
+//!
+//! Everything in this module is plain math, so it can be fed with synthetic samples as well as
+//! with the readings of [`Hid::accelerometer_vector`](super::Hid::accelerometer_vector)
+//! and [`Hid::gyroscope_rate`](super::Hid::gyroscope_rate).
+
+use std::f32::consts::PI;
+use std::ops::Mul;
+
+/// Direction the accelerometer reports when the console lies still and flat, in the world frame.
+///
+/// The accelerometer measures gravity pulling away from the screen, which is along -Z when the
+/// console lies face up.
+const UP: [f32; 3] = [0.0, 0.0, -1.0];
+
+/// Vectors shorter than this are treated as zero.
+const EPSILON: f32 = 1e-6;
+
+/// A rotation in 3D space, stored as a unit quaternion.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Quaternion {
+    pub w: f32,
+    pub x: f32,
+    pub y: f32,
+    pub z: f32,
+}
+
+/// Tracks the orientation of the console by fusing accelerometer and gyroscope samples.
+///
+/// # Examples
+///
+/// ```no_run
+/// use ctru::services::hid::{fusion::ComplementaryFilter, Hid};
+///
+/// let mut hid = Hid::init().unwrap();
+/// hid.enable_accelerometer().unwrap();
+/// hid.enable_gyroscope().unwrap();
+///
+/// let coefficient = hid.gyroscope_coefficient().unwrap();
+/// let mut filter = ComplementaryFilter::new(0.98);
+///
+/// loop {
+///     hid.scan_input();
+///
+///     let acceleration = hid.accelerometer_vector().to_g();
+///     let rate = hid.gyroscope_rate().to_dps(coefficient);
+///     let (roll, pitch, yaw) = filter.update(acceleration, rate, 1.0 / 60.0).to_euler();
+///
+///     // ...
+/// #   break;
+/// }
+/// ```
+#[derive(Copy, Clone, Debug)]
+pub struct ComplementaryFilter {
+    gyro_weight: f32,
+    orientation: Quaternion,
+}
+
+impl Quaternion {
+    /// The rotation that does nothing.
+    pub const IDENTITY: Quaternion = Quaternion {
+        w: 1.0,
+        x: 0.0,
+        y: 0.0,
+        z: 0.0,
+    };
+
+    /// Returns a rotation of `angle` radians around `axis`, which must be a unit vector.
+    pub fn from_axis_angle(axis: [f32; 3], angle: f32) -> Self {
+        let (sin, cos) = (angle / 2.0).sin_cos();
+
+        Self {
+            w: cos,
+            x: axis[0] * sin,
+            y: axis[1] * sin,
+            z: axis[2] * sin,
+        }
+    }
+
+    /// Returns a rotation around the direction of `vector`, by as many radians as its length.
+    pub fn from_rotation_vector(vector: [f32; 3]) -> Self {
+        let angle = length(vector);
+
+        if angle < EPSILON {
+            return Self::IDENTITY;
+        }
+
+        Self::from_axis_angle(scale(vector, 1.0 / angle), angle)
+    }
+
+    /// Returns the inverse rotation.
+    pub fn conjugate(self) -> Self {
+        Self {
+            w: self.w,
+            x: -self.x,
+            y: -self.y,
+            z: -self.z,
+        }
+    }
+
+    /// Scales the quaternion back to unit length, undoing accumulated rounding errors.
+    pub fn normalize(self) -> Self {
+        let norm = (self.w * self.w + self.x * self.x + self.y * self.y + self.z * self.z).sqrt();
+
+        if norm < EPSILON {
+            return Self::IDENTITY;
+        }
+
+        Self {
+            w: self.w / norm,
+            x: self.x / norm,
+            y: self.y / norm,
+            z: self.z / norm,
+        }
+    }
+
+    /// Applies the rotation to a vector.
+    pub fn rotate(self, vector: [f32; 3]) -> [f32; 3] {
+        let v = Quaternion {
+            w: 0.0,
+            x: vector[0],
+            y: vector[1],
+            z: vector[2],
+        };
+        let rotated = self * v * self.conjugate();
+
+        [rotated.x, rotated.y, rotated.z]
+    }
+
+    /// Returns the rotation as (roll, pitch, yaw) angles in radians,
+    /// respectively around the X, Y and Z axes.
+    pub fn to_euler(self) -> (f32, f32, f32) {
+        let Quaternion { w, x, y, z } = self;
+
+        let roll = (2.0 * (w * x + y * z)).atan2(1.0 - 2.0 * (x * x + y * y));
+        let pitch = (2.0 * (w * y - z * x)).clamp(-1.0, 1.0).asin();
+        let yaw = (2.0 * (w * z + x * y)).atan2(1.0 - 2.0 * (y * y + z * z));
+
+        (roll, pitch, yaw)
+    }
+}
+
+impl Default for Quaternion {
+    fn default() -> Self {
+        Self::IDENTITY
+    }
+}
+
+impl Mul for Quaternion {
+    type Output = Quaternion;
+
+    /// Composes two rotations: `a * b` applies `b` first, then `a`.
+    fn mul(self, rhs: Quaternion) -> Quaternion {
+        let (a, b) = (self, rhs);
+
+        Quaternion {
+            w: a.w * b.w - a.x * b.x - a.y * b.y - a.z * b.z,
+            x: a.w * b.x + a.x * b.w + a.y * b.z - a.z * b.y,
+            y: a.w * b.y - a.x * b.z + a.y * b.w + a.z * b.x,
+            z: a.w * b.z + a.x * b.y - a.y * b.x + a.z * b.w,
+        }
+    }
+}
+
+impl ComplementaryFilter {
+    /// Creates a new filter, starting from the identity orientation.
+    ///
+    /// `gyro_weight` is the share of each update trusted to the gyroscope, from `0.0` to `1.0`.
+    /// Values around `0.98` work well at 60 updates per second: higher values react faster to
+    /// movement, lower values correct the gyroscope drift faster.
+    pub fn new(gyro_weight: f32) -> Self {
+        Self {
+            gyro_weight: gyro_weight.clamp(0.0, 1.0),
+            orientation: Quaternion::IDENTITY,
+        }
+    }
+
+    /// Feeds a pair of samples to the filter and returns the updated orientation.
+    ///
+    /// `acceleration` is expressed in any unit (only its direction is used), `angular_rate`
+    /// in degrees per second around the X, Y and Z axes, and `dt` is the time in seconds
+    /// elapsed since the previous update.
+    pub fn update(
+        &mut self,
+        acceleration: [f32; 3],
+        angular_rate: [f32; 3],
+        dt: f32,
+    ) -> Quaternion {
+        let step = scale(angular_rate, dt * PI / 180.0);
+        let mut orientation =
+            (self.orientation * Quaternion::from_rotation_vector(step)).normalize();
+
+        let norm = length(acceleration);
+        if norm > EPSILON {
+            // Pull the measured gravity back towards the world's "up" by a fraction of the error.
+            let measured = orientation.rotate(scale(acceleration, 1.0 / norm));
+            let axis = cross(measured, UP);
+            let sin = length(axis);
+            let cos = dot(measured, UP);
+
+            let correction = if sin > EPSILON {
+                Some((scale(axis, 1.0 / sin), sin.atan2(cos)))
+            } else if cos < 0.0 {
+                // Upside down, any horizontal axis will do.
+                Some(([1.0, 0.0, 0.0], PI))
+            } else {
+                None
+            };
+
+            if let Some((axis, angle)) = correction {
+                let angle = angle * (1.0 - self.gyro_weight);
+                orientation = (Quaternion::from_axis_angle(axis, angle) * orientation).normalize();
+            }
+        }
+
+        self.orientation = orientation;
+        orientation
+    }
+
+    /// Returns the current orientation estimate.
+    pub fn orientation(&self) -> Quaternion {
+        self.orientation
+    }
+
+    /// Resets the orientation estimate to the identity.
+    pub fn reset(&mut self) {
+        self.orientation = Quaternion::IDENTITY;
+    }
+}
+
+fn dot(a: [f32; 3], b: [f32; 3]) -> f32 {
+    a[0] * b[0] + a[1] * b[1] + a[2] * b[2]
+}
+
+fn cross(a: [f32; 3], b: [f32; 3]) -> [f32; 3] {
+    [
+        a[1] * b[2] - a[2] * b[1],
+        a[2] * b[0] - a[0] * b[2],
+        a[0] * b[1] - a[1] * b[0],
+    ]
+}
+
+fn length(v: [f32; 3]) -> f32 {
+    dot(v, v).sqrt()
+}
+
+fn scale(v: [f32; 3], factor: f32) -> [f32; 3] {
+    [v[0] * factor, v[1] * factor, v[2] * factor]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_close(a: [f32; 3], b: [f32; 3]) {
+        for i in 0..3 {
+            assert!((a[i] - b[i]).abs() < 1e-3, "{a:?} != {b:?}");
+        }
+    }
+
+    #[test]
+    fn rotation() {
+        let quarter_turn = Quaternion::from_axis_angle([0.0, 0.0, 1.0], PI / 2.0);
+
+        assert_close(quarter_turn.rotate([1.0, 0.0, 0.0]), [0.0, 1.0, 0.0]);
+        assert_close(
+            (quarter_turn * quarter_turn).rotate([1.0, 0.0, 0.0]),
+            [-1.0, 0.0, 0.0],
+        );
+        assert_close(
+            (quarter_turn * quarter_turn.conjugate()).rotate([0.3, 0.4, 0.5]),
+            [0.3, 0.4, 0.5],
+        );
+    }
+
+    #[test]
+    fn gyroscope_integration() {
+        let mut filter = ComplementaryFilter::new(1.0);
+
+        // A quarter turn around Z over one second, with the accelerometer ignored.
+        for _ in 0..100 {
+            filter.update(UP, [0.0, 0.0, 90.0], 0.01);
+        }
+
+        let (roll, pitch, yaw) = filter.orientation().to_euler();
+        assert!(roll.abs() < 1e-3);
+        assert!(pitch.abs() < 1e-3);
+        assert!((yaw - PI / 2.0).abs() < 1e-3);
+    }
+
+    #[test]
+    fn converges_to_gravity() {
+        let tilt = Quaternion::from_axis_angle([1.0, 0.0, 0.0], PI / 6.0);
+        // What the accelerometer of a console tilted by 30 degrees measures.
+        let acceleration = tilt.conjugate().rotate(UP);
+
+        let mut filter = ComplementaryFilter::new(0.98);
+        for _ in 0..600 {
+            filter.update(acceleration, [0.0; 3], 1.0 / 60.0);
+        }
+
+        assert_close(filter.orientation().rotate(acceleration), UP);
+        let (roll, _, _) = filter.orientation().to_euler();
+        assert!((roll - PI / 6.0).abs() < 1e-3);
+    }
+
+    #[test]
+    fn corrects_drift() {
+        let mut filter = ComplementaryFilter::new(0.98);
+
+        // A gyroscope bias while lying flat must not make the estimate tilt away.
+        for _ in 0..6000 {
+            filter.update(UP, [2.0, -1.0, 0.0], 1.0 / 60.0);
+        }
+
+        // The bias is only balanced out by a small constant tilt.
+        let tilt = dot(filter.orientation().rotate(UP), UP).acos();
+        assert!(tilt < 3f32.to_radians(), "tilted by {tilt} radians");
+    }
+
+    /// The filter agrees with the simulated console lying flat.
+    #[cfg(feature = "host-mock")]
+    #[test]
+    fn mock_accelerometer() {
+        use crate::services::hid::Hid;
+
+        let mut hid = Hid::init().unwrap();
+        hid.enable_accelerometer().unwrap();
+        hid.scan_input();
+
+        let mut filter = ComplementaryFilter::new(0.98);
+        for _ in 0..600 {
+            filter.update(hid.accelerometer_vector().to_g(), [0.0; 3], 1.0 / 60.0);
+        }
+
+        let (roll, pitch, _) = filter.orientation().to_euler();
+        assert!(roll.abs() < 1e-3, "rolled by {roll} radians");
+        assert!(pitch.abs() < 1e-3, "pitched by {pitch} radians");
+    }
+}
@@ -0,0 +1,764 @@
+//! Higher-level input handling
+//!
+//! This module builds on top of the raw [`InputFrame`] snapshots returned by [`Hid::frame`](super::Hid::frame):
+//!
+//! * [`Actions`] binds named actions to buttons or button chords, and handles auto-repeat.
+//! * [`CirclePad`] applies a dead zone to the circle pad and quantizes it into 8 directions.
+//! * [`GestureRecognizer`] turns touch screen presses into taps, long presses, drags and swipes.
+//!
+//! Each of them is a state machine advanced once per frame by an `update` method taking a snapshot,
+//! so they work the same with live input, played back recordings or synthetic frames.
+//!
+//! # Examples
+//!
+//! ```no_run
+//! use ctru::services::hid::input::{ActionMap, Actions, Repeat};
+//! use ctru::services::hid::{Hid, KeyPad};
+//!
+//! #[derive(Copy, Clone, PartialEq, Eq, Debug)]
+//! enum Action {
+//!     Up,
+//!     Down,
+//!     Confirm,
+//!     Quit,
+//! }
+//!
+//! let mut map = ActionMap::new();
+//! map.bind(Action::Up, KeyPad::KEY_UP)
+//!     .bind(Action::Down, KeyPad::KEY_DOWN)
+//!     .bind(Action::Confirm, KeyPad::KEY_A)
+//!     .bind(Action::Quit, KeyPad::KEY_START | KeyPad::KEY_SELECT);
+//!
+//! let hid = Hid::init().unwrap();
+//! let mut actions = Actions::new(map).with_repeat(Repeat::default());
+//!
+//! loop {
+//!     hid.scan_input();
+//!     actions.update(&hid.frame());
+//!
+//!     if actions.triggered(Action::Down) {
+//!         // Move the cursor, repeatedly while the button is held.
+//!     }
+//!     if actions.pressed(Action::Quit) {
+//!         break;
+//!     }
+//! }
+//! ```
+
+use std::f32::consts::PI;
+
+use super::{InputFrame, KeyPad};
+
+/// Bindings from actions to buttons.
+///
+/// An action can have multiple bindings, and is active while any of them is.
+/// A binding with multiple buttons is a chord, active only while all of its buttons are held.
+/// The directions shared by the d-pad and the circle pad, such as [`KeyPad::KEY_UP`],
+/// count as held while either of their buttons is.
+#[derive(Clone, Debug)]
+pub struct ActionMap<A> {
+    bindings: Vec<(A, KeyPad)>,
+}
+
+/// Auto-repeat timing, in frames.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Repeat {
+    /// Frames between the press of a button and its first repetition.
+    pub delay: u32,
+    /// Frames between two repetitions.
+    pub interval: u32,
+}
+
+/// Tracks the state of the actions of an [`ActionMap`] from frame to frame.
+///
+/// When the buttons of a chord are held, bindings using a subset of those buttons for other actions
+/// are ignored. For example, with `KEY_A` bound to "jump" and `KEY_L | KEY_A` bound to "dash",
+/// holding L and A only activates "dash".
+#[derive(Clone, Debug)]
+pub struct Actions<A> {
+    map: ActionMap<A>,
+    repeat: Option<Repeat>,
+    /// Amount of consecutive frames each action has been active for, on the current and previous frame.
+    states: Vec<(A, u32, u32)>,
+}
+
+/// One of the 8 directions of a digital pad.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Direction {
+    Up,
+    UpRight,
+    Right,
+    DownRight,
+    Down,
+    DownLeft,
+    Left,
+    UpLeft,
+}
+
+/// Dead zone and quantization settings for the circle pad.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct CirclePad {
+    /// Distance from the center under which the circle pad is considered at rest.
+    pub dead_zone: i16,
+    /// Distance from the center reached when the circle pad is pushed all the way.
+    pub range: i16,
+}
+
+/// A gesture performed on the touch screen.
+///
+/// Positions are expressed in pixels, like in [`InputFrame::touch`].
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Gesture {
+    /// The screen was touched and released without moving.
+    Tap { x: u16, y: u16 },
+    /// A second tap happened shortly after a first one, at about the same position.
+    ///
+    /// The first tap is still reported as a [`Gesture::Tap`].
+    DoubleTap { x: u16, y: u16 },
+    /// The screen has been touched without moving for a while. Reported once per touch.
+    LongPress { x: u16, y: u16 },
+    /// The touch moved away from its starting point. Reported on every frame until it is released.
+    Drag {
+        start: (u16, u16),
+        position: (u16, u16),
+        /// Movement since the previous frame.
+        delta: (i32, i32),
+    },
+    /// The touch quickly moved over a long enough distance before being released.
+    Swipe {
+        direction: Direction,
+        start: (u16, u16),
+        end: (u16, u16),
+    },
+}
+
+/// Thresholds used to tell gestures apart. Distances are in pixels, durations in frames.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct GestureConfig {
+    /// Distance a touch can move while still counting as a tap or long press.
+    pub tap_radius: u16,
+    /// Duration after which a still touch becomes a long press.
+    pub long_press_frames: u32,
+    /// Maximum time between two taps for them to count as a double tap.
+    pub double_tap_frames: u32,
+    /// Minimum distance covered by a swipe.
+    pub swipe_distance: u16,
+    /// Maximum duration of a swipe.
+    pub swipe_frames: u32,
+}
+
+/// Recognizes [`Gesture`]s from the touch screen input of consecutive frames.
+#[derive(Clone, Debug)]
+pub struct GestureRecognizer {
+    config: GestureConfig,
+    frame: u64,
+    touch: Option<Touch>,
+    /// Position and frame of the last tap, while it can still become a double tap.
+    last_tap: Option<((u16, u16), u64)>,
+}
+
+#[derive(Copy, Clone, Debug)]
+struct Touch {
+    start: (u16, u16),
+    last: (u16, u16),
+    frames: u32,
+    moved: bool,
+    long_pressed: bool,
+}
+
+impl<A: Copy + PartialEq> ActionMap<A> {
+    /// Creates an empty map.
+    pub fn new() -> Self {
+        Self {
+            bindings: Vec::new(),
+        }
+    }
+
+    /// Binds an action to a button, or to a chord if `keys` contains multiple buttons.
+    ///
+    /// Binding an empty set of buttons does nothing.
+    pub fn bind(&mut self, action: A, keys: KeyPad) -> &mut Self {
+        if !keys.is_empty() && !self.bindings.contains(&(action, keys)) {
+            self.bindings.push((action, keys));
+        }
+        self
+    }
+
+    /// Removes every binding of an action.
+    pub fn unbind(&mut self, action: A) -> &mut Self {
+        self.bindings.retain(|(a, _)| *a != action);
+        self
+    }
+
+    /// Returns the bindings of an action.
+    pub fn bindings(&self, action: A) -> impl Iterator<Item = KeyPad> + '_ {
+        self.bindings
+            .iter()
+            .filter(move |(a, _)| *a == action)
+            .map(|(_, keys)| *keys)
+    }
+
+    /// Returns the actions active with the specified buttons held, without chord precedence.
+    fn matches(&self, keys: KeyPad) -> impl Iterator<Item = &(A, KeyPad)> {
+        self.bindings
+            .iter()
+            .filter(move |(_, binding)| binding_held(*binding, keys))
+    }
+}
+
+/// Returns whether a binding is held, with the shared directions satisfied by either of their buttons.
+fn binding_held(binding: KeyPad, keys: KeyPad) -> bool {
+    let mut required = binding;
+
+    for direction in [
+        KeyPad::KEY_UP,
+        KeyPad::KEY_DOWN,
+        KeyPad::KEY_LEFT,
+        KeyPad::KEY_RIGHT,
+    ] {
+        if binding.contains(direction) {
+            if !keys.intersects(direction) {
+                return false;
+            }
+            required.remove(direction);
+        }
+    }
+
+    keys.contains(required)
+}
+
+impl<A: Copy + PartialEq> Default for ActionMap<A> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Default for Repeat {
+    /// Half a second before the first repetition, then 10 repetitions per second.
+    fn default() -> Self {
+        Self {
+            delay: 30,
+            interval: 6,
+        }
+    }
+}
+
+impl<A: Copy + PartialEq> Actions<A> {
+    /// Starts tracking the actions of a map, without auto-repeat.
+    pub fn new(map: ActionMap<A>) -> Self {
+        let mut states: Vec<(A, u32, u32)> = Vec::new();
+        for (action, _) in &map.bindings {
+            if !states.iter().any(|(a, _, _)| a == action) {
+                states.push((*action, 0, 0));
+            }
+        }
+
+        Self {
+            map,
+            repeat: None,
+            states,
+        }
+    }
+
+    /// Enables auto-repeat for [`Actions::triggered`].
+    pub fn with_repeat(mut self, repeat: Repeat) -> Self {
+        self.repeat = Some(repeat);
+        self
+    }
+
+    /// Returns the underlying map.
+    pub fn map(&self) -> &ActionMap<A> {
+        &self.map
+    }
+
+    /// Advances the state of every action by one frame.
+    pub fn update(&mut self, frame: &InputFrame) {
+        let matches: Vec<_> = self.map.matches(frame.keys).collect();
+
+        for (action, current, previous) in &mut self.states {
+            *previous = *current;
+
+            let active = matches.iter().any(|(a, keys)| {
+                a == action
+                    && !matches.iter().any(|(other, chord)| {
+                        other != action && chord.contains(*keys) && chord != keys
+                    })
+            });
+
+            *current = if active { current.saturating_add(1) } else { 0 };
+        }
+    }
+
+    /// Returns whether the action is active on the current frame.
+    pub fn held(&self, action: A) -> bool {
+        self.held_frames(action) > 0
+    }
+
+    /// Returns whether the action became active on the current frame.
+    pub fn pressed(&self, action: A) -> bool {
+        self.state(action)
+            .map_or(false, |(current, previous)| current > 0 && previous == 0)
+    }
+
+    /// Returns whether the action stopped being active on the current frame.
+    pub fn released(&self, action: A) -> bool {
+        self.state(action)
+            .map_or(false, |(current, previous)| current == 0 && previous > 0)
+    }
+
+    /// Returns whether the action was pressed or auto-repeated on the current frame.
+    ///
+    /// Without auto-repeat (see [`Actions::with_repeat`]), this is the same as [`Actions::pressed`].
+    pub fn triggered(&self, action: A) -> bool {
+        let held = self.held_frames(action);
+        if held == 0 {
+            return false;
+        }
+
+        let since_press = held - 1;
+        match self.repeat {
+            _ if since_press == 0 => true,
+            Some(Repeat { delay, interval }) if since_press >= delay => {
+                (since_press - delay) % interval.max(1) == 0
+            }
+            _ => false,
+        }
+    }
+
+    /// Returns the amount of consecutive frames the action has been active for, including the current one.
+    pub fn held_frames(&self, action: A) -> u32 {
+        self.state(action).map_or(0, |(current, _)| current)
+    }
+
+    fn state(&self, action: A) -> Option<(u32, u32)> {
+        self.states
+            .iter()
+            .find(|(a, _, _)| *a == action)
+            .map(|(_, current, previous)| (*current, *previous))
+    }
+}
+
+impl Direction {
+    /// Quantizes a vector into the nearest of the 8 directions, with `y` pointing up.
+    ///
+    /// Returns `None` for a null vector.
+    pub fn from_vector(x: f32, y: f32) -> Option<Direction> {
+        if x == 0.0 && y == 0.0 {
+            return None;
+        }
+
+        // Sectors of 45 degrees, counter-clockwise starting from the right.
+        let sector = ((y.atan2(x) / (PI / 4.0)).round() as i32).rem_euclid(8);
+
+        Some(match sector {
+            0 => Direction::Right,
+            1 => Direction::UpRight,
+            2 => Direction::Up,
+            3 => Direction::UpLeft,
+            4 => Direction::Left,
+            5 => Direction::DownLeft,
+            6 => Direction::Down,
+            _ => Direction::DownRight,
+        })
+    }
+
+    /// Returns the directional pad buttons corresponding to the direction.
+    pub fn keys(self) -> KeyPad {
+        match self {
+            Direction::Up => KeyPad::KEY_DUP,
+            Direction::UpRight => KeyPad::KEY_DUP | KeyPad::KEY_DRIGHT,
+            Direction::Right => KeyPad::KEY_DRIGHT,
+            Direction::DownRight => KeyPad::KEY_DDOWN | KeyPad::KEY_DRIGHT,
+            Direction::Down => KeyPad::KEY_DDOWN,
+            Direction::DownLeft => KeyPad::KEY_DDOWN | KeyPad::KEY_DLEFT,
+            Direction::Left => KeyPad::KEY_DLEFT,
+            Direction::UpLeft => KeyPad::KEY_DUP | KeyPad::KEY_DLEFT,
+        }
+    }
+}
+
+impl CirclePad {
+    /// Creates settings with the specified dead zone and the usual range of the circle pad.
+    pub fn new(dead_zone: i16) -> Self {
+        Self {
+            dead_zone,
+            ..Default::default()
+        }
+    }
+
+    /// Maps a raw position to a vector within the unit circle, with the dead zone removed.
+    ///
+    /// The dead zone is radial, and the remaining range is rescaled so that the output
+    /// smoothly grows from `0.0` at the edge of the dead zone to `1.0` at full range.
+    pub fn normalize(&self, (x, y): (i16, i16)) -> (f32, f32) {
+        let (x, y) = (x as f32, y as f32);
+        let distance = (x * x + y * y).sqrt();
+        let dead_zone = self.dead_zone.max(0) as f32;
+        let range = (self.range as f32).max(dead_zone + 1.0);
+
+        if distance <= dead_zone {
+            return (0.0, 0.0);
+        }
+
+        let magnitude = ((distance - dead_zone) / (range - dead_zone)).min(1.0);
+        (x / distance * magnitude, y / distance * magnitude)
+    }
+
+    /// Returns the direction the circle pad is pushed towards, or `None` while it is within the dead zone.
+    pub fn direction(&self, position: (i16, i16)) -> Option<Direction> {
+        let (x, y) = self.normalize(position);
+        Direction::from_vector(x, y)
+    }
+}
+
+impl Default for CirclePad {
+    fn default() -> Self {
+        Self {
+            dead_zone: 15,
+            range: 156,
+        }
+    }
+}
+
+impl Default for GestureConfig {
+    fn default() -> Self {
+        Self {
+            tap_radius: 8,
+            long_press_frames: 30,
+            double_tap_frames: 18,
+            swipe_distance: 40,
+            swipe_frames: 20,
+        }
+    }
+}
+
+impl GestureRecognizer {
+    /// Creates a recognizer with the specified thresholds.
+    pub fn new(config: GestureConfig) -> Self {
+        Self {
+            config,
+            frame: 0,
+            touch: None,
+            last_tap: None,
+        }
+    }
+
+    /// Returns whether the touch screen is currently being touched.
+    pub fn is_touching(&self) -> bool {
+        self.touch.is_some()
+    }
+
+    /// Advances the recognizer by one frame, returning the gesture recognized on that frame, if any.
+    pub fn update(&mut self, frame: &InputFrame) -> Option<Gesture> {
+        self.frame += 1;
+
+        let config = self.config;
+        let touching = frame.keys.contains(KeyPad::KEY_TOUCH);
+
+        match (self.touch.as_mut(), touching) {
+            (None, false) => None,
+            (None, true) => {
+                self.touch = Some(Touch {
+                    start: frame.touch,
+                    last: frame.touch,
+                    frames: 1,
+                    moved: false,
+                    long_pressed: false,
+                });
+                None
+            }
+            (Some(touch), true) => {
+                let last = touch.last;
+                touch.frames += 1;
+                touch.last = frame.touch;
+
+                if !touch.moved && distance(touch.start, frame.touch) > config.tap_radius as f32 {
+                    touch.moved = true;
+                }
+
+                if touch.moved {
+                    Some(Gesture::Drag {
+                        start: touch.start,
+                        position: frame.touch,
+                        delta: (
+                            frame.touch.0 as i32 - last.0 as i32,
+                            frame.touch.1 as i32 - last.1 as i32,
+                        ),
+                    })
+                } else if !touch.long_pressed && touch.frames >= config.long_press_frames {
+                    touch.long_pressed = true;
+                    Some(Gesture::LongPress {
+                        x: touch.start.0,
+                        y: touch.start.1,
+                    })
+                } else {
+                    None
+                }
+            }
+            (Some(touch), false) => {
+                // The touch position reads as (0, 0) once released, so the last one is used instead.
+                let touch = *touch;
+                self.touch = None;
+
+                if touch.moved {
+                    let (dx, dy) = (
+                        touch.last.0 as f32 - touch.start.0 as f32,
+                        touch.last.1 as f32 - touch.start.1 as f32,
+                    );
+
+                    if touch.frames <= config.swipe_frames
+                        && distance(touch.start, touch.last) >= config.swipe_distance as f32
+                    {
+                        // Screen coordinates grow downwards.
+                        Direction::from_vector(dx, -dy).map(|direction| Gesture::Swipe {
+                            direction,
+                            start: touch.start,
+                            end: touch.last,
+                        })
+                    } else {
+                        None
+                    }
+                } else if touch.long_pressed {
+                    None
+                } else {
+                    let (x, y) = touch.start;
+
+                    match self.last_tap.take() {
+                        Some((position, at))
+                            if self.frame - at <= config.double_tap_frames as u64
+                                && distance(position, touch.start) <= config.tap_radius as f32 =>
+                        {
+                            Some(Gesture::DoubleTap { x, y })
+                        }
+                        _ => {
+                            self.last_tap = Some((touch.start, self.frame));
+                            Some(Gesture::Tap { x, y })
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+impl Default for GestureRecognizer {
+    fn default() -> Self {
+        Self::new(GestureConfig::default())
+    }
+}
+
+fn distance(a: (u16, u16), b: (u16, u16)) -> f32 {
+    let dx = a.0 as f32 - b.0 as f32;
+    let dy = a.1 as f32 - b.1 as f32;
+
+    (dx * dx + dy * dy).sqrt()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Copy, Clone, PartialEq, Eq, Debug)]
+    enum Action {
+        Jump,
+        Dash,
+        Menu,
+    }
+
+    fn actions() -> Actions<Action> {
+        let mut map = ActionMap::new();
+        map.bind(Action::Jump, KeyPad::KEY_A)
+            .bind(Action::Jump, KeyPad::KEY_B)
+            .bind(Action::Dash, KeyPad::KEY_L | KeyPad::KEY_A)
+            .bind(Action::Menu, KeyPad::KEY_START);
+
+        Actions::new(map)
+    }
+
+    fn touches(gestures: &mut GestureRecognizer, points: &[(u16, u16)]) -> Vec<Gesture> {
+        let mut frames: Vec<_> = points
+            .iter()
+            .map(|&(x, y)| InputFrame::touch(x, y))
+            .collect();
+        frames.push(InputFrame::default());
+
+        frames
+            .iter()
+            .filter_map(|frame| gestures.update(frame))
+            .collect()
+    }
+
+    #[test]
+    fn press_and_release() {
+        let mut actions = actions();
+
+        actions.update(&InputFrame::pressed(KeyPad::KEY_B));
+        assert!(actions.pressed(Action::Jump));
+        assert!(!actions.pressed(Action::Menu));
+
+        // Switching to another binding of the same action keeps it held.
+        actions.update(&InputFrame::pressed(KeyPad::KEY_A));
+        assert!(!actions.pressed(Action::Jump));
+        assert_eq!(actions.held_frames(Action::Jump), 2);
+
+        actions.update(&InputFrame::default());
+        assert!(actions.released(Action::Jump));
+        assert!(!actions.held(Action::Jump));
+    }
+
+    #[test]
+    fn chords() {
+        let mut actions = actions();
+
+        actions.update(&InputFrame::pressed(KeyPad::KEY_L));
+        assert!(!actions.held(Action::Dash));
+
+        actions.update(&InputFrame::pressed(KeyPad::KEY_L | KeyPad::KEY_A));
+        assert!(actions.pressed(Action::Dash));
+        assert!(!actions.held(Action::Jump));
+
+        // B isn't part of the chord, so it still counts as a jump.
+        actions.update(&InputFrame::pressed(
+            KeyPad::KEY_L | KeyPad::KEY_A | KeyPad::KEY_B,
+        ));
+        assert!(actions.held(Action::Dash));
+        assert!(actions.pressed(Action::Jump));
+    }
+
+    #[test]
+    fn shared_directions() {
+        let mut map = ActionMap::new();
+        map.bind(Action::Jump, KeyPad::KEY_UP)
+            .bind(Action::Dash, KeyPad::KEY_UP | KeyPad::KEY_R);
+        let mut actions = Actions::new(map);
+
+        actions.update(&InputFrame::pressed(KeyPad::KEY_DUP));
+        assert!(actions.pressed(Action::Jump));
+
+        actions.update(&InputFrame::pressed(KeyPad::KEY_CPAD_UP));
+        assert!(actions.held(Action::Jump));
+
+        actions.update(&InputFrame::pressed(KeyPad::KEY_DUP | KeyPad::KEY_R));
+        assert!(actions.pressed(Action::Dash));
+        assert!(!actions.held(Action::Jump));
+
+        actions.update(&InputFrame::pressed(KeyPad::KEY_CPAD_DOWN));
+        assert!(!actions.held(Action::Jump));
+    }
+
+    #[test]
+    fn auto_repeat() {
+        let mut actions = actions().with_repeat(Repeat {
+            delay: 10,
+            interval: 4,
+        });
+
+        let mut triggered = Vec::new();
+        for frame in 1..=25 {
+            actions.update(&InputFrame::pressed(KeyPad::KEY_START));
+            if actions.triggered(Action::Menu) {
+                triggered.push(frame);
+            }
+        }
+
+        assert_eq!(triggered, [1, 11, 15, 19, 23]);
+    }
+
+    #[test]
+    fn circle_pad() {
+        let pad = CirclePad::new(20);
+
+        assert_eq!(pad.normalize((10, -15)), (0.0, 0.0));
+        assert_eq!(pad.direction((10, -15)), None);
+        assert_eq!(pad.normalize((156, 0)), (1.0, 0.0));
+        assert_eq!(pad.normalize((0, -300)), (0.0, -1.0));
+
+        let (x, _) = pad.normalize((88, 0));
+        assert!((x - 0.5).abs() < 1e-6);
+
+        assert_eq!(pad.direction((0, 100)), Some(Direction::Up));
+        assert_eq!(pad.direction((100, 90)), Some(Direction::UpRight));
+        assert_eq!(pad.direction((-100, -30)), Some(Direction::Left));
+        assert_eq!(pad.direction((-70, -90)), Some(Direction::DownLeft));
+        assert_eq!(
+            Direction::DownLeft.keys(),
+            KeyPad::KEY_DDOWN | KeyPad::KEY_DLEFT
+        );
+    }
+
+    #[test]
+    fn taps() {
+        let mut gestures = GestureRecognizer::default();
+
+        assert_eq!(
+            touches(&mut gestures, &[(100, 100), (102, 99)]),
+            [Gesture::Tap { x: 100, y: 100 }]
+        );
+        assert_eq!(
+            touches(&mut gestures, &[(103, 101)]),
+            [Gesture::DoubleTap { x: 103, y: 101 }]
+        );
+
+        for _ in 0..30 {
+            gestures.update(&InputFrame::default());
+        }
+        assert_eq!(
+            touches(&mut gestures, &[(50, 50)]),
+            [Gesture::Tap { x: 50, y: 50 }]
+        );
+    }
+
+    #[test]
+    fn long_press() {
+        let mut gestures = GestureRecognizer::default();
+
+        assert_eq!(
+            touches(&mut gestures, &[(10, 20); 60]),
+            [Gesture::LongPress { x: 10, y: 20 }]
+        );
+    }
+
+    #[test]
+    fn drag_and_swipe() {
+        let mut gestures = GestureRecognizer::default();
+
+        let gestures = touches(
+            &mut gestures,
+            &[(200, 100), (190, 100), (170, 110), (140, 120)],
+        );
+        assert_eq!(
+            gestures,
+            [
+                Gesture::Drag {
+                    start: (200, 100),
+                    position: (190, 100),
+                    delta: (-10, 0),
+                },
+                Gesture::Drag {
+                    start: (200, 100),
+                    position: (170, 110),
+                    delta: (-20, 10),
+                },
+                Gesture::Drag {
+                    start: (200, 100),
+                    position: (140, 120),
+                    delta: (-30, 10),
+                },
+                Gesture::Swipe {
+                    direction: Direction::Left,
+                    start: (200, 100),
+                    end: (140, 120),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn slow_drags_are_not_swipes() {
+        let mut gestures = GestureRecognizer::default();
+        let points: Vec<_> = (0..40).map(|i| (100, 50 + i * 2)).collect();
+
+        let recognized = touches(&mut gestures, &points);
+        assert!(matches!(recognized.last(), Some(Gesture::Drag { .. })));
+    }
+}
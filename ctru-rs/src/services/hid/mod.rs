@@ -0,0 +1,467 @@
+//! HID service
+//!
+//! The HID service provides access to user input such as button presses, touch screen presses,
+//! and circle pad information. It also provides information from the sound volume slider,
+//! the accelerometer, and the gyroscope.
+
+pub mod fusion;
+pub mod input;
+pub mod record;
+
+use std::io::{self, Read};
+#[cfg(not(feature = "host-mock"))]
+use std::sync::Mutex;
+
+use crate::error::ResultCode;
+#[cfg(feature = "host-mock")]
+use crate::mock::sys as ctru_sys;
+use record::InputPlayer;
+
+bitflags::bitflags! {
+    /// A set of flags corresponding to the button and directional pad
+    /// inputs on the 3DS
+    #[derive(Default)]
+    pub struct KeyPad: u32 {
+        const KEY_A             = 1u32 << 0;
+        const KEY_B             = 1u32 << 1;
+        const KEY_SELECT        = 1u32 << 2;
+        const KEY_START         = 1u32 << 3;
+        const KEY_DRIGHT        = 1u32 << 4;
+        const KEY_DLEFT         = 1u32 << 5;
+        const KEY_DUP           = 1u32 << 6;
+        const KEY_DDOWN         = 1u32 << 7;
+        const KEY_R             = 1u32 << 8;
+        const KEY_L             = 1u32 << 9;
+        const KEY_X             = 1u32 << 10;
+        const KEY_Y             = 1u32 << 11;
+        const KEY_ZL            = 1u32 << 14;
+        const KEY_ZR            = 1u32 << 15;
+        const KEY_TOUCH         = 1u32 << 20;
+        const KEY_CSTICK_RIGHT  = 1u32 << 24;
+        const KEY_CSTICK_LEFT   = 1u32 << 25;
+        const KEY_CSTICK_UP     = 1u32 << 26;
+        const KEY_CSTICK_DOWN   = 1u32 << 27;
+        const KEY_CPAD_RIGHT    = 1u32 << 28;
+        const KEY_CPAD_LEFT     = 1u32 << 29;
+        const KEY_CPAD_UP       = 1u32 << 30;
+        const KEY_CPAD_DOWN     = 1u32 << 31;
+        // convenience catch-all for the dpad and cpad
+        const KEY_UP    = KeyPad::KEY_DUP.bits    | KeyPad::KEY_CPAD_UP.bits;
+        const KEY_DOWN  = KeyPad::KEY_DDOWN.bits  | KeyPad::KEY_CPAD_DOWN.bits;
+        const KEY_LEFT  = KeyPad::KEY_DLEFT.bits  | KeyPad::KEY_CPAD_LEFT.bits;
+        const KEY_RIGHT = KeyPad::KEY_DRIGHT.bits | KeyPad::KEY_CPAD_RIGHT.bits;
+    }
+}
+
+/// A reference-counted handle to the HID service. The service is closed
+/// when all instances of this struct fall out of scope.
+///
+/// This service requires no special permissions to use.
+pub struct Hid(());
+
+/// Snapshot of the user input during a single frame.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct InputFrame {
+    /// Buttons held down during the frame.
+    pub keys: KeyPad,
+    /// Touch screen position in pixels. Only meaningful when `keys` contains [`KeyPad::KEY_TOUCH`].
+    pub touch: (u16, u16),
+    /// Circle pad position in (x, y) form.
+    pub circle_pad: (i16, i16),
+}
+
+/// Recorded input being played back in place of the live one.
+struct Playback {
+    frames: Box<dyn Iterator<Item = io::Result<InputFrame>> + Send>,
+    current: InputFrame,
+    previous: InputFrame,
+}
+
+/// Recording being played back, shared by every handle to the service just like the live input.
+#[cfg(not(feature = "host-mock"))]
+static PLAYBACK: Mutex<Option<Playback>> = Mutex::new(None);
+
+#[cfg(feature = "host-mock")]
+thread_local! {
+    // The simulated console is local to each thread, and so is its playback.
+    static PLAYBACK: std::cell::RefCell<Option<Playback>> = const { std::cell::RefCell::new(None) };
+}
+
+/// Raw reading of the accelerometer, see [`Hid::accelerometer_vector`].
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct AccelVector {
+    /// Acceleration along the X axis.
+    pub x: i16,
+    /// Acceleration along the Y axis.
+    pub y: i16,
+    /// Acceleration along the Z axis.
+    pub z: i16,
+}
+
+/// Raw reading of the gyroscope, see [`Hid::gyroscope_rate`].
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct AngularRate {
+    /// Rotation around the X axis.
+    pub roll: i16,
+    /// Rotation around the Y axis.
+    pub pitch: i16,
+    /// Rotation around the Z axis.
+    pub yaw: i16,
+}
+
+/// Represents user input to the touchscreen.
+pub struct TouchPosition(ctru_sys::touchPosition);
+
+/// Represents the current position of the 3DS circle pad.
+pub struct CirclePosition(ctru_sys::circlePosition);
+
+/// Initializes the HID service.
+///
+/// # Errors
+///
+/// This function will return an error if the service was unable to be initialized.
+/// Since this service requires no special or elevated permissions, errors are
+/// rare in practice.
+impl Hid {
+    pub fn init() -> crate::Result<Hid> {
+        unsafe {
+            ResultCode(ctru_sys::hidInit())?;
+            Ok(Hid(()))
+        }
+    }
+
+    /// Scans the HID service for all user input occurring on the current
+    /// frame. This function should be called on every frame when polling
+    /// for user input.
+    ///
+    /// While a recording is being played back (see [`Hid::play`]), this advances
+    /// the recording by one frame instead.
+    pub fn scan_input(&self) {
+        unsafe { ctru_sys::hidScanInput() };
+
+        with_playback(|playback| {
+            if let Some(state) = playback.as_mut() {
+                match state.frames.next() {
+                    Some(Ok(frame)) => {
+                        state.previous = state.current;
+                        state.current = frame;
+                    }
+                    // The recording is over (or unreadable), give control back to the user.
+                    _ => *playback = None,
+                }
+            }
+        });
+    }
+
+    /// Replaces the live user input with a recording.
+    ///
+    /// From the next call to [`Hid::scan_input`] onwards, [`Hid::keys_down`], [`Hid::keys_held`],
+    /// [`Hid::keys_up`], [`Hid::touch_position`], [`Hid::circle_position`] and [`Hid::frame`], as
+    /// well as [`TouchPosition::get`] and [`CirclePosition::get`], will report the recorded input,
+    /// one frame per scan. Live input resumes once the recording ends.
+    pub fn play<R: Read + Send + 'static>(&mut self, player: InputPlayer<R>) {
+        let current = self.frame();
+
+        with_playback(|playback| {
+            *playback = Some(Playback {
+                frames: Box::new(player),
+                current,
+                previous: current,
+            })
+        });
+    }
+
+    /// Stops the playback started by [`Hid::play`], switching back to the live user input.
+    pub fn stop_playback(&mut self) {
+        with_playback(|playback| *playback = None);
+    }
+
+    /// Returns whether a recording is currently being played back.
+    pub fn is_playing(&self) -> bool {
+        with_playback(|playback| playback.is_some())
+    }
+
+    /// Returns a snapshot of the user input on the current frame.
+    pub fn frame(&self) -> InputFrame {
+        if let Some((current, _)) = playback_frames() {
+            return current;
+        }
+
+        InputFrame {
+            keys: self.keys_held(),
+            touch: TouchPosition::new().get(),
+            circle_pad: CirclePosition::new().get(),
+        }
+    }
+
+    /// Returns the current touch position in pixels.
+    pub fn touch_position(&self) -> (u16, u16) {
+        TouchPosition::new().get()
+    }
+
+    /// Returns the current circle pad position in (x, y) form.
+    pub fn circle_position(&self) -> (i16, i16) {
+        CirclePosition::new().get()
+    }
+
+    /// Starts sampling the accelerometer.
+    ///
+    /// The accelerometer consumes power while enabled, so it is off by default.
+    pub fn enable_accelerometer(&mut self) -> crate::Result<()> {
+        unsafe { ResultCode(ctru_sys::HIDUSER_EnableAccelerometer())? };
+        Ok(())
+    }
+
+    /// Stops sampling the accelerometer.
+    pub fn disable_accelerometer(&mut self) -> crate::Result<()> {
+        unsafe { ResultCode(ctru_sys::HIDUSER_DisableAccelerometer())? };
+        Ok(())
+    }
+
+    /// Starts sampling the gyroscope.
+    ///
+    /// The gyroscope consumes power while enabled, so it is off by default.
+    pub fn enable_gyroscope(&mut self) -> crate::Result<()> {
+        unsafe { ResultCode(ctru_sys::HIDUSER_EnableGyroscope())? };
+        Ok(())
+    }
+
+    /// Stops sampling the gyroscope.
+    pub fn disable_gyroscope(&mut self) -> crate::Result<()> {
+        unsafe { ResultCode(ctru_sys::HIDUSER_DisableGyroscope())? };
+        Ok(())
+    }
+
+    /// Returns the latest reading of the accelerometer.
+    ///
+    /// The reading is only updated while the accelerometer is enabled
+    /// (see [`Hid::enable_accelerometer`]) and [`Hid::scan_input`] gets called.
+    pub fn accelerometer_vector(&self) -> AccelVector {
+        let mut res = ctru_sys::accelVector { x: 0, y: 0, z: 0 };
+
+        unsafe { ctru_sys::hidAccelRead(&mut res) };
+
+        AccelVector {
+            x: res.x,
+            y: res.y,
+            z: res.z,
+        }
+    }
+
+    /// Returns the latest reading of the gyroscope.
+    ///
+    /// The reading is only updated while the gyroscope is enabled
+    /// (see [`Hid::enable_gyroscope`]) and [`Hid::scan_input`] gets called.
+    pub fn gyroscope_rate(&self) -> AngularRate {
+        let mut res = ctru_sys::angularRate { x: 0, y: 0, z: 0 };
+
+        unsafe { ctru_sys::hidGyroRead(&mut res) };
+
+        AngularRate {
+            roll: res.x,
+            pitch: res.y,
+            yaw: res.z,
+        }
+    }
+
+    /// Returns the calibration coefficient of the gyroscope,
+    /// used to convert raw readings to degrees per second with [`AngularRate::to_dps`].
+    pub fn gyroscope_coefficient(&self) -> crate::Result<f32> {
+        let mut coefficient = 0.0;
+
+        unsafe {
+            ResultCode(ctru_sys::HIDUSER_GetGyroscopeRawToDpsCoefficient(
+                &mut coefficient,
+            ))?
+        };
+
+        Ok(coefficient)
+    }
+
+    /// Returns the position of the sound volume slider, from `0` (muted) to `63`.
+    pub fn volume(&self) -> crate::Result<u8> {
+        let mut volume = 0;
+
+        unsafe { ResultCode(ctru_sys::HIDUSER_GetSoundVolume(&mut volume))? };
+
+        Ok(volume)
+    }
+
+    /// Returns the position of the 3D depth slider, from `0.0` (3D off) to `1.0`.
+    pub fn slider_3d(&self) -> f32 {
+        unsafe { ctru_sys::osGet3DSliderState() }
+    }
+
+    /// Returns a bitflag struct representing which buttons have just been pressed
+    /// on the current frame (and were not pressed on the previous frame).
+    pub fn keys_down(&self) -> KeyPad {
+        if let Some((current, previous)) = playback_frames() {
+            return current.keys - previous.keys;
+        }
+
+        unsafe {
+            let keys = ctru_sys::hidKeysDown();
+            KeyPad::from_bits_truncate(keys)
+        }
+    }
+
+    /// Returns a bitflag struct representing which buttons have been held down
+    /// during the current frame.
+    pub fn keys_held(&self) -> KeyPad {
+        if let Some((current, _)) = playback_frames() {
+            return current.keys;
+        }
+
+        unsafe {
+            let keys = ctru_sys::hidKeysHeld();
+            KeyPad::from_bits_truncate(keys)
+        }
+    }
+
+    /// Returns a bitflag struct representing which buttons have just been released on
+    /// the current frame.
+    pub fn keys_up(&self) -> KeyPad {
+        if let Some((current, previous)) = playback_frames() {
+            return previous.keys - current.keys;
+        }
+
+        unsafe {
+            let keys = ctru_sys::hidKeysUp();
+            KeyPad::from_bits_truncate(keys)
+        }
+    }
+}
+
+impl InputFrame {
+    /// Returns a frame in which only the specified buttons are held down.
+    pub fn pressed(keys: KeyPad) -> Self {
+        Self {
+            keys,
+            ..Default::default()
+        }
+    }
+
+    /// Returns a frame in which the touch screen is pressed at the specified position.
+    pub fn touch(x: u16, y: u16) -> Self {
+        Self {
+            keys: KeyPad::KEY_TOUCH,
+            touch: (x, y),
+            ..Default::default()
+        }
+    }
+
+    /// Returns a frame in which the circle pad is moved at the specified position.
+    pub fn circle_pad(x: i16, y: i16) -> Self {
+        Self {
+            circle_pad: (x, y),
+            ..Default::default()
+        }
+    }
+}
+
+impl AccelVector {
+    /// Raw value measured for an acceleration of 1 G.
+    ///
+    /// Every unit is calibrated slightly differently, so this is an approximation.
+    pub const ONE_G: f32 = 512.0;
+
+    /// Returns the acceleration in G as an array of (x, y, z) components,
+    /// ready to be used with [`ComplementaryFilter`](fusion::ComplementaryFilter).
+    pub fn to_g(self) -> [f32; 3] {
+        [
+            self.x as f32 / Self::ONE_G,
+            self.y as f32 / Self::ONE_G,
+            self.z as f32 / Self::ONE_G,
+        ]
+    }
+}
+
+impl AngularRate {
+    /// Returns the angular rate in degrees per second as an array of (roll, pitch, yaw) components,
+    /// ready to be used with [`ComplementaryFilter`](fusion::ComplementaryFilter).
+    ///
+    /// `coefficient` is the value returned by [`Hid::gyroscope_coefficient`].
+    pub fn to_dps(self, coefficient: f32) -> [f32; 3] {
+        [
+            self.roll as f32 / coefficient,
+            self.pitch as f32 / coefficient,
+            self.yaw as f32 / coefficient,
+        ]
+    }
+}
+
+impl Default for TouchPosition {
+    fn default() -> Self {
+        TouchPosition(ctru_sys::touchPosition { px: 0, py: 0 })
+    }
+}
+
+impl TouchPosition {
+    /// Create a new TouchPosition instance.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns the current touch position in pixels.
+    ///
+    /// While a recording is being played back (see [`Hid::play`]), this returns the recorded position.
+    pub fn get(&mut self) -> (u16, u16) {
+        match playback_frames() {
+            Some((current, _)) => (self.0.px, self.0.py) = current.touch,
+            None => unsafe { ctru_sys::hidTouchRead(&mut self.0) },
+        }
+        (self.0.px, self.0.py)
+    }
+}
+
+impl Default for CirclePosition {
+    fn default() -> Self {
+        CirclePosition(ctru_sys::circlePosition { dx: 0, dy: 0 })
+    }
+}
+
+impl CirclePosition {
+    /// Create a new CirclePosition instance.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns the current circle pad position in (x, y) form.
+    ///
+    /// While a recording is being played back (see [`Hid::play`]), this returns the recorded position.
+    pub fn get(&mut self) -> (i16, i16) {
+        match playback_frames() {
+            Some((current, _)) => (self.0.dx, self.0.dy) = current.circle_pad,
+            None => unsafe { ctru_sys::hidCircleRead(&mut self.0) },
+        }
+        (self.0.dx, self.0.dy)
+    }
+}
+
+/// Runs `f` on the playback state.
+fn with_playback<T>(f: impl FnOnce(&mut Option<Playback>) -> T) -> T {
+    #[cfg(not(feature = "host-mock"))]
+    {
+        f(&mut PLAYBACK.lock().expect("Hid playback state is poisoned"))
+    }
+    #[cfg(feature = "host-mock")]
+    {
+        PLAYBACK.with(|playback| f(&mut playback.borrow_mut()))
+    }
+}
+
+/// Returns the current and previous frame of the playback, if any.
+fn playback_frames() -> Option<(InputFrame, InputFrame)> {
+    with_playback(|playback| {
+        playback
+            .as_ref()
+            .map(|state| (state.current, state.previous))
+    })
+}
+
+impl Drop for Hid {
+    fn drop(&mut self) {
+        with_playback(|playback| *playback = None);
+        unsafe { ctru_sys::hidExit() };
+    }
+}
@@ -0,0 +1,310 @@
+//! Input recording and playback
+//!
+//! An [`InputRecorder`] serializes the user input of every frame into a compact binary stream,
+//! which can then be fed back through [`Hid`](super::Hid) by an [`InputPlayer`] via [`Hid::play`](super::Hid::play).
+//! This is useful to reproduce bugs deterministically or to implement demo ("attract") modes.
+//!
+//! # Stream format
+//!
+//! The stream starts with the `CTRI` magic number followed by a version byte.
+//! Every frame is then stored as a flags byte describing which parts of the input changed
+//! since the previous frame, followed only by the changed values (in little-endian order):
+//!
+//! | Flag   | Data                                  |
+//! |--------|---------------------------------------|
+//! | `0x01` | [`KeyPad`] bits as a `u32`            |
+//! | `0x02` | Touch position as two `u16`s (x, y)   |
+//! | `0x04` | Circle pad position as two `i16`s (x, y) |
+//!
+//! Frames without any change (which are the vast majority) take up a single byte.
+
+use std::io::{self, Read, Write};
+
+use super::{InputFrame, KeyPad};
+
+const MAGIC: &[u8; 4] = b"CTRI";
+const VERSION: u8 = 1;
+
+const KEYS_CHANGED: u8 = 0x01;
+const TOUCH_CHANGED: u8 = 0x02;
+const CIRCLE_PAD_CHANGED: u8 = 0x04;
+
+/// Writes the user input of each frame to a stream.
+///
+/// # Examples
+///
+/// ```no_run
+/// use ctru::services::hid::{record::InputRecorder, Hid};
+///
+/// let hid = Hid::init().unwrap();
+/// let mut recorder = InputRecorder::new(Vec::new()).unwrap();
+///
+/// for _ in 0..60 {
+///     hid.scan_input();
+///     recorder.record(&hid.frame()).unwrap();
+/// }
+///
+/// let recording = recorder.into_inner();
+/// ```
+pub struct InputRecorder<W: Write> {
+    writer: W,
+    previous: InputFrame,
+    frame_count: u64,
+}
+
+/// Reads frames of user input from a stream written by an [`InputRecorder`].
+///
+/// The player can be iterated over directly, or handed to [`Hid::play`](super::Hid::play)
+/// to make the recorded input replace the live one.
+pub struct InputPlayer<R: Read> {
+    reader: R,
+    previous: InputFrame,
+}
+
+impl<W: Write> InputRecorder<W> {
+    /// Creates a new recorder, writing the stream header to `writer`.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the header couldn't be written.
+    pub fn new(mut writer: W) -> io::Result<Self> {
+        writer.write_all(MAGIC)?;
+        writer.write_all(&[VERSION])?;
+
+        Ok(Self {
+            writer,
+            previous: InputFrame::default(),
+            frame_count: 0,
+        })
+    }
+
+    /// Appends a frame to the stream.
+    pub fn record(&mut self, frame: &InputFrame) -> io::Result<()> {
+        let mut flags = 0;
+        let mut data = [0u8; 13];
+        let mut len = 1;
+
+        if frame.keys != self.previous.keys {
+            flags |= KEYS_CHANGED;
+            data[len..len + 4].copy_from_slice(&frame.keys.bits().to_le_bytes());
+            len += 4;
+        }
+
+        if frame.touch != self.previous.touch {
+            flags |= TOUCH_CHANGED;
+            data[len..len + 2].copy_from_slice(&frame.touch.0.to_le_bytes());
+            data[len + 2..len + 4].copy_from_slice(&frame.touch.1.to_le_bytes());
+            len += 4;
+        }
+
+        if frame.circle_pad != self.previous.circle_pad {
+            flags |= CIRCLE_PAD_CHANGED;
+            data[len..len + 2].copy_from_slice(&frame.circle_pad.0.to_le_bytes());
+            data[len + 2..len + 4].copy_from_slice(&frame.circle_pad.1.to_le_bytes());
+            len += 4;
+        }
+
+        data[0] = flags;
+        self.writer.write_all(&data[..len])?;
+
+        self.previous = *frame;
+        self.frame_count += 1;
+
+        Ok(())
+    }
+
+    /// Returns the amount of frames recorded so far.
+    pub fn frame_count(&self) -> u64 {
+        self.frame_count
+    }
+
+    /// Flushes the underlying writer.
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.writer.flush()
+    }
+
+    /// Consumes the recorder, returning the underlying writer.
+    pub fn into_inner(self) -> W {
+        self.writer
+    }
+}
+
+impl<R: Read> InputPlayer<R> {
+    /// Creates a new player, reading and validating the stream header from `reader`.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error of kind [`InvalidData`](io::ErrorKind::InvalidData)
+    /// if the stream wasn't written by an [`InputRecorder`], or any error returned by `reader`.
+    pub fn new(mut reader: R) -> io::Result<Self> {
+        let mut header = [0u8; 5];
+        reader.read_exact(&mut header)?;
+
+        if &header[..4] != MAGIC {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "not an input recording",
+            ));
+        }
+        if header[4] != VERSION {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("unsupported input recording version {}", header[4]),
+            ));
+        }
+
+        Ok(Self {
+            reader,
+            previous: InputFrame::default(),
+        })
+    }
+
+    /// Reads the next frame of the stream, returning `None` once the stream is over.
+    pub fn next_frame(&mut self) -> io::Result<Option<InputFrame>> {
+        let mut flags = [0u8; 1];
+        loop {
+            match self.reader.read(&mut flags) {
+                Ok(0) => return Ok(None),
+                Ok(_) => break,
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                Err(e) => return Err(e),
+            }
+        }
+        let flags = flags[0];
+
+        if flags & !(KEYS_CHANGED | TOUCH_CHANGED | CIRCLE_PAD_CHANGED) != 0 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("invalid frame flags {flags:#04x}"),
+            ));
+        }
+
+        let mut frame = self.previous;
+
+        if flags & KEYS_CHANGED != 0 {
+            let mut bits = [0u8; 4];
+            self.reader.read_exact(&mut bits)?;
+            frame.keys = KeyPad::from_bits_truncate(u32::from_le_bytes(bits));
+        }
+
+        if flags & TOUCH_CHANGED != 0 {
+            let mut pos = [0u8; 4];
+            self.reader.read_exact(&mut pos)?;
+            frame.touch = (
+                u16::from_le_bytes([pos[0], pos[1]]),
+                u16::from_le_bytes([pos[2], pos[3]]),
+            );
+        }
+
+        if flags & CIRCLE_PAD_CHANGED != 0 {
+            let mut pos = [0u8; 4];
+            self.reader.read_exact(&mut pos)?;
+            frame.circle_pad = (
+                i16::from_le_bytes([pos[0], pos[1]]),
+                i16::from_le_bytes([pos[2], pos[3]]),
+            );
+        }
+
+        self.previous = frame;
+
+        Ok(Some(frame))
+    }
+}
+
+impl<R: Read> Iterator for InputPlayer<R> {
+    type Item = io::Result<InputFrame>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.next_frame().transpose()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::services::hid::{CirclePosition, Hid, TouchPosition};
+
+    fn sample_frames() -> Vec<InputFrame> {
+        vec![
+            InputFrame::default(),
+            InputFrame::pressed(KeyPad::KEY_A),
+            InputFrame::pressed(KeyPad::KEY_A),
+            InputFrame::touch(319, 239),
+            InputFrame {
+                keys: KeyPad::KEY_L | KeyPad::KEY_CPAD_LEFT,
+                touch: (319, 239),
+                circle_pad: (-156, 42),
+            },
+            InputFrame::default(),
+        ]
+    }
+
+    #[test]
+    fn round_trip() {
+        let frames = sample_frames();
+
+        let mut recorder = InputRecorder::new(Vec::new()).unwrap();
+        for frame in &frames {
+            recorder.record(frame).unwrap();
+        }
+        assert_eq!(recorder.frame_count(), frames.len() as u64);
+
+        let recording = recorder.into_inner();
+        let played: Vec<_> = InputPlayer::new(recording.as_slice())
+            .unwrap()
+            .collect::<io::Result<_>>()
+            .unwrap();
+
+        assert_eq!(played, frames);
+    }
+
+    #[test]
+    fn idle_frames_are_one_byte() {
+        let mut recorder = InputRecorder::new(Vec::new()).unwrap();
+        for _ in 0..100 {
+            recorder.record(&InputFrame::default()).unwrap();
+        }
+
+        assert_eq!(recorder.into_inner().len(), MAGIC.len() + 1 + 100);
+    }
+
+    #[test]
+    fn playback() {
+        let mut recorder = InputRecorder::new(Vec::new()).unwrap();
+        for frame in sample_frames() {
+            recorder.record(&frame).unwrap();
+        }
+        let player = InputPlayer::new(io::Cursor::new(recorder.into_inner())).unwrap();
+
+        let mut hid = Hid::init().unwrap();
+        hid.play(player);
+
+        let played: Vec<_> = (0..sample_frames().len())
+            .map(|_| {
+                hid.scan_input();
+                let frame = hid.frame();
+                assert_eq!(TouchPosition::new().get(), frame.touch);
+                assert_eq!(CirclePosition::new().get(), frame.circle_pad);
+                frame
+            })
+            .collect();
+        assert_eq!(played, sample_frames());
+
+        hid.scan_input();
+        assert!(!hid.is_playing());
+    }
+
+    #[test]
+    fn invalid_streams() {
+        assert!(InputPlayer::new(&b"CTRX\x01"[..]).is_err());
+        assert!(InputPlayer::new(&b"CTRI\x02"[..]).is_err());
+
+        // A frame cut in the middle of its data.
+        let mut player = InputPlayer::new(&b"CTRI\x01\x01\xff"[..]).unwrap();
+        assert!(player.next_frame().is_err());
+
+        // Unknown flags.
+        let mut player = InputPlayer::new(&b"CTRI\x01\x80"[..]).unwrap();
+        assert!(player.next_frame().is_err());
+    }
+}
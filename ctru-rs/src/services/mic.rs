@@ -0,0 +1,475 @@
+//! MIC service
+//!
+//! The MIC service gives access to the microphone. While sampling, the service continuously writes
+//! PCM samples to a buffer shared with the application, looping back to its start once it reaches
+//! the end; [`Mic::read`] returns the samples written since the last read.
+
+use std::alloc::{self, Layout};
+use std::ops::Range;
+use std::sync::Mutex;
+
+use crate::error::ResultCode;
+use crate::services::ServiceReference;
+
+/// Encoding of the samples written by the microphone.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+#[repr(u32)]
+pub enum Encoding {
+    /// Unsigned 8-bit PCM.
+    Pcm8 = ctru_sys::MICU_ENCODING_PCM8,
+    /// Unsigned 16-bit PCM.
+    Pcm16 = ctru_sys::MICU_ENCODING_PCM16,
+    /// Signed 8-bit PCM.
+    Pcm8Signed = ctru_sys::MICU_ENCODING_PCM8_SIGNED,
+    /// Signed 16-bit PCM.
+    Pcm16Signed = ctru_sys::MICU_ENCODING_PCM16_SIGNED,
+}
+
+/// Rate at which the microphone is sampled.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+#[repr(u32)]
+pub enum SampleRate {
+    /// About 32730 Hz.
+    Hz32730 = ctru_sys::MICU_SAMPLE_RATE_32730,
+    /// About 16360 Hz.
+    Hz16360 = ctru_sys::MICU_SAMPLE_RATE_16360,
+    /// About 10910 Hz.
+    Hz10910 = ctru_sys::MICU_SAMPLE_RATE_10910,
+    /// About 8180 Hz.
+    Hz8180 = ctru_sys::MICU_SAMPLE_RATE_8180,
+}
+
+/// Handle to the MIC service, owning the buffer the microphone writes to. The service is closed
+/// when this struct is dropped.
+pub struct Mic {
+    _service_handler: ServiceReference,
+    // Declared after the service handler, so that it is freed once the service stopped using it.
+    buffer: SharedBuffer,
+    data_size: usize,
+    sampling: Option<(Encoding, SampleRate)>,
+    cursor: RingCursor,
+}
+
+/// Page-aligned buffer shared with the MIC service.
+struct SharedBuffer {
+    ptr: *mut u8,
+    layout: Layout,
+}
+
+/// Read position in the looping sample buffer.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+struct RingCursor {
+    position: usize,
+    size: usize,
+}
+
+static MIC_ACTIVE: Mutex<usize> = Mutex::new(0);
+
+impl Encoding {
+    /// Returns the size of one sample in bytes.
+    pub const fn sample_size(self) -> usize {
+        match self {
+            Self::Pcm8 | Self::Pcm8Signed => 1,
+            Self::Pcm16 | Self::Pcm16Signed => 2,
+        }
+    }
+}
+
+impl SampleRate {
+    /// Returns the sample rate in Hz.
+    pub fn hz(self) -> f32 {
+        match self {
+            Self::Hz32730 => 32728.5,
+            Self::Hz16360 => 16364.25,
+            Self::Hz10910 => 10909.5,
+            Self::Hz8180 => 8182.1,
+        }
+    }
+}
+
+impl Mic {
+    /// Initialize the MIC service with a default buffer size of 0x30000 bytes, which holds about
+    /// 3 seconds of 16-bit samples at the highest sample rate.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the `Mic` service is already initialized
+    pub fn init() -> crate::Result<Self> {
+        Self::init_with_buffer_size(0x30000)
+    }
+
+    /// Initialize the MIC service with a custom buffer size in bytes, rounded up to a multiple
+    /// of 0x1000. The last 4 bytes of the buffer are used by the service and can't hold samples.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the `Mic` service is already initialized, or
+    /// [`Error::BufferTooLarge`](crate::Error::BufferTooLarge) if the buffer can't be allocated
+    /// with that size.
+    pub fn init_with_buffer_size(num_bytes: usize) -> crate::Result<Self> {
+        let layout = buffer_layout(num_bytes)?;
+        let size = layout.size();
+        let ptr = unsafe { alloc::alloc_zeroed(layout) };
+        if ptr.is_null() {
+            alloc::handle_alloc_error(layout);
+        }
+        let buffer = SharedBuffer { ptr, layout };
+
+        let _service_handler = ServiceReference::new(
+            &MIC_ACTIVE,
+            false,
+            || {
+                ResultCode(unsafe { ctru_sys::micInit(ptr, size as u32) })?;
+
+                Ok(())
+            },
+            || unsafe {
+                // Make sure the service doesn't write to the buffer anymore before it is freed.
+                let _ = ctru_sys::MICU_StopSampling();
+                ctru_sys::micExit();
+            },
+        )?;
+
+        let data_size = unsafe { ctru_sys::micGetSampleDataSize() } as usize;
+
+        Ok(Self {
+            _service_handler,
+            buffer,
+            data_size,
+            sampling: None,
+            cursor: RingCursor::new(data_size),
+        })
+    }
+
+    /// Starts sampling the microphone, looping over the whole buffer.
+    ///
+    /// The microphone must be powered on with [`Mic::set_power`] to record anything.
+    pub fn start_sampling(&mut self, encoding: Encoding, rate: SampleRate) -> crate::Result<()> {
+        // The service only updates the offset of the last sample once it wrote one, so reset it
+        // to not read what was left over by a previous sampling.
+        unsafe {
+            self.buffer
+                .ptr
+                .add(self.data_size)
+                .cast::<u32>()
+                .write_volatile(0)
+        };
+        self.cursor = RingCursor::new(self.data_size);
+
+        ResultCode(unsafe {
+            ctru_sys::MICU_StartSampling(
+                encoding as u32,
+                rate as u32,
+                0,
+                self.data_size as u32,
+                true,
+            )
+        })?;
+        self.sampling = Some((encoding, rate));
+
+        Ok(())
+    }
+
+    /// Changes the sample rate while sampling.
+    pub fn adjust_sampling(&mut self, rate: SampleRate) -> crate::Result<()> {
+        ResultCode(unsafe { ctru_sys::MICU_AdjustSampling(rate as u32) })?;
+        if let Some((_, current)) = &mut self.sampling {
+            *current = rate;
+        }
+
+        Ok(())
+    }
+
+    /// Stops sampling the microphone. Samples which were not read yet are discarded.
+    pub fn stop_sampling(&mut self) -> crate::Result<()> {
+        ResultCode(unsafe { ctru_sys::MICU_StopSampling() })?;
+        self.sampling = None;
+
+        Ok(())
+    }
+
+    /// Returns whether the microphone is being sampled.
+    pub fn is_sampling(&self) -> crate::Result<bool> {
+        let mut sampling = false;
+        ResultCode(unsafe { ctru_sys::MICU_IsSampling(&mut sampling) })?;
+
+        Ok(sampling)
+    }
+
+    /// Returns the encoding of the samples, or [`None`] if not sampling.
+    pub fn encoding(&self) -> Option<Encoding> {
+        self.sampling.map(|(encoding, _)| encoding)
+    }
+
+    /// Returns the sample rate, or [`None`] if not sampling.
+    pub fn sample_rate(&self) -> Option<SampleRate> {
+        self.sampling.map(|(_, rate)| rate)
+    }
+
+    /// Sets the gain of the microphone amplifier.
+    pub fn set_gain(&mut self, gain: u8) -> crate::Result<()> {
+        ResultCode(unsafe { ctru_sys::MICU_SetGain(gain) })?;
+
+        Ok(())
+    }
+
+    /// Returns the gain of the microphone amplifier.
+    pub fn get_gain(&self) -> crate::Result<u8> {
+        let mut gain = 0;
+        ResultCode(unsafe { ctru_sys::MICU_GetGain(&mut gain) })?;
+
+        Ok(gain)
+    }
+
+    /// Powers the microphone on or off.
+    pub fn set_power(&mut self, power: bool) -> crate::Result<()> {
+        ResultCode(unsafe { ctru_sys::MICU_SetPower(power) })?;
+
+        Ok(())
+    }
+
+    /// Returns whether the microphone is powered on.
+    pub fn get_power(&self) -> crate::Result<bool> {
+        let mut power = false;
+        ResultCode(unsafe { ctru_sys::MICU_GetPower(&mut power) })?;
+
+        Ok(power)
+    }
+
+    /// Sets whether the input of the microphone is clamped.
+    pub fn set_clamp(&mut self, clamp: bool) -> crate::Result<()> {
+        ResultCode(unsafe { ctru_sys::MICU_SetClamp(clamp) })?;
+
+        Ok(())
+    }
+
+    /// Returns whether the input of the microphone is clamped.
+    pub fn get_clamp(&self) -> crate::Result<bool> {
+        let mut clamp = false;
+        ResultCode(unsafe { ctru_sys::MICU_GetClamp(&mut clamp) })?;
+
+        Ok(clamp)
+    }
+
+    /// Sets whether sampling continues while the console's shell is closed.
+    pub fn set_allow_shell_closed(&mut self, allow: bool) -> crate::Result<()> {
+        ResultCode(unsafe { ctru_sys::MICU_SetAllowShellClosed(allow) })?;
+
+        Ok(())
+    }
+
+    /// Returns the number of samples which can be read.
+    pub fn available(&self) -> usize {
+        match self.sampling {
+            Some((encoding, _)) => {
+                self.cursor.available(self.write_offset()) / encoding.sample_size()
+            }
+            None => 0,
+        }
+    }
+
+    /// Reads the samples written since the last read into `samples`, converted to signed 16-bit
+    /// PCM, and returns how many were read.
+    ///
+    /// Samples must be read faster than the buffer fills up: if the microphone laps the read
+    /// position, the samples in between are silently lost.
+    pub fn read(&mut self, samples: &mut [i16]) -> usize {
+        let encoding = match self.sampling {
+            Some((encoding, _)) => encoding,
+            None => return 0,
+        };
+
+        let mut read = 0;
+        for range in self.take(samples.len() * encoding.sample_size()) {
+            let bytes = self.data(range);
+            let count = bytes.len() / encoding.sample_size();
+            convert(encoding, bytes, &mut samples[read..read + count]);
+            read += count;
+        }
+
+        read
+    }
+
+    /// Reads the samples written since the last read into `bytes`, as written by the microphone,
+    /// and returns how many bytes were read. Only whole samples are read.
+    pub fn read_raw(&mut self, bytes: &mut [u8]) -> usize {
+        let sample_size = match self.sampling {
+            Some((encoding, _)) => encoding.sample_size(),
+            None => return 0,
+        };
+
+        let mut read = 0;
+        for range in self.take(bytes.len() / sample_size * sample_size) {
+            let data = self.data(range);
+            bytes[read..read + data.len()].copy_from_slice(data);
+            read += data.len();
+        }
+
+        read
+    }
+
+    /// Discards the samples which were not read yet.
+    pub fn discard(&mut self) {
+        self.cursor.seek(self.write_offset());
+    }
+
+    fn take(&mut self, max: usize) -> [Range<usize>; 2] {
+        let write = self.write_offset();
+        self.cursor.take(write, max)
+    }
+
+    fn data(&self, range: Range<usize>) -> &[u8] {
+        let data = unsafe { std::slice::from_raw_parts(self.buffer.ptr, self.data_size) };
+        &data[range]
+    }
+
+    fn write_offset(&self) -> usize {
+        unsafe { ctru_sys::micGetLastSampleOffset() as usize }
+    }
+}
+
+impl Drop for SharedBuffer {
+    fn drop(&mut self) {
+        unsafe { alloc::dealloc(self.ptr, self.layout) };
+    }
+}
+
+impl RingCursor {
+    fn new(size: usize) -> Self {
+        Self { position: 0, size }
+    }
+
+    /// Returns the number of bytes between the cursor and the write offset of the service.
+    fn available(&self, write: usize) -> usize {
+        (write % self.size + self.size - self.position) % self.size
+    }
+
+    /// Advances the cursor by at most `max` bytes towards the write offset, returning the ranges
+    /// of the buffer it went over.
+    fn take(&mut self, write: usize, max: usize) -> [Range<usize>; 2] {
+        let end = self.position + self.available(write).min(max);
+        let ranges = if end <= self.size {
+            [self.position..end, 0..0]
+        } else {
+            [self.position..self.size, 0..end - self.size]
+        };
+        self.position = end % self.size;
+
+        ranges
+    }
+
+    /// Moves the cursor to the write offset.
+    fn seek(&mut self, write: usize) {
+        self.position = write % self.size;
+    }
+}
+
+/// Converts samples of the given encoding to signed 16-bit PCM.
+fn convert(encoding: Encoding, bytes: &[u8], samples: &mut [i16]) {
+    match encoding {
+        Encoding::Pcm8 => {
+            for (sample, &byte) in samples.iter_mut().zip(bytes) {
+                *sample = ((byte ^ 0x80) as i8 as i16) << 8;
+            }
+        }
+        Encoding::Pcm8Signed => {
+            for (sample, &byte) in samples.iter_mut().zip(bytes) {
+                *sample = (byte as i8 as i16) << 8;
+            }
+        }
+        Encoding::Pcm16 => {
+            for (sample, bytes) in samples.iter_mut().zip(bytes.chunks_exact(2)) {
+                *sample = (u16::from_le_bytes([bytes[0], bytes[1]]) ^ 0x8000) as i16;
+            }
+        }
+        Encoding::Pcm16Signed => {
+            for (sample, bytes) in samples.iter_mut().zip(bytes.chunks_exact(2)) {
+                *sample = i16::from_le_bytes([bytes[0], bytes[1]]);
+            }
+        }
+    }
+}
+
+/// Returns the layout of a buffer of at least `num_bytes`, rounded up to whole pages.
+fn buffer_layout(num_bytes: usize) -> crate::Result<Layout> {
+    let too_large = || crate::Error::BufferTooLarge(num_bytes);
+    let size = num_bytes
+        .checked_add(0xfff)
+        .filter(|&size| size <= u32::MAX as usize)
+        .ok_or_else(too_large)?;
+
+    Layout::from_size_align((size & !0xfff).max(0x1000), 0x1000).map_err(|_| too_large())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ring_cursor() {
+        let mut cursor = RingCursor::new(8);
+        assert_eq!(cursor.available(0), 0);
+        assert_eq!(cursor.take(0, 8), [0..0, 0..0]);
+
+        assert_eq!(cursor.available(6), 6);
+        assert_eq!(cursor.take(6, 4), [0..4, 0..0]);
+        assert_eq!(cursor.take(6, 4), [4..6, 0..0]);
+
+        // The writer wrapped around.
+        assert_eq!(cursor.available(2), 4);
+        assert_eq!(cursor.take(2, 8), [6..8, 0..2]);
+        assert_eq!(cursor.position, 2);
+
+        assert_eq!(cursor.take(7, 3), [2..5, 0..0]);
+        assert_eq!(cursor.take(1, 8), [5..8, 0..1]);
+
+        // Stopping right at the end of the buffer.
+        let mut cursor = RingCursor::new(8);
+        cursor.seek(4);
+        assert_eq!(cursor.take(0, 8), [4..8, 0..0]);
+        assert_eq!(cursor.position, 0);
+
+        cursor.seek(11);
+        assert_eq!(cursor.position, 3);
+        assert_eq!(cursor.available(9), 6);
+    }
+
+    #[test]
+    fn buffer_sizes() {
+        assert_eq!(buffer_layout(0).unwrap().size(), 0x1000);
+        assert_eq!(buffer_layout(0x1001).unwrap().size(), 0x2000);
+        assert_eq!(buffer_layout(0x30000).unwrap().align(), 0x1000);
+
+        for size in [usize::MAX, usize::MAX - 0xfff] {
+            assert!(matches!(
+                buffer_layout(size),
+                Err(crate::Error::BufferTooLarge(s)) if s == size
+            ));
+        }
+    }
+
+    #[test]
+    fn conversion() {
+        let mut samples = [0; 3];
+
+        convert(Encoding::Pcm8, &[0x00, 0x80, 0xff], &mut samples);
+        assert_eq!(samples, [i16::MIN, 0, 0x7f00]);
+
+        convert(Encoding::Pcm8Signed, &[0x80, 0x00, 0x7f], &mut samples);
+        assert_eq!(samples, [i16::MIN, 0, 0x7f00]);
+
+        convert(
+            Encoding::Pcm16,
+            &[0x00, 0x00, 0x00, 0x80, 0xff, 0xff],
+            &mut samples,
+        );
+        assert_eq!(samples, [i16::MIN, 0, i16::MAX]);
+
+        convert(
+            Encoding::Pcm16Signed,
+            &[0x00, 0x80, 0x00, 0x00, 0xff, 0x7f],
+            &mut samples,
+        );
+        assert_eq!(samples, [i16::MIN, 0, i16::MAX]);
+    }
+}
@@ -9,13 +9,16 @@ pub mod am;
 pub mod apt;
 pub mod cam;
 pub mod cfgu;
+pub mod csnd;
 pub mod fs;
 pub mod gspgpu;
 pub mod hid;
+pub mod mic;
 pub mod ndsp;
 pub mod ps;
 mod reference;
 pub mod soc;
+pub mod sound;
 pub mod sslc;
 
 pub use self::apt::Apt;
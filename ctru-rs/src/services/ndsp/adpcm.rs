@@ -0,0 +1,481 @@
+//! DSPADPCM encoding and decoding
+//!
+//! DSPADPCM is the compressed format played natively by the DSP, taking about a quarter of the memory
+//! of 16-bit PCM. Samples are stored as 4-bit differences from a prediction made out of the 2
+//! previous samples, in frames of 8 bytes: a header selecting one of 8 pairs of predictor
+//! coefficients and a scale for the differences, then 14 samples.
+//!
+//! The coefficients are shared by a whole sound, and must be given to the channel playing it with
+//! [`Channel::set_adpcm_coefficients`](super::Channel::set_adpcm_coefficients). Encoding and decoding
+//! are implemented in pure Rust, so sounds can also be converted on the host.
+//!
+//! # Examples
+//!
+//! ```no_run
+//! use ctru::services::ndsp::adpcm::Adpcm;
+//! use ctru::services::ndsp::{AudioFormat, Ndsp};
+//!
+//! let ndsp = Ndsp::init().unwrap();
+//! let channel = ndsp.channel(0).unwrap();
+//!
+//! let samples: Vec<i16> = (0..32728).map(|i| ((i as f32 / 20.0).sin() * 8000.0) as i16).collect();
+//! let sound = Adpcm::encode(&samples);
+//!
+//! channel.set_format(AudioFormat::Adpcm);
+//! channel.set_adpcm_coefficients(sound.coefficients());
+//! channel.set_sample_rate(32728.0);
+//!
+//! let mut wave = sound.into_wave_info(false);
+//! channel.queue_wave(&mut wave).unwrap();
+//! ```
+
+use std::ops::Range;
+
+use super::wave::WaveInfo;
+use super::AudioFormat;
+use crate::linear::LinearAllocator;
+
+/// Amount of samples in a frame.
+pub const SAMPLES_PER_FRAME: usize = 14;
+
+/// Size of a frame in bytes, including its header.
+pub const BYTES_PER_FRAME: usize = 8;
+
+/// Largest scale that may be needed, making the differences cover the whole 16-bit range.
+const MAX_SCALE: u8 = 12;
+
+/// Rounds of refinement of the predictor coefficients when encoding.
+const CLUSTERING_ROUNDS: usize = 16;
+
+/// Decoder state before a sample: the header of its frame and the 2 samples preceding it.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct AdpcmContext {
+    /// Header of the frame, with the index of the coefficients in the high nibble and the scale in
+    /// the low nibble.
+    pub predictor_scale: u8,
+    /// Previous samples, the most recent first.
+    pub history: [i16; 2],
+}
+
+impl From<AdpcmContext> for ctru_sys::ndspAdpcmData {
+    fn from(context: AdpcmContext) -> Self {
+        Self {
+            index: context.predictor_scale.into(),
+            history0: context.history[0],
+            history1: context.history[1],
+        }
+    }
+}
+
+/// Returns the size in bytes of `samples` samples, with the header of every frame.
+pub fn byte_len(samples: usize) -> usize {
+    let frames = samples / SAMPLES_PER_FRAME;
+    match samples % SAMPLES_PER_FRAME {
+        0 => frames * BYTES_PER_FRAME,
+        rest => frames * BYTES_PER_FRAME + 1 + (rest + 1) / 2,
+    }
+}
+
+/// Returns the amount of whole samples stored in `len` bytes.
+pub fn sample_count(len: usize) -> usize {
+    let frames = len / BYTES_PER_FRAME;
+    match len % BYTES_PER_FRAME {
+        0 | 1 => frames * SAMPLES_PER_FRAME,
+        rest => frames * SAMPLES_PER_FRAME + (rest - 1) * 2,
+    }
+}
+
+/// Decodes `out.len()` samples from the start of `data`, which must be at the start of a frame.
+///
+/// `history` holds the 2 samples preceding the data, the most recent first, and is updated with the
+/// last decoded samples. Decoding stops early if `data` is too short.
+pub fn decode_into(coefficients: &[i16; 16], data: &[u8], history: &mut [i16; 2], out: &mut [i16]) {
+    for (frame, samples) in data
+        .chunks(BYTES_PER_FRAME)
+        .zip(out.chunks_mut(SAMPLES_PER_FRAME))
+    {
+        let header = frame[0];
+        let predictor = (header >> 4) as usize & 7;
+        let scale = header & 0xf;
+        let pair = [coefficients[predictor * 2], coefficients[predictor * 2 + 1]];
+
+        let nibbles = frame[1..].iter().flat_map(|&byte| [byte >> 4, byte & 0xf]);
+        for (sample, nibble) in samples.iter_mut().zip(nibbles) {
+            // Sign-extend the nibble.
+            let difference = ((nibble << 4) as i8 >> 4) as i32;
+            *sample = predict(pair, difference << scale, *history);
+            *history = [*sample, history[0]];
+        }
+    }
+}
+
+/// Returns the sample decoded from a scaled difference and the 2 previous samples.
+fn predict(pair: [i16; 2], difference: i32, history: [i16; 2]) -> i16 {
+    // Wider than the products, which only fit in 32 bits one at a time.
+    let prediction = pair[0] as i64 * history[0] as i64 + pair[1] as i64 * history[1] as i64;
+    let value = (((difference as i64) << 11) + 1024 + prediction) >> 11;
+    value.clamp(i16::MIN.into(), i16::MAX.into()) as i16
+}
+
+/// Mono sound encoded as DSPADPCM.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Adpcm {
+    coefficients: [i16; 16],
+    data: Vec<u8>,
+    sample_count: usize,
+}
+
+impl Adpcm {
+    /// Wraps already encoded data, holding `sample_count` samples encoded with `coefficients`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `data` is too short to hold `sample_count` samples.
+    pub fn from_parts(coefficients: [i16; 16], data: Vec<u8>, sample_count: usize) -> Self {
+        assert!(
+            data.len() >= byte_len(sample_count),
+            "DSPADPCM data too short for {sample_count} samples"
+        );
+
+        Self {
+            coefficients,
+            data,
+            sample_count,
+        }
+    }
+
+    /// Encodes 16-bit samples, picking the coefficients which suit them best.
+    pub fn encode(samples: &[i16]) -> Self {
+        let coefficients = estimate_coefficients(samples);
+        let mut data = Vec::with_capacity(byte_len(samples.len()));
+        let mut history = [0; 2];
+
+        for frame in samples.chunks(SAMPLES_PER_FRAME) {
+            let start = data.len();
+            data.resize(start + BYTES_PER_FRAME, 0);
+            encode_frame(&coefficients, frame, &mut history, &mut data[start..]);
+        }
+        data.truncate(byte_len(samples.len()));
+
+        Self {
+            coefficients,
+            data,
+            sample_count: samples.len(),
+        }
+    }
+
+    /// Returns the predictor coefficients, as 8 pairs of 4.11 fixed point numbers.
+    pub fn coefficients(&self) -> &[i16; 16] {
+        &self.coefficients
+    }
+
+    /// Returns the encoded frames.
+    pub fn data(&self) -> &[u8] {
+        &self.data
+    }
+
+    /// Returns the amount of samples.
+    pub fn sample_count(&self) -> usize {
+        self.sample_count
+    }
+
+    /// Decodes all of the samples.
+    pub fn decode(&self) -> Vec<i16> {
+        let mut samples = vec![0; self.sample_count];
+        decode_into(&self.coefficients, &self.data, &mut [0; 2], &mut samples);
+        samples
+    }
+
+    /// Returns the decoder state before the given sample, which is needed to start playing from it.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `sample` is past the end of the sound.
+    pub fn context_at(&self, sample: usize) -> AdpcmContext {
+        assert!(sample <= self.sample_count, "sample out of bounds");
+
+        let mut history = [0; 2];
+        let mut scratch = vec![0; sample];
+        decode_into(&self.coefficients, &self.data, &mut history, &mut scratch);
+
+        AdpcmContext {
+            predictor_scale: self
+                .data
+                .get(sample / SAMPLES_PER_FRAME * BYTES_PER_FRAME)
+                .copied()
+                .unwrap_or(0),
+            history,
+        }
+    }
+
+    /// Copies all of the samples into a wave in LINEAR memory, which starts from a clean decoder
+    /// state.
+    pub fn into_wave_info(self, looping: bool) -> WaveInfo {
+        self.wave_info(0..self.sample_count, looping)
+    }
+
+    /// Copies some of the samples into a wave in LINEAR memory, along with the decoder state at its
+    /// start.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the range is past the end of the sound, or doesn't start at the start of a frame.
+    pub fn wave_info(&self, samples: Range<usize>, looping: bool) -> WaveInfo {
+        assert!(
+            samples.start % SAMPLES_PER_FRAME == 0,
+            "DSPADPCM waves must start at the start of a frame"
+        );
+        assert!(
+            samples.start <= samples.end && samples.end <= self.sample_count,
+            "sample range out of bounds"
+        );
+
+        let start = samples.start / SAMPLES_PER_FRAME * BYTES_PER_FRAME;
+        let data = &self.data[start..start + byte_len(samples.len())];
+        let mut buffer = Vec::with_capacity_in(data.len(), LinearAllocator);
+        buffer.extend_from_slice(data);
+
+        let mut wave = WaveInfo::new(buffer.into_boxed_slice(), AudioFormat::Adpcm, looping);
+        // Neither can fail, the wave is new and the count fits in the buffer.
+        let _ = wave.set_sample_count(samples.len() as u32);
+        let _ = wave.set_adpcm_context(Some(self.context_at(samples.start)));
+        wave
+    }
+}
+
+/// Picks the 8 pairs of coefficients used to encode `samples`.
+///
+/// The best second-order predictor of every frame is computed with least squares, and the
+/// predictors are then grouped into 8 clusters with k-means, whose centers are the coefficients.
+fn estimate_coefficients(samples: &[i16]) -> [i16; 16] {
+    let at = |index: isize| match usize::try_from(index) {
+        Ok(index) => samples[index] as f64,
+        Err(_) => 0.0,
+    };
+
+    let mut predictors = Vec::new();
+    for start in (0..samples.len()).step_by(SAMPLES_PER_FRAME) {
+        let end = (start + SAMPLES_PER_FRAME).min(samples.len());
+        // Autocorrelations of the samples with the 2 previous ones.
+        let (mut r11, mut r12, mut r22, mut r01, mut r02) = (0.0, 0.0, 0.0, 0.0, 0.0);
+        for index in start as isize..end as isize {
+            let (x0, x1, x2) = (at(index), at(index - 1), at(index - 2));
+            r11 += x1 * x1;
+            r12 += x1 * x2;
+            r22 += x2 * x2;
+            r01 += x0 * x1;
+            r02 += x0 * x2;
+        }
+
+        let determinant = r11 * r22 - r12 * r12;
+        let predictor = if determinant.abs() > 1e-9 * (r11 * r22).max(1.0) {
+            (
+                (r01 * r22 - r02 * r12) / determinant,
+                (r02 * r11 - r01 * r12) / determinant,
+            )
+        } else if r11 > 0.0 {
+            (r01 / r11, 0.0)
+        } else {
+            continue;
+        };
+
+        // Keep the predictors stable, which also keeps them in range of the 4.11 format.
+        predictors.push((predictor.0.clamp(-2.0, 2.0), predictor.1.clamp(-1.0, 1.0)));
+    }
+
+    // Common predictors as a start: none, first order, and second order of increasing strength.
+    let mut centers = [
+        (0.0, 0.0),
+        (1.0, 0.0),
+        (0.5, 0.0),
+        (2.0, -1.0),
+        (1.875, -0.9375),
+        (1.5, -0.5625),
+        (1.0, -0.25),
+        (-0.5, 0.0),
+    ];
+    for _ in 0..CLUSTERING_ROUNDS {
+        let mut sums = [(0.0, 0.0, 0usize); 8];
+        for &(a, b) in &predictors {
+            let nearest = (0..centers.len())
+                .min_by(|&i, &j| {
+                    let distance = |(x, y): (f64, f64)| (x - a).powi(2) + (y - b).powi(2);
+                    distance(centers[i]).total_cmp(&distance(centers[j]))
+                })
+                .unwrap();
+            sums[nearest].0 += a;
+            sums[nearest].1 += b;
+            sums[nearest].2 += 1;
+        }
+
+        // Centers without predictors are left where they are.
+        for (center, &(a, b, count)) in centers.iter_mut().zip(&sums) {
+            if count > 0 {
+                *center = (a / count as f64, b / count as f64);
+            }
+        }
+    }
+
+    let mut coefficients = [0; 16];
+    for (pair, center) in coefficients.chunks_exact_mut(2).zip(centers) {
+        pair[0] = (center.0 * 2048.0).round().clamp(-32768.0, 32767.0) as i16;
+        pair[1] = (center.1 * 2048.0).round().clamp(-32768.0, 32767.0) as i16;
+    }
+    coefficients
+}
+
+/// Encodes up to 14 samples into a frame, picking the coefficients and scale with the least error.
+fn encode_frame(coefficients: &[i16; 16], samples: &[i16], history: &mut [i16; 2], out: &mut [u8]) {
+    let mut best: Option<(u64, u8, [i8; SAMPLES_PER_FRAME], [i16; 2])> = None;
+
+    for (predictor, pair) in coefficients.chunks_exact(2).enumerate() {
+        let pair = [pair[0], pair[1]];
+
+        // Smallest scale fitting the differences from the prediction out of the original samples.
+        let mut previous = *history;
+        let mut largest = 0;
+        for &sample in samples {
+            let difference = sample as i32 - predict(pair, 0, previous) as i32;
+            largest = largest.max(difference.abs());
+            previous = [sample, previous[0]];
+        }
+        let mut scale = 0;
+        while scale < MAX_SCALE && largest > 7 << scale {
+            scale += 1;
+        }
+
+        // The decoded samples drift from the original ones, so neighbouring scales may do better.
+        for scale in scale.saturating_sub(1)..=(scale + 1).min(MAX_SCALE) {
+            let mut nibbles = [0; SAMPLES_PER_FRAME];
+            let mut decoded = *history;
+            let mut error = 0;
+            for (&sample, nibble) in samples.iter().zip(&mut nibbles) {
+                let target = (sample as i32) << 11;
+                let prediction =
+                    pair[0] as i32 * decoded[0] as i32 + pair[1] as i32 * decoded[1] as i32 + 1024;
+                let step = 1 << (scale + 11);
+                let difference = (target - prediction) as f64 / step as f64;
+                *nibble = difference.round().clamp(-8.0, 7.0) as i8;
+
+                let value = predict(pair, (*nibble as i32) << scale, decoded);
+                error += (value as i64 - sample as i64).pow(2) as u64;
+                decoded = [value, decoded[0]];
+            }
+
+            if best.map_or(true, |(best_error, ..)| error < best_error) {
+                best = Some((error, (predictor as u8) << 4 | scale, nibbles, decoded));
+            }
+        }
+    }
+
+    let (_, header, nibbles, decoded) = best.unwrap();
+    out[0] = header;
+    for (byte, pair) in out[1..].iter_mut().zip(nibbles.chunks_exact(2)) {
+        *byte = (pair[0] as u8) << 4 | (pair[1] as u8 & 0xf);
+    }
+    *history = decoded;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Signal to noise ratio of the decoded samples, in decibels.
+    fn snr(original: &[i16], decoded: &[i16]) -> f64 {
+        let signal: f64 = original.iter().map(|&s| (s as f64).powi(2)).sum();
+        let noise: f64 = original
+            .iter()
+            .zip(decoded)
+            .map(|(&a, &b)| (a as f64 - b as f64).powi(2))
+            .sum();
+        10.0 * (signal / noise.max(1.0)).log10()
+    }
+
+    #[test]
+    fn sizes() {
+        assert_eq!(byte_len(0), 0);
+        assert_eq!(byte_len(1), 2);
+        assert_eq!(byte_len(14), 8);
+        assert_eq!(byte_len(15), 10);
+        assert_eq!(byte_len(16), 10);
+        assert_eq!(byte_len(17), 11);
+
+        for samples in 0..100 {
+            assert_eq!(sample_count(byte_len(samples)) / 2, (samples + 1) / 2);
+        }
+        assert_eq!(AudioFormat::Adpcm.sample_count(16), 28);
+        assert_eq!(AudioFormat::Adpcm.checked_sample_size(), None);
+        assert_eq!(AudioFormat::PCM16Stereo.checked_sample_size(), Some(4));
+    }
+
+    #[test]
+    fn decoding() {
+        let mut coefficients = [0; 16];
+        coefficients[2] = 2048;
+
+        // Predictor 1 repeats the previous sample, so the differences accumulate.
+        let frame = [0x12, 0x17, 0xf8, 0, 0, 0, 0, 0];
+        let mut history = [100, 0];
+        let mut out = [0; 5];
+        decode_into(&coefficients, &frame, &mut history, &mut out);
+        assert_eq!(out, [104, 132, 128, 96, 96]);
+        assert_eq!(history, [96, 96]);
+    }
+
+    #[test]
+    fn extreme_coefficients() {
+        let coefficients = [i16::MIN; 16];
+
+        // The largest difference and scale on top of the largest prediction saturate.
+        let frame = [0x7f, 0x70, 0, 0, 0, 0, 0, 0];
+        let mut history = [i16::MIN, i16::MIN];
+        let mut out = [0; 1];
+        decode_into(&coefficients, &frame, &mut history, &mut out);
+        assert_eq!(out, [i16::MAX]);
+
+        assert_eq!(predict([i16::MIN; 2], 7 << 15, [i16::MIN; 2]), i16::MAX);
+        assert_eq!(predict([i16::MIN; 2], -8 << 15, [i16::MAX; 2]), i16::MIN);
+    }
+
+    #[test]
+    fn round_trip() {
+        let samples: Vec<i16> = (0..20000)
+            .map(|i| {
+                let t = i as f64 / 32000.0;
+                let tone = (t * 440.0 * std::f64::consts::TAU).sin() * 9000.0
+                    + (t * 1234.0 * std::f64::consts::TAU).sin() * 3000.0;
+                // The tone fades in, to exercise the scales.
+                (tone * (i as f64 / 20000.0)) as i16
+            })
+            .collect();
+
+        let sound = Adpcm::encode(&samples);
+        assert_eq!(sound.sample_count(), samples.len());
+        assert_eq!(sound.data().len(), byte_len(samples.len()));
+        let decoded = sound.decode();
+        assert!(
+            snr(&samples, &decoded) > 30.0,
+            "{}",
+            snr(&samples, &decoded)
+        );
+
+        // Decoding from a context gives the same samples.
+        let context = sound.context_at(7000);
+        let mut history = context.history;
+        let mut rest = vec![0; samples.len() - 7000];
+        decode_into(
+            sound.coefficients(),
+            &sound.data()[7000 / 14 * 8..],
+            &mut history,
+            &mut rest,
+        );
+        assert_eq!(rest, decoded[7000..]);
+        assert_eq!(context.predictor_scale, sound.data()[4000]);
+
+        // Silence and sounds shorter than a frame.
+        assert!(Adpcm::encode(&[0; 100]).decode().iter().all(|&s| s == 0));
+        let short = Adpcm::encode(&[1000, -1000, 500]);
+        assert!(snr(&[1000, -1000, 500], &short.decode()) > 20.0);
+        assert!(Adpcm::encode(&[]).data().is_empty());
+    }
+}
@@ -0,0 +1,507 @@
+use std::io::Error as IoError;
+use std::io::ErrorKind as IoErrorKind;
+use std::io::Result as IoResult;
+use std::io::{Read, Seek, SeekFrom};
+
+use super::{
+    add, check_magic, decode_channel, find_block, invalid_data, out_of_range, parse_header,
+    read_reference, read_reference_table, read_u16, read_u32, read_u8, truncated, AdpcmInfo,
+    Encoding,
+};
+use crate::services::ndsp::decoder::Decoder;
+use crate::services::ndsp::stream::Source;
+use crate::services::ndsp::AudioFormat;
+
+const INFO_BLOCK: u16 = 0x4000;
+const SEEK_BLOCK: u16 = 0x4001;
+const DATA_BLOCK: u16 = 0x4002;
+const STREAM_INFO: u16 = 0x4100;
+const REFERENCE_TABLE: u16 = 0x0101;
+const CHANNEL_INFO: u16 = 0x4102;
+const SAMPLE_DATA: u16 = 0x1f00;
+const DSP_ADPCM_INFO: u16 = 0x0300;
+
+/// Music streamed from a BCSTM file.
+///
+/// The samples are stored in blocks, which are read and decoded to 16-bit one at a time as the
+/// samples are read through the [`Decoder`] interface. Rewinding goes back to the start of the loop
+/// of the file, so that an [`AudioStream`](crate::services::ndsp::stream::AudioStream) set to loop
+/// plays it as intended.
+///
+/// Read errors end the samples early.
+///
+/// # Examples
+///
+/// ```no_run
+/// use ctru::services::ndsp::container::Bcstm;
+/// use ctru::services::ndsp::decoder::Decoder;
+/// use ctru::services::ndsp::stream::AudioStream;
+/// use ctru::services::ndsp::Ndsp;
+///
+/// let ndsp = Ndsp::init().unwrap();
+///
+/// let file = std::fs::File::open("romfs:/music.bcstm").unwrap();
+/// let music = Bcstm::new(file).unwrap();
+/// let looping = music.loop_start().is_some();
+///
+/// let (format, rate) = (music.format(), music.sample_rate());
+/// let stream = AudioStream::new(ndsp.channel(0).unwrap(), format, rate as f32, music).unwrap();
+/// stream.set_looping(looping);
+/// ```
+pub struct Bcstm<R> {
+    reader: R,
+    encoding: Encoding,
+    sample_rate: u32,
+    loop_start: Option<usize>,
+    sample_count: usize,
+    adpcm: Vec<Option<AdpcmInfo>>,
+    /// Position of the samples in the file, and of its end.
+    data_start: u64,
+    stream_end: u64,
+    block_count: usize,
+    block_size: usize,
+    block_samples: usize,
+    last_block_size: usize,
+    last_block_padded_size: usize,
+    /// Samples preceding each block, for each channel, if the file has them.
+    seek_table: Vec<[i16; 2]>,
+
+    next_block: usize,
+    history: Vec<[i16; 2]>,
+    /// Samples left to drop from the next block, to start from the middle of it.
+    skip: usize,
+    /// Samples of the current block, and the position of the first one not read yet.
+    decoded: Vec<i16>,
+    position: usize,
+    scratch: Vec<u8>,
+    channel_samples: Vec<i16>,
+}
+
+impl<R: Read + Seek> Bcstm<R> {
+    /// Parses the headers of a BCSTM file, which starts from the current position of `reader`.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if reading fails, the file is invalid, or holds IMA-ADPCM
+    /// samples or more than 2 channels, which aren't supported.
+    pub fn new(mut reader: R) -> IoResult<Self> {
+        let base = reader.stream_position()?;
+        // Sizes read from the file are checked against its length before allocating anything.
+        let stream_end = reader.seek(SeekFrom::End(0))?;
+        reader.seek(SeekFrom::Start(base))?;
+
+        let mut header = vec![0; 0x14];
+        reader.read_exact(&mut header)?;
+        let header_size = read_u16(&header, 6)? as usize;
+        header.resize(header_size.max(0x14), 0);
+        reader.read_exact(&mut header[0x14..])?;
+
+        let blocks = parse_header(&header, b"CSTM")?;
+        let mut read_block = |kind: u16, magic: &[u8; 4]| -> IoResult<Option<Vec<u8>>> {
+            let (offset, size) = match find_block(&blocks, kind) {
+                Some(block) => block,
+                None => return Ok(None),
+            };
+            let start = base + offset as u64;
+            if start + size as u64 > stream_end {
+                return Err(truncated());
+            }
+            let mut block = vec![0; size];
+            reader.seek(SeekFrom::Start(start))?;
+            reader.read_exact(&mut block)?;
+            check_magic(&block, 0, magic)?;
+            Ok(Some(block))
+        };
+        let info = read_block(INFO_BLOCK, b"INFO")?.ok_or_else(truncated)?;
+        let seek = read_block(SEEK_BLOCK, b"SEEK")?;
+        let (data, _) = find_block(&blocks, DATA_BLOCK).ok_or_else(truncated)?;
+
+        // Offsets in the block are relative to the end of its header.
+        let stream = read_reference(&info, 8, STREAM_INFO)?.ok_or_else(truncated)?;
+        let stream = add(stream, 8)?;
+        let encoding = Encoding::from_raw(read_u8(&info, stream)?)?;
+        let looping = read_u8(&info, stream + 1)? != 0;
+        let channel_count = read_u8(&info, stream + 2)? as usize;
+        let sample_rate = read_u32(&info, stream + 4)?;
+        let loop_start = read_u32(&info, stream + 8)? as usize;
+        let sample_count = read_u32(&info, stream + 0xc)? as usize;
+        let block_count = read_u32(&info, stream + 0x10)? as usize;
+        let block_size = read_u32(&info, stream + 0x14)? as usize;
+        let block_samples = read_u32(&info, stream + 0x18)? as usize;
+        let last_block_size = read_u32(&info, stream + 0x1c)? as usize;
+        let last_block_padded_size = read_u32(&info, stream + 0x24)? as usize;
+        let seek_interval = read_u32(&info, stream + 0x2c)? as usize;
+        let samples = read_reference(&info, stream + 0x30, SAMPLE_DATA)?.unwrap_or(0);
+
+        if channel_count == 0 || channel_count > 2 {
+            return Err(IoError::new(
+                IoErrorKind::Unsupported,
+                format!("unsupported channel count {channel_count}"),
+            ));
+        }
+        let total_samples = block_count
+            .checked_mul(block_samples)
+            .ok_or_else(out_of_range)?;
+        if block_samples == 0 || total_samples < sample_count {
+            return Err(invalid_data("blocks too short for the sound"));
+        }
+        if looping && loop_start >= sample_count {
+            return Err(invalid_data("loop start past the end of the sound"));
+        }
+
+        let table = read_reference(&info, 24, REFERENCE_TABLE)?.ok_or_else(truncated)?;
+        let table = add(table, 8)?;
+        let references = read_reference_table(&info, table, CHANNEL_INFO)?;
+        if references.len() != channel_count {
+            return Err(invalid_data("channel count mismatch"));
+        }
+        let adpcm = references
+            .into_iter()
+            .map(|reference| match encoding {
+                Encoding::DspAdpcm => {
+                    let channel = add(table, reference)?;
+                    let offset = read_reference(&info, channel, DSP_ADPCM_INFO)?
+                        .ok_or_else(|| invalid_data("DSPADPCM channel without parameters"))?;
+                    Ok(Some(AdpcmInfo::parse(&info, add(channel, offset)?)?))
+                }
+                _ => Ok(None),
+            })
+            .collect::<IoResult<_>>()?;
+
+        // The seek table is only usable if it has an entry for every block.
+        let mut seek_table = Vec::new();
+        if let Some(seek) = seek.filter(|_| seek_interval == block_samples) {
+            let entries = block_count
+                .checked_mul(channel_count)
+                .ok_or_else(out_of_range)?;
+            for entry in 0..entries {
+                seek_table.push([
+                    read_u16(&seek, 8 + entry * 4)? as i16,
+                    read_u16(&seek, 8 + entry * 4 + 2)? as i16,
+                ]);
+            }
+        }
+
+        let mut stream = Self {
+            reader,
+            encoding,
+            sample_rate,
+            loop_start: looping.then_some(loop_start),
+            sample_count,
+            adpcm,
+            data_start: base + data as u64 + 8 + samples as u64,
+            stream_end,
+            block_count,
+            block_size,
+            block_samples,
+            last_block_size,
+            last_block_padded_size,
+            seek_table,
+            next_block: 0,
+            history: Vec::new(),
+            skip: 0,
+            decoded: Vec::new(),
+            position: 0,
+            scratch: Vec::new(),
+            channel_samples: Vec::new(),
+        };
+        stream.seek_to(0);
+        Ok(stream)
+    }
+
+    /// Returns the encoding of the samples.
+    pub fn encoding(&self) -> Encoding {
+        self.encoding
+    }
+
+    /// Returns the amount of samples of each channel.
+    pub fn sample_count(&self) -> usize {
+        self.sample_count
+    }
+
+    /// Returns the amount of channels, which is 1 or 2.
+    pub fn channel_count(&self) -> usize {
+        self.adpcm.len()
+    }
+
+    /// Returns the sample the music goes back to once it reaches its end, if it loops.
+    pub fn loop_start(&self) -> Option<usize> {
+        self.loop_start
+    }
+
+    /// Returns the underlying reader.
+    pub fn into_inner(self) -> R {
+        self.reader
+    }
+
+    /// Moves to a sample, from the closest preceding block whose decoder state is known.
+    fn seek_to(&mut self, sample: usize) {
+        let channels = self.channel_count();
+        let mut block = sample / self.block_samples;
+        if block > 0 && self.seek_table.is_empty() && self.encoding == Encoding::DspAdpcm {
+            block = 0;
+        }
+
+        self.history = match block {
+            0 => self
+                .adpcm
+                .iter()
+                .map(|info| info.map_or([0; 2], |info| info.context.history))
+                .collect(),
+            _ if self.seek_table.is_empty() => vec![[0; 2]; channels],
+            _ => self.seek_table[block * channels..(block + 1) * channels].to_vec(),
+        };
+        self.next_block = block;
+        self.skip = sample - block * self.block_samples;
+        self.decoded.clear();
+        self.position = 0;
+    }
+
+    /// Reads and decodes the next block.
+    fn decode_block(&mut self) -> IoResult<()> {
+        let channels = self.channel_count();
+        let block = self.next_block;
+        let last = block + 1 == self.block_count;
+        let (size, stride) = match last {
+            true => (self.last_block_size, self.last_block_padded_size),
+            false => (self.block_size, self.block_size),
+        };
+        let samples = self
+            .block_samples
+            .min(self.sample_count - block * self.block_samples);
+
+        if !matches!(self.encoding.byte_len(samples), Some(len) if len <= size) {
+            return Err(invalid_data("block too short"));
+        }
+
+        let offset = (block as u64)
+            .checked_mul(channels as u64 * self.block_size as u64)
+            .and_then(|offset| offset.checked_add(self.data_start))
+            .ok_or_else(out_of_range)?;
+        let len = (channels - 1)
+            .checked_mul(stride)
+            .and_then(|len| len.checked_add(size))
+            .ok_or_else(out_of_range)?;
+        if offset
+            .checked_add(len as u64)
+            .map_or(true, |end| end > self.stream_end)
+        {
+            return Err(truncated());
+        }
+        self.scratch.resize(len, 0);
+        self.reader.seek(SeekFrom::Start(offset))?;
+        self.reader.read_exact(&mut self.scratch)?;
+
+        self.decoded.resize(samples * channels, 0);
+        self.channel_samples.resize(samples, 0);
+        for channel in 0..channels {
+            let data = &self.scratch[channel * stride..channel * stride + size];
+            decode_channel(
+                self.encoding,
+                self.adpcm[channel].as_ref(),
+                data,
+                &mut self.history[channel],
+                &mut self.channel_samples,
+            );
+
+            for (frame, &sample) in self
+                .decoded
+                .chunks_exact_mut(channels)
+                .zip(&self.channel_samples)
+            {
+                frame[channel] = sample;
+            }
+        }
+
+        self.next_block += 1;
+        let skipped = self.skip.min(samples);
+        self.position = skipped * channels;
+        self.skip -= skipped;
+        Ok(())
+    }
+}
+
+impl<R: Read + Seek> Source for Bcstm<R> {
+    fn read(&mut self, buf: &mut [i16]) -> usize {
+        while self.position == self.decoded.len() {
+            let remaining = self
+                .sample_count
+                .saturating_sub(self.next_block * self.block_samples);
+            if remaining == 0 || self.decode_block().is_err() {
+                self.decoded.clear();
+                self.position = 0;
+                return 0;
+            }
+        }
+
+        let samples = &self.decoded[self.position..];
+        let len = samples.len().min(buf.len());
+        buf[..len].copy_from_slice(&samples[..len]);
+        self.position += len;
+        len
+    }
+
+    fn rewind(&mut self) -> bool {
+        self.seek_to(self.loop_start.unwrap_or(0));
+        true
+    }
+}
+
+impl<R: Read + Seek> Decoder for Bcstm<R> {
+    fn format(&self) -> AudioFormat {
+        match self.channel_count() {
+            1 => AudioFormat::PCM16Mono,
+            _ => AudioFormat::PCM16Stereo,
+        }
+    }
+
+    fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::tests::{adpcm_info, file, reference};
+    use super::*;
+    use crate::services::ndsp::adpcm::{byte_len, Adpcm};
+    use std::io::Cursor;
+
+    const BLOCK_SAMPLES: usize = 140;
+
+    /// Builds a BCSTM file out of the samples of each channel, encoded as DSPADPCM.
+    fn cstm(channels: &[Vec<i16>], loop_start: Option<u32>, seek_table: bool) -> Vec<u8> {
+        let sample_count = channels[0].len();
+        let block_count = (sample_count + BLOCK_SAMPLES - 1) / BLOCK_SAMPLES;
+        let last_samples = sample_count - (block_count - 1) * BLOCK_SAMPLES;
+        let block_size = byte_len(BLOCK_SAMPLES);
+        let last_size = byte_len(last_samples);
+        let last_padded_size = (last_size + 0x1f) & !0x1f;
+        let encoded: Vec<Adpcm> = channels
+            .iter()
+            .map(|samples| Adpcm::encode(samples))
+            .collect();
+
+        let mut info = Vec::new();
+        reference(&mut info, STREAM_INFO, 0x18);
+        reference(&mut info, 0, u32::MAX);
+        reference(&mut info, REFERENCE_TABLE, 0x18 + 0x38);
+
+        info.extend([2, loop_start.is_some().into(), channels.len() as u8, 0]);
+        for value in [
+            32728,
+            loop_start.unwrap_or(0),
+            sample_count as u32,
+            block_count as u32,
+            block_size as u32,
+            BLOCK_SAMPLES as u32,
+            last_size as u32,
+            last_samples as u32,
+            last_padded_size as u32,
+            4,
+            BLOCK_SAMPLES as u32,
+        ] {
+            info.extend(value.to_le_bytes());
+        }
+        reference(&mut info, SAMPLE_DATA, 0x18);
+
+        let table_len = 4 + channels.len() * 8;
+        info.extend((channels.len() as u32).to_le_bytes());
+        for index in 0..channels.len() {
+            reference(&mut info, CHANNEL_INFO, (table_len + index * 8) as u32);
+        }
+        for index in 0..channels.len() {
+            let offset = (channels.len() - index) * 8 + index * 0x2e;
+            reference(&mut info, DSP_ADPCM_INFO, offset as u32);
+        }
+        for encoded in &encoded {
+            let info_block = AdpcmInfo {
+                coefficients: *encoded.coefficients(),
+                context: encoded.context_at(0),
+                loop_context: encoded.context_at(loop_start.unwrap_or(0) as usize),
+            };
+            adpcm_info(&mut info, &info_block);
+        }
+
+        let mut seek = Vec::new();
+        let mut data = vec![0; 0x18];
+        for block in 0..block_count {
+            let last = block + 1 == block_count;
+            for encoded in &encoded {
+                let context = encoded.context_at(block * BLOCK_SAMPLES);
+                seek.extend(context.history.iter().flat_map(|h| h.to_le_bytes()));
+
+                let start = block * block_size;
+                let size = if last { last_size } else { block_size };
+                data.extend(&encoded.data()[start..start + size]);
+                if last {
+                    data.resize(data.len() + last_padded_size - last_size, 0);
+                }
+            }
+        }
+
+        let mut blocks = vec![(INFO_BLOCK, b"INFO", info)];
+        if seek_table {
+            blocks.push((SEEK_BLOCK, b"SEEK", seek));
+        }
+        blocks.push((DATA_BLOCK, b"DATA", data));
+        file(b"CSTM", &blocks)
+    }
+
+    fn interleave(left: &[i16], right: &[i16]) -> Vec<i16> {
+        left.iter().zip(right).flat_map(|(&l, &r)| [l, r]).collect()
+    }
+
+    #[test]
+    fn streaming() {
+        let left: Vec<i16> = (0..1000).map(|i| ((i * 37) % 3000 - 1500) as i16).collect();
+        let right: Vec<i16> = (0..1000).map(|i| (i * 20 - 10000) as i16).collect();
+        let expected_left = Adpcm::encode(&left).decode();
+        let expected_right = Adpcm::encode(&right).decode();
+        let expected = interleave(&expected_left, &expected_right);
+
+        for seek_table in [true, false] {
+            let file = cstm(&[left.clone(), right.clone()], Some(300), seek_table);
+            let mut music = Bcstm::new(Cursor::new(file)).unwrap();
+            assert_eq!(music.encoding(), Encoding::DspAdpcm);
+            assert_eq!(music.channel_count(), 2);
+            assert_eq!(music.sample_count(), 1000);
+            assert_eq!(music.loop_start(), Some(300));
+            assert!(matches!(music.format(), AudioFormat::PCM16Stereo));
+            assert_eq!(music.sample_rate(), 32728);
+
+            assert_eq!(music.decode_all(), expected);
+            assert!(music.rewind());
+            assert_eq!(music.decode_all(), expected[600..]);
+        }
+
+        let file = cstm(std::slice::from_ref(&left), None, true);
+        let mut music = Bcstm::new(Cursor::new(file.clone())).unwrap();
+        assert!(matches!(music.format(), AudioFormat::PCM16Mono));
+        let mut buf = [0; 5];
+        assert_eq!(music.read(&mut buf), 5);
+        assert_eq!(buf, expected_left[..5]);
+        assert!(music.rewind());
+        assert_eq!(music.decode_all(), expected_left);
+
+        // A truncated file ends early.
+        let mut music = Bcstm::new(Cursor::new(&file[..file.len() - 0x100])).unwrap();
+        let decoded = music.decode_all();
+        assert!(decoded.len() < 1000);
+        assert_eq!(decoded, expected_left[..decoded.len()]);
+
+        assert!(Bcstm::new(Cursor::new(&file[..0x30])).is_err());
+
+        // Sizes past the end of the file are rejected before allocating them.
+        let mut huge_info = file.clone();
+        huge_info[0x1c..0x20].copy_from_slice(&u32::MAX.to_le_bytes());
+        assert!(Bcstm::new(Cursor::new(huge_info)).is_err());
+
+        // The stream info follows the header and the 3 references of the INFO block.
+        let block_size = 0x40 + 0x20 + 0x14;
+        let mut huge_blocks = file;
+        huge_blocks[block_size..block_size + 4].copy_from_slice(&u32::MAX.to_le_bytes());
+        let mut music = Bcstm::new(Cursor::new(huge_blocks)).unwrap();
+        assert_eq!(music.decode_all(), []);
+    }
+}
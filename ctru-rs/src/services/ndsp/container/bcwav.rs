@@ -0,0 +1,377 @@
+use std::io::Error as IoError;
+use std::io::ErrorKind as IoErrorKind;
+use std::io::Read;
+use std::io::Result as IoResult;
+
+use super::{
+    add, check_magic, decode_channel, find_block, invalid_data, out_of_range, parse_header,
+    read_reference, read_reference_table, read_u32, read_u8, truncated, AdpcmInfo, Encoding,
+};
+use crate::linear::LinearAllocator;
+use crate::services::ndsp::adpcm::SAMPLES_PER_FRAME;
+use crate::services::ndsp::mixer::Sound;
+use crate::services::ndsp::wave::WaveInfo;
+use crate::services::ndsp::AudioFormat;
+
+const INFO_BLOCK: u16 = 0x7000;
+const DATA_BLOCK: u16 = 0x7001;
+const CHANNEL_INFO: u16 = 0x7100;
+const SAMPLE_DATA: u16 = 0x1f00;
+const DSP_ADPCM_INFO: u16 = 0x0300;
+
+/// Samples of one channel.
+#[derive(Clone, Debug)]
+struct WaveChannel {
+    data: Vec<u8>,
+    adpcm: Option<AdpcmInfo>,
+}
+
+/// Short sound loaded from a BCWAV file.
+///
+/// The DSP plays the channels of the file separately, see [`Bcwav::wave_info`]. They can also be
+/// decoded to 16-bit samples, to be played with a [`Mixer`](crate::services::ndsp::mixer::Mixer)
+/// for example.
+///
+/// # Examples
+///
+/// ```no_run
+/// use ctru::services::ndsp::container::Bcwav;
+/// use ctru::services::ndsp::Ndsp;
+///
+/// let ndsp = Ndsp::init().unwrap();
+/// let sound = Bcwav::read(std::fs::File::open("romfs:/coin.bcwav").unwrap()).unwrap();
+///
+/// let channel = ndsp.channel(0).unwrap();
+/// channel.set_format(sound.format());
+/// channel.set_sample_rate(sound.sample_rate() as f32);
+/// if let Some(info) = sound.adpcm_info(0) {
+///     channel.set_adpcm_coefficients(&info.coefficients);
+/// }
+///
+/// let mut wave = sound.wave_info(0);
+/// channel.queue_wave(&mut wave).unwrap();
+/// ```
+#[derive(Clone, Debug)]
+pub struct Bcwav {
+    encoding: Encoding,
+    sample_rate: u32,
+    loop_start: Option<usize>,
+    sample_count: usize,
+    channels: Vec<WaveChannel>,
+}
+
+impl Bcwav {
+    /// Parses a whole BCWAV file.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the file is invalid, or holds IMA-ADPCM samples or more
+    /// than 2 channels, which aren't supported.
+    pub fn parse(data: &[u8]) -> IoResult<Self> {
+        let blocks = parse_header(data, b"CWAV")?;
+        let (info, _) = find_block(&blocks, INFO_BLOCK).ok_or_else(truncated)?;
+        let (samples, _) = find_block(&blocks, DATA_BLOCK).ok_or_else(truncated)?;
+        check_magic(data, info, b"INFO")?;
+        check_magic(data, samples, b"DATA")?;
+
+        let encoding = Encoding::from_raw(read_u8(data, info + 8)?)?;
+        let looping = read_u8(data, info + 9)? != 0;
+        let sample_rate = read_u32(data, info + 0xc)?;
+        let loop_start = read_u32(data, info + 0x10)? as usize;
+        let sample_count = read_u32(data, info + 0x14)? as usize;
+        if looping && loop_start >= sample_count {
+            return Err(invalid_data("loop start past the end of the sound"));
+        }
+
+        let table = add(info, 0x1c)?;
+        let references = read_reference_table(data, table, CHANNEL_INFO)?;
+        if references.is_empty() || references.len() > 2 {
+            return Err(IoError::new(
+                IoErrorKind::Unsupported,
+                format!("unsupported channel count {}", references.len()),
+            ));
+        }
+
+        let channels = references
+            .into_iter()
+            .map(|reference| {
+                let channel = add(table, reference)?;
+                let offset = read_reference(data, channel, SAMPLE_DATA)?
+                    .ok_or_else(|| invalid_data("channel without samples"))?;
+                let start = add(add(samples, 8)?, offset)?;
+                let data_len = encoding.byte_len(sample_count).ok_or_else(out_of_range)?;
+                let channel_data = data
+                    .get(start..add(start, data_len)?)
+                    .ok_or_else(truncated)?;
+
+                let adpcm = match encoding {
+                    Encoding::DspAdpcm => {
+                        let offset = read_reference(data, add(channel, 8)?, DSP_ADPCM_INFO)?
+                            .ok_or_else(|| invalid_data("DSPADPCM channel without parameters"))?;
+                        Some(AdpcmInfo::parse(data, add(channel, offset)?)?)
+                    }
+                    _ => None,
+                };
+
+                Ok(WaveChannel {
+                    data: channel_data.to_vec(),
+                    adpcm,
+                })
+            })
+            .collect::<IoResult<_>>()?;
+
+        Ok(Self {
+            encoding,
+            sample_rate,
+            loop_start: looping.then_some(loop_start),
+            sample_count,
+            channels,
+        })
+    }
+
+    /// Reads and parses a whole BCWAV file.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if reading fails, or in the cases of [`Bcwav::parse`].
+    pub fn read<R: Read>(mut reader: R) -> IoResult<Self> {
+        let mut data = Vec::new();
+        reader.read_to_end(&mut data)?;
+        Self::parse(&data)
+    }
+
+    /// Returns the encoding of the samples.
+    pub fn encoding(&self) -> Encoding {
+        self.encoding
+    }
+
+    /// Returns the format to play one channel with.
+    pub fn format(&self) -> AudioFormat {
+        match self.encoding {
+            Encoding::Pcm8 => AudioFormat::PCM8Mono,
+            Encoding::Pcm16 => AudioFormat::PCM16Mono,
+            Encoding::DspAdpcm => AudioFormat::Adpcm,
+        }
+    }
+
+    /// Returns the amount of samples per second.
+    pub fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    /// Returns the amount of samples of each channel.
+    pub fn sample_count(&self) -> usize {
+        self.sample_count
+    }
+
+    /// Returns the amount of channels, which is 1 or 2.
+    pub fn channel_count(&self) -> usize {
+        self.channels.len()
+    }
+
+    /// Returns the sample the sound goes back to once it reaches its end, if it loops.
+    pub fn loop_start(&self) -> Option<usize> {
+        self.loop_start
+    }
+
+    /// Returns the DSPADPCM parameters of a channel, if its samples are encoded with it.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the channel doesn't exist.
+    pub fn adpcm_info(&self, channel: usize) -> Option<&AdpcmInfo> {
+        self.channels[channel].adpcm.as_ref()
+    }
+
+    /// Decodes all of the samples to 16-bit, interleaved when the sound is stereo.
+    pub fn decode(&self) -> Vec<i16> {
+        let count = self.channels.len();
+        // Parsing checked that the data of every channel holds the samples, which bounds their amount.
+        let mut samples = vec![0; self.sample_count * count];
+        let mut channel_samples = vec![0; self.sample_count];
+
+        for (index, channel) in self.channels.iter().enumerate() {
+            let mut history = channel.adpcm.map_or([0; 2], |info| info.context.history);
+            decode_channel(
+                self.encoding,
+                channel.adpcm.as_ref(),
+                &channel.data,
+                &mut history,
+                &mut channel_samples,
+            );
+
+            for (frame, &sample) in samples.chunks_exact_mut(count).zip(&channel_samples) {
+                frame[index] = sample;
+            }
+        }
+
+        samples
+    }
+
+    /// Decodes all of the samples into a sound for a [`Mixer`](crate::services::ndsp::mixer::Mixer).
+    pub fn to_sound(&self) -> Sound {
+        let format = match self.channels.len() {
+            1 => AudioFormat::PCM16Mono,
+            _ => AudioFormat::PCM16Stereo,
+        };
+
+        Sound::new(self.decode(), format, self.sample_rate)
+    }
+
+    /// Copies the samples of a channel into a wave in LINEAR memory, played with
+    /// [`Bcwav::format`].
+    ///
+    /// The wave loops if the sound loops from its first sample. Sounds looping from a later sample
+    /// play this wave once, followed by the one of [`Bcwav::loop_wave_info`].
+    ///
+    /// # Panics
+    ///
+    /// Panics if the channel doesn't exist.
+    pub fn wave_info(&self, channel: usize) -> WaveInfo {
+        self.wave(channel, 0, self.loop_start == Some(0))
+    }
+
+    /// Copies the looping part of the samples of a channel into a looping wave in LINEAR memory.
+    ///
+    /// Returns [`None`] if the sound doesn't loop, loops from its first sample, or is encoded with
+    /// DSPADPCM and loops from a sample which doesn't start a frame, which the DSP can't play.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the channel doesn't exist.
+    pub fn loop_wave_info(&self, channel: usize) -> Option<WaveInfo> {
+        match self.loop_start {
+            Some(0) | None => None,
+            Some(start)
+                if self.encoding == Encoding::DspAdpcm && start % SAMPLES_PER_FRAME != 0 =>
+            {
+                None
+            }
+            Some(start) => Some(self.wave(channel, start, true)),
+        }
+    }
+
+    fn wave(&self, channel: usize, start: usize, looping: bool) -> WaveInfo {
+        let channel = &self.channels[channel];
+        // The size of every sample was checked when parsing.
+        let data = &channel.data[self.encoding.byte_len(start).unwrap()..];
+
+        let mut buffer = Vec::with_capacity_in(data.len(), LinearAllocator);
+        buffer.extend_from_slice(data);
+
+        let mut wave = WaveInfo::new(buffer.into_boxed_slice(), self.format(), looping);
+        // Neither can fail, the wave is new and the count fits in the buffer.
+        let _ = wave.set_sample_count((self.sample_count - start) as u32);
+        if let Some(info) = channel.adpcm {
+            let context = match start {
+                0 => info.context,
+                _ => info.loop_context,
+            };
+            let _ = wave.set_adpcm_context(Some(context));
+        }
+        wave
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::tests::{adpcm_info, file, reference};
+    use super::*;
+    use crate::services::ndsp::adpcm::Adpcm;
+
+    /// Builds a BCWAV file out of the data and parameters of each channel.
+    fn cwav(
+        encoding: u8,
+        loop_start: Option<u32>,
+        sample_count: u32,
+        channels: &[(Vec<u8>, Option<AdpcmInfo>)],
+    ) -> Vec<u8> {
+        let mut info = vec![encoding, loop_start.is_some().into(), 0, 0];
+        info.extend(32000u32.to_le_bytes());
+        info.extend(loop_start.unwrap_or(0).to_le_bytes());
+        info.extend(sample_count.to_le_bytes());
+        info.extend([0; 4]);
+
+        // Reference table, then the information of each channel, then the DSPADPCM parameters.
+        let table_len = 4 + channels.len() * 8;
+        info.extend((channels.len() as u32).to_le_bytes());
+        for index in 0..channels.len() {
+            reference(&mut info, CHANNEL_INFO, (table_len + index * 0x14) as u32);
+        }
+        let mut samples = Vec::new();
+        for (index, (data, adpcm)) in channels.iter().enumerate() {
+            reference(&mut info, SAMPLE_DATA, samples.len() as u32);
+            samples.extend(data);
+            samples.resize((samples.len() + 0x1f) & !0x1f, 0);
+
+            match adpcm {
+                Some(_) => {
+                    let offset = (channels.len() - index) * 0x14 + index * 0x2e;
+                    reference(&mut info, DSP_ADPCM_INFO, offset as u32);
+                }
+                None => reference(&mut info, 0, u32::MAX),
+            }
+            info.extend([0; 4]);
+        }
+        for (_, adpcm) in channels {
+            if let Some(adpcm) = adpcm {
+                adpcm_info(&mut info, adpcm);
+            }
+        }
+
+        file(
+            b"CWAV",
+            &[(INFO_BLOCK, b"INFO", info), (DATA_BLOCK, b"DATA", samples)],
+        )
+    }
+
+    #[test]
+    fn adpcm() {
+        let samples: Vec<i16> = (0..500).map(|i| ((i * 97) % 2000 - 1000) as i16).collect();
+        let encoded = Adpcm::encode(&samples);
+        let info = AdpcmInfo {
+            coefficients: *encoded.coefficients(),
+            context: encoded.context_at(0),
+            loop_context: encoded.context_at(140),
+        };
+
+        let file = cwav(2, Some(140), 500, &[(encoded.data().to_vec(), Some(info))]);
+        let sound = Bcwav::parse(&file).unwrap();
+        assert_eq!(sound.encoding(), Encoding::DspAdpcm);
+        assert!(matches!(sound.format(), AudioFormat::Adpcm));
+        assert_eq!(sound.sample_rate(), 32000);
+        assert_eq!(sound.sample_count(), 500);
+        assert_eq!(sound.channel_count(), 1);
+        assert_eq!(sound.loop_start(), Some(140));
+        assert_eq!(sound.adpcm_info(0), Some(&info));
+        assert_eq!(sound.decode(), encoded.decode());
+
+        let error = Bcwav::parse(&cwav(3, None, 500, &[(vec![0; 300], None)])).unwrap_err();
+        assert_eq!(error.kind(), IoErrorKind::Unsupported);
+        let error = Bcwav::parse(&file[..file.len() - 0x40]).unwrap_err();
+        assert_eq!(error.kind(), IoErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn pcm() {
+        let left: Vec<u8> = [1i16, 2, 3].iter().flat_map(|s| s.to_le_bytes()).collect();
+        let right: Vec<u8> = [-1i16, -2, -3]
+            .iter()
+            .flat_map(|s| s.to_le_bytes())
+            .collect();
+        let sound = Bcwav::parse(&cwav(1, None, 3, &[(left, None), (right, None)])).unwrap();
+
+        assert_eq!(sound.channel_count(), 2);
+        assert_eq!(sound.loop_start(), None);
+        assert_eq!(sound.adpcm_info(1), None);
+        assert_eq!(sound.decode(), [1, -1, 2, -2, 3, -3]);
+
+        let sound = Bcwav::parse(&cwav(0, Some(1), 2, &[(vec![0x7f, 0x80], None)])).unwrap();
+        assert_eq!(sound.decode(), [0x7f00, -0x8000]);
+        assert_eq!(sound.to_sound().frames(), 2);
+
+        // A sample count the data can't hold.
+        let error = Bcwav::parse(&cwav(1, None, u32::MAX, &[(vec![0; 4], None)])).unwrap_err();
+        assert_eq!(error.kind(), IoErrorKind::InvalidData);
+    }
+}
@@ -0,0 +1,328 @@
+//! BCWAV and BCSTM sound files
+//!
+//! These are the formats of the sounds of most 3DS software: [`Bcwav`] holds short sounds, loaded
+//! whole and played from memory, while [`Bcstm`] holds music, streamed from the file as it plays.
+//! Both store PCM or [DSPADPCM](super::adpcm) samples, one channel after the other, and may loop
+//! back to a sample past the start once they reach their end.
+//!
+//! Only little-endian files, as made for the 3DS, are supported.
+
+use std::io::Error as IoError;
+use std::io::ErrorKind as IoErrorKind;
+use std::io::Result as IoResult;
+
+use super::adpcm::AdpcmContext;
+
+mod bcstm;
+mod bcwav;
+
+pub use bcstm::Bcstm;
+pub use bcwav::Bcwav;
+
+/// Value of references which point to nothing.
+const NULL_OFFSET: u32 = 0xffff_ffff;
+
+/// Encoding of the samples of a sound file.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Encoding {
+    /// Signed 8-bit PCM.
+    Pcm8,
+    /// Signed 16-bit PCM.
+    Pcm16,
+    /// DSPADPCM, see the [`adpcm`](super::adpcm) module.
+    DspAdpcm,
+}
+
+impl Encoding {
+    fn from_raw(value: u8) -> IoResult<Self> {
+        match value {
+            0 => Ok(Self::Pcm8),
+            1 => Ok(Self::Pcm16),
+            2 => Ok(Self::DspAdpcm),
+            3 => Err(IoError::new(
+                IoErrorKind::Unsupported,
+                "IMA-ADPCM sounds aren't supported",
+            )),
+            _ => Err(invalid_data("unknown sample encoding")),
+        }
+    }
+
+    /// Returns the size in bytes of `samples` samples of one channel, or [`None`] if it overflows.
+    fn byte_len(self, samples: usize) -> Option<usize> {
+        match self {
+            Self::Pcm8 => Some(samples),
+            Self::Pcm16 => samples.checked_mul(2),
+            Self::DspAdpcm => Some(super::adpcm::byte_len(samples)),
+        }
+    }
+}
+
+/// DSPADPCM parameters of a channel of a sound file.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct AdpcmInfo {
+    /// Predictor coefficients of the channel.
+    pub coefficients: [i16; 16],
+    /// Decoder state at the first sample.
+    pub context: AdpcmContext,
+    /// Decoder state at the start of the loop.
+    pub loop_context: AdpcmContext,
+}
+
+impl AdpcmInfo {
+    fn parse(data: &[u8], offset: usize) -> IoResult<Self> {
+        // Fields are read relative to the parameters, so that their offsets can't overflow.
+        let data = data.get(offset..).ok_or_else(truncated)?;
+
+        let mut coefficients = [0; 16];
+        for (index, coefficient) in coefficients.iter_mut().enumerate() {
+            *coefficient = read_u16(data, index * 2)? as i16;
+        }
+
+        let context = |offset: usize| -> IoResult<AdpcmContext> {
+            Ok(AdpcmContext {
+                predictor_scale: read_u16(data, offset)? as u8,
+                history: [
+                    read_u16(data, offset + 2)? as i16,
+                    read_u16(data, offset + 4)? as i16,
+                ],
+            })
+        };
+
+        Ok(Self {
+            coefficients,
+            context: context(0x20)?,
+            loop_context: context(0x26)?,
+        })
+    }
+}
+
+/// Checks the header shared by sound files, returning the type, offset and size of every block.
+fn parse_header(data: &[u8], magic: &[u8; 4]) -> IoResult<Vec<(u16, usize, usize)>> {
+    if data.get(..4) != Some(&magic[..]) {
+        return Err(invalid_data("wrong file magic"));
+    }
+    match read_u16(data, 4)? {
+        0xfeff => {}
+        0xfffe => {
+            return Err(IoError::new(
+                IoErrorKind::Unsupported,
+                "big-endian sound files aren't supported",
+            ))
+        }
+        _ => return Err(invalid_data("invalid byte order mark")),
+    }
+
+    let block_count = read_u16(data, 0x10)? as usize;
+    (0..block_count)
+        .map(|index| {
+            let entry = 0x14 + index * 12;
+            Ok((
+                read_u16(data, entry)?,
+                read_u32(data, entry + 4)? as usize,
+                read_u32(data, entry + 8)? as usize,
+            ))
+        })
+        .collect()
+}
+
+/// Returns the offset and size of the block of the given type.
+fn find_block(blocks: &[(u16, usize, usize)], kind: u16) -> Option<(usize, usize)> {
+    blocks
+        .iter()
+        .find(|(block, ..)| *block == kind)
+        .map(|&(_, offset, size)| (offset, size))
+}
+
+/// Checks the magic at the start of a block.
+fn check_magic(data: &[u8], offset: usize, magic: &[u8; 4]) -> IoResult<()> {
+    match offset.checked_add(4).and_then(|end| data.get(offset..end)) {
+        Some(found) if found == magic => Ok(()),
+        Some(_) => Err(invalid_data("wrong block magic")),
+        None => Err(truncated()),
+    }
+}
+
+/// Reads the offset of a reference of the given type, or [`None`] if it is null.
+fn read_reference(data: &[u8], offset: usize, kind: u16) -> IoResult<Option<usize>> {
+    let found = read_u16(data, offset)?;
+    let target = read_u32(data, add(offset, 4)?)?;
+
+    if target == NULL_OFFSET || found == 0 {
+        Ok(None)
+    } else if found != kind {
+        Err(invalid_data("unexpected reference type"))
+    } else {
+        Ok(Some(target as usize))
+    }
+}
+
+/// Reads a table of references of the given type, returning their offsets relative to the table.
+fn read_reference_table(data: &[u8], offset: usize, kind: u16) -> IoResult<Vec<usize>> {
+    let count = read_u32(data, offset)? as usize;
+    let table = data.get(offset + 4..).ok_or_else(truncated)?;
+    if count > table.len() / 8 {
+        return Err(truncated());
+    }
+
+    (0..count)
+        .map(|index| {
+            read_reference(table, index * 8, kind)?
+                .ok_or_else(|| invalid_data("null reference in table"))
+        })
+        .collect()
+}
+
+/// Decodes `samples.len()` samples of one channel, starting from the start of a frame for
+/// DSPADPCM.
+fn decode_channel(
+    encoding: Encoding,
+    adpcm: Option<&AdpcmInfo>,
+    data: &[u8],
+    history: &mut [i16; 2],
+    samples: &mut [i16],
+) {
+    match (encoding, adpcm) {
+        (Encoding::Pcm8, _) => {
+            for (sample, &byte) in samples.iter_mut().zip(data) {
+                *sample = (byte as i8 as i16) << 8;
+            }
+        }
+        (Encoding::Pcm16, _) => {
+            for (sample, bytes) in samples.iter_mut().zip(data.chunks_exact(2)) {
+                *sample = i16::from_le_bytes([bytes[0], bytes[1]]);
+            }
+        }
+        (Encoding::DspAdpcm, Some(info)) => {
+            super::adpcm::decode_into(&info.coefficients, data, history, samples);
+        }
+        (Encoding::DspAdpcm, None) => unreachable!("DSPADPCM channel without parameters"),
+    }
+}
+
+fn read_u8(data: &[u8], offset: usize) -> IoResult<u8> {
+    data.get(offset).copied().ok_or_else(truncated)
+}
+
+fn read_u16(data: &[u8], offset: usize) -> IoResult<u16> {
+    match offset.checked_add(2).and_then(|end| data.get(offset..end)) {
+        Some(bytes) => Ok(u16::from_le_bytes([bytes[0], bytes[1]])),
+        None => Err(truncated()),
+    }
+}
+
+fn read_u32(data: &[u8], offset: usize) -> IoResult<u32> {
+    match offset.checked_add(4).and_then(|end| data.get(offset..end)) {
+        Some(bytes) => Ok(u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]])),
+        None => Err(truncated()),
+    }
+}
+
+/// Adds offsets or sizes read from a file, which can overflow on 32-bit targets.
+fn add(a: usize, b: usize) -> IoResult<usize> {
+    a.checked_add(b).ok_or_else(out_of_range)
+}
+
+fn invalid_data(message: &str) -> IoError {
+    IoError::new(IoErrorKind::InvalidData, message)
+}
+
+fn truncated() -> IoError {
+    invalid_data("truncated sound file")
+}
+
+fn out_of_range() -> IoError {
+    invalid_data("offset or size out of range")
+}
+
+/// Writers of sound files, to test the parsers.
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Appends a reference of the given type.
+    pub(super) fn reference(out: &mut Vec<u8>, kind: u16, offset: u32) {
+        out.extend(kind.to_le_bytes());
+        out.extend([0; 2]);
+        out.extend(offset.to_le_bytes());
+    }
+
+    pub(super) fn adpcm_info(out: &mut Vec<u8>, info: &AdpcmInfo) {
+        out.extend(info.coefficients.iter().flat_map(|c| c.to_le_bytes()));
+        for context in [info.context, info.loop_context] {
+            out.extend(u16::from(context.predictor_scale).to_le_bytes());
+            out.extend(context.history.iter().flat_map(|h| h.to_le_bytes()));
+        }
+        out.extend([0; 2]);
+    }
+
+    /// Builds a file out of blocks, each given with its type and magic.
+    pub(super) fn file(magic: &[u8; 4], blocks: &[(u16, &[u8; 4], Vec<u8>)]) -> Vec<u8> {
+        let header_size = (0x14 + blocks.len() * 12 + 0x1f) & !0x1f;
+        let mut out = magic.to_vec();
+        out.extend(0xfeffu16.to_le_bytes());
+        out.extend((header_size as u16).to_le_bytes());
+        out.extend(0x0200_0000u32.to_le_bytes());
+        out.extend([0; 4]);
+        out.extend((blocks.len() as u16).to_le_bytes());
+        out.extend([0; 2]);
+
+        let mut offset = header_size;
+        for (kind, _, body) in blocks {
+            let size = (body.len() + 8 + 0x1f) & !0x1f;
+            out.extend(kind.to_le_bytes());
+            out.extend([0; 2]);
+            out.extend((offset as u32).to_le_bytes());
+            out.extend((size as u32).to_le_bytes());
+            offset += size;
+        }
+        out.resize(header_size, 0);
+
+        for (_, magic, body) in blocks {
+            let size = (body.len() + 8 + 0x1f) & !0x1f;
+            out.extend(*magic);
+            out.extend((size as u32).to_le_bytes());
+            out.extend(body);
+            out.resize(out.len() + size - 8 - body.len(), 0);
+        }
+
+        let len = out.len() as u32;
+        out[0xc..0x10].copy_from_slice(&len.to_le_bytes());
+        out
+    }
+
+    #[test]
+    fn header() {
+        let data = file(b"CWAV", &[(0x7000, b"INFO", vec![1, 2, 3])]);
+        let blocks = parse_header(&data, b"CWAV").unwrap();
+        assert_eq!(blocks, [(0x7000, 0x20, 0x20)]);
+        assert_eq!(find_block(&blocks, 0x7000), Some((0x20, 0x20)));
+        assert_eq!(find_block(&blocks, 0x7001), None);
+
+        assert!(check_magic(&data, 0x20, b"INFO").is_ok());
+        assert!(check_magic(&data, 0x20, b"DATA").is_err());
+        assert!(check_magic(&data, 0x40, b"INFO").is_err());
+        assert!(parse_header(&data, b"CSTM").is_err());
+        assert!(parse_header(&data[..0x18], b"CWAV").is_err());
+
+        let mut swapped = data;
+        swapped[4..6].copy_from_slice(&[0xfe, 0xff]);
+        let error = parse_header(&swapped, b"CWAV").unwrap_err();
+        assert_eq!(error.kind(), IoErrorKind::Unsupported);
+    }
+
+    #[test]
+    fn overflowing_offsets() {
+        let mut data = vec![0; 16];
+        assert!(read_u32(&data, usize::MAX - 1).is_err());
+        assert!(check_magic(&data, usize::MAX - 2, b"INFO").is_err());
+        assert!(AdpcmInfo::parse(&data, usize::MAX).is_err());
+        assert_eq!(
+            add(usize::MAX, 1).unwrap_err().kind(),
+            IoErrorKind::InvalidData
+        );
+
+        // A table claiming more references than the data can hold.
+        data[..4].copy_from_slice(&u32::MAX.to_le_bytes());
+        assert!(read_reference_table(&data, 0, 0x4102).is_err());
+    }
+}
@@ -0,0 +1,717 @@
+//! Decoding of sound files
+//!
+//! [`WavDecoder`] reads RIFF WAV files holding 8 or 16-bit PCM, and [`VorbisDecoder`] (with the
+//! `vorbis` feature) reads Ogg Vorbis files. Both are [`Decoder`]s, which report the [`AudioFormat`]
+//! and sample rate to play them with, and [`Source`]s which can feed an
+//! [`AudioStream`](super::stream::AudioStream) directly. Short sounds can instead be decoded at once
+//! into a [`WaveInfo`] with [`Decoder::into_wave_info`].
+//!
+//! Sounds are converted to another sample rate with [`Decoder::resample`].
+//!
+//! # Examples
+//!
+//! ```no_run
+//! use ctru::services::ndsp::decoder::{Decoder, WavDecoder};
+//! use ctru::services::ndsp::stream::AudioStream;
+//! use ctru::services::ndsp::Ndsp;
+//!
+//! let ndsp = Ndsp::init().unwrap();
+//!
+//! let file = std::fs::File::open("romfs:/music.wav").unwrap();
+//! let music = WavDecoder::new(std::io::BufReader::new(file))
+//!     .unwrap()
+//!     .resample(32728);
+//!
+//! let (format, rate) = (music.format(), music.sample_rate());
+//! let stream = AudioStream::new(ndsp.channel(0).unwrap(), format, rate as f32, music).unwrap();
+//! stream.set_looping(true);
+//! ```
+
+use std::io::Error as IoError;
+use std::io::ErrorKind as IoErrorKind;
+use std::io::Result as IoResult;
+use std::io::{Read, Seek, SeekFrom};
+
+use super::stream::{write_samples, Source};
+use super::wave::WaveInfo;
+use super::AudioFormat;
+use crate::linear::LinearAllocator;
+
+/// Sound whose samples are read as a [`Source`].
+pub trait Decoder: Source {
+    /// Returns the format to play the samples with, which is one of the PCM formats.
+    ///
+    /// The samples are always read as 16-bit, and can be played as 8-bit without loss when the sound
+    /// was stored with 8-bit samples.
+    fn format(&self) -> AudioFormat;
+
+    /// Returns the amount of frames (one sample per audio channel) per second.
+    fn sample_rate(&self) -> u32;
+
+    /// Converts the samples to another sample rate, with linear interpolation.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `sample_rate` or the sample rate of the decoder is 0.
+    fn resample(self, sample_rate: u32) -> Resampler<Self>
+    where
+        Self: Sized,
+    {
+        Resampler::new(self, sample_rate)
+    }
+
+    /// Reads all of the remaining samples.
+    fn decode_all(&mut self) -> Vec<i16>
+    where
+        Self: Sized,
+    {
+        let mut samples = Vec::new();
+        let mut filled = 0;
+        loop {
+            samples.resize(filled + 4096, 0);
+            match self.read(&mut samples[filled..]) {
+                0 => break,
+                read => filled += read,
+            }
+        }
+
+        samples.truncate(filled);
+        samples
+    }
+
+    /// Reads all of the remaining samples into a wave in LINEAR memory, played with
+    /// [`Decoder::format`] at [`Decoder::sample_rate`].
+    fn into_wave_info(mut self, looping: bool) -> WaveInfo
+    where
+        Self: Sized,
+    {
+        let format = self.format();
+        let samples = self.decode_all();
+        let channels = format.channel_count() as usize;
+        let frames = samples.len() / channels;
+
+        let len = frames * format.sample_size() as usize;
+        let mut buffer = Vec::with_capacity_in(len, LinearAllocator);
+        buffer.resize(len, 0);
+        write_samples(format, &samples[..frames * channels], &mut buffer);
+
+        WaveInfo::new(buffer.into_boxed_slice(), format, looping)
+    }
+}
+
+/// Decoder of RIFF WAV files holding 8 or 16-bit PCM, mono or stereo.
+///
+/// Read errors end the samples early.
+pub struct WavDecoder<R> {
+    reader: R,
+    format: AudioFormat,
+    sample_rate: u32,
+    /// Offset and size of the samples in the file.
+    data_start: u64,
+    data_len: u64,
+    /// Amount of bytes of samples left to read.
+    remaining: u64,
+    scratch: Vec<u8>,
+}
+
+impl<R: Read + Seek> WavDecoder<R> {
+    /// Parses the header of a WAV file, starting from the current position of `reader`.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if reading fails, the file isn't a WAV file, or its samples
+    /// aren't stored in a supported format.
+    pub fn new(mut reader: R) -> IoResult<Self> {
+        let mut header = [0; 12];
+        reader.read_exact(&mut header)?;
+        if &header[..4] != b"RIFF" || &header[8..] != b"WAVE" {
+            return Err(invalid_data("not a RIFF WAV file"));
+        }
+
+        let mut layout = None;
+        loop {
+            let mut chunk = [0; 8];
+            reader.read_exact(&mut chunk).map_err(|e| match e.kind() {
+                IoErrorKind::UnexpectedEof => invalid_data("missing data chunk"),
+                _ => e,
+            })?;
+            let len = u32::from_le_bytes([chunk[4], chunk[5], chunk[6], chunk[7]]);
+
+            match &chunk[..4] {
+                b"fmt " => {
+                    // The basic fields, followed by the size of the extension and the start of the
+                    // subformat of extensible headers. Missing fields are left to 0.
+                    let mut fmt = [0; 26];
+                    if len < 16 {
+                        return Err(invalid_data("format chunk too short"));
+                    }
+                    let read = len.min(fmt.len() as u32);
+                    reader.read_exact(&mut fmt[..read as usize])?;
+                    reader.seek(SeekFrom::Current(padded(len) as i64 - read as i64))?;
+
+                    let field = |offset: usize| u16::from_le_bytes([fmt[offset], fmt[offset + 1]]);
+                    let sample_rate = u32::from_le_bytes([fmt[4], fmt[5], fmt[6], fmt[7]]);
+                    if sample_rate == 0 {
+                        return Err(invalid_data("sample rate of 0 Hz"));
+                    }
+                    // Extensible headers are only accepted if they hold plain PCM as well.
+                    let encoding = match field(0) {
+                        0xfffe if field(16) >= 22 => field(24),
+                        tag => tag,
+                    };
+                    if encoding != 1 {
+                        return Err(unsupported(format!("WAV encoding {encoding:#06x}")));
+                    }
+                    let format =
+                        AudioFormat::from_layout(field(14), field(2)).ok_or_else(|| {
+                            unsupported(format!("{}-bit WAV with {} channels", field(14), field(2)))
+                        })?;
+                    layout = Some((format, sample_rate));
+                }
+                b"data" => {
+                    let (format, sample_rate) =
+                        layout.ok_or_else(|| invalid_data("data chunk before format chunk"))?;
+                    let data_start = reader.stream_position()?;
+
+                    return Ok(Self {
+                        reader,
+                        format,
+                        sample_rate,
+                        data_start,
+                        data_len: len.into(),
+                        remaining: len.into(),
+                        scratch: Vec::new(),
+                    });
+                }
+                _ => {
+                    reader.seek(SeekFrom::Current(padded(len) as i64))?;
+                }
+            }
+        }
+    }
+
+    /// Returns the underlying reader.
+    pub fn into_inner(self) -> R {
+        self.reader
+    }
+}
+
+impl<R: Read + Seek> Source for WavDecoder<R> {
+    fn read(&mut self, buf: &mut [i16]) -> usize {
+        let bytes_per_sample = (self.format.sample_size() / self.format.channel_count()) as usize;
+        let len = (buf.len() * bytes_per_sample).min(self.remaining as usize);
+        let len = len - len % bytes_per_sample;
+
+        self.scratch.resize(len, 0);
+        let mut filled = 0;
+        while filled < len {
+            match self.reader.read(&mut self.scratch[filled..]) {
+                Ok(0) => break,
+                Ok(read) => filled += read,
+                Err(e) if e.kind() == IoErrorKind::Interrupted => {}
+                Err(_) => break,
+            }
+        }
+
+        let len = if filled < len {
+            // The samples end early, after the whole frames read so far.
+            self.remaining = 0;
+            filled - filled % self.format.sample_size() as usize
+        } else {
+            self.remaining -= len as u64;
+            len
+        };
+        self.scratch.truncate(len);
+
+        if bytes_per_sample == 1 {
+            // 8-bit WAV samples are unsigned.
+            for (sample, &byte) in buf.iter_mut().zip(&self.scratch) {
+                *sample = (byte as i16 - 0x80) << 8;
+            }
+        } else {
+            for (sample, bytes) in buf.iter_mut().zip(self.scratch.chunks_exact(2)) {
+                *sample = i16::from_le_bytes([bytes[0], bytes[1]]);
+            }
+        }
+
+        len / bytes_per_sample
+    }
+
+    fn rewind(&mut self) -> bool {
+        self.remaining = self.data_len;
+        self.reader.seek(SeekFrom::Start(self.data_start)).is_ok()
+    }
+}
+
+impl<R: Read + Seek> Decoder for WavDecoder<R> {
+    fn format(&self) -> AudioFormat {
+        self.format
+    }
+
+    fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+}
+
+/// Decoder of Ogg Vorbis files, mono or stereo.
+///
+/// Decoding errors end the samples early.
+#[cfg(feature = "vorbis")]
+pub struct VorbisDecoder<R: Read + Seek> {
+    reader: lewton::inside_ogg::OggStreamReader<R>,
+    format: AudioFormat,
+    /// Decoded samples which were not read yet.
+    packet: Vec<i16>,
+    position: usize,
+}
+
+#[cfg(feature = "vorbis")]
+impl<R: Read + Seek> VorbisDecoder<R> {
+    /// Parses the headers of an Ogg Vorbis file.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if reading fails, the file isn't a valid Ogg Vorbis file,
+    /// or it has more than 2 audio channels.
+    pub fn new(reader: R) -> IoResult<Self> {
+        let reader = lewton::inside_ogg::OggStreamReader::new(reader).map_err(vorbis_error)?;
+        let channels = reader.ident_hdr.audio_channels;
+        let format = AudioFormat::from_layout(16, channels.into())
+            .ok_or_else(|| unsupported(format!("Vorbis with {channels} channels")))?;
+
+        Ok(Self {
+            reader,
+            format,
+            packet: Vec::new(),
+            position: 0,
+        })
+    }
+}
+
+#[cfg(feature = "vorbis")]
+impl<R: Read + Seek> Source for VorbisDecoder<R> {
+    fn read(&mut self, buf: &mut [i16]) -> usize {
+        while self.position == self.packet.len() {
+            match self.reader.read_dec_packet_itl() {
+                Ok(Some(packet)) => {
+                    self.packet = packet;
+                    self.position = 0;
+                }
+                Ok(None) | Err(_) => return 0,
+            }
+        }
+
+        let samples = &self.packet[self.position..];
+        let len = samples.len().min(buf.len());
+        buf[..len].copy_from_slice(&samples[..len]);
+        self.position += len;
+        len
+    }
+
+    fn rewind(&mut self) -> bool {
+        self.packet.clear();
+        self.position = 0;
+        self.reader.seek_absgp_pg(0).is_ok()
+    }
+}
+
+#[cfg(feature = "vorbis")]
+impl<R: Read + Seek> Decoder for VorbisDecoder<R> {
+    fn format(&self) -> AudioFormat {
+        self.format
+    }
+
+    fn sample_rate(&self) -> u32 {
+        self.reader.ident_hdr.audio_sample_rate
+    }
+}
+
+#[cfg(feature = "vorbis")]
+fn vorbis_error(error: lewton::VorbisError) -> IoError {
+    match error {
+        lewton::VorbisError::OggError(lewton::OggReadError::ReadError(error)) => error,
+        error => IoError::new(IoErrorKind::InvalidData, error),
+    }
+}
+
+/// [`Decoder`] converting the samples of another one to a different sample rate.
+///
+/// Created with [`Decoder::resample`].
+pub struct Resampler<D> {
+    inner: D,
+    sample_rate: u32,
+    /// Distance between two output frames, in input frames.
+    step: f64,
+    /// Position of the next output frame, between the two current input frames.
+    position: f64,
+    /// The two input frames around the position, one after the other.
+    frames: Vec<i16>,
+    /// Samples read from the inner decoder which were not used yet.
+    input: Vec<i16>,
+    consumed: usize,
+    started: bool,
+    /// Last position to output, known once the end of the input is reached.
+    end: Option<f64>,
+}
+
+impl<D: Decoder> Resampler<D> {
+    fn new(inner: D, sample_rate: u32) -> Self {
+        assert!(
+            sample_rate > 0 && inner.sample_rate() > 0,
+            "cannot resample from or to a sample rate of 0"
+        );
+
+        Self {
+            step: inner.sample_rate() as f64 / sample_rate as f64,
+            inner,
+            sample_rate,
+            position: 0.0,
+            frames: Vec::new(),
+            input: Vec::new(),
+            consumed: 0,
+            started: false,
+            end: None,
+        }
+    }
+
+    /// Returns the decoder being resampled.
+    pub fn into_inner(self) -> D {
+        self.inner
+    }
+
+    /// Replaces the older of the two current frames with the next input frame, returning `false` at
+    /// the end of the input.
+    fn advance(&mut self) -> bool {
+        let channels = self.format().channel_count() as usize;
+
+        if self.input.len() - self.consumed < channels {
+            self.input.drain(..self.consumed);
+            self.consumed = 0;
+
+            let filled = self.input.len();
+            self.input.resize(filled.max(channels) + 1024 * channels, 0);
+            let mut end = filled;
+            while end - filled < channels {
+                match self.inner.read(&mut self.input[end..]) {
+                    0 => break,
+                    read => end += read,
+                }
+            }
+            self.input.truncate(end);
+            if end < channels {
+                return false;
+            }
+        }
+
+        let frame = &self.input[self.consumed..self.consumed + channels];
+        if self.frames.len() == 2 * channels {
+            self.frames.drain(..channels);
+        }
+        self.frames.extend_from_slice(frame);
+        self.consumed += channels;
+        true
+    }
+}
+
+impl<D: Decoder> Source for Resampler<D> {
+    fn read(&mut self, buf: &mut [i16]) -> usize {
+        let channels = self.format().channel_count() as usize;
+
+        if !self.started {
+            self.started = true;
+            if !self.advance() {
+                return 0;
+            }
+            // A single frame is interpolated with itself.
+            if !self.advance() {
+                self.frames.extend_from_within(..);
+                self.end = Some(0.0);
+            }
+        }
+
+        let mut written = 0;
+        for frame in buf.chunks_exact_mut(channels) {
+            while self.position >= 1.0 && self.end.is_none() {
+                if self.advance() {
+                    self.position -= 1.0;
+                } else {
+                    // The last input frame is still output.
+                    self.end = Some(1.0);
+                }
+            }
+            if self.end.map_or(false, |end| self.position > end) {
+                return written;
+            }
+
+            let (current, next) = self.frames.split_at(channels);
+            for ((sample, &a), &b) in frame.iter_mut().zip(current).zip(next) {
+                *sample = (a as f64 + (b as f64 - a as f64) * self.position).round() as i16;
+            }
+            self.position += self.step;
+            written += channels;
+        }
+
+        written
+    }
+
+    fn rewind(&mut self) -> bool {
+        self.position = 0.0;
+        self.frames.clear();
+        self.input.clear();
+        self.consumed = 0;
+        self.started = false;
+        self.end = None;
+        self.inner.rewind()
+    }
+}
+
+impl<D: Decoder> Decoder for Resampler<D> {
+    fn format(&self) -> AudioFormat {
+        self.inner.format()
+    }
+
+    fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+}
+
+/// Rounds the size of a RIFF chunk up to its padding to 2 bytes.
+fn padded(len: u32) -> u64 {
+    (len as u64 + 1) & !1
+}
+
+fn invalid_data(message: &str) -> IoError {
+    IoError::new(IoErrorKind::InvalidData, message)
+}
+
+fn unsupported(what: String) -> IoError {
+    IoError::new(
+        IoErrorKind::Unsupported,
+        format!("unsupported audio: {what}"),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    /// Builds a WAV file, with an odd-sized chunk before the samples.
+    fn wav(bits: u16, channels: u16, sample_rate: u32, data: &[u8]) -> Vec<u8> {
+        wav_with_format(1, &[], bits, channels, sample_rate, data)
+    }
+
+    /// Builds a WAV file with the given format tag, and `extension` after the basic format fields.
+    fn wav_with_format(
+        tag: u16,
+        extension: &[u8],
+        bits: u16,
+        channels: u16,
+        sample_rate: u32,
+        data: &[u8],
+    ) -> Vec<u8> {
+        let block_align = bits / 8 * channels;
+        let mut fmt = Vec::new();
+        fmt.extend(tag.to_le_bytes());
+        fmt.extend(channels.to_le_bytes());
+        fmt.extend(sample_rate.to_le_bytes());
+        fmt.extend((sample_rate * block_align as u32).to_le_bytes());
+        fmt.extend(block_align.to_le_bytes());
+        fmt.extend(bits.to_le_bytes());
+        fmt.extend(extension);
+
+        let mut body = b"WAVE".to_vec();
+        for (id, chunk) in [(b"fmt ", &fmt[..]), (b"LIST", b"odd"), (b"data", data)] {
+            body.extend(id);
+            body.extend((chunk.len() as u32).to_le_bytes());
+            body.extend(chunk);
+            if chunk.len() % 2 == 1 {
+                body.push(0);
+            }
+        }
+
+        let mut file = b"RIFF".to_vec();
+        file.extend((body.len() as u32).to_le_bytes());
+        file.extend(body);
+        file
+    }
+
+    /// Source of samples at a given rate, to test resampling.
+    struct Ramp {
+        samples: Vec<i16>,
+        position: usize,
+        format: AudioFormat,
+        sample_rate: u32,
+    }
+
+    impl Source for Ramp {
+        fn read(&mut self, buf: &mut [i16]) -> usize {
+            // One sample at a time, to cover partial reads.
+            match (buf.first_mut(), self.samples.get(self.position)) {
+                (Some(sample), Some(&value)) => {
+                    *sample = value;
+                    self.position += 1;
+                    1
+                }
+                _ => 0,
+            }
+        }
+
+        fn rewind(&mut self) -> bool {
+            self.position = 0;
+            true
+        }
+    }
+
+    impl Decoder for Ramp {
+        fn format(&self) -> AudioFormat {
+            self.format
+        }
+
+        fn sample_rate(&self) -> u32 {
+            self.sample_rate
+        }
+    }
+
+    fn ramp(samples: Vec<i16>, format: AudioFormat, sample_rate: u32) -> Ramp {
+        Ramp {
+            samples,
+            position: 0,
+            format,
+            sample_rate,
+        }
+    }
+
+    #[test]
+    fn wav_pcm16() {
+        let data: Vec<u8> = [1i16, -1, 300, -300, i16::MAX, i16::MIN]
+            .iter()
+            .flat_map(|s| s.to_le_bytes())
+            .collect();
+        let mut decoder = WavDecoder::new(Cursor::new(wav(16, 2, 44100, &data))).unwrap();
+
+        assert!(matches!(decoder.format(), AudioFormat::PCM16Stereo));
+        assert_eq!(decoder.sample_rate(), 44100);
+        assert_eq!(decoder.decode_all(), [1, -1, 300, -300, i16::MAX, i16::MIN]);
+
+        assert!(decoder.rewind());
+        let mut buf = [0; 4];
+        assert_eq!(decoder.read(&mut buf), 4);
+        assert_eq!(buf, [1, -1, 300, -300]);
+    }
+
+    #[test]
+    fn wav_pcm8() {
+        let mut decoder =
+            WavDecoder::new(Cursor::new(wav(8, 1, 11025, &[0x80, 0xff, 0x00]))).unwrap();
+
+        assert!(matches!(decoder.format(), AudioFormat::PCM8Mono));
+        assert_eq!(decoder.decode_all(), [0, 0x7f00, -0x8000]);
+    }
+
+    #[test]
+    fn wav_extensible() {
+        /// Extension of an extensible header: its size, valid bits, channel mask and subformat GUID.
+        fn extension(size: u16, sub_format: u16) -> Vec<u8> {
+            let mut extension = size.to_le_bytes().to_vec();
+            extension.extend(16u16.to_le_bytes());
+            extension.extend(3u32.to_le_bytes());
+            extension.extend(sub_format.to_le_bytes());
+            extension.extend(b"\0\0\0\0\x10\0\x80\0\0\xaa\0\x38\x9b\x71");
+            extension
+        }
+        let extensible = |extension: &[u8]| {
+            let file = wav_with_format(0xfffe, extension, 16, 2, 48000, &[1, 0, 2, 0]);
+            WavDecoder::new(Cursor::new(file))
+        };
+
+        let mut decoder = extensible(&extension(22, 1)).unwrap();
+        assert!(matches!(decoder.format(), AudioFormat::PCM16Stereo));
+        assert_eq!(decoder.decode_all(), [1, 2]);
+
+        // Floating point samples, and headers too short to have a subformat.
+        let kind = |result: IoResult<WavDecoder<_>>| result.err().unwrap().kind();
+        assert_eq!(
+            kind(extensible(&extension(22, 3))),
+            IoErrorKind::Unsupported
+        );
+        assert_eq!(
+            kind(extensible(&extension(0, 1)[..2])),
+            IoErrorKind::Unsupported
+        );
+        assert_eq!(kind(extensible(&[])), IoErrorKind::Unsupported);
+    }
+
+    #[test]
+    fn wav_errors() {
+        let error = |file: Vec<u8>| WavDecoder::new(Cursor::new(file)).err().unwrap().kind();
+
+        assert_eq!(
+            error(b"RIFX\0\0\0\0WAVE".to_vec()),
+            IoErrorKind::InvalidData
+        );
+        assert_eq!(error(wav(24, 2, 48000, &[])), IoErrorKind::Unsupported);
+        assert_eq!(error(wav(16, 6, 48000, &[])), IoErrorKind::Unsupported);
+
+        let mut truncated = wav(16, 1, 48000, &[]);
+        truncated.truncate(truncated.len() - 8);
+        assert_eq!(error(truncated), IoErrorKind::InvalidData);
+
+        assert_eq!(error(wav(16, 1, 0, &[])), IoErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn wav_truncated_data() {
+        let data: Vec<u8> = [1i16, 2, 3, 4, 5, 6]
+            .iter()
+            .flat_map(|s| s.to_le_bytes())
+            .collect();
+        let mut file = wav(16, 2, 44100, &data);
+        // Cut the file in the middle of the last frame, without updating the chunk size.
+        file.truncate(file.len() - 3);
+
+        let mut decoder = WavDecoder::new(Cursor::new(file)).unwrap();
+        let mut buf = [0; 16];
+        assert_eq!(decoder.read(&mut buf), 4);
+        assert_eq!(buf[..4], [1, 2, 3, 4]);
+        assert_eq!(decoder.read(&mut buf), 0);
+    }
+
+    #[test]
+    fn resampling() {
+        let samples: Vec<i16> = (0..5).map(|i| i * 100).collect();
+
+        let mut up = ramp(samples.clone(), AudioFormat::PCM16Mono, 100).resample(200);
+        assert_eq!(up.sample_rate(), 200);
+        assert_eq!(up.decode_all(), [0, 50, 100, 150, 200, 250, 300, 350, 400]);
+
+        let mut down = ramp(samples, AudioFormat::PCM16Mono, 200).resample(100);
+        assert_eq!(down.decode_all(), [0, 200, 400]);
+
+        // Channels are interpolated separately, and rewinding starts over.
+        let stereo = vec![0, 1000, 100, 900, 200, 800];
+        let mut resampler = ramp(stereo, AudioFormat::PCM16Stereo, 2).resample(4);
+        let expected = [0, 1000, 50, 950, 100, 900, 150, 850, 200, 800];
+        assert_eq!(resampler.decode_all(), expected);
+        assert!(resampler.rewind());
+        assert_eq!(resampler.decode_all(), expected);
+
+        let mut single = ramp(vec![42], AudioFormat::PCM16Mono, 1).resample(3);
+        assert_eq!(single.decode_all(), [42]);
+    }
+
+    #[test]
+    #[should_panic]
+    fn resampling_to_zero() {
+        ramp(vec![42], AudioFormat::PCM16Mono, 100).resample(0);
+    }
+
+    #[cfg(feature = "vorbis")]
+    #[test]
+    fn vorbis_errors() {
+        let error = VorbisDecoder::new(Cursor::new(wav(16, 2, 44100, &[])))
+            .err()
+            .unwrap();
+        assert_eq!(error.kind(), IoErrorKind::InvalidData);
+    }
+}
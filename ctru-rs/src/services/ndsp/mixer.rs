@@ -0,0 +1,598 @@
+//! Software mixing
+//!
+//! The DSP only has 24 channels, each playing a single sound at a time. A [`Mixer`] instead plays any
+//! amount of [`Sound`]s at once, as voices which each have their own gain, stereo pan, pitch and
+//! fades, and mixes them into a single stereo signal. A [`MixerStream`] plays that signal on one
+//! channel, with the `std-threads` feature.
+//!
+//! A mixer has a fixed amount of voices. When all of them are playing, starting another sound stops
+//! the voice with the lowest priority, if it isn't higher than the one of the new sound.
+//!
+//! # Examples
+//!
+//! ```no_run
+//! use std::time::Duration;
+//!
+//! use ctru::services::ndsp::decoder::WavDecoder;
+//! use ctru::services::ndsp::mixer::{Mixer, MixerStream, Sound};
+//! use ctru::services::ndsp::Ndsp;
+//!
+//! let ndsp = Ndsp::init().unwrap();
+//! let stream = MixerStream::new(ndsp.channel(0).unwrap(), Mixer::new(32, 32728)).unwrap();
+//!
+//! let file = std::fs::File::open("romfs:/jump.wav").unwrap();
+//! let jump = Sound::decode(WavDecoder::new(std::io::BufReader::new(file)).unwrap());
+//!
+//! let mut mixer = stream.lock();
+//! if let Some(voice) = mixer.play(&jump, 10) {
+//!     mixer.set_pan(voice, -0.5);
+//!     mixer.set_pitch(voice, 1.2);
+//!     mixer.fade_out(voice, Duration::from_millis(300));
+//! }
+//! ```
+
+use std::sync::Arc;
+#[cfg(feature = "std-threads")]
+use std::sync::{Mutex, MutexGuard};
+use std::time::Duration;
+
+use super::decoder::Decoder;
+use super::AudioFormat;
+#[cfg(feature = "std-threads")]
+use super::{
+    stream::{AudioStream, Source},
+    Channel,
+};
+
+/// Amount of buffers of a [`MixerStream`].
+#[cfg(feature = "std-threads")]
+const STREAM_BUFFER_COUNT: usize = 4;
+
+/// Length of the buffers of a [`MixerStream`], in frames. Short buffers keep sound effects in time
+/// with what triggers them.
+#[cfg(feature = "std-threads")]
+const STREAM_BUFFER_FRAMES: usize = 512;
+
+/// Samples of a sound, which can be played by many voices at once.
+///
+/// Cloning a sound doesn't copy its samples.
+#[derive(Clone, Debug)]
+pub struct Sound {
+    samples: Arc<[i16]>,
+    channels: usize,
+    sample_rate: u32,
+}
+
+impl Sound {
+    /// Creates a sound from 16-bit samples, interleaved when `format` is stereo.
+    ///
+    /// Only the amount of channels of `format` matters, the samples are always 16-bit.
+    pub fn new(samples: impl Into<Arc<[i16]>>, format: AudioFormat, sample_rate: u32) -> Self {
+        Self {
+            samples: samples.into(),
+            channels: format.channel_count().into(),
+            sample_rate,
+        }
+    }
+
+    /// Decodes all of the samples of a sound.
+    pub fn decode<D: Decoder>(mut decoder: D) -> Self {
+        let format = decoder.format();
+        let sample_rate = decoder.sample_rate();
+
+        Self::new(decoder.decode_all(), format, sample_rate)
+    }
+
+    /// Returns the length of the sound, in frames (one sample per audio channel).
+    pub fn frames(&self) -> usize {
+        self.samples.len() / self.channels
+    }
+
+    /// Returns the amount of frames per second.
+    pub fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    /// Returns the left and right samples of a frame, which are the same for mono sounds.
+    fn frame(&self, index: usize) -> (f32, f32) {
+        let start = index * self.channels;
+        let left = self.samples[start] as f32;
+        let right = self.samples[start + self.channels - 1] as f32;
+        (left, right)
+    }
+}
+
+/// Handle to a voice of a [`Mixer`], returned when it starts playing a sound.
+///
+/// Handles stay valid once the voice stops playing, and are then ignored by the mixer.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct VoiceId {
+    slot: usize,
+    generation: u32,
+}
+
+/// Linear change of the gain of a voice.
+#[derive(Copy, Clone, Debug)]
+struct Fade {
+    target: f32,
+    /// Change of the gain per frame.
+    step: f32,
+    frames_left: u32,
+    /// Whether the voice stops at the end of the fade.
+    stop: bool,
+}
+
+#[derive(Clone, Debug)]
+struct Voice {
+    sound: Sound,
+    /// Position in the sound, in frames.
+    position: f64,
+    gain: f32,
+    pan: f32,
+    pitch: f32,
+    looping: bool,
+    priority: u8,
+    /// Order in which voices were started, to steal the oldest first.
+    started: u64,
+    fade: Option<Fade>,
+}
+
+impl Voice {
+    /// Adds the voice to `out`, returning `false` once it stopped.
+    fn mix(&mut self, out: &mut [f32], output_rate: u32) -> bool {
+        let frames = self.sound.frames();
+        let step = self.pitch as f64 * self.sound.sample_rate as f64 / output_rate as f64;
+
+        for frame in out.chunks_exact_mut(2) {
+            if self.looping && frames > 0 {
+                self.position %= frames as f64;
+            } else if self.position >= frames as f64 {
+                return false;
+            }
+
+            let index = self.position as usize;
+            let next = match index + 1 {
+                next if next < frames => next,
+                _ if self.looping => 0,
+                _ => index,
+            };
+            let fraction = (self.position - index as f64) as f32;
+            let (left, right) = self.sound.frame(index);
+            let (next_left, next_right) = self.sound.frame(next);
+
+            // Panning lowers the gain of the opposite side, so that centered sounds are left as is.
+            let left_gain = self.gain * (1.0 - self.pan).min(1.0);
+            let right_gain = self.gain * (1.0 + self.pan).min(1.0);
+            frame[0] += (left + (next_left - left) * fraction) * left_gain;
+            frame[1] += (right + (next_right - right) * fraction) * right_gain;
+
+            self.position += step;
+
+            if let Some(fade) = &mut self.fade {
+                fade.frames_left -= 1;
+                if fade.frames_left == 0 {
+                    self.gain = fade.target;
+                    let stop = fade.stop;
+                    self.fade = None;
+                    if stop {
+                        return false;
+                    }
+                } else {
+                    self.gain += fade.step;
+                }
+            }
+        }
+
+        true
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+struct Slot {
+    /// Incremented whenever the slot gets a new voice, invalidating older handles.
+    generation: u32,
+    voice: Option<Voice>,
+}
+
+/// Mixer of many sounds into one stereo signal.
+///
+/// Changes to voices which stopped playing are ignored.
+#[derive(Clone, Debug)]
+pub struct Mixer {
+    sample_rate: u32,
+    slots: Vec<Slot>,
+    gain: f32,
+    started: u64,
+    /// Mixed frames, before clipping to 16-bit.
+    scratch: Vec<f32>,
+}
+
+impl Mixer {
+    /// Creates a mixer with `voice_count` voices, whose output has `sample_rate` frames per second.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `voice_count` is 0.
+    pub fn new(voice_count: usize, sample_rate: u32) -> Self {
+        assert!(voice_count > 0, "a mixer needs at least 1 voice");
+
+        Self {
+            sample_rate,
+            slots: vec![Slot::default(); voice_count],
+            gain: 1.0,
+            started: 0,
+            scratch: Vec::new(),
+        }
+    }
+
+    /// Returns the amount of frames per second of the output.
+    pub fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    /// Returns the amount of voices.
+    pub fn voice_count(&self) -> usize {
+        self.slots.len()
+    }
+
+    /// Returns the amount of voices playing a sound.
+    pub fn active_voices(&self) -> usize {
+        self.slots
+            .iter()
+            .filter(|slot| slot.voice.is_some())
+            .count()
+    }
+
+    /// Sets the gain applied to the whole output. The default is 1.
+    pub fn set_master_gain(&mut self, gain: f32) {
+        self.gain = gain;
+    }
+
+    /// Starts playing a sound once, centered and at its own pitch.
+    ///
+    /// If every voice is playing, the one with the lowest priority is stopped, the oldest one among
+    /// those with the same priority. Returns [`None`] without playing the sound if that priority is
+    /// higher than `priority`.
+    pub fn play(&mut self, sound: &Sound, priority: u8) -> Option<VoiceId> {
+        let slot = match self.slots.iter().position(|slot| slot.voice.is_none()) {
+            Some(slot) => slot,
+            None => {
+                let (slot, lowest) = self
+                    .slots
+                    .iter()
+                    .enumerate()
+                    .filter_map(|(index, slot)| Some((index, slot.voice.as_ref()?)))
+                    .min_by_key(|(_, voice)| (voice.priority, voice.started))?;
+                if lowest.priority > priority {
+                    return None;
+                }
+                slot
+            }
+        };
+
+        self.started += 1;
+        let entry = &mut self.slots[slot];
+        entry.generation = entry.generation.wrapping_add(1);
+        entry.voice = Some(Voice {
+            sound: sound.clone(),
+            position: 0.0,
+            gain: 1.0,
+            pan: 0.0,
+            pitch: 1.0,
+            looping: false,
+            priority,
+            started: self.started,
+            fade: None,
+        });
+
+        Some(VoiceId {
+            slot,
+            generation: entry.generation,
+        })
+    }
+
+    /// Returns whether a voice is still playing its sound.
+    pub fn is_playing(&self, id: VoiceId) -> bool {
+        self.voice(id).is_some()
+    }
+
+    /// Stops a voice immediately.
+    pub fn stop(&mut self, id: VoiceId) {
+        if self.voice_mut(id).is_some() {
+            self.slots[id.slot].voice = None;
+        }
+    }
+
+    /// Stops every voice immediately.
+    pub fn stop_all(&mut self) {
+        for slot in &mut self.slots {
+            slot.voice = None;
+        }
+    }
+
+    /// Sets the gain of a voice, cancelling its fade. The default is 1.
+    pub fn set_gain(&mut self, id: VoiceId, gain: f32) {
+        if let Some(voice) = self.voice_mut(id) {
+            voice.gain = gain;
+            voice.fade = None;
+        }
+    }
+
+    /// Sets the stereo position of a voice, from -1 (left only) to 1 (right only). The default is 0.
+    pub fn set_pan(&mut self, id: VoiceId, pan: f32) {
+        if let Some(voice) = self.voice_mut(id) {
+            voice.pan = pan.clamp(-1.0, 1.0);
+        }
+    }
+
+    /// Sets the speed at which a voice plays its sound, which also changes its pitch. The default
+    /// is 1.
+    pub fn set_pitch(&mut self, id: VoiceId, pitch: f32) {
+        if let Some(voice) = self.voice_mut(id) {
+            voice.pitch = pitch.max(0.0);
+        }
+    }
+
+    /// Sets whether a voice plays its sound again once it reaches its end.
+    pub fn set_looping(&mut self, id: VoiceId, looping: bool) {
+        if let Some(voice) = self.voice_mut(id) {
+            voice.looping = looping;
+        }
+    }
+
+    /// Changes the gain of a voice to `gain` linearly over `duration`.
+    pub fn fade(&mut self, id: VoiceId, gain: f32, duration: Duration) {
+        self.start_fade(id, gain, duration, false);
+    }
+
+    /// Fades a voice to silence over `duration`, then stops it.
+    pub fn fade_out(&mut self, id: VoiceId, duration: Duration) {
+        self.start_fade(id, 0.0, duration, true);
+    }
+
+    fn start_fade(&mut self, id: VoiceId, target: f32, duration: Duration, stop: bool) {
+        let frames = (duration.as_secs_f64() * self.sample_rate as f64).round() as u32;
+
+        if frames == 0 {
+            if stop {
+                self.stop(id);
+            } else {
+                self.set_gain(id, target);
+            }
+        } else if let Some(voice) = self.voice_mut(id) {
+            voice.fade = Some(Fade {
+                target,
+                step: (target - voice.gain) / frames as f32,
+                frames_left: frames,
+                stop,
+            });
+        }
+    }
+
+    /// Mixes the next frames of every voice into `out`, as interleaved stereo samples, left first.
+    ///
+    /// Samples which don't fit in 16 bits are clipped.
+    pub fn mix(&mut self, out: &mut [i16]) {
+        self.scratch.clear();
+        self.scratch.resize(out.len() & !1, 0.0);
+
+        for slot in &mut self.slots {
+            if let Some(voice) = &mut slot.voice {
+                if !voice.mix(&mut self.scratch, self.sample_rate) {
+                    slot.voice = None;
+                }
+            }
+        }
+
+        for (sample, &mixed) in out.iter_mut().zip(&self.scratch) {
+            *sample = (mixed * self.gain)
+                .round()
+                .clamp(i16::MIN as f32, i16::MAX as f32) as i16;
+        }
+    }
+
+    fn voice(&self, id: VoiceId) -> Option<&Voice> {
+        let slot = self.slots.get(id.slot)?;
+        match slot.generation == id.generation {
+            true => slot.voice.as_ref(),
+            false => None,
+        }
+    }
+
+    fn voice_mut(&mut self, id: VoiceId) -> Option<&mut Voice> {
+        let slot = self.slots.get_mut(id.slot)?;
+        match slot.generation == id.generation {
+            true => slot.voice.as_mut(),
+            false => None,
+        }
+    }
+}
+
+/// [`Source`] of the samples of a mixer shared with a [`MixerStream`], which never ends.
+#[cfg(feature = "std-threads")]
+struct SharedMixer(Arc<Mutex<Mixer>>);
+
+#[cfg(feature = "std-threads")]
+impl Source for SharedMixer {
+    fn read(&mut self, buf: &mut [i16]) -> usize {
+        let len = buf.len() & !1;
+        self.0.lock().unwrap().mix(&mut buf[..len]);
+        len
+    }
+}
+
+/// [`Mixer`] playing on a channel, through an [`AudioStream`].
+#[cfg(feature = "std-threads")]
+pub struct MixerStream<'ndsp> {
+    mixer: Arc<Mutex<Mixer>>,
+    stream: AudioStream<'ndsp>,
+}
+
+#[cfg(feature = "std-threads")]
+impl<'ndsp> MixerStream<'ndsp> {
+    /// Starts playing the output of `mixer` on `channel`.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the sample rate of the mixer is 0, or the thread of the
+    /// stream can't be started.
+    pub fn new(channel: Channel<'ndsp>, mixer: Mixer) -> crate::Result<Self> {
+        let sample_rate = mixer.sample_rate() as f32;
+        let mixer = Arc::new(Mutex::new(mixer));
+        let stream = AudioStream::with_buffers(
+            channel,
+            AudioFormat::PCM16Stereo,
+            sample_rate,
+            STREAM_BUFFER_COUNT,
+            STREAM_BUFFER_FRAMES,
+            SharedMixer(Arc::clone(&mixer)),
+        )?;
+
+        Ok(Self { mixer, stream })
+    }
+
+    /// Locks the mixer to play sounds or change its voices.
+    ///
+    /// Mixing waits for the lock to be released, so it shouldn't be held for long.
+    pub fn lock(&self) -> MutexGuard<'_, Mixer> {
+        self.mixer.lock().unwrap()
+    }
+
+    /// Returns the stream playing the mixer.
+    pub fn stream(&self) -> &AudioStream<'ndsp> {
+        &self.stream
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn mixed(mixer: &mut Mixer, frames: usize) -> Vec<i16> {
+        let mut out = vec![0; frames * 2];
+        mixer.mix(&mut out);
+        out
+    }
+
+    fn mono(samples: &[i16]) -> Sound {
+        Sound::new(samples.to_vec(), AudioFormat::PCM16Mono, 100)
+    }
+
+    #[test]
+    fn mixing() {
+        let mut mixer = Mixer::new(4, 100);
+        let a = mixer.play(&mono(&[100, 200, 300]), 0).unwrap();
+        let stereo = Sound::new(vec![1, -1, 2, -2], AudioFormat::PCM16Stereo, 100);
+        mixer.play(&stereo, 0).unwrap();
+        assert_eq!(mixer.active_voices(), 2);
+
+        assert_eq!(mixed(&mut mixer, 1), [101, 99]);
+        assert_eq!(mixed(&mut mixer, 3), [202, 198, 300, 300, 0, 0]);
+        assert_eq!(mixer.active_voices(), 0);
+        assert!(!mixer.is_playing(a));
+
+        // Panning, gain and clipping.
+        let b = mixer.play(&mono(&[i16::MAX; 8]), 0).unwrap();
+        mixer.set_pan(b, -1.0);
+        assert_eq!(mixed(&mut mixer, 1), [i16::MAX, 0]);
+        mixer.set_pan(b, 0.5);
+        mixer.set_gain(b, 0.5);
+        assert_eq!(mixed(&mut mixer, 1), [8192, 16384]);
+        mixer.set_pan(b, 0.0);
+        mixer.set_gain(b, 1.0);
+        mixer.play(&mono(&[i16::MAX; 8]), 0).unwrap();
+        mixer.set_master_gain(0.25);
+        assert_eq!(mixed(&mut mixer, 1), [16384, 16384]);
+        mixer.set_master_gain(1.0);
+        assert_eq!(mixed(&mut mixer, 1), [i16::MAX, i16::MAX]);
+    }
+
+    #[test]
+    fn pitch_and_looping() {
+        let ramp: Vec<i16> = (0..8).map(|i| i * 10).collect();
+
+        let mut mixer = Mixer::new(1, 100);
+        let voice = mixer.play(&mono(&ramp), 0).unwrap();
+        mixer.set_pitch(voice, 2.0);
+        let out = mixed(&mut mixer, 6);
+        assert_eq!(out, [0, 0, 20, 20, 40, 40, 60, 60, 0, 0, 0, 0]);
+        assert!(!mixer.is_playing(voice));
+
+        // Slower playback interpolates between frames.
+        let voice = mixer.play(&mono(&ramp), 0).unwrap();
+        mixer.set_pitch(voice, 0.5);
+        assert_eq!(mixed(&mut mixer, 3), [0, 0, 5, 5, 10, 10]);
+
+        // The sound rate is converted to the output rate.
+        let mut mixer = Mixer::new(1, 200);
+        mixer.play(&mono(&ramp), 0).unwrap();
+        assert_eq!(mixed(&mut mixer, 3), [0, 0, 5, 5, 10, 10]);
+
+        let voice = mixer.play(&mono(&[1, 2, 3]), 0).unwrap();
+        mixer.set_looping(voice, true);
+        mixer.set_pitch(voice, 4.0);
+        let out = mixed(&mut mixer, 5);
+        assert_eq!(out, [1, 1, 3, 3, 2, 2, 1, 1, 3, 3]);
+        assert!(mixer.is_playing(voice));
+    }
+
+    #[test]
+    fn fades() {
+        let mut mixer = Mixer::new(2, 100);
+        let sound = mono(&[1000; 100]);
+
+        let voice = mixer.play(&sound, 0).unwrap();
+        mixer.fade_out(voice, Duration::from_millis(40));
+        let out = mixed(&mut mixer, 6);
+        assert_eq!(out, [1000, 1000, 750, 750, 500, 500, 250, 250, 0, 0, 0, 0]);
+        assert!(!mixer.is_playing(voice));
+
+        let voice = mixer.play(&sound, 0).unwrap();
+        mixer.set_gain(voice, 0.0);
+        mixer.fade(voice, 1.0, Duration::from_millis(20));
+        assert_eq!(
+            mixed(&mut mixer, 4),
+            [0, 0, 500, 500, 1000, 1000, 1000, 1000]
+        );
+        assert!(mixer.is_playing(voice));
+
+        // Setting the gain cancels the fade.
+        mixer.fade_out(voice, Duration::from_millis(20));
+        mixer.set_gain(voice, 0.5);
+        assert_eq!(mixed(&mut mixer, 3), [500; 6]);
+
+        mixer.fade_out(voice, Duration::ZERO);
+        assert!(!mixer.is_playing(voice));
+    }
+
+    #[test]
+    fn voice_stealing() {
+        let mut mixer = Mixer::new(2, 100);
+        let sound = mono(&[1; 100]);
+
+        let low = mixer.play(&sound, 1).unwrap();
+        let high = mixer.play(&sound, 5).unwrap();
+        assert_eq!(mixer.play(&sound, 0), None);
+
+        let stolen = mixer.play(&sound, 1).unwrap();
+        assert!(!mixer.is_playing(low));
+        assert!(mixer.is_playing(high));
+        assert_ne!(stolen, low);
+
+        // The oldest voice with the lowest priority goes first.
+        let newest = mixer.play(&sound, 5).unwrap();
+        assert!(!mixer.is_playing(stolen));
+        let last = mixer.play(&sound, 5).unwrap();
+        assert!(!mixer.is_playing(high));
+        assert!(mixer.is_playing(newest));
+
+        // Handles of stopped voices are ignored.
+        mixer.set_gain(high, 0.0);
+        mixer.stop(low);
+        assert_eq!(mixed(&mut mixer, 1), [2, 2]);
+        mixer.stop(last);
+        assert_eq!(mixer.active_voices(), 1);
+        mixer.stop_all();
+        assert_eq!(mixer.active_voices(), 0);
+    }
+}
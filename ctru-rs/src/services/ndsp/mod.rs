@@ -1,14 +1,24 @@
 //! NDSP (Audio) service
 
+pub mod adpcm;
+pub mod container;
+pub mod decoder;
+pub mod mixer;
+pub mod stream;
 pub mod wave;
 use wave::{WaveInfo, WaveStatus};
 
 use crate::error::ResultCode;
+use crate::linear::LinearAllocator;
+use crate::services::sound::{self, SoundChannel};
 use crate::services::ServiceReference;
 
 use std::cell::{RefCell, RefMut};
 use std::error;
+use std::ffi::{c_int, c_void};
 use std::fmt;
+use std::panic::{self, AssertUnwindSafe};
+use std::slice;
 use std::sync::Mutex;
 
 const NUMBER_OF_CHANNELS: u8 = 24;
@@ -28,6 +38,8 @@ pub enum AudioFormat {
     PCM16Mono = ctru_sys::NDSP_FORMAT_MONO_PCM16,
     PCM8Stereo = ctru_sys::NDSP_FORMAT_STEREO_PCM8,
     PCM16Stereo = ctru_sys::NDSP_FORMAT_STEREO_PCM16,
+    /// Mono DSPADPCM, see the [`adpcm`] module.
+    Adpcm = ctru_sys::NDSP_FORMAT_MONO_ADPCM,
 }
 
 #[derive(Copy, Clone, Debug)]
@@ -38,6 +50,68 @@ pub enum InterpolationType {
     None = ctru_sys::NDSP_INTERP_NONE,
 }
 
+/// Handling of output samples which don't fit in 16 bits.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+#[repr(u32)]
+pub enum ClippingMode {
+    /// Samples are clamped.
+    Normal = ctru_sys::NDSP_CLIP_NORMAL,
+    /// Samples are compressed near the limits, which distorts less.
+    Soft = ctru_sys::NDSP_CLIP_SOFT,
+}
+
+/// Position of the speakers used by surround output.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+#[repr(u32)]
+pub enum SpeakerPosition {
+    Square = ctru_sys::NDSP_SPKPOS_SQUARE,
+    Wide = ctru_sys::NDSP_SPKPOS_WIDE,
+    Num = ctru_sys::NDSP_SPKPOS_NUM,
+}
+
+/// Auxiliary output of the DSP, fed by the mix of each channel (see [`Channel::set_mix`]).
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+#[repr(u8)]
+pub enum AuxDevice {
+    Zero = 0,
+    One = 1,
+}
+
+/// Samples of an auxiliary output during one sound frame, given to the callbacks set with
+/// [`Ndsp::set_aux_callback`].
+///
+/// The samples are 32-bit, with one slice per output channel: front left, front right, back left and
+/// back right. Changes to them are mixed into the final output, unless the output is
+/// [bypassed](Ndsp::set_aux_front_bypass).
+pub struct AuxFrame<'a> {
+    channels: [&'a mut [i32]; 4],
+}
+
+impl<'a> AuxFrame<'a> {
+    /// Returns the amount of samples per channel.
+    pub fn len(&self) -> usize {
+        self.channels[0].len()
+    }
+
+    /// Returns `true` if the frame has no samples.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Returns the samples of every channel.
+    pub fn channels_mut(&mut self) -> &mut [&'a mut [i32]; 4] {
+        &mut self.channels
+    }
+}
+
+type AuxCallback = Box<dyn FnMut(&mut AuxFrame<'_>) + Send>;
+type FrameCallback = Box<dyn FnMut() + Send>;
+
+// Callbacks run on the thread of NDSP, and are locked while they do so that they aren't dropped
+// while running.
+static AUX_CALLBACKS: [Mutex<Option<AuxCallback>>; 2] = [Mutex::new(None), Mutex::new(None)];
+static FRAME_CALLBACK: Mutex<Option<FrameCallback>> = Mutex::new(None);
+
 #[derive(Copy, Clone, Debug)]
 pub enum NdspError {
     /// Channel ID
@@ -117,6 +191,202 @@ impl Ndsp {
     pub fn set_output_mode(&mut self, mode: OutputMode) {
         unsafe { ctru_sys::ndspSetOutputMode(mode as u32) };
     }
+
+    /// Set the volume of the whole output. Defaults to 1.
+    pub fn set_master_volume(&mut self, volume: f32) {
+        unsafe { ctru_sys::ndspSetMasterVol(volume) };
+    }
+
+    /// Returns the volume of the whole output.
+    pub fn get_master_volume(&self) -> f32 {
+        unsafe { ctru_sys::ndspGetMasterVol() }
+    }
+
+    /// Set the output count. Defaults to 2.
+    pub fn set_output_count(&mut self, count: u8) {
+        unsafe { ctru_sys::ndspSetOutputCount(count.into()) };
+    }
+
+    /// Returns the output count.
+    pub fn get_output_count(&self) -> u8 {
+        unsafe { ctru_sys::ndspGetOutputCount() as u8 }
+    }
+
+    /// Set how output samples which don't fit in 16 bits are handled. Defaults to `ClippingMode::Soft`.
+    pub fn set_clipping_mode(&mut self, mode: ClippingMode) {
+        unsafe { ctru_sys::ndspSetClippingMode(mode as u32) };
+    }
+
+    /// Returns how output samples which don't fit in 16 bits are handled.
+    pub fn get_clipping_mode(&self) -> ClippingMode {
+        match unsafe { ctru_sys::ndspGetClippingMode() } {
+            ctru_sys::NDSP_CLIP_NORMAL => ClippingMode::Normal,
+            _ => ClippingMode::Soft,
+        }
+    }
+
+    /// Set the depth of the surround effect. Defaults to `0x7FFF`.
+    pub fn set_surround_depth(&mut self, depth: u16) {
+        unsafe { ctru_sys::ndspSurroundSetDepth(depth) };
+    }
+
+    /// Returns the depth of the surround effect.
+    pub fn get_surround_depth(&self) -> u16 {
+        unsafe { ctru_sys::ndspSurroundGetDepth() }
+    }
+
+    /// Set the position of the speakers for surround output. Defaults to `SpeakerPosition::Square`.
+    pub fn set_surround_position(&mut self, position: SpeakerPosition) {
+        unsafe { ctru_sys::ndspSurroundSetPos(position as u32) };
+    }
+
+    /// Returns the position of the speakers for surround output.
+    pub fn get_surround_position(&self) -> SpeakerPosition {
+        match unsafe { ctru_sys::ndspSurroundGetPos() } {
+            ctru_sys::NDSP_SPKPOS_WIDE => SpeakerPosition::Wide,
+            ctru_sys::NDSP_SPKPOS_NUM => SpeakerPosition::Num,
+            _ => SpeakerPosition::Square,
+        }
+    }
+
+    /// Set the ratio of the surround output sent to the rear speakers. Defaults to `0x8000`.
+    pub fn set_surround_rear_ratio(&mut self, ratio: u16) {
+        unsafe { ctru_sys::ndspSurroundSetRearRatio(ratio) };
+    }
+
+    /// Returns the ratio of the surround output sent to the rear speakers.
+    pub fn get_surround_rear_ratio(&self) -> u16 {
+        unsafe { ctru_sys::ndspSurroundGetRearRatio() }
+    }
+
+    /// Enable or disable an auxiliary output.
+    pub fn set_aux_enabled(&mut self, aux: AuxDevice, enable: bool) {
+        unsafe { ctru_sys::ndspAuxSetEnable(aux as c_int, enable) };
+    }
+
+    /// Returns whether an auxiliary output is enabled.
+    pub fn is_aux_enabled(&self, aux: AuxDevice) -> bool {
+        unsafe { ctru_sys::ndspAuxIsEnabled(aux as c_int) }
+    }
+
+    /// Set whether an auxiliary output bypasses the front speakers.
+    pub fn set_aux_front_bypass(&mut self, aux: AuxDevice, bypass: bool) {
+        unsafe { ctru_sys::ndspAuxSetFrontBypass(aux as c_int, bypass) };
+    }
+
+    /// Returns whether an auxiliary output bypasses the front speakers.
+    pub fn get_aux_front_bypass(&self, aux: AuxDevice) -> bool {
+        unsafe { ctru_sys::ndspAuxGetFrontBypass(aux as c_int) }
+    }
+
+    /// Set the volume of an auxiliary output.
+    pub fn set_aux_volume(&mut self, aux: AuxDevice, volume: f32) {
+        unsafe { ctru_sys::ndspAuxSetVolume(aux as c_int, volume) };
+    }
+
+    /// Returns the volume of an auxiliary output.
+    pub fn get_aux_volume(&self, aux: AuxDevice) -> f32 {
+        unsafe { ctru_sys::ndspAuxGetVolume(aux as c_int) }
+    }
+
+    /// Set a function processing the samples of an auxiliary output, replacing the previous one.
+    ///
+    /// It is called on the thread of the DSP service for every sound frame, which is a few
+    /// milliseconds long, so it should return quickly. It is removed if it panics.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// use ctru::services::ndsp::{AuxDevice, Ndsp};
+    ///
+    /// let mut ndsp = Ndsp::init().unwrap();
+    ///
+    /// // Echo of the previous frame, at half volume.
+    /// let mut previous = Vec::new();
+    /// ndsp.set_aux_callback(AuxDevice::Zero, move |frame| {
+    ///     let len = frame.len();
+    ///     previous.resize(len * 4, 0);
+    ///     for (channel, history) in frame.channels_mut().iter_mut().zip(previous.chunks_mut(len)) {
+    ///         for (sample, old) in channel.iter_mut().zip(history) {
+    ///             *sample += *old / 2;
+    ///             *old = *sample;
+    ///         }
+    ///     }
+    /// });
+    /// ndsp.set_aux_enabled(AuxDevice::Zero, true);
+    /// ```
+    pub fn set_aux_callback<F>(&mut self, aux: AuxDevice, callback: F)
+    where
+        F: FnMut(&mut AuxFrame<'_>) + Send + 'static,
+    {
+        *AUX_CALLBACKS[aux as usize].lock().unwrap() = Some(Box::new(callback));
+
+        unsafe {
+            ctru_sys::ndspAuxSetCallback(
+                aux as c_int,
+                Some(aux_callback),
+                aux as usize as *mut c_void,
+            )
+        };
+    }
+
+    /// Remove the function processing the samples of an auxiliary output.
+    pub fn clear_aux_callback(&mut self, aux: AuxDevice) {
+        unsafe { ctru_sys::ndspAuxSetCallback(aux as c_int, None, std::ptr::null_mut()) };
+
+        *AUX_CALLBACKS[aux as usize].lock().unwrap() = None;
+    }
+
+    /// Set a function called at every sound frame, replacing the previous one.
+    ///
+    /// It is called on the thread of the DSP service, and should return quickly. It is removed if it
+    /// panics.
+    pub fn set_frame_callback<F>(&mut self, callback: F)
+    where
+        F: FnMut() + Send + 'static,
+    {
+        *FRAME_CALLBACK.lock().unwrap() = Some(Box::new(callback));
+
+        unsafe { ctru_sys::ndspSetCallback(Some(frame_callback), std::ptr::null_mut()) };
+    }
+
+    /// Remove the function called at every sound frame.
+    pub fn clear_frame_callback(&mut self) {
+        unsafe { ctru_sys::ndspSetCallback(None, std::ptr::null_mut()) };
+
+        *FRAME_CALLBACK.lock().unwrap() = None;
+    }
+}
+
+unsafe extern "C" fn aux_callback(data: *mut c_void, nsamples: c_int, samples: *mut *mut c_void) {
+    let pointers = slice::from_raw_parts(samples, 4);
+    let channels = std::array::from_fn(|channel| {
+        slice::from_raw_parts_mut(pointers[channel].cast::<i32>(), nsamples as usize)
+    });
+    let mut frame = AuxFrame { channels };
+
+    run_callback(&AUX_CALLBACKS[data as usize], |callback| {
+        callback(&mut frame)
+    });
+}
+
+unsafe extern "C" fn frame_callback(_data: *mut c_void) {
+    run_callback(&FRAME_CALLBACK, |callback| callback());
+}
+
+/// Calls the callback in `slot` if there is one, and drops it if it panics.
+///
+/// Unwinding out of the trampolines would cross into the C code of the DSP thread, so panics are
+/// caught here instead, and the callback removed so that it doesn't panic on every frame.
+fn run_callback<C: ?Sized>(slot: &Mutex<Option<Box<C>>>, call: impl FnOnce(&mut C)) {
+    // Skip the frame rather than panic on the DSP thread.
+    if let Ok(mut callback) = slot.lock() {
+        if let Some(f) = callback.as_mut() {
+            if panic::catch_unwind(AssertUnwindSafe(|| call(f))).is_err() {
+                *callback = None;
+            }
+        }
+    }
 }
 
 impl Channel<'_> {
@@ -187,14 +457,27 @@ impl Channel<'_> {
         unsafe { ctru_sys::ndspChnSetMix(self.id.into(), mix.as_ptr().cast_mut()) }
     }
 
+    /// Returns the channel's volume mix, in the layout of [`Channel::set_mix`].
+    pub fn mix(&self) -> [f32; 12] {
+        let mut mix = [0.0; 12];
+        unsafe { ctru_sys::ndspChnGetMix(self.id.into(), mix.as_mut_ptr()) };
+        mix
+    }
+
     /// Set the channel's rate of sampling.
     pub fn set_sample_rate(&self, rate: f32) {
         unsafe { ctru_sys::ndspChnSetRate(self.id.into(), rate) };
     }
 
-    // `ndspChnSetAdpcmCoefs` isn't wrapped on purpose.
-    // DSPADPCM is a proprietary format used by Nintendo, unavailable by "normal" means.
-    // We suggest using other wave formats when developing homebrew applications.
+    /// Set the predictor coefficients used to decode [`AudioFormat::Adpcm`] waves.
+    ///
+    /// Every wave played on the channel must have been encoded with these coefficients, see
+    /// [`Adpcm::coefficients`](adpcm::Adpcm::coefficients).
+    pub fn set_adpcm_coefficients(&self, coefficients: &[i16; 16]) {
+        unsafe {
+            ctru_sys::ndspChnSetAdpcmCoefs(self.id.into(), coefficients.as_ptr().cast_mut().cast())
+        };
+    }
 
     /// Clear the wave buffer queue and stop playback.
     pub fn clear_queue(&self) {
@@ -222,6 +505,55 @@ impl Channel<'_> {
     }
 }
 
+impl SoundChannel for Channel<'_> {
+    type Wave = WaveInfo;
+    type Error = NdspError;
+
+    fn new_wave(
+        buffer: Box<[u8], LinearAllocator>,
+        format: AudioFormat,
+        looping: bool,
+    ) -> Result<WaveInfo, NdspError> {
+        Ok(WaveInfo::new(buffer, format, looping))
+    }
+
+    /// Plays `wave`, setting the channel's format to the one of the wave.
+    ///
+    /// [`AudioFormat::Adpcm`] waves also need [`Channel::set_adpcm_coefficients`] to be called.
+    fn play(&self, wave: &mut WaveInfo) -> Result<(), NdspError> {
+        self.clear_queue();
+        self.set_format(wave.get_format());
+        self.queue_wave(wave)
+    }
+
+    fn stop(&self) -> Result<(), NdspError> {
+        self.clear_queue();
+
+        Ok(())
+    }
+
+    fn is_playing(&self) -> Result<bool, NdspError> {
+        Ok(Channel::is_playing(self))
+    }
+
+    fn set_sample_rate(&self, rate: f32) -> Result<(), NdspError> {
+        Channel::set_sample_rate(self, rate);
+
+        Ok(())
+    }
+
+    fn set_volume(&self, volume: f32, pan: f32) -> Result<(), NdspError> {
+        // Only the front outputs are set, keeping the rest of the mix.
+        let [left, right] = sound::stereo_volumes(volume, pan);
+        let mut mix = self.mix();
+        mix[0] = left;
+        mix[1] = right;
+        self.set_mix(&mix);
+
+        Ok(())
+    }
+}
+
 /// Functions to handle audio filtering.
 ///
 /// Refer to [libctru](https://libctru.devkitpro.org/channel_8h.html#a1da3b363c2edfd318c92276b527daae6) for more info.
@@ -305,11 +637,52 @@ impl AudioFormat {
     /// Eg.
     /// 8 bit formats return 1 (byte)
     /// 16 bit formats return 2 (bytes)
+    ///
+    /// # Panics
+    ///
+    /// Panics for [`AudioFormat::Adpcm`], whose samples take less than a byte.
+    /// Use [`AudioFormat::sample_count`] instead.
     pub fn sample_size(self) -> u8 {
         match self {
             AudioFormat::PCM8Mono => 1,
             AudioFormat::PCM16Mono | AudioFormat::PCM8Stereo => 2,
             AudioFormat::PCM16Stereo => 4,
+            AudioFormat::Adpcm => panic!("DSPADPCM samples are smaller than a byte"),
+        }
+    }
+
+    /// Returns the amount of bytes needed to store one sample, or `None` for [`AudioFormat::Adpcm`].
+    pub fn checked_sample_size(self) -> Option<u8> {
+        match self {
+            AudioFormat::Adpcm => None,
+            _ => Some(self.sample_size()),
+        }
+    }
+
+    /// Returns the amount of whole samples (one per audio channel) stored in `len` bytes.
+    pub fn sample_count(self, len: usize) -> usize {
+        match self {
+            AudioFormat::Adpcm => adpcm::sample_count(len),
+            _ => len / self.sample_size() as usize,
+        }
+    }
+
+    /// Returns the amount of audio channels, which is 1 for mono formats and 2 for stereo ones.
+    pub fn channel_count(self) -> u8 {
+        match self {
+            AudioFormat::PCM8Mono | AudioFormat::PCM16Mono | AudioFormat::Adpcm => 1,
+            AudioFormat::PCM8Stereo | AudioFormat::PCM16Stereo => 2,
+        }
+    }
+
+    /// Returns the format with the given amount of bits per sample and audio channels, if any.
+    pub fn from_layout(bits: u16, channels: u16) -> Option<Self> {
+        match (bits, channels) {
+            (8, 1) => Some(AudioFormat::PCM8Mono),
+            (16, 1) => Some(AudioFormat::PCM16Mono),
+            (8, 2) => Some(AudioFormat::PCM8Stereo),
+            (16, 2) => Some(AudioFormat::PCM16Stereo),
+            _ => None,
         }
     }
 }
@@ -335,8 +708,35 @@ impl<'ndsp> Drop for Channel<'ndsp> {
 
 impl Drop for Ndsp {
     fn drop(&mut self) {
+        self.clear_aux_callback(AuxDevice::Zero);
+        self.clear_aux_callback(AuxDevice::One);
+        self.clear_frame_callback();
+
         for i in 0..NUMBER_OF_CHANNELS {
             self.channel(i).unwrap().clear_queue();
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn panicking_callback() {
+        let slot: Mutex<Option<FrameCallback>> = Mutex::new(None);
+        run_callback(&slot, |callback| callback());
+
+        let mut calls = 0;
+        *slot.lock().unwrap() = Some(Box::new(move || {
+            calls += 1;
+            assert!(calls < 2, "second call");
+        }));
+        run_callback(&slot, |callback| callback());
+        assert!(slot.lock().unwrap().is_some());
+
+        // The panic doesn't escape, and doesn't poison the slot either.
+        run_callback(&slot, |callback| callback());
+        assert!(slot.lock().unwrap().is_none());
+    }
+}
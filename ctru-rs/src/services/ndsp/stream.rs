@@ -0,0 +1,650 @@
+//! Streaming playback
+//!
+//! [`AudioStream`] plays an endless or very long sound on a [`Channel`](super::Channel) without
+//! holding all of it in memory. It owns a ring of [`WaveInfo`](super::wave::WaveInfo) buffers in
+//! LINEAR memory, and a background thread refills them from a [`Source`] as the DSP plays them.
+//!
+//! [`AudioStream`] needs the `std-threads` feature, while [`Source`]s are always available to be
+//! decoded by hand.
+
+#[cfg(feature = "std-threads")]
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+#[cfg(feature = "std-threads")]
+use std::sync::{Arc, Condvar, Mutex};
+#[cfg(feature = "std-threads")]
+use std::thread::{self, JoinHandle};
+#[cfg(feature = "std-threads")]
+use std::time::Duration;
+
+use super::AudioFormat;
+#[cfg(feature = "std-threads")]
+use super::{
+    wave::{WaveInfo, WaveStatus},
+    Channel,
+};
+#[cfg(feature = "std-threads")]
+use crate::linear::LinearAllocator;
+
+/// Default amount of buffers in the ring of a stream.
+#[cfg(feature = "std-threads")]
+const DEFAULT_BUFFER_COUNT: usize = 4;
+
+/// Default length of each buffer of a stream, in frames (one sample per audio channel).
+#[cfg(feature = "std-threads")]
+const DEFAULT_BUFFER_FRAMES: usize = 2048;
+
+/// Provider of the samples of an [`AudioStream`].
+///
+/// Samples are 16-bit and interleaved when the stream is stereo, left channel first. They are
+/// converted when the stream plays 8-bit audio.
+///
+/// This is implemented for closures filling a buffer, and for iterators wrapped in [`Samples`].
+pub trait Source {
+    /// Writes samples to the start of `buf`, returning how many were written.
+    ///
+    /// Returning 0 marks the end of the samples. Returning less than the length of `buf` otherwise is
+    /// fine, this will be called again to fill the rest.
+    fn read(&mut self, buf: &mut [i16]) -> usize;
+
+    /// Goes back to the first sample, so that the stream can loop.
+    ///
+    /// Returns `false` if this isn't supported, which is the default.
+    fn rewind(&mut self) -> bool {
+        false
+    }
+}
+
+impl<F: FnMut(&mut [i16]) -> usize> Source for F {
+    fn read(&mut self, buf: &mut [i16]) -> usize {
+        self(buf)
+    }
+}
+
+/// [`Source`] taking samples from an iterator, which can be rewound by cloning it.
+#[derive(Clone, Debug)]
+pub struct Samples<I> {
+    start: I,
+    iter: I,
+}
+
+impl<I: Iterator<Item = i16> + Clone> Samples<I> {
+    /// Wraps an iterator over samples.
+    pub fn new<T: IntoIterator<IntoIter = I>>(iter: T) -> Self {
+        let iter = iter.into_iter();
+
+        Self {
+            start: iter.clone(),
+            iter,
+        }
+    }
+}
+
+impl<I: Iterator<Item = i16> + Clone> Source for Samples<I> {
+    fn read(&mut self, buf: &mut [i16]) -> usize {
+        buf.iter_mut()
+            .zip(&mut self.iter)
+            .map(|(sample, value)| *sample = value)
+            .count()
+    }
+
+    fn rewind(&mut self) -> bool {
+        self.iter = self.start.clone();
+        true
+    }
+}
+
+/// Ring of buffers and the DSP playing them, which is simulated in tests.
+#[cfg_attr(not(feature = "std-threads"), allow(dead_code))]
+trait Sink {
+    /// Returns the amount of buffers.
+    fn buffer_count(&self) -> usize;
+
+    /// Returns whether the buffer is neither queued nor playing.
+    fn is_free(&self, index: usize) -> bool;
+
+    /// Returns the data of a free buffer.
+    fn buffer_mut(&mut self, index: usize) -> &mut [u8];
+
+    /// Queues a buffer for playback, with the given amount of frames filled.
+    fn queue(&mut self, index: usize, frames: usize);
+}
+
+/// State of a stream after a round of refills.
+#[cfg_attr(not(feature = "std-threads"), allow(dead_code))]
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+struct Step {
+    /// Whether the DSP ran out of buffers to play since the previous round.
+    underrun: bool,
+    /// Whether every sample was played.
+    finished: bool,
+}
+
+/// Fills the free buffers of a [`Sink`] from a [`Source`], in ring order.
+#[cfg_attr(not(feature = "std-threads"), allow(dead_code))]
+struct Scheduler<S, R> {
+    sink: S,
+    source: R,
+    format: AudioFormat,
+    looping: bool,
+    /// Next buffer to fill, which is the first one to be queued.
+    next: usize,
+    /// Whether a buffer was ever queued.
+    started: bool,
+    source_done: bool,
+    scratch: Vec<i16>,
+}
+
+#[cfg_attr(not(feature = "std-threads"), allow(dead_code))]
+impl<S: Sink, R: Source> Scheduler<S, R> {
+    fn new(sink: S, source: R, format: AudioFormat) -> Self {
+        Self {
+            sink,
+            source,
+            format,
+            looping: false,
+            next: 0,
+            started: false,
+            source_done: false,
+            scratch: Vec::new(),
+        }
+    }
+
+    fn step(&mut self) -> Step {
+        let count = self.sink.buffer_count();
+        let busy = (0..count).filter(|&i| !self.sink.is_free(i)).count();
+        let underrun = self.started && !self.source_done && busy == 0;
+
+        while !self.source_done && self.sink.is_free(self.next) {
+            let frames = self.fill(self.next);
+            if frames == 0 {
+                self.source_done = true;
+                break;
+            }
+
+            self.sink.queue(self.next, frames);
+            self.started = true;
+            self.next = (self.next + 1) % count;
+        }
+
+        Step {
+            underrun,
+            finished: self.source_done && (0..count).all(|i| self.sink.is_free(i)),
+        }
+    }
+
+    /// Fills a buffer with as many whole frames as possible, returning their amount.
+    fn fill(&mut self, index: usize) -> usize {
+        let channels = self.format.channel_count() as usize;
+        let sample_size = self.format.sample_size() as usize / channels;
+        let buffer = self.sink.buffer_mut(index);
+
+        self.scratch.resize(buffer.len() / sample_size, 0);
+        let mut filled = 0;
+        // Rewinding a source without samples mustn't loop forever.
+        let mut rewound = false;
+        while filled < self.scratch.len() {
+            let read = self.source.read(&mut self.scratch[filled..]);
+            if read > 0 {
+                filled += read;
+                rewound = false;
+            } else if self.looping && !rewound && self.source.rewind() {
+                rewound = true;
+            } else {
+                break;
+            }
+        }
+
+        // A partial frame at the very end is dropped.
+        let frames = filled / channels;
+        write_samples(self.format, &self.scratch[..frames * channels], buffer);
+        frames
+    }
+}
+
+/// Writes 16-bit samples to `buffer` in the layout of `format`, keeping the high byte of the
+/// samples of 8-bit formats.
+pub(super) fn write_samples(format: AudioFormat, samples: &[i16], buffer: &mut [u8]) {
+    if format.sample_size() == format.channel_count() {
+        for (byte, sample) in buffer.iter_mut().zip(samples) {
+            *byte = (sample >> 8) as u8;
+        }
+    } else {
+        for (bytes, sample) in buffer.chunks_exact_mut(2).zip(samples) {
+            bytes.copy_from_slice(&sample.to_ne_bytes());
+        }
+    }
+}
+
+/// Buffers of a stream played by a channel of the DSP.
+#[cfg(feature = "std-threads")]
+struct DspSink {
+    channel_id: u8,
+    waves: Vec<WaveInfo>,
+}
+
+// SAFETY: The buffers are only accessed by the thread of the stream, and the DSP.
+#[cfg(feature = "std-threads")]
+unsafe impl Send for DspSink {}
+
+#[cfg(feature = "std-threads")]
+impl Sink for DspSink {
+    fn buffer_count(&self) -> usize {
+        self.waves.len()
+    }
+
+    fn is_free(&self, index: usize) -> bool {
+        matches!(
+            self.waves[index].get_status(),
+            WaveStatus::Free | WaveStatus::Done
+        )
+    }
+
+    fn buffer_mut(&mut self, index: usize) -> &mut [u8] {
+        self.waves[index].get_buffer_mut().unwrap()
+    }
+
+    fn queue(&mut self, index: usize, frames: usize) {
+        let wave = &mut self.waves[index];
+        wave.set_sample_count(frames as u32).unwrap();
+        wave.set_channel(self.channel_id);
+
+        let buffer = wave.get_buffer();
+        unsafe {
+            let _r = ctru_sys::DSP_FlushDataCache(buffer.as_ptr().cast(), buffer.len() as u32);
+            ctru_sys::ndspChnWaveBufAdd(self.channel_id.into(), &mut wave.raw_data);
+        }
+    }
+}
+
+/// State shared between a stream and its thread.
+#[cfg(feature = "std-threads")]
+struct Shared {
+    stop: Mutex<bool>,
+    wake: Condvar,
+    looping: AtomicBool,
+    underruns: AtomicUsize,
+    finished: AtomicBool,
+}
+
+/// Sound played from a [`Source`] through a ring of buffers, refilled by a background thread.
+///
+/// The stream takes over the channel, whose queue it manages. Other settings of the channel, like
+/// its mix and filters, can still be changed through [`AudioStream::channel`]. Dropping the stream
+/// stops playback.
+///
+/// # Examples
+///
+/// ```no_run
+/// use ctru::services::ndsp::stream::AudioStream;
+/// use ctru::services::ndsp::{AudioFormat, Ndsp};
+///
+/// let ndsp = Ndsp::init().unwrap();
+///
+/// // A 440 Hz sine wave, forever.
+/// let mut phase = 0.0f32;
+/// let sine = move |buf: &mut [i16]| {
+///     for sample in buf.iter_mut() {
+///         *sample = (phase.sin() * 8000.0) as i16;
+///         phase += 440.0 * std::f32::consts::TAU / 22050.0;
+///     }
+///     buf.len()
+/// };
+///
+/// let stream =
+///     AudioStream::new(ndsp.channel(0).unwrap(), AudioFormat::PCM16Mono, 22050.0, sine).unwrap();
+/// ```
+#[cfg(feature = "std-threads")]
+pub struct AudioStream<'ndsp> {
+    channel: Channel<'ndsp>,
+    shared: Arc<Shared>,
+    thread: Option<JoinHandle<()>>,
+}
+
+#[cfg(feature = "std-threads")]
+impl<'ndsp> AudioStream<'ndsp> {
+    /// Starts playing samples from `source` on `channel`, with 4 buffers of 2048 frames.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if `sample_rate` isn't positive, or the thread of the
+    /// stream can't be started.
+    pub fn new<R: Source + Send + 'static>(
+        channel: Channel<'ndsp>,
+        format: AudioFormat,
+        sample_rate: f32,
+        source: R,
+    ) -> crate::Result<Self> {
+        Self::with_buffers(
+            channel,
+            format,
+            sample_rate,
+            DEFAULT_BUFFER_COUNT,
+            DEFAULT_BUFFER_FRAMES,
+            source,
+        )
+    }
+
+    /// Starts playing samples from `source` on `channel`, with `buffer_count` buffers of
+    /// `buffer_frames` frames.
+    ///
+    /// Larger buffers make underruns less likely, at the cost of memory and latency.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if `sample_rate` isn't positive, or the thread of the
+    /// stream can't be started.
+    ///
+    /// # Panics
+    ///
+    /// Panics if there are less than 2 buffers, they are empty, or `format` is
+    /// [`AudioFormat::Adpcm`], which can't be streamed.
+    pub fn with_buffers<R: Source + Send + 'static>(
+        channel: Channel<'ndsp>,
+        format: AudioFormat,
+        sample_rate: f32,
+        buffer_count: usize,
+        buffer_frames: usize,
+        source: R,
+    ) -> crate::Result<Self> {
+        assert!(buffer_count >= 2, "a stream needs at least 2 buffers");
+        assert!(buffer_frames > 0, "the buffers of a stream can't be empty");
+        assert!(
+            !matches!(format, AudioFormat::Adpcm),
+            "streams can only play PCM"
+        );
+        if !(sample_rate > 0.0 && sample_rate.is_finite()) {
+            return Err(crate::Error::InvalidSampleRate(sample_rate));
+        }
+
+        channel.clear_queue();
+        channel.set_format(format);
+        channel.set_sample_rate(sample_rate);
+
+        let len = buffer_frames * format.sample_size() as usize;
+        let waves = (0..buffer_count)
+            .map(|_| {
+                let mut buffer = Vec::with_capacity_in(len, LinearAllocator);
+                buffer.resize(len, 0);
+                WaveInfo::new(buffer.into_boxed_slice(), format, false)
+            })
+            .collect();
+        let sink = DspSink {
+            channel_id: channel.get_id(),
+            waves,
+        };
+
+        let shared = Arc::new(Shared {
+            stop: Mutex::new(false),
+            wake: Condvar::new(),
+            looping: AtomicBool::new(false),
+            underruns: AtomicUsize::new(0),
+            finished: AtomicBool::new(false),
+        });
+
+        // Waking up 4 times per buffer leaves time to refill one before the queue runs dry. Very low
+        // rates still check every second, which also keeps the period in range of a `Duration`.
+        let period = Duration::from_secs_f32((buffer_frames as f32 / sample_rate / 4.0).min(1.0));
+        let thread_shared = Arc::clone(&shared);
+        let thread = thread::Builder::new()
+            .name("ndsp-stream".into())
+            .spawn(move || {
+                let shared = thread_shared;
+                let mut scheduler = Scheduler::new(sink, source, format);
+
+                let mut stop = shared.stop.lock().unwrap();
+                while !*stop {
+                    scheduler.looping = shared.looping.load(Ordering::Relaxed);
+                    let step = scheduler.step();
+                    if step.underrun {
+                        shared.underruns.fetch_add(1, Ordering::Relaxed);
+                    }
+                    if step.finished {
+                        shared.finished.store(true, Ordering::Relaxed);
+                        break;
+                    }
+
+                    stop = shared.wake.wait_timeout(stop, period).unwrap().0;
+                }
+            })
+            .map_err(crate::Error::Io)?;
+
+        Ok(Self {
+            channel,
+            shared,
+            thread: Some(thread),
+        })
+    }
+
+    /// Returns the channel playing the stream.
+    pub fn channel(&self) -> &Channel<'ndsp> {
+        &self.channel
+    }
+
+    /// Pauses playback, keeping the queued samples.
+    pub fn pause(&self) {
+        self.channel.set_paused(true);
+    }
+
+    /// Resumes playback after [`AudioStream::pause`].
+    pub fn resume(&self) {
+        self.channel.set_paused(false);
+    }
+
+    /// Returns whether playback is paused.
+    pub fn is_paused(&self) -> bool {
+        self.channel.is_paused()
+    }
+
+    /// Sets whether the source is rewound to play again once it reaches its end.
+    ///
+    /// This has no effect on sources which can't be rewound.
+    pub fn set_looping(&self, looping: bool) {
+        self.shared.looping.store(looping, Ordering::Relaxed);
+        self.shared.wake.notify_one();
+    }
+
+    /// Returns how many times playback stalled because the buffers weren't refilled in time.
+    pub fn underruns(&self) -> usize {
+        self.shared.underruns.load(Ordering::Relaxed)
+    }
+
+    /// Returns whether the source reached its end and all of its samples were played.
+    pub fn is_finished(&self) -> bool {
+        self.shared.finished.load(Ordering::Relaxed)
+    }
+}
+
+#[cfg(feature = "std-threads")]
+impl Drop for AudioStream<'_> {
+    fn drop(&mut self) {
+        *self.shared.stop.lock().unwrap() = true;
+        self.shared.wake.notify_one();
+
+        // The buffers are dropped with the thread, which clears the queue of the channel if needed.
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::VecDeque;
+
+    /// DSP playing a fixed amount of frames per tick, in queue order.
+    struct SimulatedDsp {
+        buffers: Vec<Vec<u8>>,
+        frames: Vec<usize>,
+        queue: VecDeque<usize>,
+        /// Frames of the buffer at the front of the queue which were already played.
+        position: usize,
+        paused: bool,
+        played: Vec<i16>,
+    }
+
+    impl SimulatedDsp {
+        fn new(count: usize, len: usize) -> Self {
+            Self {
+                buffers: vec![vec![0; len]; count],
+                frames: vec![0; count],
+                queue: VecDeque::new(),
+                position: 0,
+                paused: false,
+                played: Vec::new(),
+            }
+        }
+
+        /// Plays up to `frames` mono 16-bit frames.
+        fn tick(&mut self, mut frames: usize) {
+            while frames > 0 && !self.paused {
+                let index = match self.queue.front() {
+                    Some(&index) => index,
+                    None => return,
+                };
+
+                let available = self.frames[index] - self.position;
+                let played = available.min(frames);
+                let bytes = &self.buffers[index][self.position * 2..(self.position + played) * 2];
+                self.played.extend(
+                    bytes
+                        .chunks_exact(2)
+                        .map(|b| i16::from_ne_bytes([b[0], b[1]])),
+                );
+
+                frames -= played;
+                self.position += played;
+                if self.position == self.frames[index] {
+                    self.queue.pop_front();
+                    self.position = 0;
+                }
+            }
+        }
+    }
+
+    impl Sink for SimulatedDsp {
+        fn buffer_count(&self) -> usize {
+            self.buffers.len()
+        }
+
+        fn is_free(&self, index: usize) -> bool {
+            !self.queue.contains(&index)
+        }
+
+        fn buffer_mut(&mut self, index: usize) -> &mut [u8] {
+            assert!(self.is_free(index));
+            &mut self.buffers[index]
+        }
+
+        fn queue(&mut self, index: usize, frames: usize) {
+            self.frames[index] = frames;
+            self.queue.push_back(index);
+        }
+    }
+
+    fn ramp(len: i16) -> Samples<std::ops::Range<i16>> {
+        Samples::new(0..len)
+    }
+
+    #[test]
+    fn continuous_playback() {
+        let mut scheduler = Scheduler::new(
+            SimulatedDsp::new(3, 8 * 2),
+            ramp(100),
+            AudioFormat::PCM16Mono,
+        );
+
+        let mut steps = Vec::new();
+        for _ in 0..40 {
+            steps.push(scheduler.step());
+            scheduler.sink.tick(5);
+        }
+
+        assert_eq!(scheduler.sink.played, (0..100).collect::<Vec<_>>());
+        assert!(steps.iter().all(|step| !step.underrun));
+        assert!(steps.last().unwrap().finished);
+        // The last buffer only holds the remaining 4 frames.
+        assert_eq!(scheduler.sink.frames[(100 / 8) % 3], 4);
+    }
+
+    #[test]
+    fn underruns() {
+        let mut scheduler = Scheduler::new(
+            SimulatedDsp::new(2, 4 * 2),
+            ramp(100),
+            AudioFormat::PCM16Mono,
+        );
+
+        // The DSP plays both buffers between two refills.
+        assert!(!scheduler.step().underrun);
+        scheduler.sink.tick(10);
+        assert!(scheduler.step().underrun);
+
+        // Nothing is lost, playback just stalled.
+        while !scheduler.step().finished {
+            scheduler.sink.tick(3);
+        }
+        assert_eq!(scheduler.sink.played, (0..100).collect::<Vec<_>>());
+
+        // Pausing doesn't drain the buffers.
+        let mut scheduler = Scheduler::new(
+            SimulatedDsp::new(2, 4 * 2),
+            ramp(100),
+            AudioFormat::PCM16Mono,
+        );
+        scheduler.step();
+        scheduler.sink.paused = true;
+        scheduler.sink.tick(10);
+        assert_eq!(
+            scheduler.step(),
+            Step {
+                underrun: false,
+                finished: false
+            }
+        );
+        assert!(scheduler.sink.played.is_empty());
+    }
+
+    #[test]
+    fn looping() {
+        let mut scheduler =
+            Scheduler::new(SimulatedDsp::new(2, 8 * 2), ramp(5), AudioFormat::PCM16Mono);
+        scheduler.looping = true;
+
+        for _ in 0..3 {
+            scheduler.step();
+            scheduler.sink.tick(8);
+        }
+        let expected: Vec<i16> = (0..5).cycle().take(24).collect();
+        assert_eq!(scheduler.sink.played, expected);
+
+        // Closures can't be rewound, and neither can empty sources loop forever.
+        let mut scheduler = Scheduler::new(
+            SimulatedDsp::new(2, 8 * 2),
+            |_: &mut [i16]| 0,
+            AudioFormat::PCM16Mono,
+        );
+        scheduler.looping = true;
+        assert!(scheduler.step().finished);
+
+        let mut scheduler =
+            Scheduler::new(SimulatedDsp::new(2, 8 * 2), ramp(0), AudioFormat::PCM16Mono);
+        scheduler.looping = true;
+        assert!(scheduler.step().finished);
+    }
+
+    #[test]
+    fn sample_formats() {
+        // Stereo frames are never split, and 8-bit samples keep their high byte.
+        let mut scheduler = Scheduler::new(
+            SimulatedDsp::new(2, 4),
+            Samples::new([0x1234, -0x100, 0x7f00, 0x0100, 0x5555]),
+            AudioFormat::PCM8Stereo,
+        );
+        scheduler.step();
+
+        assert_eq!(scheduler.sink.frames, [2, 0]);
+        assert_eq!(scheduler.sink.buffers[0], [0x12, 0xff, 0x7f, 0x01]);
+        assert!(scheduler.source_done);
+    }
+}
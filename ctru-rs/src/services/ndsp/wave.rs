@@ -1,3 +1,4 @@
+use super::adpcm::AdpcmContext;
 use super::{AudioFormat, NdspError};
 use crate::linear::LinearAllocator;
 
@@ -8,6 +9,8 @@ pub struct WaveInfo {
     audio_format: AudioFormat,
     // Holding the data with the raw format is necessary since `libctru` will access it.
     pub(crate) raw_data: ctru_sys::ndspWaveBuf,
+    // Boxed so that `raw_data` can point to it wherever the wave is moved.
+    adpcm_context: Option<Box<ctru_sys::ndspAdpcmData>>,
     played_on_channel: Option<u8>,
 }
 
@@ -28,7 +31,7 @@ impl WaveInfo {
         audio_format: AudioFormat,
         looping: bool,
     ) -> Self {
-        let sample_count: usize = buffer.len() / (audio_format.sample_size() as usize);
+        let sample_count: usize = audio_format.sample_count(buffer.len());
 
         // Signal to the DSP processor the buffer's RAM sector.
         // This step may seem delicate, but testing reports failure most of the time, while still having no repercussions on the resulting audio.
@@ -56,6 +59,7 @@ impl WaveInfo {
             buffer,
             audio_format,
             raw_data,
+            adpcm_context: None,
             played_on_channel: None,
         }
     }
@@ -126,7 +130,7 @@ impl WaveInfo {
             _ => (),
         }
 
-        let max_count: usize = self.buffer.len() / (self.audio_format.sample_size() as usize);
+        let max_count: usize = self.audio_format.sample_count(self.buffer.len());
 
         if sample_count > max_count as u32 {
             return Err(NdspError::SampleCountOutOfBounds(
@@ -139,6 +143,31 @@ impl WaveInfo {
 
         Ok(())
     }
+
+    /// Set the decoder state at the start of an [`AudioFormat::Adpcm`] wave.
+    ///
+    /// Without one, the channel keeps the state left by the previous wave, which is only right if this
+    /// wave follows it in the original sound.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the WaveInfo is currently queued.
+    pub fn set_adpcm_context(&mut self, context: Option<AdpcmContext>) -> Result<(), NdspError> {
+        match self.get_status() {
+            WaveStatus::Playing | WaveStatus::Queued => {
+                return Err(NdspError::WaveBusy(self.played_on_channel.unwrap()));
+            }
+            _ => (),
+        }
+
+        self.adpcm_context = context.map(|context| Box::new(context.into()));
+        self.raw_data.adpcm_data = match &mut self.adpcm_context {
+            Some(data) => &mut **data,
+            None => std::ptr::null_mut(),
+        };
+
+        Ok(())
+    }
 }
 
 impl TryFrom<u8> for WaveStatus {
@@ -0,0 +1,71 @@
+//! Traits shared by the sound services
+//!
+//! The 3DS plays sounds through two services: [`ndsp`](super::ndsp), which runs on the DSP and needs
+//! its firmware to be dumped on the console, and the older [`csnd`](super::csnd), which is always
+//! available but only has mono channels and no effects. Both play buffers of LINEAR memory on
+//! numbered channels, and [`SoundChannel`] covers what they have in common, so that the same sound
+//! code can run on either.
+
+use crate::linear::LinearAllocator;
+use crate::services::ndsp::AudioFormat;
+
+/// A channel of a sound service, playing one wave at a time.
+pub trait SoundChannel {
+    /// Wave played by the channel, owning its samples.
+    type Wave;
+    /// Error returned by the operations of the channel.
+    type Error: std::error::Error;
+
+    /// Builds a wave which can be played on this kind of channel out of a buffer on LINEAR memory.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the service can't play samples of the given format.
+    fn new_wave(
+        buffer: Box<[u8], LinearAllocator>,
+        format: AudioFormat,
+        looping: bool,
+    ) -> Result<Self::Wave, Self::Error>;
+
+    /// Starts playing `wave`, stopping whatever the channel was playing before.
+    ///
+    /// The wave must be kept alive until it stops playing, or it stops the channel when dropped.
+    fn play(&self, wave: &mut Self::Wave) -> Result<(), Self::Error>;
+
+    /// Stops playback.
+    fn stop(&self) -> Result<(), Self::Error>;
+
+    /// Returns whether the channel is playing a wave.
+    fn is_playing(&self) -> Result<bool, Self::Error>;
+
+    /// Sets the rate at which samples are played, in Hz.
+    fn set_sample_rate(&self, rate: f32) -> Result<(), Self::Error>;
+
+    /// Sets the volume of the channel, between 0 and 1, and its pan, from -1 (left) to 1 (right).
+    ///
+    /// The volume is split between the speakers, so each plays at half of it when centered.
+    fn set_volume(&self, volume: f32, pan: f32) -> Result<(), Self::Error>;
+}
+
+/// Returns the volume of the left and right speakers for the given volume and pan, as set by
+/// [`SoundChannel::set_volume`].
+pub(crate) fn stereo_volumes(volume: f32, pan: f32) -> [f32; 2] {
+    let volume = volume.clamp(0.0, 1.0);
+    let right = ((pan + 1.0) / 2.0).clamp(0.0, 1.0);
+
+    [volume * (1.0 - right), volume * right]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn volumes() {
+        assert_eq!(stereo_volumes(1.0, 0.0), [0.5, 0.5]);
+        assert_eq!(stereo_volumes(0.5, -1.0), [0.5, 0.0]);
+        assert_eq!(stereo_volumes(2.0, 1.0), [0.0, 1.0]);
+        assert_eq!(stereo_volumes(1.0, 0.5), [0.25, 0.75]);
+        assert_eq!(stereo_volumes(-1.0, 3.0), [0.0, 0.0]);
+    }
+}
@@ -1,10 +1,17 @@
 use std::env;
 
 fn main() {
+    println!("cargo:rerun-if-changed=build.rs");
+
+    // Host builds (e.g. `ctru-rs` with the `host-mock` feature) only use the types
+    // and constants of the bindings, so there is nothing to link against.
+    if env::var("CARGO_CFG_TARGET_OS").as_deref() != Ok("horizon") {
+        return;
+    }
+
     let dkp_path = env::var("DEVKITPRO").unwrap();
     let profile = env::var("PROFILE").unwrap();
 
-    println!("cargo:rerun-if-changed=build.rs");
     println!("cargo:rerun-if-env-changed=DEVKITPRO");
     println!("cargo:rustc-link-search=native={dkp_path}/libctru/lib");
     println!(
@@ -17,3 +17,12 @@ pub use result::*;
 pub unsafe fn errno() -> s32 {
     (*__getreent())._errno
 }
+
+/// Returns the position of the 3D depth slider, from `0.0` (off) to `1.0`.
+///
+/// This is a port of the `osGet3DSliderState` inline function of `libctru`,
+/// which reads the value from the shared configuration page.
+pub unsafe fn osGet3DSliderState() -> f32 {
+    let config = OS_SHAREDCFG_VADDR as *const osSharedConfig_s;
+    core::ptr::read_volatile(core::ptr::addr_of!((*config).slider_3d))
+}